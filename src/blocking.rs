@@ -0,0 +1,210 @@
+//! Bookkeeping for clients blocked waiting for keys, e.g. by BLPOP.
+//!
+//! Each blocked client waits on one or more keys of a database, and is queued on
+//! each of them in the order it blocked, so that clients are served first come,
+//! first served.  A client is sent its reply through a channel once a write makes
+//! one of its keys ready, at which point it is removed from all its queues.
+
+use std::collections::{HashMap, VecDeque};
+
+use tokio::sync::oneshot;
+
+use crate::resp_command::ListEnd;
+use crate::streams::StreamId;
+
+/// What to do for a blocked client once one of its keys is ready.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum BlockedAction {
+    // Pop an element from the list, as for BLPOP and BRPOP.
+    Pop(ListEnd),
+    // Move an element from the list to the destination, as for BLMOVE.
+    Move {
+        destination: Vec<u8>,
+        from: ListEnd,
+        to: ListEnd,
+    },
+    // Pop the member with the lowest score from the sorted set, or the highest if max is
+    // set, as for BZPOPMIN and BZPOPMAX.
+    ZPop {
+        max: bool,
+    },
+    // Read the entries after the ID of each stream, as for XREAD.
+    XRead {
+        after: Vec<(Vec<u8>, StreamId)>,
+        count: Option<usize>,
+    },
+    // Deliver entries the group is yet to deliver to the consumer, as for XREADGROUP.
+    XReadGroup {
+        group: Vec<u8>,
+        consumer: Vec<u8>,
+        count: Option<usize>,
+        no_ack: bool,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) struct BlockedClient {
+    pub(crate) db: usize,
+    pub(crate) keys: Vec<Vec<u8>>,
+    pub(crate) action: BlockedAction,
+    // Receives the serialised reply.
+    pub(crate) sender: oneshot::Sender<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct BlockedClients {
+    next_id: u64,
+    clients: HashMap<u64, BlockedClient>,
+    // The ids of the clients waiting on each database and key, in the order they blocked.
+    queues: HashMap<(usize, Vec<u8>), VecDeque<u64>>,
+}
+
+impl BlockedClients {
+    /// Queues a client on each of its keys, returning its id.
+    pub(crate) fn block(&mut self, client: BlockedClient) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in &client.keys {
+            let queue = self.queues.entry((client.db, key.clone())).or_default();
+            // A key given twice only queues the client once.
+            if queue.back() != Some(&id) {
+                queue.push_back(id);
+            }
+        }
+        self.clients.insert(id, client);
+        id
+    }
+
+    /// Removes a client from all its queues, returning it if it was still blocked.
+    pub(crate) fn unblock(&mut self, id: u64) -> Option<BlockedClient> {
+        let client = self.clients.remove(&id)?;
+        for key in &client.keys {
+            let queue_key = (client.db, key.clone());
+            if let Some(queue) = self.queues.get_mut(&queue_key) {
+                queue.retain(|queued| *queued != id);
+                if queue.is_empty() {
+                    self.queues.remove(&queue_key);
+                }
+            }
+        }
+        Some(client)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// The database and key of every key a client is waiting on.
+    pub(crate) fn waited_keys(&self) -> Vec<(usize, Vec<u8>)> {
+        self.queues.keys().cloned().collect()
+    }
+
+    /// Returns the id and action of the client that has waited longest on the key among
+    /// those whose action is ready, first dropping any clients which have gone away.
+    /// Clients whose action doesn't suit the key's type keep waiting behind the others.
+    pub(crate) fn first_waiting(
+        &mut self,
+        db: usize,
+        key: &[u8],
+        ready: impl Fn(&BlockedAction) -> bool,
+    ) -> Option<(u64, BlockedAction)> {
+        let (closed, waiting): (Vec<u64>, Vec<u64>) = self
+            .queues
+            .get(&(db, key.to_vec()))?
+            .iter()
+            .partition(|id| self.clients[id].sender.is_closed());
+        for id in closed {
+            self.unblock(id);
+        }
+        waiting
+            .into_iter()
+            .map(|id| (id, &self.clients[&id].action))
+            .find(|(_, action)| ready(action))
+            .map(|(id, action)| (id, action.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(keys: &[&[u8]]) -> (BlockedClient, oneshot::Receiver<Vec<u8>>) {
+        let (sender, receiver) = oneshot::channel();
+        let client = BlockedClient {
+            db: 0,
+            keys: keys.iter().map(|key| key.to_vec()).collect(),
+            action: BlockedAction::Pop(ListEnd::Left),
+            sender,
+        };
+        (client, receiver)
+    }
+
+    #[test]
+    fn serves_in_blocking_order() {
+        let mut blocked = BlockedClients::default();
+        let (first, _first_receiver) = client(&[b"a", b"b"]);
+        let (second, _second_receiver) = client(&[b"b"]);
+        let first = blocked.block(first);
+        let second = blocked.block(second);
+
+        assert_eq!(
+            blocked.first_waiting(0, b"b", |_| true).map(|(id, _)| id),
+            Some(first)
+        );
+        blocked.unblock(first);
+        assert_eq!(
+            blocked.first_waiting(0, b"b", |_| true).map(|(id, _)| id),
+            Some(second)
+        );
+        assert_eq!(blocked.first_waiting(0, b"a", |_| true), None);
+        assert_eq!(blocked.first_waiting(1, b"b", |_| true), None);
+    }
+
+    #[test]
+    fn skips_clients_that_went_away() {
+        let mut blocked = BlockedClients::default();
+        let (first, first_receiver) = client(&[b"a"]);
+        let (second, _second_receiver) = client(&[b"a"]);
+        blocked.block(first);
+        let second = blocked.block(second);
+        drop(first_receiver);
+
+        assert_eq!(
+            blocked.first_waiting(0, b"a", |_| true).map(|(id, _)| id),
+            Some(second)
+        );
+    }
+
+    #[test]
+    fn skips_clients_whose_action_is_not_ready() {
+        let mut blocked = BlockedClients::default();
+        let (list_client, _list_receiver) = client(&[b"a"]);
+        let (mut zset_client, _zset_receiver) = client(&[b"a"]);
+        zset_client.action = BlockedAction::ZPop { max: false };
+        let list_client = blocked.block(list_client);
+        let zset_client = blocked.block(zset_client);
+
+        let zset_ready = |action: &BlockedAction| matches!(action, BlockedAction::ZPop { .. });
+        assert_eq!(
+            blocked.first_waiting(0, b"a", zset_ready).map(|(id, _)| id),
+            Some(zset_client)
+        );
+        assert_eq!(
+            blocked.first_waiting(0, b"a", |_| true).map(|(id, _)| id),
+            Some(list_client)
+        );
+    }
+
+    #[test]
+    fn unblock_removes_empty_queues() {
+        let mut blocked = BlockedClients::default();
+        let (client, _receiver) = client(&[b"a", b"a", b"b"]);
+        let id = blocked.block(client);
+        assert_eq!(blocked.waited_keys().len(), 2);
+
+        assert!(blocked.unblock(id).is_some());
+        assert!(blocked.unblock(id).is_none());
+        assert!(blocked.is_empty());
+        assert!(blocked.waited_keys().is_empty());
+    }
+}
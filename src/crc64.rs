@@ -0,0 +1,54 @@
+//! The CRC64 variant Redis uses to checksum RDB files.
+//!
+//! This is the Jones polynomial with reflected input and output, an initial
+//! value of zero and no final xor.
+
+// The Jones polynomial, bit reversed for the reflected algorithm.
+const POLYNOMIAL: u64 = 0x95ac_9329_ac4b_c9b5;
+
+// Lookup table of the CRC of every single byte value.
+const TABLE: [u64; 256] = make_table();
+
+const fn make_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Extends the checksum of some data with the bytes that follow it.  The checksum
+/// of empty data is zero.
+pub(crate) fn crc64(crc: u64, data: &[u8]) -> u64 {
+    data.iter().fold(crc, |crc, b| {
+        TABLE[((crc ^ *b as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_redis_check_value() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+
+    #[test]
+    fn extends_checksum() {
+        let partial = crc64(0, b"1234");
+        assert_eq!(crc64(partial, b"56789"), crc64(0, b"123456789"));
+    }
+}
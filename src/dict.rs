@@ -0,0 +1,307 @@
+//! A hash table which can be scanned with a cursor, as the keyspace is by SCAN.
+//!
+//! Like Redis's dict, the table is a power of two number of buckets, each holding the
+//! entries whose hashes' low bits are its index.  A scan visits the buckets in order of
+//! their indexes with the bits reversed, and its cursor is the index of the next bucket
+//! to visit.  Since growing or shrinking the table splits or merges buckets which are
+//! next to each other in that order, the buckets already visited are still the ones
+//! before the cursor after a resize.  So every entry present for the whole of a scan is
+//! returned at least once, although entries may be returned again if the table shrinks.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use rand::Rng;
+
+// The table never shrinks below this many buckets.
+const MIN_BUCKETS: usize = 4;
+
+// The table shrinks once fewer than one in this many buckets would be needed.
+const SHRINK_RATIO: usize = 8;
+
+/// An iterator over the entries of a Dict, in no particular order.
+pub(crate) type Iter<'a, V> = std::iter::Map<
+    std::iter::Flatten<std::slice::Iter<'a, Vec<(Vec<u8>, V)>>>,
+    fn(&'a (Vec<u8>, V)) -> (&'a Vec<u8>, &'a V),
+>;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Dict<V> {
+    buckets: Vec<Vec<(Vec<u8>, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<V> Dict<V> {
+    pub(crate) fn new() -> Self {
+        Dict {
+            buckets: empty_buckets(MIN_BUCKETS),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn get(&self, key: &[u8]) -> Option<&V> {
+        self.buckets[self.bucket(key)]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let idx = self.bucket(key);
+        self.buckets[idx]
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of the key, returning the value it replaced if any.
+    pub(crate) fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        if let Some(existing) = self.get_mut(&key) {
+            return Some(std::mem::replace(existing, value));
+        }
+        let idx = self.bucket(&key);
+        self.buckets[idx].push((key, value));
+        self.len += 1;
+        if self.len > self.buckets.len() {
+            self.resize(self.buckets.len() * 2);
+        }
+        None
+    }
+
+    pub(crate) fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let idx = self.bucket(key);
+        let pos = self.buckets[idx].iter().position(|(k, _)| k == key)?;
+        let (_, value) = self.buckets[idx].swap_remove(pos);
+        self.len -= 1;
+        self.shrink_if_sparse();
+        Some(value)
+    }
+
+    /// Removes the entries for which keep returns false.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&Vec<u8>, &V) -> bool) {
+        for bucket in &mut self.buckets {
+            bucket.retain(|(key, value)| keep(key, value));
+        }
+        self.len = self.buckets.iter().map(Vec::len).sum();
+        self.shrink_if_sparse();
+    }
+
+    pub(crate) fn iter(&self) -> Iter<'_, V> {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// A key chosen at random, or None if the dict is empty.  Keys in buckets with fewer
+    /// entries are more likely to be chosen, as in Redis.
+    pub(crate) fn random_key(&self, rng: &mut impl Rng) -> Option<&Vec<u8>> {
+        if self.is_empty() {
+            return None;
+        }
+        // The table is kept at least an eighth full, so an entry is soon found.
+        loop {
+            let bucket = &self.buckets[rng.gen_range(0..self.buckets.len())];
+            if !bucket.is_empty() {
+                return Some(&bucket[rng.gen_range(0..bucket.len())].0);
+            }
+        }
+    }
+
+    /// Calls visit with each entry in the bucket at the cursor, and returns the cursor of
+    /// the next bucket, which is zero once the scan is complete.  A scan starts with a
+    /// cursor of zero.
+    pub(crate) fn scan(&self, cursor: u64, mut visit: impl FnMut(&Vec<u8>, &V)) -> u64 {
+        let mask = (self.buckets.len() - 1) as u64;
+        for (key, value) in &self.buckets[(cursor & mask) as usize] {
+            visit(key, value);
+        }
+        // Increment the reversed cursor, with the bits above the mask set so that the
+        // carry passes through them and wraps to zero after the last bucket.
+        (cursor | !mask)
+            .reverse_bits()
+            .wrapping_add(1)
+            .reverse_bits()
+    }
+
+    fn bucket(&self, key: &[u8]) -> usize {
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    fn resize(&mut self, num_buckets: usize) {
+        let old = std::mem::replace(&mut self.buckets, empty_buckets(num_buckets));
+        for (key, value) in old.into_iter().flatten() {
+            let idx = self.bucket(&key);
+            self.buckets[idx].push((key, value));
+        }
+    }
+
+    fn shrink_if_sparse(&mut self) {
+        let needed = self.len.next_power_of_two().max(MIN_BUCKETS);
+        if self.len * SHRINK_RATIO < self.buckets.len() && needed < self.buckets.len() {
+            self.resize(needed);
+        }
+    }
+}
+
+impl<V> Default for Dict<V> {
+    fn default() -> Self {
+        Dict::new()
+    }
+}
+
+impl<V: PartialEq> PartialEq for Dict<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<'a, V> IntoIterator for &'a Dict<V> {
+    type Item = (&'a Vec<u8>, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<V> FromIterator<(Vec<u8>, V)> for Dict<V> {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, V)>>(entries: I) -> Self {
+        let mut dict = Dict::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        dict
+    }
+}
+
+impl<V, const N: usize> From<[(Vec<u8>, V); N]> for Dict<V> {
+    fn from(entries: [(Vec<u8>, V); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+// A dict without values serves as a set of its keys.
+impl FromIterator<Vec<u8>> for Dict<()> {
+    fn from_iter<I: IntoIterator<Item = Vec<u8>>>(keys: I) -> Self {
+        keys.into_iter().map(|key| (key, ())).collect()
+    }
+}
+
+impl<const N: usize> From<[Vec<u8>; N]> for Dict<()> {
+    fn from(keys: [Vec<u8>; N]) -> Self {
+        keys.into_iter().collect()
+    }
+}
+
+fn empty_buckets<V>(num_buckets: usize) -> Vec<Vec<(Vec<u8>, V)>> {
+    (0..num_buckets).map(|_| Vec::new()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn dict_of(range: std::ops::Range<u32>) -> Dict<u32> {
+        range
+            .map(|idx| (idx.to_string().into_bytes(), idx))
+            .collect()
+    }
+
+    // Scans the whole dict, calling between before each step after the first.
+    fn scan_all(dict: &mut Dict<u32>, mut between: impl FnMut(&mut Dict<u32>)) -> Vec<u32> {
+        let mut seen = Vec::new();
+        let mut cursor = dict.scan(0, |_, value| seen.push(*value));
+        while cursor != 0 {
+            between(dict);
+            cursor = dict.scan(cursor, |_, value| seen.push(*value));
+        }
+        seen
+    }
+
+    #[test]
+    fn behaves_like_a_map() {
+        let mut dict = dict_of(0..100);
+        assert_eq!(dict.len(), 100);
+        assert_eq!(dict.get(b"42"), Some(&42));
+        assert_eq!(dict.insert(b"42".to_vec(), 420), Some(42));
+        assert_eq!(dict.remove(b"42"), Some(420));
+        assert_eq!(dict.remove(b"42"), None);
+        for idx in 10..100 {
+            dict.remove(idx.to_string().as_bytes());
+        }
+        assert_eq!(dict.len(), 10);
+        assert_eq!(dict.buckets.len(), 16);
+        assert_eq!(dict, dict_of(0..10));
+        assert_ne!(dict, dict_of(0..11));
+        dict.retain(|_, value| value % 2 == 0);
+        assert_eq!(dict.len(), 5);
+        assert_eq!(dict.get(b"4"), Some(&4));
+        assert_eq!(dict.get(b"5"), None);
+        let mut rng = rand::thread_rng();
+        let key = dict.random_key(&mut rng).unwrap();
+        assert!(dict.contains_key(key));
+        assert_eq!(Dict::<u32>::new().random_key(&mut rng), None);
+    }
+
+    #[test]
+    fn scans_every_entry_once() {
+        let mut dict = dict_of(0..1000);
+        let mut seen = scan_all(&mut dict, |_| ());
+        seen.sort();
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn scans_every_entry_through_resizes() {
+        // Grow the table part way through the scan.
+        let mut dict = dict_of(0..100);
+        let mut next = 100;
+        let seen: HashSet<u32> = scan_all(&mut dict, |dict| {
+            dict.insert(next.to_string().into_bytes(), next);
+            next += 1;
+        })
+        .into_iter()
+        .collect();
+        assert!((0..100).all(|idx| seen.contains(&idx)));
+
+        // Shrink it instead, keeping the first hundred entries.
+        let mut dict = dict_of(0..2000);
+        let mut next = 100;
+        let seen: HashSet<u32> = scan_all(&mut dict, |dict| {
+            for _ in 0..50 {
+                dict.remove(next.to_string().as_bytes());
+                next += 1;
+            }
+        })
+        .into_iter()
+        .collect();
+        assert!((0..100).all(|idx| seen.contains(&idx)));
+    }
+}
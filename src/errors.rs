@@ -1,4 +1,4 @@
-/// Error types for Redis implementation.
+//! Error types for Redis implementation.
 
 /// Errors encountered while handling redis requests.
 #[derive(Debug)]
@@ -9,6 +9,14 @@ pub(crate) enum RedisError {
     UnexpectedNumberOfArgs(String),
     UnexpectedArgumentType(String),
     RdbParserError(RdbFileError),
+    ReplicationError(String),
+    SaveError(String),
+    // A command was used on a key holding a value of the wrong type.
+    WrongType,
+    NoSuchKey,
+    // A stream command named a consumer group that doesn't exist.
+    NoGroup { key: String, group: String },
+    BusyGroup,
 }
 
 /// Errors encountered while parsing RESP values.
@@ -18,6 +26,8 @@ pub(crate) enum RespError {
     UnknownStartingByte(u8),
     BadBulkStringSize(i64),
     BadArraySize(i64),
+    // A line went on for too long without a separator.
+    LineTooLong,
     IOError(std::io::Error),
     IntParseFailure(std::num::ParseIntError),
     StringParseFailure(std::str::Utf8Error),
@@ -31,7 +41,7 @@ pub(crate) enum RdbFileError {
     NotRedisFile,
     InvalidFile(String),
     IOError(std::io::Error),
-    Unimplemented(String),
+    ChecksumMismatch { expected: u64, actual: u64 },
 }
 
 impl std::fmt::Display for RedisError {
@@ -47,6 +57,32 @@ impl std::fmt::Display for RedisError {
                 write!(f, "Unexpected argument type: {}", val)
             }
             RedisError::RdbParserError(inner) => inner.fmt(f),
+            RedisError::ReplicationError(val) => write!(f, "Replication error: {}", val),
+            RedisError::SaveError(val) => write!(f, "Save error: {}", val),
+            RedisError::WrongType => write!(
+                f,
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ),
+            RedisError::NoSuchKey => write!(f, "no such key"),
+            RedisError::NoGroup { key, group } => write!(
+                f,
+                "NOGROUP No such key '{}' or consumer group '{}'",
+                key, group
+            ),
+            RedisError::BusyGroup => write!(f, "BUSYGROUP Consumer Group name already exists"),
+        }
+    }
+}
+
+impl RedisError {
+    /// The message for the error reply sent to clients, which starts with the error code.
+    pub(crate) fn reply_message(&self) -> String {
+        match self {
+            // The message already starts with the code.
+            RedisError::WrongType | RedisError::NoGroup { .. } | RedisError::BusyGroup => {
+                self.to_string()
+            }
+            _ => format!("ERR {}", self),
         }
     }
 }
@@ -81,6 +117,7 @@ impl std::fmt::Display for RespError {
             RespError::StringParseFailure(e) => e.fmt(f),
             RespError::BadBulkStringSize(sz) => write!(f, "Invalid size for BulkString {}", sz),
             RespError::BadArraySize(sz) => write!(f, "Invalid size for Array {}", sz),
+            RespError::LineTooLong => write!(f, "Line too long without a separator"),
         }
     }
 }
@@ -121,7 +158,11 @@ impl std::fmt::Display for RdbFileError {
             }
             RdbFileError::NotRedisFile => write!(f, "Input was not a redis file"),
             RdbFileError::InvalidFile(inner) => write!(f, "Invalid RDB file: {}", inner),
-            RdbFileError::Unimplemented(inner) => write!(f, "Unimplemented: {}", inner),
+            RdbFileError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch; computed {:#018x} but file has {:#018x}",
+                expected, actual
+            ),
         }
     }
 }
@@ -0,0 +1,112 @@
+//! Glob-style pattern matching, as used by KEYS and the MATCH option of the scan commands.
+//!
+//! Patterns support the same syntax as Redis:
+//!   * `*` matches any sequence of bytes, including none.
+//!   * `?` matches any single byte.
+//!   * `[abc]`, `[a-z]` and `[^abc]` match a single byte in, or not in, the class.
+//!   * `\` matches the byte after it literally.
+
+/// Whether the whole of input matches the pattern.
+pub(crate) fn glob_match(pattern: &[u8], input: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // The pattern position after the last `*` seen, and the input position it has
+    // matched up to, for backtracking when the rest of the pattern fails to match.
+    let mut star: Option<(usize, usize)> = None;
+    while i < input.len() {
+        let next = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, i));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => {
+                let (matched, next) = match_class(pattern, p, input[i]);
+                matched.then_some(next)
+            }
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == input[i]).then_some(p + 2),
+            Some(c) => (*c == input[i]).then_some(p + 1),
+            None => None,
+        };
+        match (next, star) {
+            (Some(next), _) => {
+                p = next;
+                i += 1;
+            }
+            // Let the last `*` match one more byte and try again.
+            (None, Some((star_p, star_i))) => {
+                star = Some((star_p, star_i + 1));
+                p = star_p;
+                i = star_i + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+// Matches a byte against the class starting at pattern[start], which is a `[`.  Returns
+// whether it matched and the pattern position after the class.  An unterminated class
+// extends to the end of the pattern.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> (bool, usize) {
+    let mut p = start + 1;
+    let negated = pattern.get(p) == Some(&b'^');
+    if negated {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == c;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+            let (low, high) = (
+                pattern[p].min(pattern[p + 2]),
+                pattern[p].max(pattern[p + 2]),
+            );
+            matched |= (low..=high).contains(&c);
+            p += 3;
+        } else {
+            matched |= pattern[p] == c;
+            p += 1;
+        }
+    }
+    (matched != negated, (p + 1).min(pattern.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"*:name", b"user:1:name"));
+        assert!(!glob_match(b"*:name", b"user:1:names"));
+        assert!(glob_match(b"a*b*c", b"aXbYbZc"));
+        assert!(!glob_match(b"a*b*c", b"aXbYbZ"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"h[b-a]llo", b"hallo"));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo"));
+        assert!(glob_match(b"h[\\]]llo", b"h]llo"));
+    }
+
+    #[test]
+    fn matches_escapes() {
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(glob_match(b"what\\?", b"what?"));
+    }
+}
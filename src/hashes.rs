@@ -0,0 +1,671 @@
+//! Operations on hash values for the hash commands.
+//!
+//! Each field of a hash may have its own expiration, set with HEXPIRE.  Expired fields
+//! are removed when the hash is next used, and as with lists a hash key never holds an
+//! empty hash.
+
+use std::time::SystemTime;
+
+use rand::seq::IteratorRandom;
+
+use crate::dict::{self, Dict};
+use crate::errors::RedisError;
+use crate::glob::glob_match;
+use crate::keys::SCAN_BUCKETS_PER_KEY;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::ExpireCondition;
+
+/// The fields of a hash, kept in a dict so that HSCAN can page through them with a
+/// cursor.  It keeps a bound on when the first of its fields expires, so that only
+/// hashes which may have expired fields are searched for them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Hash {
+    fields: Dict<HashField>,
+    // No field expires before this, and without it no field has an expiration.  It is
+    // only brought up to date when expired fields are removed.
+    next_expiration: Option<SystemTime>,
+}
+
+/// Fields of a hash paired with their values.
+pub(crate) type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HashField {
+    pub(crate) value: Vec<u8>,
+    pub(crate) expiration: Option<SystemTime>,
+}
+
+impl HashField {
+    pub(crate) fn new(value: Vec<u8>) -> Self {
+        HashField {
+            value,
+            expiration: None,
+        }
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expiration.is_some_and(|expiration| expiration <= now)
+    }
+}
+
+impl Hash {
+    pub(crate) fn new() -> Self {
+        Hash::default()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> dict::Iter<'_, HashField> {
+        self.fields.iter()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &HashField> {
+        self.fields.values()
+    }
+
+    // Removes the expired fields, if the next expiration says there may be any.
+    fn remove_expired(&mut self, now: SystemTime) {
+        if self.next_expiration.is_none_or(|next| next > now) {
+            return;
+        }
+        self.fields.retain(|_, entry| !entry.is_expired(now));
+        self.next_expiration = self
+            .fields
+            .values()
+            .filter_map(|entry| entry.expiration)
+            .min();
+    }
+}
+
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.fields == other.fields
+    }
+}
+
+impl<'a> IntoIterator for &'a Hash {
+    type Item = (&'a Vec<u8>, &'a HashField);
+    type IntoIter = dict::Iter<'a, HashField>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<(Vec<u8>, HashField)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, HashField)>>(fields: I) -> Self {
+        let fields: Dict<_> = fields.into_iter().collect();
+        let next_expiration = fields.values().filter_map(|entry| entry.expiration).min();
+        Hash {
+            fields,
+            next_expiration,
+        }
+    }
+}
+
+impl<const N: usize> From<[(Vec<u8>, HashField); N]> for Hash {
+    fn from(fields: [(Vec<u8>, HashField); N]) -> Self {
+        fields.into_iter().collect()
+    }
+}
+
+// The replies to HEXPIRE, HTTL and HPERSIST for fields they don't change.
+const NO_SUCH_FIELD: i64 = -2;
+const NO_EXPIRATION: i64 = -1;
+const CONDITION_NOT_MET: i64 = 0;
+
+/// Sets the fields of the hash at key, creating it if needed, and returns the number of
+/// fields that are new.  Setting a field clears its expiration.
+pub(crate) fn set(
+    db: &mut Database,
+    key: &[u8],
+    pairs: &[(&[u8], &[u8])],
+) -> Result<usize, RedisError> {
+    let hash = hash_or_insert(db, key)?;
+    Ok(pairs
+        .iter()
+        .filter(|(field, value)| {
+            hash.fields
+                .insert(field.to_vec(), HashField::new(value.to_vec()))
+                .is_none()
+        })
+        .count())
+}
+
+/// Sets the field only if it doesn't already exist, returning whether it was set.
+pub(crate) fn set_if_absent(
+    db: &mut Database,
+    key: &[u8],
+    field: &[u8],
+    value: &[u8],
+) -> Result<bool, RedisError> {
+    if hash_mut(db, key)?.is_some_and(|hash| hash.fields.contains_key(field)) {
+        return Ok(false);
+    }
+    set(db, key, &[(field, value)])?;
+    Ok(true)
+}
+
+pub(crate) fn get(
+    db: &mut Database,
+    key: &[u8],
+    field: &[u8],
+) -> Result<Option<Vec<u8>>, RedisError> {
+    Ok(hash_mut(db, key)?.and_then(|hash| Some(hash.fields.get(field)?.value.clone())))
+}
+
+/// Removes the fields, returning how many existed.
+pub(crate) fn delete(db: &mut Database, key: &[u8], fields: &[&[u8]]) -> Result<usize, RedisError> {
+    let Some(hash) = hash_mut(db, key)? else {
+        return Ok(0);
+    };
+    let deleted = fields
+        .iter()
+        .filter(|field| hash.fields.remove(field).is_some())
+        .count();
+    remove_if_empty(db, key);
+    Ok(deleted)
+}
+
+pub(crate) fn len(db: &mut Database, key: &[u8]) -> Result<usize, RedisError> {
+    Ok(hash_mut(db, key)?.map_or(0, |hash| hash.len()))
+}
+
+/// Returns every field and its value.
+pub(crate) fn entries(db: &mut Database, key: &[u8]) -> Result<Entries, RedisError> {
+    Ok(hash_mut(db, key)?.map_or_else(Vec::new, |hash| {
+        hash.fields
+            .iter()
+            .map(|(field, entry)| (field.clone(), entry.value.clone()))
+            .collect()
+    }))
+}
+
+/// Adds the increment to the integer value of the field, which starts at zero if it
+/// doesn't exist, and returns the result.
+pub(crate) fn increment_by(
+    db: &mut Database,
+    key: &[u8],
+    field: &[u8],
+    increment: i64,
+) -> Result<i64, RedisError> {
+    let current = match get(db, key, field)? {
+        Some(value) => std::str::from_utf8(&value)
+            .ok()
+            .and_then(|value| value.parse::<i64>().ok())
+            .ok_or_else(|| {
+                RedisError::UnexpectedArgumentType("hash value is not an integer".to_string())
+            })?,
+        None => 0,
+    };
+    let result = current.checked_add(increment).ok_or_else(|| {
+        RedisError::UnexpectedArgumentType("increment or decrement would overflow".to_string())
+    })?;
+    set_keeping_expiration(db, key, field, result.to_string().into_bytes())?;
+    Ok(result)
+}
+
+/// Adds the increment to the floating point value of the field, which starts at zero if
+/// it doesn't exist, and returns the result as it is stored.
+pub(crate) fn increment_by_float(
+    db: &mut Database,
+    key: &[u8],
+    field: &[u8],
+    increment: f64,
+) -> Result<Vec<u8>, RedisError> {
+    let current = match get(db, key, field)? {
+        Some(value) => std::str::from_utf8(&value)
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| {
+                RedisError::UnexpectedArgumentType("hash value is not a float".to_string())
+            })?,
+        None => 0.0,
+    };
+    let result = current + increment;
+    if !result.is_finite() {
+        return Err(RedisError::UnexpectedArgumentType(
+            "increment would produce NaN or Infinity".to_string(),
+        ));
+    }
+    let result = result.to_string().into_bytes();
+    set_keeping_expiration(db, key, field, result.clone())?;
+    Ok(result)
+}
+
+/// Continues an HSCAN from the cursor, as SCAN does through the keyspace, returning the
+/// cursor to continue from, which is zero once the scan is complete, and the fields found
+/// which match the pattern, if given, with their values.
+pub(crate) fn scan(
+    db: &mut Database,
+    key: &[u8],
+    mut cursor: u64,
+    pattern: Option<&[u8]>,
+    count: usize,
+) -> Result<(u64, Entries), RedisError> {
+    let Some(hash) = hash_mut(db, key)? else {
+        return Ok((0, Vec::new()));
+    };
+    let mut entries = Vec::new();
+    let mut found = 0;
+    let mut buckets = count.saturating_mul(SCAN_BUCKETS_PER_KEY);
+    loop {
+        cursor = hash.fields.scan(cursor, |field, entry| {
+            found += 1;
+            if pattern.is_none_or(|pattern| glob_match(pattern, field)) {
+                entries.push((field.clone(), entry.value.clone()));
+            }
+        });
+        buckets -= 1;
+        if cursor == 0 || buckets == 0 || found >= count {
+            return Ok((cursor, entries));
+        }
+    }
+}
+
+/// Returns random fields with their values.  A positive count returns that many
+/// distinct fields, or the whole hash if it is smaller, and a negative count returns
+/// that many fields which may repeat.
+pub(crate) fn random_entries(
+    db: &mut Database,
+    key: &[u8],
+    count: i64,
+) -> Result<Entries, RedisError> {
+    let Some(hash) = hash_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    let mut rng = rand::thread_rng();
+    let chosen: Vec<_> = if count >= 0 {
+        hash.fields
+            .keys()
+            .choose_multiple(&mut rng, (count as usize).min(hash.len()))
+    } else {
+        (0..count.unsigned_abs())
+            .map(|_| hash.fields.random_key(&mut rng).expect("hash is not empty"))
+            .collect()
+    };
+    Ok(chosen
+        .into_iter()
+        .map(|field| {
+            (
+                field.clone(),
+                hash.fields.get(field).expect("field exists").value.clone(),
+            )
+        })
+        .collect())
+}
+
+/// Sets the expiration of each of the fields if the condition allows, and returns the
+/// HEXPIRE reply for each: -2 if there is no such field, 0 if the condition wasn't met,
+/// 1 if the expiration was set, or 2 if the field was deleted because the expiration
+/// has already passed.
+pub(crate) fn expire(
+    db: &mut Database,
+    key: &[u8],
+    expiration: SystemTime,
+    condition: Option<ExpireCondition>,
+    fields: &[&[u8]],
+) -> Result<Vec<i64>, RedisError> {
+    let Some(hash) = hash_mut(db, key)? else {
+        return Ok(vec![NO_SUCH_FIELD; fields.len()]);
+    };
+    let now = SystemTime::now();
+    let replies = fields
+        .iter()
+        .map(|field| {
+            let Some(entry) = hash.fields.get_mut(field) else {
+                return NO_SUCH_FIELD;
+            };
+            if !condition.is_none_or(|condition| condition.allows(entry.expiration, expiration)) {
+                return CONDITION_NOT_MET;
+            }
+            if expiration <= now {
+                hash.fields.remove(field);
+                2
+            } else {
+                entry.expiration = Some(expiration);
+                hash.next_expiration = Some(
+                    hash.next_expiration
+                        .map_or(expiration, |next| next.min(expiration)),
+                );
+                1
+            }
+        })
+        .collect();
+    remove_if_empty(db, key);
+    Ok(replies)
+}
+
+/// Returns the HTTL reply for each of the fields: -2 if there is no such field, -1 if
+/// it has no expiration, or else the seconds it has left to live.
+pub(crate) fn ttl(db: &mut Database, key: &[u8], fields: &[&[u8]]) -> Result<Vec<i64>, RedisError> {
+    let Some(hash) = hash_mut(db, key)? else {
+        return Ok(vec![NO_SUCH_FIELD; fields.len()]);
+    };
+    let now = SystemTime::now();
+    Ok(fields
+        .iter()
+        .map(|field| match hash.fields.get(field) {
+            None => NO_SUCH_FIELD,
+            Some(HashField {
+                expiration: None, ..
+            }) => NO_EXPIRATION,
+            Some(HashField {
+                expiration: Some(expiration),
+                ..
+            }) => {
+                let millis = expiration.duration_since(now).map_or(0, |d| d.as_millis());
+                millis.div_ceil(1000) as i64
+            }
+        })
+        .collect())
+}
+
+/// Removes the expiration of each of the fields, and returns the HPERSIST reply for each:
+/// -2 if there is no such field, -1 if it has no expiration, or 1 if it was removed.
+pub(crate) fn persist(
+    db: &mut Database,
+    key: &[u8],
+    fields: &[&[u8]],
+) -> Result<Vec<i64>, RedisError> {
+    let Some(hash) = hash_mut(db, key)? else {
+        return Ok(vec![NO_SUCH_FIELD; fields.len()]);
+    };
+    Ok(fields
+        .iter()
+        .map(|field| match hash.fields.get_mut(field) {
+            None => NO_SUCH_FIELD,
+            Some(entry) => match entry.expiration.take() {
+                Some(_) => 1,
+                None => NO_EXPIRATION,
+            },
+        })
+        .collect())
+}
+
+// Sets the value of the field without changing its expiration, as the increment
+// commands do.
+fn set_keeping_expiration(
+    db: &mut Database,
+    key: &[u8],
+    field: &[u8],
+    value: Vec<u8>,
+) -> Result<(), RedisError> {
+    let hash = hash_or_insert(db, key)?;
+    match hash.fields.get_mut(field) {
+        Some(entry) => entry.value = value,
+        None => {
+            hash.fields.insert(field.to_vec(), HashField::new(value));
+        }
+    }
+    Ok(())
+}
+
+// Returns the hash at key, or None if the key doesn't exist.  Any expired fields are
+// removed first, along with the key if none are left.
+fn hash_mut<'d>(db: &'d mut Database, key: &[u8]) -> Result<Option<&'d mut Hash>, RedisError> {
+    match live_value_mut(db, key).map(ValueType::value_mut) {
+        Some(Value::Hash(hash)) => hash.remove_expired(SystemTime::now()),
+        Some(_) => return Err(RedisError::WrongType),
+        None => return Ok(None),
+    }
+    remove_if_empty(db, key);
+    match db.get_mut(key).map(ValueType::value_mut) {
+        Some(Value::Hash(hash)) => Ok(Some(hash)),
+        _ => Ok(None),
+    }
+}
+
+fn hash_or_insert<'d>(db: &'d mut Database, key: &[u8]) -> Result<&'d mut Hash, RedisError> {
+    if hash_mut(db, key)?.is_none() {
+        db.insert(key.to_vec(), ValueType::from(Value::Hash(Hash::new())));
+    }
+    // Fetched directly, since hash_mut would remove the empty hash again.
+    match db.get_mut(key).map(ValueType::value_mut) {
+        Some(Value::Hash(hash)) => Ok(hash),
+        _ => unreachable!("hash was just inserted"),
+    }
+}
+
+fn remove_if_empty(db: &mut Database, key: &[u8]) {
+    if let Some(Value::Hash(hash)) = db.get(key).map(ValueType::value) {
+        if hash.is_empty() {
+            db.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn hash_db(pairs: &[(&[u8], &[u8])]) -> Database {
+        let mut db = Database::new();
+        set(&mut db, b"hash", pairs).unwrap();
+        db
+    }
+
+    fn sorted(mut entries: Entries) -> Entries {
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn set_counts_new_fields() {
+        let mut db = hash_db(&[(b"a", b"1")]);
+        assert_eq!(
+            set(&mut db, b"hash", &[(b"a", b"2"), (b"b", b"3")]).unwrap(),
+            1
+        );
+        assert_eq!(get(&mut db, b"hash", b"a").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(
+            sorted(entries(&mut db, b"hash").unwrap()),
+            vec![
+                (b"a".to_vec(), b"2".to_vec()),
+                (b"b".to_vec(), b"3".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn set_on_string_is_wrong_type() {
+        let mut db = Database::from([(b"key".to_vec(), ValueType::new(b"value".to_vec()))]);
+        assert!(matches!(
+            set(&mut db, b"key", &[(b"a", b"1")]),
+            Err(RedisError::WrongType)
+        ));
+    }
+
+    #[test]
+    fn set_if_absent_keeps_existing() {
+        let mut db = hash_db(&[(b"a", b"1")]);
+        assert!(!set_if_absent(&mut db, b"hash", b"a", b"2").unwrap());
+        assert!(set_if_absent(&mut db, b"hash", b"b", b"2").unwrap());
+        assert_eq!(get(&mut db, b"hash", b"a").unwrap(), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn delete_last_field_removes_key() {
+        let mut db = hash_db(&[(b"a", b"1"), (b"b", b"2")]);
+        assert_eq!(delete(&mut db, b"hash", &[b"a", b"c"]).unwrap(), 1);
+        assert_eq!(delete(&mut db, b"hash", &[b"b"]).unwrap(), 1);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn increments() {
+        let mut db = hash_db(&[(b"count", b"10"), (b"name", b"x")]);
+        assert_eq!(increment_by(&mut db, b"hash", b"count", -3).unwrap(), 7);
+        assert_eq!(increment_by(&mut db, b"hash", b"new", 5).unwrap(), 5);
+        assert!(increment_by(&mut db, b"hash", b"name", 1).is_err());
+        assert!(increment_by(&mut db, b"hash", b"count", i64::MAX).is_err());
+
+        assert_eq!(
+            increment_by_float(&mut db, b"hash", b"count", 0.5).unwrap(),
+            b"7.5".to_vec()
+        );
+        assert_eq!(
+            increment_by_float(&mut db, b"hash", b"count", 2.5).unwrap(),
+            b"10".to_vec()
+        );
+        assert!(increment_by_float(&mut db, b"hash", b"name", 1.0).is_err());
+    }
+
+    #[test]
+    fn scan_matches_pattern() {
+        let mut db = hash_db(&[(b"name", b"x"), (b"nickname", b"y"), (b"age", b"3")]);
+        assert_eq!(
+            scan(&mut db, b"hash", 0, Some(b"*name"), 10)
+                .map(|(cursor, entries)| (cursor, sorted(entries)))
+                .unwrap(),
+            (
+                0,
+                vec![
+                    (b"name".to_vec(), b"x".to_vec()),
+                    (b"nickname".to_vec(), b"y".to_vec())
+                ]
+            )
+        );
+        assert_eq!(scan(&mut db, b"hash", 0, None, 10).unwrap().1.len(), 3);
+        assert_eq!(scan(&mut db, b"missing", 0, None, 10).unwrap(), (0, vec![]));
+    }
+
+    #[test]
+    fn scan_pages_with_cursor() {
+        let fields: Vec<Vec<u8>> = (0..1000).map(|idx| idx.to_string().into_bytes()).collect();
+        let pairs: Vec<(&[u8], &[u8])> = fields.iter().map(|f| (&f[..], &b"v"[..])).collect();
+        let mut db = Database::new();
+        set(&mut db, b"hash", &pairs).unwrap();
+
+        let mut seen = Vec::new();
+        let mut pages = 0;
+        let mut cursor = 0;
+        loop {
+            let (next, entries) = scan(&mut db, b"hash", cursor, None, 10).unwrap();
+            // Whole buckets are returned, so a page may hold a few more than asked for.
+            assert!(entries.len() < 20, "page of {}", entries.len());
+            seen.extend(entries.into_iter().map(|(field, _)| field));
+            pages += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!(pages > 50, "{} pages", pages);
+        seen.sort();
+        let mut expected = fields.clone();
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn random_entries_counts() {
+        let mut db = hash_db(&[(b"a", b"1"), (b"b", b"2")]);
+        let distinct = random_entries(&mut db, b"hash", 5).unwrap();
+        assert_eq!(
+            sorted(distinct.clone()),
+            sorted(entries(&mut db, b"hash").unwrap())
+        );
+        assert_eq!(random_entries(&mut db, b"hash", -5).unwrap().len(), 5);
+        assert!(random_entries(&mut db, b"hash", 0).unwrap().is_empty());
+        assert!(random_entries(&mut db, b"missing", 3).unwrap().is_empty());
+        assert_eq!(
+            random_entries(&mut db, b"hash", 1_000_000_000_000)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn expired_fields_are_removed() {
+        let mut db = hash_db(&[(b"a", b"1"), (b"b", b"2")]);
+        let past = SystemTime::now() - Duration::from_secs(1);
+        if let Some(Value::Hash(hash)) = db.get_mut(&b"hash"[..]).map(ValueType::value_mut) {
+            hash.fields.get_mut(&b"a"[..]).unwrap().expiration = Some(past);
+            hash.next_expiration = Some(past);
+        }
+        assert_eq!(get(&mut db, b"hash", b"a").unwrap(), None);
+        assert_eq!(len(&mut db, b"hash").unwrap(), 1);
+
+        assert_eq!(
+            expire(&mut db, b"hash", past, None, &[b"b"]).unwrap(),
+            vec![2]
+        );
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn removes_expired_fields_once_due() {
+        let at = |secs| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        let field = |expiration| HashField {
+            value: b"v".to_vec(),
+            expiration,
+        };
+        let mut hash = Hash::from([
+            (b"a".to_vec(), field(at(10))),
+            (b"b".to_vec(), field(at(20))),
+            (b"c".to_vec(), field(None)),
+        ]);
+        assert_eq!(hash.next_expiration, at(10));
+        hash.remove_expired(at(5).unwrap());
+        assert_eq!(hash.len(), 3);
+        hash.remove_expired(at(10).unwrap());
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.next_expiration, at(20));
+        hash.remove_expired(at(30).unwrap());
+        assert_eq!(hash.len(), 1);
+        assert_eq!(hash.next_expiration, None);
+    }
+
+    #[test]
+    fn expire_ttl_and_persist() {
+        let mut db = hash_db(&[(b"a", b"1"), (b"b", b"2")]);
+        let later = SystemTime::now() + Duration::from_secs(100);
+        assert_eq!(
+            expire(&mut db, b"hash", later, None, &[b"a", b"missing"]).unwrap(),
+            vec![1, -2]
+        );
+        assert_eq!(
+            ttl(&mut db, b"hash", &[b"a", b"b", b"missing"]).unwrap(),
+            vec![100, -1, -2]
+        );
+        assert_eq!(
+            expire(
+                &mut db,
+                b"hash",
+                later + Duration::from_secs(1),
+                Some(ExpireCondition::Lt),
+                &[b"a", b"b"]
+            )
+            .unwrap(),
+            vec![0, 1]
+        );
+        assert_eq!(
+            persist(&mut db, b"hash", &[b"a", b"b", b"missing"]).unwrap(),
+            vec![1, 1, -2]
+        );
+        assert_eq!(persist(&mut db, b"hash", &[b"a"]).unwrap(), vec![-1]);
+        assert_eq!(ttl(&mut db, b"missing", &[b"a"]).unwrap(), vec![-2]);
+    }
+
+    #[test]
+    fn set_clears_field_expiration() {
+        let mut db = hash_db(&[(b"a", b"1")]);
+        let later = SystemTime::now() + Duration::from_secs(100);
+        expire(&mut db, b"hash", later, None, &[b"a"]).unwrap();
+        increment_by(&mut db, b"hash", b"a", 1).unwrap();
+        assert_eq!(ttl(&mut db, b"hash", &[b"a"]).unwrap(), vec![100]);
+        set(&mut db, b"hash", &[(b"a", b"1")]).unwrap();
+        assert_eq!(ttl(&mut db, b"hash", &[b"a"]).unwrap(), vec![-1]);
+    }
+}
@@ -0,0 +1,498 @@
+//! Operations on keys whatever the type of their values, such as the expiration commands.
+//!
+//! Expired keys are removed when they are next looked up, or sooner by the active
+//! expiration cycle, so until then an expired key must be treated as though it doesn't
+//! exist.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::errors::RedisError;
+use crate::glob::glob_match;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::ExpireCondition;
+
+const NO_SUCH_KEY: i64 = -2;
+const NO_EXPIRATION: i64 = -1;
+
+// How many keys the active expiration cycle samples at a time.
+const ACTIVE_EXPIRE_SAMPLE_SIZE: usize = 20;
+
+// The percentage of a sample which may have expired before the cycle stops sampling.
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 25;
+
+// How many buckets SCAN and HSCAN may visit for each entry they were asked for, so that
+// a sparse table doesn't make them visit every bucket.
+pub(crate) const SCAN_BUCKETS_PER_KEY: usize = 10;
+
+/// The keys which haven't expired and match the glob-style pattern, for KEYS.
+pub(crate) fn matching(db: &Database, pattern: &[u8]) -> Vec<Vec<u8>> {
+    db.iter()
+        .filter(|(key, value)| !value.is_expired() && glob_match(pattern, key))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// Continues a SCAN from the cursor, returning the cursor to continue from, which is zero
+/// once the scan is complete, and the keys found which haven't expired and match the
+/// pattern and type, if given.  Buckets are visited until count keys have been found,
+/// before filtering, or ten buckets for each of them have been visited.
+pub(crate) fn scan(
+    db: &Database,
+    mut cursor: u64,
+    pattern: Option<&[u8]>,
+    value_type: Option<&[u8]>,
+    count: usize,
+) -> (u64, Vec<Vec<u8>>) {
+    let mut keys = Vec::new();
+    let mut found = 0;
+    let mut buckets = count.saturating_mul(SCAN_BUCKETS_PER_KEY);
+    loop {
+        cursor = db.scan(cursor, |key, value| {
+            found += 1;
+            if !value.is_expired()
+                && pattern.is_none_or(|pattern| glob_match(pattern, key))
+                && value_type.is_none_or(|value_type| {
+                    value_type.eq_ignore_ascii_case(value.value().type_name().as_bytes())
+                })
+            {
+                keys.push(key.clone());
+            }
+        });
+        buckets -= 1;
+        if cursor == 0 || buckets == 0 || found >= count {
+            return (cursor, keys);
+        }
+    }
+}
+
+/// Removes the keys, for DEL and UNLINK, returning the values of those which existed.
+pub(crate) fn remove(db: &mut Database, keys: &[&[u8]]) -> Vec<ValueType> {
+    let mut removed = Vec::new();
+    for key in keys {
+        if live_value_mut(db, key).is_some() {
+            removed.extend(db.remove(key));
+        }
+    }
+    removed
+}
+
+/// Roughly how many allocations freeing the value involves, which UNLINK uses to decide
+/// whether to free it on another thread.
+pub(crate) fn free_effort(value: &Value) -> usize {
+    match value {
+        Value::String(_) => 1,
+        Value::List(list) => list.len(),
+        Value::Hash(hash) => hash.len(),
+        Value::Set(set) => set.len(),
+        Value::SortedSet(sorted_set) => sorted_set.len(),
+        Value::Stream(stream) => stream.entries.len() + stream.groups.len(),
+    }
+}
+
+/// Counts how many of the keys exist, for EXISTS and TOUCH, which count a key each time
+/// it is repeated.
+pub(crate) fn count_existing(db: &mut Database, keys: &[&[u8]]) -> usize {
+    keys.iter()
+        .filter(|key| live_value_mut(db, key).is_some())
+        .count()
+}
+
+/// The name of the type of the key's value, or "none" if there is no such key.
+pub(crate) fn type_name(db: &mut Database, key: &[u8]) -> &'static str {
+    live_value_mut(db, key).map_or("none", |value| value.value().type_name())
+}
+
+/// Renames the key, keeping its expiration, and returns whether it was renamed.  Any value
+/// at the new key is replaced, unless nx is set, when the key is left alone instead.
+pub(crate) fn rename(
+    db: &mut Database,
+    key: &[u8],
+    new_key: &[u8],
+    nx: bool,
+) -> Result<bool, RedisError> {
+    if live_value_mut(db, key).is_none() {
+        return Err(RedisError::NoSuchKey);
+    }
+    if nx && live_value_mut(db, new_key).is_some() {
+        return Ok(false);
+    }
+    let value = db.remove(key).expect("key was just found");
+    db.insert(new_key.to_vec(), value);
+    Ok(true)
+}
+
+/// Copies the value at source to destination, keeping its expiration, and returns
+/// whether it was copied.  An existing value at destination is only replaced if replace
+/// is set.
+pub(crate) fn copy(
+    source_db: &mut Database,
+    source: &[u8],
+    destination_db: Option<&mut Database>,
+    destination: &[u8],
+    replace: bool,
+) -> bool {
+    let Some(value) = live_value_mut(source_db, source).cloned() else {
+        return false;
+    };
+    // Without another database, the copy is within the source's.
+    let destination_db = destination_db.unwrap_or(source_db);
+    if !replace && live_value_mut(destination_db, destination).is_some() {
+        return false;
+    }
+    destination_db.insert(destination.to_vec(), value);
+    true
+}
+
+/// A random key which hasn't expired, or None if there are none.
+pub(crate) fn random_key(db: &mut Database) -> Option<Vec<u8>> {
+    let mut rng = rand::thread_rng();
+    // Each expired key chosen is removed, so this ends.
+    loop {
+        let key = db.random_key(&mut rng)?.clone();
+        if live_value_mut(db, &key).is_some() {
+            return Some(key);
+        }
+    }
+}
+
+/// Sets the expiration of the key if all the conditions allow, deleting the key if the
+/// expiration has already passed.  Returns whether the key was changed.
+pub(crate) fn expire(
+    db: &mut Database,
+    key: &[u8],
+    expiration: SystemTime,
+    conditions: &[ExpireCondition],
+) -> bool {
+    let Some(value) = live_value_mut(db, key) else {
+        return false;
+    };
+    if !conditions
+        .iter()
+        .all(|condition| condition.allows(value.expiration(), expiration))
+    {
+        return false;
+    }
+    if expiration <= SystemTime::now() {
+        db.remove(key);
+    } else {
+        db.set_expiration(key, Some(expiration));
+    }
+    true
+}
+
+/// Removes the expiration of the key, returning whether it had one.
+pub(crate) fn persist(db: &mut Database, key: &[u8]) -> bool {
+    match live_value_mut(db, key) {
+        Some(value) if value.expiration().is_some() => {
+            db.set_expiration(key, None);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns the TTL or PTTL reply: -2 if there is no such key, -1 if it has no
+/// expiration, or else the time it has left to live in seconds, rounded, or milliseconds.
+pub(crate) fn ttl(db: &mut Database, key: &[u8], millis: bool) -> i64 {
+    match live_value_mut(db, key).map(|value| value.expiration()) {
+        None => NO_SUCH_KEY,
+        Some(None) => NO_EXPIRATION,
+        Some(Some(expiration)) => {
+            let left = expiration
+                .duration_since(SystemTime::now())
+                .map_or(0, |d| d.as_millis() as i64);
+            if millis {
+                left
+            } else {
+                (left + 500) / 1000
+            }
+        }
+    }
+}
+
+/// Returns the EXPIRETIME or PEXPIRETIME reply: -2 if there is no such key, -1 if it has
+/// no expiration, or else the unix time at which it expires in seconds or milliseconds.
+pub(crate) fn expire_time(db: &mut Database, key: &[u8], millis: bool) -> i64 {
+    match live_value_mut(db, key).map(|value| value.expiration()) {
+        None => NO_SUCH_KEY,
+        Some(None) => NO_EXPIRATION,
+        Some(Some(expiration)) => {
+            let since_epoch = expiration.duration_since(UNIX_EPOCH).unwrap_or_default();
+            if millis {
+                since_epoch.as_millis() as i64
+            } else {
+                since_epoch.as_secs() as i64
+            }
+        }
+    }
+}
+
+/// Removes expired keys the way Redis's active expiration cycle does, so that keys which
+/// are never looked up again don't live forever.  Keys with expirations are sampled at
+/// random and the expired ones removed, again and again while more than a quarter of
+/// the sampled keys had expired, or until the deadline.  Returns how many keys were
+/// removed.
+pub(crate) fn remove_expired(db: &mut Database, deadline: Instant) -> usize {
+    let mut rng = rand::thread_rng();
+    let mut removed = 0;
+    loop {
+        let now = SystemTime::now();
+        let mut sampled = 0;
+        let mut expired = 0;
+        for _ in 0..ACTIVE_EXPIRE_SAMPLE_SIZE {
+            let Some(key) = db.random_expiring_key(&mut rng) else {
+                break;
+            };
+            let Some(expiration) = db.get(key).and_then(ValueType::expiration) else {
+                continue;
+            };
+            sampled += 1;
+            if expiration < now {
+                let key = key.clone();
+                db.remove(&key);
+                expired += 1;
+            }
+        }
+        removed += expired;
+        if expired * 100 <= sampled * ACTIVE_EXPIRE_ACCEPTABLE_STALE || Instant::now() >= deadline {
+            return removed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lists::List;
+    use crate::redis_handler::{Value, ValueType};
+    use std::time::Duration;
+
+    fn db_with(key: &[u8]) -> Database {
+        let mut db = Database::new();
+        db.insert(key.to_vec(), ValueType::new(b"value".to_vec()));
+        db
+    }
+
+    #[test]
+    fn removes_and_counts_keys() {
+        let mut db = Database::new();
+        for key in [&b"a"[..], b"b", b"c"] {
+            db.insert(key.to_vec(), ValueType::new(key.to_vec()));
+        }
+        assert_eq!(count_existing(&mut db, &[b"a", b"a", b"missing"]), 2);
+        assert_eq!(remove(&mut db, &[b"a", b"a", b"missing", b"b"]).len(), 2);
+        assert_eq!(count_existing(&mut db, &[b"a", b"b", b"c"]), 1);
+        assert_eq!(type_name(&mut db, b"c"), "string");
+        assert_eq!(type_name(&mut db, b"a"), "none");
+        assert_eq!(random_key(&mut db), Some(b"c".to_vec()));
+        expire(&mut db, b"c", UNIX_EPOCH, &[]);
+        assert_eq!(random_key(&mut db), None);
+    }
+
+    #[test]
+    fn renames_keys() {
+        let mut db = Database::new();
+        db.insert(b"a".to_vec(), ValueType::new(b"1".to_vec()));
+        db.insert(b"b".to_vec(), ValueType::new(b"2".to_vec()));
+        let later = SystemTime::now() + Duration::from_secs(100);
+        expire(&mut db, b"a", later, &[]);
+        assert!(matches!(
+            rename(&mut db, b"missing", b"c", false),
+            Err(RedisError::NoSuchKey)
+        ));
+        assert!(!rename(&mut db, b"a", b"b", true).unwrap());
+        assert!(rename(&mut db, b"a", b"b", false).unwrap());
+        assert_eq!(count_existing(&mut db, &[b"a"]), 0);
+        assert_eq!(db.get(b"b").unwrap().value(), &Value::String(b"1".to_vec()));
+        assert_eq!(ttl(&mut db, b"b", false), 100);
+        assert!(rename(&mut db, b"b", b"b", false).unwrap());
+        assert_eq!(ttl(&mut db, b"b", false), 100);
+    }
+
+    #[test]
+    fn copies_keys() {
+        let mut db = Database::new();
+        let mut other = Database::new();
+        db.insert(b"a".to_vec(), ValueType::new(b"1".to_vec()));
+        db.insert(b"b".to_vec(), ValueType::new(b"2".to_vec()));
+        let later = SystemTime::now() + Duration::from_secs(100);
+        expire(&mut db, b"a", later, &[]);
+        assert!(!copy(&mut db, b"missing", None, b"c", false));
+        assert!(!copy(&mut db, b"a", None, b"b", false));
+        assert!(copy(&mut db, b"a", None, b"b", true));
+        assert_eq!(ttl(&mut db, b"b", false), 100);
+        assert!(copy(&mut db, b"a", Some(&mut other), b"a", false));
+        assert_eq!(other.get(b"a"), db.get(b"a"));
+    }
+
+    #[test]
+    fn matches_live_keys() {
+        let mut db = Database::new();
+        for key in [&b"session:1"[..], b"session:2", b"user:1", b"session:old"] {
+            db.insert(key.to_vec(), ValueType::new(b"value".to_vec()));
+        }
+        expire(
+            &mut db,
+            b"session:old",
+            SystemTime::now() + Duration::from_millis(1),
+            &[],
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        let mut keys = matching(&db, b"session:*");
+        keys.sort();
+        assert_eq!(keys, vec![b"session:1".to_vec(), b"session:2".to_vec()]);
+        assert_eq!(matching(&db, b"user:[0-9]"), vec![b"user:1".to_vec()]);
+        assert_eq!(matching(&db, b"*").len(), 3);
+    }
+
+    #[test]
+    fn scans_matching_keys() {
+        let mut db = Database::new();
+        for idx in 0..100 {
+            db.insert(
+                format!("key{}", idx).into_bytes(),
+                ValueType::new(b"value".to_vec()),
+            );
+        }
+        db.insert(
+            b"key-list".to_vec(),
+            ValueType::from(Value::List(List::from([b"a".to_vec()]))),
+        );
+        let scan_all = |pattern, value_type| {
+            let mut cursor = 0;
+            let mut keys = Vec::new();
+            loop {
+                let (next, found) = scan(&db, cursor, pattern, value_type, 10);
+                keys.extend(found);
+                if next == 0 {
+                    keys.sort();
+                    return keys;
+                }
+                cursor = next;
+            }
+        };
+        assert_eq!(scan_all(None, None).len(), 101);
+        assert_eq!(
+            scan_all(Some(b"key1?"), None),
+            (10..20)
+                .map(|idx| format!("key{}", idx).into_bytes())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(scan_all(None, Some(b"LIST")), vec![b"key-list".to_vec()]);
+        // The count bounds how many keys are returned at a time, give or take a bucket.
+        let (_, keys) = scan(&db, 0, None, None, 1);
+        assert!(keys.len() < 10);
+    }
+
+    #[test]
+    fn expires_keys() {
+        let mut db = db_with(b"key");
+        assert_eq!(ttl(&mut db, b"key", false), NO_EXPIRATION);
+        assert_eq!(ttl(&mut db, b"missing", false), NO_SUCH_KEY);
+        let later = SystemTime::now() + Duration::from_secs(100);
+        assert!(expire(&mut db, b"key", later, &[]));
+        assert!(!expire(&mut db, b"missing", later, &[]));
+        assert_eq!(ttl(&mut db, b"key", false), 100);
+        assert!((99_000..=100_000).contains(&ttl(&mut db, b"key", true)));
+        assert_eq!(
+            expire_time(&mut db, b"key", true),
+            later.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+        );
+        assert!(persist(&mut db, b"key"));
+        assert!(!persist(&mut db, b"key"));
+        assert_eq!(expire_time(&mut db, b"key", false), NO_EXPIRATION);
+    }
+
+    #[test]
+    fn past_expirations_delete_the_key() {
+        let mut db = db_with(b"key");
+        assert!(expire(&mut db, b"key", UNIX_EPOCH, &[]));
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn removes_expired_keys_actively() {
+        let mut db = Database::new();
+        let past = UNIX_EPOCH + Duration::from_secs(1);
+        let later = SystemTime::now() + Duration::from_secs(100);
+        for idx in 0..1000 {
+            let mut value = ValueType::new(b"value".to_vec());
+            value.set_expiration(Some(if idx < 900 { past } else { later }));
+            db.insert(format!("key{}", idx).into_bytes(), value);
+        }
+        db.insert(b"persistent".to_vec(), ValueType::new(b"value".to_vec()));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        // Sampling carries on until a sample is mostly live, which it only is once most
+        // of the expired keys have gone.
+        let removed = remove_expired(&mut db, deadline);
+        assert!(removed > 600, "removed {}", removed);
+        assert_eq!(db.len(), 1001 - removed);
+        assert!(db.contains_key(&b"persistent"[..]));
+        assert!(db.contains_key(&b"key950"[..]));
+
+        // Once the deadline has passed, a single sample is taken.
+        let mut db = Database::new();
+        for idx in 0..1000 {
+            let mut value = ValueType::new(b"value".to_vec());
+            value.set_expiration(Some(past));
+            db.insert(format!("key{}", idx).into_bytes(), value);
+        }
+        assert!(remove_expired(&mut db, Instant::now()) <= ACTIVE_EXPIRE_SAMPLE_SIZE);
+    }
+
+    #[test]
+    fn removes_expired_keys_among_persistent_ones() {
+        let mut db = Database::new();
+        for idx in 0..10_000 {
+            db.insert(
+                format!("persistent{}", idx).into_bytes(),
+                ValueType::new(b"value".to_vec()),
+            );
+        }
+        let past = UNIX_EPOCH + Duration::from_secs(1);
+        for idx in 0..1000 {
+            let mut value = ValueType::new(b"value".to_vec());
+            value.set_expiration(Some(past));
+            db.insert(format!("session{}", idx).into_bytes(), value);
+        }
+        // Only keys with expirations are sampled, so all of them are, however few.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert_eq!(remove_expired(&mut db, deadline), 1000);
+        assert_eq!(db.len(), 10_000);
+
+        // Keys stop being sampled once their expiration is removed or they are.
+        let later = SystemTime::now() + Duration::from_secs(100);
+        assert!(expire(&mut db, b"persistent0", later, &[]));
+        assert!(db.random_expiring_key(&mut rand::thread_rng()).is_some());
+        assert!(persist(&mut db, b"persistent0"));
+        assert!(db.random_expiring_key(&mut rand::thread_rng()).is_none());
+        assert!(expire(&mut db, b"persistent1", later, &[]));
+        db.remove(b"persistent1");
+        assert!(db.random_expiring_key(&mut rand::thread_rng()).is_none());
+    }
+
+    #[test]
+    fn conditions_must_all_allow() {
+        let mut db = db_with(b"key");
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(100);
+        let sooner = now + Duration::from_secs(50);
+        assert!(!expire(&mut db, b"key", later, &[ExpireCondition::Xx]));
+        assert!(!expire(&mut db, b"key", later, &[ExpireCondition::Gt]));
+        assert!(expire(&mut db, b"key", later, &[ExpireCondition::Nx]));
+        assert!(!expire(&mut db, b"key", sooner, &[ExpireCondition::Nx]));
+        assert!(!expire(
+            &mut db,
+            b"key",
+            sooner,
+            &[ExpireCondition::Xx, ExpireCondition::Gt]
+        ));
+        assert!(expire(
+            &mut db,
+            b"key",
+            sooner,
+            &[ExpireCondition::Xx, ExpireCondition::Lt]
+        ));
+        assert_eq!(ttl(&mut db, b"key", false), 50);
+    }
+}
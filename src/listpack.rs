@@ -0,0 +1,240 @@
+//! Listpacks, the compact lists of strings and integers which RDB files use to store
+//! stream entries.
+//!
+//! A listpack is its total length in bytes and its number of elements, 4 and 2 bytes
+//! little endian, then the elements and a 0xff terminator.  Each element is an encoding
+//! byte, which may hold part of the length or value, then the data, then the length of
+//! the encoding and data written backwards, so that the list can be walked either way:
+//!   * 0xxxxxxx: a 7 bit unsigned integer.
+//!   * 10LLLLLL: a string of up to 63 bytes.
+//!   * 110xxxxx xxxxxxxx: a 13 bit signed integer.
+//!   * 1110LLLL LLLLLLLL: a string of up to 4095 bytes.
+//!   * 11110000 then 4 bytes of length: a longer string.
+//!   * 0xf1 to 0xf4: a 16, 24, 32 or 64 bit signed integer follows.
+
+const END: u8 = 0xff;
+
+// The element count used when there are too many elements to record.
+const UNKNOWN_COUNT: u16 = u16::MAX;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ListpackEntry {
+    Integer(i64),
+    String(Vec<u8>),
+}
+
+impl ListpackEntry {
+    /// The entry as a string, where integers are written in decimal.
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        match self {
+            ListpackEntry::Integer(value) => value.to_string().into_bytes(),
+            ListpackEntry::String(value) => value,
+        }
+    }
+
+    /// The entry as an integer, if it is one or is a string holding one.
+    pub(crate) fn as_integer(&self) -> Option<i64> {
+        match self {
+            ListpackEntry::Integer(value) => Some(*value),
+            ListpackEntry::String(value) => std::str::from_utf8(value).ok()?.parse().ok(),
+        }
+    }
+}
+
+/// Serialises the entries as a listpack.
+pub(crate) fn encode(entries: &[ListpackEntry]) -> Vec<u8> {
+    // The header is filled in once the length is known.
+    let mut output = vec![0; 6];
+    for entry in entries {
+        let start = output.len();
+        match entry {
+            ListpackEntry::Integer(value) => encode_integer(*value, &mut output),
+            ListpackEntry::String(value) => encode_string(value, &mut output),
+        }
+        let len = output.len() - start;
+        output.extend_from_slice(&encode_backlen(len));
+    }
+    output.push(END);
+    let total = output.len() as u32;
+    output[..4].copy_from_slice(&total.to_le_bytes());
+    let count = u16::try_from(entries.len()).unwrap_or(UNKNOWN_COUNT);
+    output[4..6].copy_from_slice(&count.to_le_bytes());
+    output
+}
+
+/// Parses a listpack, returning None if it is corrupt.
+pub(crate) fn decode(input: &[u8]) -> Option<Vec<ListpackEntry>> {
+    let total = u32::from_le_bytes(input.get(..4)?.try_into().ok()?) as usize;
+    if total != input.len() || input.last() != Some(&END) {
+        return None;
+    }
+    let mut entries = Vec::new();
+    let mut idx = 6;
+    while *input.get(idx)? != END {
+        let (entry, len) = decode_entry(&input[idx..])?;
+        entries.push(entry);
+        idx += len + encode_backlen(len).len();
+        if idx >= input.len() {
+            return None;
+        }
+    }
+    Some(entries)
+}
+
+fn encode_integer(value: i64, output: &mut Vec<u8>) {
+    if (0..=127).contains(&value) {
+        output.push(value as u8);
+    } else if (-4096..=4095).contains(&value) {
+        let value = (value as u64) & 0x1fff;
+        output.extend_from_slice(&[0xc0 | (value >> 8) as u8, value as u8]);
+    } else if let Ok(value) = i16::try_from(value) {
+        output.push(0xf1);
+        output.extend_from_slice(&value.to_le_bytes());
+    } else if (-(1 << 23)..(1 << 23)).contains(&value) {
+        output.push(0xf2);
+        output.extend_from_slice(&value.to_le_bytes()[..3]);
+    } else if let Ok(value) = i32::try_from(value) {
+        output.push(0xf3);
+        output.extend_from_slice(&value.to_le_bytes());
+    } else {
+        output.push(0xf4);
+        output.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn encode_string(value: &[u8], output: &mut Vec<u8>) {
+    let len = value.len();
+    if len < 1 << 6 {
+        output.push(0x80 | len as u8);
+    } else if len < 1 << 12 {
+        output.extend_from_slice(&[0xe0 | (len >> 8) as u8, len as u8]);
+    } else {
+        output.push(0xf0);
+        output.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    output.extend_from_slice(value);
+}
+
+// Encodes the length of an element's encoding and data in 7 bit groups, most
+// significant first, where every byte after the first has its top bit set.  Read from
+// the end, a set top bit means there are more bytes.
+fn encode_backlen(len: usize) -> Vec<u8> {
+    let mut backlen = vec![(len & 0x7f) as u8 | 0x80];
+    let mut rest = len >> 7;
+    while rest > 0 {
+        backlen.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    // The most significant byte is last until reversed.
+    *backlen.last_mut().unwrap() &= 0x7f;
+    backlen.reverse();
+    backlen
+}
+
+// Decodes the element at the start of input, returning it and the length of its
+// encoding and data.
+fn decode_entry(input: &[u8]) -> Option<(ListpackEntry, usize)> {
+    let first = input[0];
+    let string = |header: usize, len: usize| {
+        let value = input.get(header..header + len)?.to_vec();
+        Some((ListpackEntry::String(value), header + len))
+    };
+    let integer = |len: usize| {
+        let bytes = input.get(1..1 + len)?;
+        // Sign extend from the top byte given.
+        let fill = if bytes[len - 1] & 0x80 != 0 { 0xff } else { 0 };
+        let mut buffer = [fill; 8];
+        buffer[..len].copy_from_slice(bytes);
+        Some((ListpackEntry::Integer(i64::from_le_bytes(buffer)), 1 + len))
+    };
+    match first {
+        0x00..=0x7f => Some((ListpackEntry::Integer(first as i64), 1)),
+        0x80..=0xbf => string(1, (first & 0x3f) as usize),
+        0xc0..=0xdf => {
+            let value = (((first & 0x1f) as i64) << 8) | *input.get(1)? as i64;
+            // Sign extend from 13 bits.
+            let value = if value >= 1 << 12 {
+                value - (1 << 13)
+            } else {
+                value
+            };
+            Some((ListpackEntry::Integer(value), 2))
+        }
+        0xe0..=0xef => string(
+            2,
+            (((first & 0x0f) as usize) << 8) | *input.get(1)? as usize,
+        ),
+        0xf0 => {
+            let len = u32::from_le_bytes(input.get(1..5)?.try_into().ok()?);
+            string(5, len as usize)
+        }
+        0xf1 => integer(2),
+        0xf2 => integer(3),
+        0xf3 => integer(4),
+        0xf4 => integer(8),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_small_values() {
+        let encoded = encode(&[
+            ListpackEntry::Integer(5),
+            ListpackEntry::String(b"ab".to_vec()),
+        ]);
+        assert_eq!(
+            encoded,
+            [
+                0x0d, 0x00, 0x00, 0x00, 0x02, 0x00, // Header.
+                0x05, 0x01, // 5, then its backlen.
+                0x82, b'a', b'b', 0x03, // "ab", then its backlen.
+                0xff,
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut entries: Vec<ListpackEntry> = [
+            0,
+            127,
+            128,
+            -1,
+            4095,
+            -4096,
+            30000,
+            -30000,
+            1 << 20,
+            -(1 << 23),
+            1 << 30,
+            i64::MIN,
+            i64::MAX,
+        ]
+        .into_iter()
+        .map(ListpackEntry::Integer)
+        .collect();
+        for len in [0, 63, 64, 4095, 4096, 20000] {
+            entries.push(ListpackEntry::String(vec![b'x'; len]));
+        }
+        assert_eq!(decode(&encode(&entries)), Some(entries));
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let mut encoded = encode(&[ListpackEntry::String(b"abc".to_vec())]);
+        assert_eq!(decode(&encoded[..encoded.len() - 1]), None);
+        encoded[6] = 0x8f;
+        assert_eq!(decode(&encoded), None);
+    }
+
+    #[test]
+    fn backlen_is_read_from_the_end() {
+        assert_eq!(encode_backlen(127), [127]);
+        assert_eq!(encode_backlen(128), [0x01, 0x80]);
+        assert_eq!(encode_backlen(300), [0x02, 0xac]);
+    }
+}
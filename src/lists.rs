@@ -0,0 +1,394 @@
+//! Operations on list values for the list commands.
+//!
+//! Lists are stored as double ended queues.  As in Redis, a list key never holds
+//! an empty list: popping or removing the last element deletes the key.
+
+use std::collections::VecDeque;
+
+use crate::errors::RedisError;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::ListEnd;
+
+pub(crate) type List = VecDeque<Vec<u8>>;
+
+/// Pushes the values one at a time onto the given end of the list at key, creating it
+/// if needed, and returns the new length.
+pub(crate) fn push(
+    db: &mut Database,
+    key: &[u8],
+    values: &[&[u8]],
+    end: ListEnd,
+) -> Result<usize, RedisError> {
+    if list_mut(db, key)?.is_none() {
+        db.insert(key.to_vec(), ValueType::from(Value::List(List::new())));
+    }
+    let list = list_mut(db, key)?.expect("list was just inserted");
+    for value in values {
+        match end {
+            ListEnd::Left => list.push_front(value.to_vec()),
+            ListEnd::Right => list.push_back(value.to_vec()),
+        }
+    }
+    Ok(list.len())
+}
+
+/// Pops up to count values from the given end of the list at key, or returns None if
+/// there is no list.
+pub(crate) fn pop(
+    db: &mut Database,
+    key: &[u8],
+    count: usize,
+    end: ListEnd,
+) -> Result<Option<Vec<Vec<u8>>>, RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Ok(None);
+    };
+    let count = count.min(list.len());
+    let popped = match end {
+        ListEnd::Left => list.drain(..count).collect(),
+        ListEnd::Right => list.drain(list.len() - count..).rev().collect(),
+    };
+    remove_if_empty(db, key);
+    Ok(Some(popped))
+}
+
+pub(crate) fn len(db: &mut Database, key: &[u8]) -> Result<usize, RedisError> {
+    Ok(list_mut(db, key)?.map_or(0, |list| list.len()))
+}
+
+/// Returns the elements between the start and stop indices, inclusive.  Negative
+/// indices count back from the end of the list.
+pub(crate) fn range(
+    db: &mut Database,
+    key: &[u8],
+    start: i64,
+    stop: i64,
+) -> Result<Vec<Vec<u8>>, RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    Ok(match index_range(list.len(), start, stop) {
+        Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+        None => Vec::new(),
+    })
+}
+
+pub(crate) fn index(
+    db: &mut Database,
+    key: &[u8],
+    index: i64,
+) -> Result<Option<Vec<u8>>, RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Ok(None);
+    };
+    Ok(normalize_index(list.len(), index).map(|index| list[index].clone()))
+}
+
+pub(crate) fn set(
+    db: &mut Database,
+    key: &[u8],
+    index: i64,
+    value: &[u8],
+) -> Result<(), RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Err(RedisError::NoSuchKey);
+    };
+    let Some(index) = normalize_index(list.len(), index) else {
+        return Err(RedisError::UnexpectedArgumentType(
+            "index out of range".to_string(),
+        ));
+    };
+    list[index] = value.to_vec();
+    Ok(())
+}
+
+/// Removes up to count occurrences of value, searching from the head if count is
+/// positive and from the tail if it is negative.  A count of zero removes all of them.
+pub(crate) fn remove(
+    db: &mut Database,
+    key: &[u8],
+    count: i64,
+    value: &[u8],
+) -> Result<usize, RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Ok(0);
+    };
+    let limit = if count == 0 {
+        usize::MAX
+    } else {
+        count.unsigned_abs() as usize
+    };
+    let mut removed = 0;
+    if count >= 0 {
+        list.retain(|element| {
+            let remove = removed < limit && element == value;
+            removed += remove as usize;
+            !remove
+        });
+    } else {
+        let mut idx = list.len();
+        while idx > 0 && removed < limit {
+            idx -= 1;
+            if list[idx] == value {
+                list.remove(idx);
+                removed += 1;
+            }
+        }
+    }
+    remove_if_empty(db, key);
+    Ok(removed)
+}
+
+/// Trims the list to the elements between the start and stop indices, inclusive.
+pub(crate) fn trim(db: &mut Database, key: &[u8], start: i64, stop: i64) -> Result<(), RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Ok(());
+    };
+    match index_range(list.len(), start, stop) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    remove_if_empty(db, key);
+    Ok(())
+}
+
+/// Inserts value next to the first occurrence of pivot, returning the new length, -1 if
+/// the pivot wasn't found or 0 if there is no list.
+pub(crate) fn insert(
+    db: &mut Database,
+    key: &[u8],
+    before: bool,
+    pivot: &[u8],
+    value: &[u8],
+) -> Result<i64, RedisError> {
+    let Some(list) = list_mut(db, key)? else {
+        return Ok(0);
+    };
+    let Some(position) = list.iter().position(|element| element == pivot) else {
+        return Ok(-1);
+    };
+    let position = if before { position } else { position + 1 };
+    list.insert(position, value.to_vec());
+    Ok(list.len() as i64)
+}
+
+/// Atomically pops an element from one end of the source list and pushes it onto the
+/// given end of the destination list, returning it, or None if there is no source list.
+pub(crate) fn move_element(
+    db: &mut Database,
+    source: &[u8],
+    destination: &[u8],
+    from: ListEnd,
+    to: ListEnd,
+) -> Result<Option<Vec<u8>>, RedisError> {
+    if list_mut(db, source)?.is_none() {
+        return Ok(None);
+    }
+    // Check the destination before popping, so that nothing changes on error.
+    list_mut(db, destination)?;
+    let Some(element) = pop(db, source, 1, from)?.and_then(|mut popped| popped.pop()) else {
+        return Ok(None);
+    };
+    push(db, destination, &[&element], to)?;
+    Ok(Some(element))
+}
+
+// Returns the list at key, or None if the key doesn't exist.
+fn list_mut<'d>(db: &'d mut Database, key: &[u8]) -> Result<Option<&'d mut List>, RedisError> {
+    match live_value_mut(db, key).map(ValueType::value_mut) {
+        Some(Value::List(list)) => Ok(Some(list)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn remove_if_empty(db: &mut Database, key: &[u8]) {
+    if let Some(Value::List(list)) = db.get(key).map(ValueType::value) {
+        if list.is_empty() {
+            db.remove(key);
+        }
+    }
+}
+
+// Converts a possibly negative index into an index into a list of length len.
+fn normalize_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+// Converts possibly negative start and stop indices into an inclusive range of indices
+// into a list of length len, or None if the range is empty.
+fn index_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    (start <= stop).then_some((start as usize, stop as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_db(key: &[u8], values: &[&[u8]]) -> Database {
+        let mut db = Database::new();
+        push(&mut db, key, values, ListEnd::Right).unwrap();
+        db
+    }
+
+    #[test]
+    fn push_to_both_ends() {
+        let mut db = Database::new();
+        assert_eq!(
+            push(&mut db, b"list", &[b"b", b"a"], ListEnd::Left).unwrap(),
+            2
+        );
+        assert_eq!(push(&mut db, b"list", &[b"c"], ListEnd::Right).unwrap(), 3);
+        assert_eq!(
+            range(&mut db, b"list", 0, -1).unwrap(),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+        );
+    }
+
+    #[test]
+    fn push_to_string_is_wrong_type() {
+        let mut db = Database::from([(b"key".to_vec(), ValueType::new(b"value".to_vec()))]);
+        assert!(matches!(
+            push(&mut db, b"key", &[b"a"], ListEnd::Left),
+            Err(RedisError::WrongType)
+        ));
+    }
+
+    #[test]
+    fn pop_deletes_empty_list() {
+        let mut db = list_db(b"list", &[b"a", b"b", b"c"]);
+        assert_eq!(
+            pop(&mut db, b"list", 2, ListEnd::Right).unwrap(),
+            Some(vec![b"c".to_vec(), b"b".to_vec()])
+        );
+        assert_eq!(
+            pop(&mut db, b"list", 5, ListEnd::Left).unwrap(),
+            Some(vec![b"a".to_vec()])
+        );
+        assert!(db.is_empty());
+        assert_eq!(pop(&mut db, b"list", 1, ListEnd::Left).unwrap(), None);
+    }
+
+    #[test]
+    fn range_clamps_indices() {
+        let mut db = list_db(b"list", &[b"a", b"b", b"c"]);
+        assert_eq!(
+            range(&mut db, b"list", -2, 100).unwrap(),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(
+            range(&mut db, b"list", -100, 0).unwrap(),
+            vec![b"a".to_vec()]
+        );
+        assert!(range(&mut db, b"list", 2, 1).unwrap().is_empty());
+        assert!(range(&mut db, b"list", 5, 10).unwrap().is_empty());
+        assert!(range(&mut db, b"missing", 0, -1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_and_set() {
+        let mut db = list_db(b"list", &[b"a", b"b", b"c"]);
+        assert_eq!(index(&mut db, b"list", -1).unwrap(), Some(b"c".to_vec()));
+        assert_eq!(index(&mut db, b"list", 3).unwrap(), None);
+
+        set(&mut db, b"list", 1, b"x").unwrap();
+        assert_eq!(index(&mut db, b"list", 1).unwrap(), Some(b"x".to_vec()));
+        assert!(matches!(
+            set(&mut db, b"list", 3, b"x"),
+            Err(RedisError::UnexpectedArgumentType(_))
+        ));
+        assert!(matches!(
+            set(&mut db, b"missing", 0, b"x"),
+            Err(RedisError::NoSuchKey)
+        ));
+    }
+
+    #[test]
+    fn remove_from_head_and_tail() {
+        let mut db = list_db(b"list", &[b"a", b"b", b"a", b"c", b"a"]);
+        assert_eq!(remove(&mut db, b"list", -2, b"a").unwrap(), 2);
+        assert_eq!(
+            range(&mut db, b"list", 0, -1).unwrap(),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(remove(&mut db, b"list", 1, b"b").unwrap(), 1);
+        assert_eq!(remove(&mut db, b"list", 0, b"a").unwrap(), 1);
+        assert_eq!(remove(&mut db, b"list", 0, b"c").unwrap(), 1);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn trim_keeps_range() {
+        let mut db = list_db(b"list", &[b"a", b"b", b"c", b"d"]);
+        trim(&mut db, b"list", 1, -2).unwrap();
+        assert_eq!(
+            range(&mut db, b"list", 0, -1).unwrap(),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+        trim(&mut db, b"list", 5, 10).unwrap();
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn insert_around_pivot() {
+        let mut db = list_db(b"list", &[b"a", b"c"]);
+        assert_eq!(insert(&mut db, b"list", true, b"c", b"b").unwrap(), 3);
+        assert_eq!(insert(&mut db, b"list", false, b"c", b"d").unwrap(), 4);
+        assert_eq!(insert(&mut db, b"list", false, b"x", b"y").unwrap(), -1);
+        assert_eq!(insert(&mut db, b"missing", false, b"x", b"y").unwrap(), 0);
+        assert_eq!(
+            range(&mut db, b"list", 0, -1).unwrap(),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]
+        );
+    }
+
+    #[test]
+    fn move_between_lists() {
+        let mut db = list_db(b"source", &[b"a", b"b"]);
+        assert_eq!(
+            move_element(&mut db, b"source", b"dest", ListEnd::Left, ListEnd::Right).unwrap(),
+            Some(b"a".to_vec())
+        );
+        assert_eq!(
+            move_element(&mut db, b"source", b"dest", ListEnd::Right, ListEnd::Left).unwrap(),
+            Some(b"b".to_vec())
+        );
+        assert!(!db.contains_key(b"source".as_slice()));
+        assert_eq!(
+            range(&mut db, b"dest", 0, -1).unwrap(),
+            vec![b"b".to_vec(), b"a".to_vec()]
+        );
+        assert_eq!(
+            move_element(&mut db, b"source", b"dest", ListEnd::Left, ListEnd::Left).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn move_rotates_same_list() {
+        let mut db = list_db(b"list", &[b"a", b"b", b"c"]);
+        move_element(&mut db, b"list", b"list", ListEnd::Left, ListEnd::Right).unwrap();
+        assert_eq!(
+            range(&mut db, b"list", 0, -1).unwrap(),
+            vec![b"b".to_vec(), b"c".to_vec(), b"a".to_vec()]
+        );
+    }
+
+    #[test]
+    fn move_to_string_is_wrong_type() {
+        let mut db = list_db(b"list", &[b"a"]);
+        db.insert(b"key".to_vec(), ValueType::new(b"value".to_vec()));
+        assert!(matches!(
+            move_element(&mut db, b"list", b"key", ListEnd::Left, ListEnd::Left),
+            Err(RedisError::WrongType)
+        ));
+        assert_eq!(len(&mut db, b"list").unwrap(), 1);
+    }
+}
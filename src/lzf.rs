@@ -0,0 +1,187 @@
+//! LZF compression, which Redis uses for long strings in RDB files.
+//!
+//! Compressed data is a sequence of chunks, each starting with a control byte:
+//!   * 000LLLLL: a run of L + 1 literal bytes follows.
+//!   * LLLooooo oooooooo: a back reference of L + 2 bytes, copied from
+//!     o + 1 bytes before the current end of the output.
+//!   * 111ooooo LLLLLLLL oooooooo: a back reference of L + 9 bytes.
+
+// Log2 of the number of entries in the compressor's hash table.
+const HASH_LOG: u32 = 14;
+
+// Limits of the encoding.
+const MAX_LITERAL_RUN: usize = 1 << 5;
+const MAX_OFFSET: usize = 1 << 13;
+const MAX_MATCH_LEN: usize = (1 << 8) + 8;
+
+/// Decompresses the input, which should expand to exactly expected_len bytes.  Returns
+/// None if the input is corrupt.
+pub(crate) fn decompress(input: &[u8], expected_len: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(expected_len);
+    let mut idx = 0;
+    while idx < input.len() {
+        let ctrl = input[idx] as usize;
+        idx += 1;
+        if ctrl < MAX_LITERAL_RUN {
+            let run = input.get(idx..idx + ctrl + 1)?;
+            output.extend_from_slice(run);
+            idx += ctrl + 1;
+        } else {
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input.get(idx)? as usize;
+                idx += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8) + *input.get(idx)? as usize + 1;
+            idx += 1;
+            let start = output.len().checked_sub(offset)?;
+            // The reference may overlap the bytes being copied, so copy one at a time.
+            for i in start..start + len + 2 {
+                output.push(output[i]);
+            }
+        }
+        if output.len() > expected_len {
+            return None;
+        }
+    }
+    (output.len() == expected_len).then_some(output)
+}
+
+/// Compresses the input.  The result may be larger than the input if it doesn't
+/// compress well.
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() + input.len() / MAX_LITERAL_RUN + 1);
+    // The position after the last occurrence of each hashed 3 byte sequence, or zero.
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    // Position in the output of the control byte of the current literal run.
+    let mut ctrl_idx = 0;
+    let mut literals = 0;
+    output.push(0);
+
+    let mut idx = 0;
+    while idx + 2 < input.len() {
+        let hash = hash(&input[idx..idx + 3]);
+        let candidate = table[hash];
+        table[hash] = idx + 1;
+        if candidate > 0
+            && idx - candidate < MAX_OFFSET
+            && input[candidate - 1..candidate + 2] == input[idx..idx + 3]
+        {
+            let reference = candidate - 1;
+            let max_len = MAX_MATCH_LEN.min(input.len() - idx);
+            let mut len = 3;
+            while len < max_len && input[reference + len] == input[idx + len] {
+                len += 1;
+            }
+
+            // End the current literal run, dropping its control byte if it is empty.
+            if literals == 0 {
+                output.pop();
+            } else {
+                output[ctrl_idx] = (literals - 1) as u8;
+            }
+            let offset = idx - reference - 1;
+            let encoded_len = len - 2;
+            if encoded_len < 7 {
+                output.push(((encoded_len << 5) + (offset >> 8)) as u8);
+            } else {
+                output.push(((7 << 5) + (offset >> 8)) as u8);
+                output.push((encoded_len - 7) as u8);
+            }
+            output.push((offset & 0xff) as u8);
+            idx += len;
+
+            ctrl_idx = output.len();
+            literals = 0;
+            output.push(0);
+        } else {
+            output.push(input[idx]);
+            literals += 1;
+            idx += 1;
+            if literals == MAX_LITERAL_RUN {
+                output[ctrl_idx] = (literals - 1) as u8;
+                ctrl_idx = output.len();
+                literals = 0;
+                output.push(0);
+            }
+        }
+    }
+
+    // The last couple of bytes are too short to start a match.
+    for b in &input[idx..] {
+        output.push(*b);
+        literals += 1;
+        if literals == MAX_LITERAL_RUN {
+            output[ctrl_idx] = (literals - 1) as u8;
+            ctrl_idx = output.len();
+            literals = 0;
+            output.push(0);
+        }
+    }
+    if literals == 0 {
+        output.pop();
+    } else {
+        output[ctrl_idx] = (literals - 1) as u8;
+    }
+    output
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+    (value.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_back_reference() {
+        // The literal "abc" then 6 bytes copied from 3 bytes back.
+        let input = [0x02, b'a', b'b', b'c', 0x80, 0x02];
+        assert_eq!(decompress(&input, 9), Some(b"abcabcabc".to_vec()));
+    }
+
+    #[test]
+    fn decompress_long_back_reference() {
+        // The literal "a" then 20 bytes copied from 1 byte back.
+        let input = [0x00, b'a', 0xe0, 0x0b, 0x00];
+        assert_eq!(decompress(&input, 21), Some(vec![b'a'; 21]));
+    }
+
+    #[test]
+    fn decompress_rejects_corrupt_input() {
+        // Reference before the start of the output.
+        assert_eq!(decompress(&[0x00, b'a', 0x20, 0x05], 4), None);
+        // Truncated literal run.
+        assert_eq!(decompress(&[0x05, b'a', b'b'], 6), None);
+        // Wrong length.
+        assert_eq!(decompress(&[0x02, b'a', b'b', b'c'], 4), None);
+    }
+
+    #[test]
+    fn round_trips() {
+        let inputs = [
+            b"".to_vec(),
+            b"ab".to_vec(),
+            b"hello hello hello hello hello world".to_vec(),
+            vec![b'x'; 1000],
+            (0..5000u32).map(|i| (i * i % 251) as u8).collect(),
+            (0..5000u32).map(|i| (i % 7 + i / 100) as u8).collect(),
+        ];
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(
+                decompress(&compressed, input.len()),
+                Some(input.clone()),
+                "Failed to round trip {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn compresses_repetitive_input() {
+        assert!(compress(&[b'x'; 1000]).len() < 50);
+    }
+}
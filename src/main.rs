@@ -1,11 +1,26 @@
+mod blocking;
+mod crc64;
+mod dict;
 mod errors;
+mod glob;
+mod hashes;
+mod keys;
+mod listpack;
+mod lists;
+mod lzf;
 mod rdb_parser;
+mod rdb_writer;
 mod redis_handler;
+mod replication;
 mod resp_command;
 mod resp_parser;
+mod sets;
+mod skiplist;
+mod sorted_sets;
+mod streams;
+mod strings;
 
 use clap::Parser;
-use rand::Rng;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::net::TcpListener;
@@ -19,7 +34,7 @@ struct RedisArgs {
     #[arg(short, long)]
     dir: Option<String>,
 
-    #[arg(short, long)]
+    #[arg(long)]
     dbfilename: Option<String>,
 
     #[arg(short, long, default_value_t = 6379)]
@@ -27,6 +42,12 @@ struct RedisArgs {
 
     #[arg(short, long)]
     replicaof: Option<String>,
+
+    #[arg(long, default_value_t = 1024 * 1024, value_parser = clap::value_parser!(u64).range(1..))]
+    repl_backlog_size: u64,
+
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u64).range(1..))]
+    databases: u64,
 }
 
 impl RedisArgs {
@@ -39,6 +60,14 @@ impl RedisArgs {
             result.insert(b"dbfilename".to_vec(), dbfilename.clone().into_bytes());
         }
         result.insert(b"port".to_vec(), self.port.to_string().into_bytes());
+        result.insert(
+            b"repl-backlog-size".to_vec(),
+            self.repl_backlog_size.to_string().into_bytes(),
+        );
+        result.insert(
+            b"databases".to_vec(),
+            self.databases.to_string().into_bytes(),
+        );
         result
     }
 }
@@ -57,11 +86,14 @@ async fn main() {
             }
             fully_qualified_path.push(filepath);
             if !fully_qualified_path.exists() {
-                Arc::new(RedisHandler::new_with_contents(
-                    args.to_config_dict(),
-                    replication_info,
-                    HashMap::new(),
-                ))
+                Arc::new(
+                    RedisHandler::new_with_contents(
+                        args.to_config_dict(),
+                        replication_info,
+                        Vec::new(),
+                    )
+                    .expect("Error creating databases"),
+                )
             } else {
                 Arc::new(
                     RedisHandler::new_from_file(
@@ -73,12 +105,25 @@ async fn main() {
                 )
             }
         }
-        None => Arc::new(RedisHandler::new_with_contents(
-            args.to_config_dict(),
-            replication_info,
-            HashMap::new(),
-        )),
+        None => Arc::new(
+            RedisHandler::new_with_contents(args.to_config_dict(), replication_info, Vec::new())
+                .expect("Error creating databases"),
+        ),
     };
+    // Runs for the lifetime of the server, since REPLICAOF can make it a replica at any time.
+    let h = handler.clone();
+    tokio::spawn(async move {
+        unsafe {
+            h.run_replication().await;
+        }
+    });
+    let h = handler.clone();
+    tokio::spawn(async move {
+        unsafe {
+            h.run_active_expiration().await;
+        }
+    });
+
     let addr = format!("{}:{}", IP, args.port);
     let listener = TcpListener::bind(addr).await.expect("Error connecting");
 
@@ -104,19 +149,30 @@ async fn main() {
 
 fn replication_info_from_args(args: &RedisArgs) -> RedisReplicationInfo {
     let mut replication_info = RedisReplicationInfo::default();
-    match args.replicaof {
-        Some(..) => {
+    match &args.replicaof {
+        Some(replicaof) => {
+            let (host, port) = parse_replicaof(replicaof)
+                .expect("--replicaof should be of the form \"<host> <port>\"");
             replication_info.role = redis_handler::RedisRole::Slave;
+            replication_info.master_host = host;
+            replication_info.master_port = port;
         }
         None => {
             replication_info.role = redis_handler::RedisRole::Master;
-            replication_info.master_replid = rand::thread_rng()
-                .sample_iter(&rand::distributions::Alphanumeric)
-                .take(40)
-                .map(char::from)
-                .collect();
+            replication_info.master_replid = replication::new_replication_id();
             replication_info.master_repl_offset = 0;
         }
     }
     replication_info
 }
+
+// Parses the master address from a --replicaof value such as "localhost 6379".
+fn parse_replicaof(replicaof: &str) -> Option<(String, u16)> {
+    let mut parts = replicaof.split_whitespace();
+    let host = parts.next()?;
+    let port = parts.next()?.parse::<u16>().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((host.to_string(), port))
+}
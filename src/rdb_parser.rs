@@ -1,29 +1,48 @@
 /// A parser for RDB files.
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Read;
+use std::time::{Duration, UNIX_EPOCH};
 
+use crate::crc64::crc64;
 use crate::errors::RdbFileError;
-use crate::redis_handler::ValueType;
+use crate::hashes::HashField;
+use crate::listpack::{self, ListpackEntry};
+use crate::lzf;
+use crate::redis_handler::{Database, Value, ValueType};
+use crate::streams::{Consumer, ConsumerGroup, Fields, PendingEntry, Stream, StreamId};
+
+// Flags of the entries in a stream's listpacks.
+const STREAM_ITEM_DELETED: i64 = 1;
+const STREAM_ITEM_SAME_FIELDS: i64 = 2;
 
 pub(crate) struct RdbReader<R> {
     reader: R,
-    buffer: [u8; 1],
+    // Checksum of everything read so far.
+    checksum: u64,
+    // The metadata read so far.
+    aux_fields: HashMap<Vec<u8>, Vec<u8>>,
 }
 
 impl<R> RdbReader<R> {
     pub(crate) fn new(reader: R) -> Self {
         RdbReader {
             reader,
-            buffer: [0u8; 1],
+            checksum: 0,
+            aux_fields: HashMap::new(),
         }
     }
+
+    /// Returns the value of a metadata field read from the file.
+    pub(crate) fn aux_field(&self, key: &[u8]) -> Option<&[u8]> {
+        self.aux_fields.get(key).map(|v| v.as_slice())
+    }
 }
 
 #[derive(Debug, PartialEq)]
 enum RdbValue {
     Header { version: [u8; 4] },
     MetadataSection { key: Vec<u8>, value: Vec<u8> },
-    Database(HashMap<Vec<u8>, ValueType>),
+    Database { index: usize, contents: Database },
     EndOfFile { checksum: [u8; 8] },
 }
 
@@ -31,9 +50,11 @@ impl<R> RdbReader<R>
 where
     R: Read,
 {
-    pub(crate) fn read_contents(&mut self) -> Result<HashMap<Vec<u8>, ValueType>, RdbFileError> {
+    /// Reads all the databases in the file, indexed by their number.  Databases absent
+    /// from the file are empty.
+    pub(crate) fn read_contents(&mut self) -> Result<Vec<Database>, RdbFileError> {
         self.read_header()?;
-        let mut db = std::collections::HashMap::new();
+        let mut databases = Vec::new();
         loop {
             match self.read_next_value()? {
                 RdbValue::Header { .. } => {
@@ -41,9 +62,16 @@ where
                         "Multiple file headers".to_string(),
                     ))
                 }
-                RdbValue::MetadataSection { .. } => (),
-                RdbValue::Database(contents) => db = contents,
-                RdbValue::EndOfFile { .. } => return Ok(db),
+                RdbValue::MetadataSection { key, value } => {
+                    self.aux_fields.insert(key, value);
+                }
+                RdbValue::Database { index, contents } => {
+                    if databases.len() <= index {
+                        databases.resize_with(index + 1, Database::new);
+                    }
+                    databases[index] = contents;
+                }
+                RdbValue::EndOfFile { .. } => return Ok(databases),
             }
         }
     }
@@ -51,7 +79,7 @@ where
     /// Read the magic header from a reader and verify it is valid.
     fn read_header(&mut self) -> Result<RdbValue, RdbFileError> {
         let mut buffer = [0u8; 9];
-        self.reader.read_exact(&mut buffer)?;
+        self.read_exact(&mut buffer)?;
         if buffer[0..5] != *b"REDIS" {
             Err(RdbFileError::NotRedisFile)
         } else {
@@ -79,12 +107,7 @@ where
     }
 
     fn read_database(&mut self) -> Result<RdbValue, RdbFileError> {
-        let database_idx = self.read_size()?;
-        if database_idx != 0 {
-            return Err(RdbFileError::Unimplemented(
-                "Multiple databases not supported".to_string(),
-            ));
-        }
+        let index = self.read_size()?;
         let b = self.read_next_byte()?;
         if b != 0xfb {
             return Err(RdbFileError::UnexpectedByte {
@@ -97,64 +120,229 @@ where
         self.read_size()?; // Number of expires, which we don't use.
 
         // Values.
-        let mut database_contents = HashMap::new();
+        let mut database_contents = Database::new();
         for _ in 0..n_values {
-            let b = self.read_next_byte()?;
-            match b {
-                0x00 => {
-                    // Value without expiration.
-                    let key = self.read_string()?;
-                    let value = self.read_string()?;
-                    database_contents.insert(key, ValueType::new(value));
-                }
+            let mut value_type = self.read_next_byte()?;
+            let expiration_millis = match value_type {
                 0xfc => {
-                    // Expiration in milliseconds, 8 bytes, unisgned, little endian.
+                    // Expiration in milliseconds, 8 bytes, unsigned, little endian.
                     let mut buffer = [0u8; 8];
-                    self.reader.read_exact(&mut buffer)?;
-                    let expiration_millis = u64::from_le_bytes(buffer);
-                    let b = self.read_next_byte()?;
-                    if b != 0 {
-                        return Err(RdbFileError::UnexpectedByte {
-                            expected: "0x00".to_string(),
-                            actual: b,
-                        });
-                    }
-                    let key = self.read_string()?;
-                    let value = self.read_string()?;
-                    database_contents
-                        .insert(key, ValueType::new_from_millis(value, expiration_millis));
+                    self.read_exact(&mut buffer)?;
+                    value_type = self.read_next_byte()?;
+                    Some(u64::from_le_bytes(buffer))
                 }
                 0xfd => {
-                    // Expiration in seconds, 4 bytes, unisgned, little endian.
+                    // Expiration in seconds, 4 bytes, unsigned, little endian.
                     let mut buffer = [0u8; 4];
-                    self.reader.read_exact(&mut buffer)?;
-                    let expiration_seconds = u32::from_le_bytes(buffer);
-                    let b = self.read_next_byte()?;
-                    if b != 0 {
-                        return Err(RdbFileError::UnexpectedByte {
-                            expected: "0x00".to_string(),
-                            actual: b,
-                        });
-                    }
-                    let key = self.read_string()?;
-                    let value = self.read_string()?;
-                    database_contents
-                        .insert(key, ValueType::new_from_seconds(value, expiration_seconds));
-                }
-                _ => {
-                    return Err(RdbFileError::UnexpectedByte {
-                        expected: "One of (0xfc, 0xfd, 00)".to_string(),
-                        actual: b,
-                    });
+                    self.read_exact(&mut buffer)?;
+                    value_type = self.read_next_byte()?;
+                    Some(u32::from_le_bytes(buffer) as u64 * 1000)
                 }
+                _ => None,
+            };
+            let key = self.read_string()?;
+            let mut value = ValueType::from(self.read_value(value_type)?);
+            if let Some(expiration_millis) = expiration_millis {
+                value = value.with_expiration_millis(expiration_millis);
+            }
+            database_contents.insert(key, value);
+        }
+        Ok(RdbValue::Database {
+            index,
+            contents: database_contents,
+        })
+    }
+
+    // Reads a value of the given type.
+    fn read_value(&mut self, value_type: u8) -> Result<Value, RdbFileError> {
+        match value_type {
+            0x00 => Ok(Value::String(self.read_string()?)),
+            0x01 => {
+                // A list, as its length followed by the elements.
+                let len = self.read_size()?;
+                let list = (0..len)
+                    .map(|_| self.read_string())
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::List(list))
+            }
+            0x02 => {
+                // A set, as its size followed by the members.
+                let len = self.read_size()?;
+                let set = (0..len)
+                    .map(|_| self.read_string())
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Set(set))
+            }
+            0x04 => {
+                // A hash, as its size followed by each field and value.
+                let len = self.read_size()?;
+                let hash = (0..len)
+                    .map(|_| Ok((self.read_string()?, HashField::new(self.read_string()?))))
+                    .collect::<Result<_, RdbFileError>>()?;
+                Ok(Value::Hash(hash))
+            }
+            0x05 => {
+                // A sorted set, as its size followed by each member and its score as a
+                // binary double.
+                let len = self.read_size()?;
+                let set = (0..len)
+                    .map(|_| {
+                        let member = self.read_string()?;
+                        let mut buffer = [0u8; 8];
+                        self.read_exact(&mut buffer)?;
+                        Ok((member, f64::from_le_bytes(buffer)))
+                    })
+                    .collect::<Result<_, RdbFileError>>()?;
+                Ok(Value::SortedSet(set))
+            }
+            0x0f | 0x13 | 0x15 => Ok(Value::Stream(self.read_stream(value_type)?)),
+            0x18 => {
+                // A hash with field expirations: the earliest expiration in milliseconds,
+                // then the size, then each field's expiration relative to the earliest
+                // plus one, or zero for none, followed by the field and value.
+                let mut buffer = [0u8; 8];
+                self.read_exact(&mut buffer)?;
+                let min_millis = u64::from_le_bytes(buffer);
+                let len = self.read_size()?;
+                let hash = (0..len)
+                    .map(|_| {
+                        let ttl = self.read_size()? as u64;
+                        let field = self.read_string()?;
+                        let mut entry = HashField::new(self.read_string()?);
+                        if ttl > 0 {
+                            entry.expiration =
+                                Some(UNIX_EPOCH + Duration::from_millis(min_millis + ttl - 1));
+                        }
+                        Ok((field, entry))
+                    })
+                    .collect::<Result<_, RdbFileError>>()?;
+                Ok(Value::Hash(hash))
             }
+            _ => Err(RdbFileError::UnexpectedByte {
+                expected: "One of (0xfc, 0xfd) or a supported value type".to_string(),
+                actual: value_type,
+            }),
         }
-        Ok(RdbValue::Database(database_contents))
+    }
+
+    // Reads a stream, stored as listpacks of entries keyed by the ID their entries are
+    // relative to, then the stream's metadata, of which later versions have more.
+    fn read_stream(&mut self, value_type: u8) -> Result<Stream, RdbFileError> {
+        let corrupt = || RdbFileError::InvalidFile("Corrupt stream listpack".to_string());
+        let mut stream = Stream::default();
+        let nodes = self.read_size()?;
+        for _ in 0..nodes {
+            let node_key: [u8; 16] = self.read_string()?.try_into().map_err(|_| corrupt())?;
+            let master_id = parse_raw_stream_id(node_key);
+            let node = listpack::decode(&self.read_string()?).ok_or_else(corrupt)?;
+            read_stream_node(master_id, node, &mut stream.entries).ok_or_else(corrupt)?;
+        }
+        // The number of entries, which we already know.
+        self.read_size()?;
+        stream.last_id = self.read_stream_id()?;
+        if value_type >= 0x13 {
+            // The first ID, which we also know.
+            self.read_stream_id()?;
+            stream.max_deleted_id = self.read_stream_id()?;
+            stream.entries_added = self.read_size()? as u64;
+        } else {
+            stream.entries_added = stream.entries.len() as u64;
+        }
+        for _ in 0..self.read_size()? {
+            let name = self.read_string()?;
+            let group = self.read_consumer_group(value_type)?;
+            stream.groups.insert(name, group);
+        }
+        Ok(stream)
+    }
+
+    fn read_consumer_group(&mut self, value_type: u8) -> Result<ConsumerGroup, RdbFileError> {
+        let mut group = ConsumerGroup {
+            last_id: self.read_stream_id()?,
+            ..ConsumerGroup::default()
+        };
+        if value_type >= 0x13 {
+            // -1 means the number read is unknown.
+            group.entries_read = Some(self.read_size()? as u64).filter(|read| *read != u64::MAX);
+        }
+        // The pending entries, whose consumers are only given with the consumers.
+        for _ in 0..self.read_size()? {
+            let id = self.read_raw_stream_id()?;
+            let entry = PendingEntry {
+                consumer: Vec::new(),
+                delivery_time: self.read_millis()? as u64,
+                delivery_count: self.read_size()? as u64,
+            };
+            group.pending.insert(id, entry);
+        }
+        let mut owned = 0;
+        for _ in 0..self.read_size()? {
+            let name = self.read_string()?;
+            let seen_time = self.read_millis()? as u64;
+            // Before version 3, there was only the seen time.
+            let active_time = match value_type {
+                0x15 => Some(self.read_millis()?)
+                    .filter(|time| *time >= 0)
+                    .map(|time| time as u64),
+                _ => Some(seen_time),
+            };
+            for _ in 0..self.read_size()? {
+                let id = self.read_raw_stream_id()?;
+                let entry = group.pending.get_mut(&id).ok_or_else(|| {
+                    RdbFileError::InvalidFile(format!(
+                        "Consumer has pending entry {} the group does not",
+                        id
+                    ))
+                })?;
+                entry.consumer = name.clone();
+                owned += 1;
+            }
+            group.consumers.insert(
+                name,
+                Consumer {
+                    seen_time,
+                    active_time,
+                },
+            );
+        }
+        if owned != group.pending.len() {
+            return Err(RdbFileError::InvalidFile(
+                "Stream group has pending entries without a consumer".to_string(),
+            ));
+        }
+        Ok(group)
+    }
+
+    fn read_raw_stream_id(&mut self) -> Result<StreamId, RdbFileError> {
+        let mut buffer = [0u8; 16];
+        self.read_exact(&mut buffer)?;
+        Ok(parse_raw_stream_id(buffer))
+    }
+
+    // Reads a time in milliseconds, 8 bytes, signed, little endian.
+    fn read_millis(&mut self) -> Result<i64, RdbFileError> {
+        let mut buffer = [0u8; 8];
+        self.read_exact(&mut buffer)?;
+        Ok(i64::from_le_bytes(buffer))
+    }
+
+    fn read_stream_id(&mut self) -> Result<StreamId, RdbFileError> {
+        Ok(StreamId::new(
+            self.read_size()? as u64,
+            self.read_size()? as u64,
+        ))
     }
 
     fn read_end_of_file(&mut self) -> Result<RdbValue, RdbFileError> {
+        // The checksum covers everything before it, including the EOF marker.
+        let expected = self.checksum;
         let mut checksum = [0u8; 8];
         self.reader.read_exact(&mut checksum)?;
+        let actual = u64::from_le_bytes(checksum);
+        // A zero checksum means the writer didn't compute one, e.g. with rdbchecksum no.
+        if actual != 0 && actual != expected {
+            return Err(RdbFileError::ChecksumMismatch { expected, actual });
+        }
         Ok(RdbValue::EndOfFile { checksum })
     }
 
@@ -165,10 +353,16 @@ where
             0 => Ok((b & 0x3f) as usize),
             // The size is the next 14 bits.
             1 => Ok((((b & 0x3f) as usize) << 8) + self.read_next_byte()? as usize),
+            2 if b == 0x81 => {
+                // The size is the next 8 bytes.
+                let mut buffer = [0u8; 8];
+                self.read_exact(&mut buffer)?;
+                Ok(u64::from_be_bytes(buffer) as usize)
+            }
             2 => {
                 // The size is the next 4 bytes (ignore the rest of the first byte).
                 let mut buffer = [0u8; 4];
-                self.reader.read_exact(&mut buffer)?;
+                self.read_exact(&mut buffer)?;
                 Ok(u32::from_be_bytes(buffer) as usize)
             }
             _ => Err(RdbFileError::UnknownStartingByte(b)),
@@ -183,23 +377,23 @@ where
                 // Next 6 bits of the lead byte specify the length of the string.
                 let len = b & 0x3f;
                 let mut v = vec![0u8; len as usize];
-                self.reader.read_exact(&mut v)?;
+                self.read_exact(&mut v)?;
                 Ok(v)
             }
             1 => {
                 // The size is the next 14 bits.
                 let len = (((b & 0x3f) as usize) << 8) + self.read_next_byte()? as usize;
                 let mut v = vec![0u8; len];
-                self.reader.read_exact(&mut v)?;
+                self.read_exact(&mut v)?;
                 Ok(v)
             }
             2 => {
                 // The size is the next 4 bytes (ignore the rest of the first byte).
                 let mut buffer = [0u8; 4];
-                self.reader.read_exact(&mut buffer)?;
+                self.read_exact(&mut buffer)?;
                 let len = u32::from_be_bytes(buffer) as usize;
                 let mut v = vec![0u8; len];
-                self.reader.read_exact(&mut v)?;
+                self.read_exact(&mut v)?;
                 Ok(v)
             }
             3 => {
@@ -213,15 +407,25 @@ where
                     0xc1 => {
                         // 16 bit value.
                         let mut buffer = [0u8; 2];
-                        self.reader.read_exact(&mut buffer)?;
+                        self.read_exact(&mut buffer)?;
                         Ok(format!("{}", i16::from_le_bytes(buffer)).into_bytes())
                     }
                     0xc2 => {
                         // 32 bit value.
                         let mut buffer = [0u8; 4];
-                        self.reader.read_exact(&mut buffer)?;
+                        self.read_exact(&mut buffer)?;
                         Ok(format!("{}", i32::from_le_bytes(buffer)).into_bytes())
                     }
+                    0xc3 => {
+                        // LZF compressed string.
+                        let compressed_len = self.read_size()?;
+                        let len = self.read_size()?;
+                        let mut compressed = vec![0u8; compressed_len];
+                        self.read_exact(&mut compressed)?;
+                        lzf::decompress(&compressed, len).ok_or_else(|| {
+                            RdbFileError::InvalidFile("Corrupt LZF compressed string".to_string())
+                        })
+                    }
                     _ => Err(RdbFileError::UnknownStartingByte(b)),
                 }
             }
@@ -231,11 +435,68 @@ where
     }
 
     fn read_next_byte(&mut self) -> Result<u8, RdbFileError> {
-        self.reader.read_exact(&mut self.buffer[0..1])?;
-        Ok(self.buffer[0])
+        let mut buffer = [0u8; 1];
+        self.read_exact(&mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    // Fills the buffer from the input, including the bytes in the checksum.
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), RdbFileError> {
+        self.reader.read_exact(buffer)?;
+        self.checksum = crc64(self.checksum, buffer);
+        Ok(())
     }
 }
 
+// Parses a stream ID stored as 16 bytes, the time then the sequence number, both big
+// endian.
+fn parse_raw_stream_id(raw: [u8; 16]) -> StreamId {
+    StreamId::new(
+        u64::from_be_bytes(raw[..8].try_into().unwrap()),
+        u64::from_be_bytes(raw[8..].try_into().unwrap()),
+    )
+}
+
+// Adds the entries of a stream listpack that haven't been deleted.  See stream_node in
+// rdb_writer for the layout.  Returns None if the listpack doesn't hold stream entries.
+fn read_stream_node(
+    master_id: StreamId,
+    node: Vec<ListpackEntry>,
+    entries: &mut BTreeMap<StreamId, Fields>,
+) -> Option<()> {
+    let mut node = node.into_iter();
+    let integer = |node: &mut std::vec::IntoIter<ListpackEntry>| node.next()?.as_integer();
+    let count = integer(&mut node)? + integer(&mut node)?;
+    let num_master_fields = integer(&mut node)?;
+    let master_fields = (0..num_master_fields)
+        .map(|_| Some(node.next()?.into_bytes()))
+        .collect::<Option<Vec<_>>>()?;
+    // The master entry ends with a zero.
+    integer(&mut node)?;
+    for _ in 0..count {
+        let flags = integer(&mut node)?;
+        let ms = master_id.ms.checked_add(integer(&mut node)? as u64)?;
+        let seq = master_id.seq.wrapping_add(integer(&mut node)? as u64);
+        let fields = if flags & STREAM_ITEM_SAME_FIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Some((field.clone(), node.next()?.into_bytes())))
+                .collect::<Option<Fields>>()?
+        } else {
+            let num_fields = integer(&mut node)?;
+            (0..num_fields)
+                .map(|_| Some((node.next()?.into_bytes(), node.next()?.into_bytes())))
+                .collect::<Option<Fields>>()?
+        };
+        // The number of elements in the entry, for walking the listpack backwards.
+        integer(&mut node)?;
+        if flags & STREAM_ITEM_DELETED == 0 {
+            entries.insert(StreamId::new(ms, seq), fields);
+        }
+    }
+    Some(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -352,6 +613,127 @@ mod tests {
         assert_eq!(actual.unwrap(), b"1234567".to_vec());
     }
 
+    #[test]
+    fn read_contents_verifies_checksum() {
+        #[rustfmt::skip]
+        let mut input = vec![
+            // Header
+            0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31,
+            // Database 0 with foo -> bar.
+            0xfe, 0x00, 0xfb, 0x01, 0x00, 0x00, 0x03, 0x66, 0x6F, 0x6F, 0x03, 0x62, 0x61, 0x72,
+            // End of file.
+            0xff,
+        ];
+        input.extend_from_slice(&crc64(0, &input).to_le_bytes());
+
+        let actual = RdbReader::new(&input[..]).read_contents();
+        assert!(
+            actual.is_ok(),
+            "Expected successful read, got {:?}",
+            actual.unwrap_err()
+        );
+
+        // Corrupt the value.
+        input[20] = 0x7a;
+        assert!(matches!(
+            RdbReader::new(&input[..]).read_contents(),
+            Err(RdbFileError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn read_contents_multiple_databases() {
+        #[rustfmt::skip]
+        let input = [
+            // Header
+            0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31,
+            // Database 0 with foo -> bar.
+            0xfe, 0x00, 0xfb, 0x01, 0x00, 0x00, 0x03, 0x66, 0x6F, 0x6F, 0x03, 0x62, 0x61, 0x72,
+            // Database 2 with baz -> qux.
+            0xfe, 0x02, 0xfb, 0x01, 0x00, 0x00, 0x03, 0x62, 0x61, 0x7A, 0x03, 0x71, 0x75, 0x78,
+            // End of file without a checksum.
+            0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let actual = RdbReader::new(&input[..]).read_contents();
+        assert!(
+            actual.is_ok(),
+            "Expected successful read, got {:?}",
+            actual.unwrap_err()
+        );
+        assert_eq!(
+            actual.unwrap(),
+            vec![
+                Database::from([(b"foo".to_vec(), ValueType::new(b"bar".to_vec()))]),
+                Database::new(),
+                Database::from([(b"baz".to_vec(), ValueType::new(b"qux".to_vec()))]),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_contents_records_aux_fields() {
+        #[rustfmt::skip]
+        let input = [
+            // Header
+            0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31,
+            // redis-ver -> 6.0.16
+            0xFA, 0x09, 0x72, 0x65, 0x64, 0x69, 0x73, 0x2D, 0x76, 0x65, 0x72, 0x06, 0x36, 0x2E,
+            0x30, 0x2E, 0x31, 0x36,
+            // End of file without a checksum.
+            0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let mut reader = RdbReader::new(&input[..]);
+        assert!(reader.read_contents().is_ok());
+        assert_eq!(reader.aux_field(b"redis-ver"), Some(&b"6.0.16"[..]));
+        assert_eq!(reader.aux_field(b"ctime"), None);
+    }
+
+    #[test]
+    fn read_contents_skips_zero_checksum() {
+        #[rustfmt::skip]
+        let input = [
+            // Header
+            0x52, 0x45, 0x44, 0x49, 0x53, 0x30, 0x30, 0x31, 0x31,
+            // End of file without a checksum.
+            0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let actual = RdbReader::new(&input[..]).read_contents();
+        assert!(
+            actual.is_ok(),
+            "Expected successful read, got {:?}",
+            actual.unwrap_err()
+        );
+    }
+
+    #[test]
+    fn read_compressed_string() {
+        // "abcabcabc" compressed as the literal "abc" and a back reference.
+        let input = [0xC3, 0x06, 0x09, 0x02, 0x61, 0x62, 0x63, 0x80, 0x02];
+        let mut reader = RdbReader::new(&input[..]);
+
+        let actual = reader.read_string();
+        assert!(
+            actual.is_ok(),
+            "Expected successful string read, got {:?}",
+            actual.unwrap_err()
+        );
+        assert_eq!(actual.unwrap(), b"abcabcabc".to_vec());
+    }
+
+    #[test]
+    fn read_corrupt_compressed_string() {
+        let input = [0xC3, 0x06, 0x0a, 0x02, 0x61, 0x62, 0x63, 0x80, 0x02];
+        let mut reader = RdbReader::new(&input[..]);
+
+        assert!(matches!(
+            reader.read_string(),
+            Err(RdbFileError::InvalidFile(_))
+        ));
+    }
+
     #[test]
     fn read_database() {
         #[rustfmt::skip]
@@ -376,20 +758,100 @@ mod tests {
             actual.unwrap_err()
         );
 
-        let expected = vec![
+        let expected = [
             (b"foobar".to_vec(), ValueType::new(b"bazqux".to_vec())),
             (
                 b"foo".to_vec(),
-                ValueType::new_from_millis(b"bar".to_vec(), 1713824559637),
+                ValueType::new(b"bar".to_vec()).with_expiration_millis(1713824559637),
             ),
             (
                 b"baz".to_vec(),
-                ValueType::new_from_seconds(b"qux".to_vec(), 1714089298),
+                ValueType::new(b"qux".to_vec()).with_expiration_millis(1714089298 * 1000),
             ),
         ]
         .iter()
         .cloned()
-        .collect::<HashMap<_, _>>();
-        assert_eq!(actual.unwrap(), RdbValue::Database(expected));
+        .collect::<Database>();
+        assert_eq!(
+            actual.unwrap(),
+            RdbValue::Database {
+                index: 0,
+                contents: expected
+            }
+        );
+    }
+
+    #[test]
+    fn read_list() {
+        #[rustfmt::skip]
+        let input = [
+            // Header
+            0x00, 0xfb, 0x01, 0x00,
+            // list -> [a, bc]
+            0x01, 0x04, 0x6C, 0x69, 0x73, 0x74, 0x02, 0x01, 0x61, 0x02, 0x62, 0x63,
+        ];
+        let mut reader = RdbReader::new(&input[..]);
+
+        let actual = reader.read_database();
+        assert!(
+            actual.is_ok(),
+            "Expected successful database read, got {}",
+            actual.unwrap_err()
+        );
+        let expected = Database::from([(
+            b"list".to_vec(),
+            ValueType::from(Value::List([b"a".to_vec(), b"bc".to_vec()].into())),
+        )]);
+        assert_eq!(
+            actual.unwrap(),
+            RdbValue::Database {
+                index: 0,
+                contents: expected
+            }
+        );
+    }
+
+    #[test]
+    fn read_hash_with_field_expirations() {
+        #[rustfmt::skip]
+        let input = [
+            // Header
+            0x00, 0xfb, 0x01, 0x00,
+            // h -> {a: 1, b: 2 expiring at 1000ms}
+            0x18, 0x01, 0x68, 0xe8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+            0x00, 0x01, 0x61, 0x01, 0x31,
+            0x01, 0x01, 0x62, 0x01, 0x32,
+        ];
+        let mut reader = RdbReader::new(&input[..]);
+
+        let actual = reader.read_database();
+        assert!(
+            actual.is_ok(),
+            "Expected successful database read, got {}",
+            actual.unwrap_err()
+        );
+        let expected = Database::from([(
+            b"h".to_vec(),
+            ValueType::from(Value::Hash(
+                [
+                    (b"a".to_vec(), HashField::new(b"1".to_vec())),
+                    (
+                        b"b".to_vec(),
+                        HashField {
+                            value: b"2".to_vec(),
+                            expiration: Some(UNIX_EPOCH + Duration::from_millis(1000)),
+                        },
+                    ),
+                ]
+                .into(),
+            )),
+        )]);
+        assert_eq!(
+            actual.unwrap(),
+            RdbValue::Database {
+                index: 0,
+                contents: expected
+            }
+        );
     }
 }
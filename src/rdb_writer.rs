@@ -0,0 +1,741 @@
+/// A writer for RDB files.
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crc64::crc64;
+use crate::errors::RdbFileError;
+use crate::listpack::{self, ListpackEntry};
+use crate::lzf;
+use crate::redis_handler::{Database, Value};
+use crate::streams::{Fields, Stream, StreamId};
+
+const RDB_VERSION: &[u8; 4] = b"0011";
+
+// The Redis version we report in the aux fields.
+const REDIS_VERSION: &[u8] = b"7.2.0";
+
+// The versions for files holding hash field expirations, which Redis 7.4 added with RDB
+// version 12.  Other files keep the older versions, so that older Redis can load them.
+const RDB_VERSION_FIELD_EXPIRATIONS: &[u8; 4] = b"0012";
+const REDIS_VERSION_FIELD_EXPIRATIONS: &[u8] = b"7.4.0";
+
+// Like Redis, only try to compress strings longer than this.
+const MIN_COMPRESS_LEN: usize = 20;
+
+// The most entries Redis puts in each listpack of a stream.
+pub(crate) const STREAM_NODE_MAX_ENTRIES: usize = 100;
+
+// Marks a stream entry with the same fields as its node's master entry, so only the
+// values are stored.
+const STREAM_ITEM_SAME_FIELDS: i64 = 2;
+
+pub(crate) struct RdbWriter<W> {
+    writer: W,
+    // Checksum of everything written so far.
+    checksum: u64,
+    // For snapshots sent to replicas, the database the replication stream has selected.
+    stream_db: Option<usize>,
+}
+
+impl<W> RdbWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        RdbWriter {
+            writer,
+            checksum: 0,
+            stream_db: None,
+        }
+    }
+
+    /// Records which database the replication stream following this snapshot applies to.
+    pub(crate) fn with_stream_db(mut self, db: usize) -> Self {
+        self.stream_db = Some(db);
+        self
+    }
+}
+
+/// Saves the database to the RDB file at path.  The snapshot is written to a temporary
+/// file first, so that the file at path is always complete.
+pub(crate) fn save_to_file(path: &Path, databases: &[Database]) -> Result<(), RdbFileError> {
+    let temp_path = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    save_via(path, &temp_path, databases, || Some(()))?;
+    Ok(())
+}
+
+/// Saves the database to the RDB file at path as a background save, which may have been
+/// overtaken by a later save.  Once the snapshot is written, lock is called, and the
+/// file is only replaced if it returns a guard, which is held until it has been.
+/// Returns whether the file was replaced.
+pub(crate) fn save_to_file_if_current<G>(
+    path: &Path,
+    databases: &[Database],
+    lock: impl FnOnce() -> Option<G>,
+) -> Result<bool, RdbFileError> {
+    // A different temporary file, since a foreground save may run at the same time.
+    let temp_path = path.with_file_name(format!("temp-bgsave-{}.rdb", std::process::id()));
+    save_via(path, &temp_path, databases, lock)
+}
+
+fn save_via<G>(
+    path: &Path,
+    temp_path: &Path,
+    databases: &[Database],
+    lock: impl FnOnce() -> Option<G>,
+) -> Result<bool, RdbFileError> {
+    let result = (|| {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(temp_path)?);
+        RdbWriter::new(&mut file).write_contents(databases)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        let Some(_guard) = lock() else {
+            return Ok(false);
+        };
+        std::fs::rename(temp_path, path)?;
+        Ok(true)
+    })();
+    if !matches!(result, Ok(true)) {
+        let _ = std::fs::remove_file(temp_path);
+    }
+    result
+}
+
+impl<W> RdbWriter<W>
+where
+    W: Write,
+{
+    /// Writes a complete RDB file containing the provided databases, numbered by their
+    /// position.
+    pub(crate) fn write_contents(&mut self, databases: &[Database]) -> Result<(), RdbFileError> {
+        let field_expirations = databases
+            .iter()
+            .flat_map(|data| data.values())
+            .any(|value| match value.value() {
+                Value::Hash(hash) => hash.values().any(|field| field.expiration.is_some()),
+                _ => false,
+            });
+        let (rdb_version, redis_version) = if field_expirations {
+            (
+                RDB_VERSION_FIELD_EXPIRATIONS,
+                REDIS_VERSION_FIELD_EXPIRATIONS,
+            )
+        } else {
+            (RDB_VERSION, REDIS_VERSION)
+        };
+        self.write_header(rdb_version)?;
+        self.write_aux_fields(databases, redis_version)?;
+        for (index, data) in databases.iter().enumerate() {
+            // Redis omits empty databases entirely.
+            if !data.is_empty() {
+                self.write_database(index, data)?;
+            }
+        }
+        self.write_end_of_file()
+    }
+
+    fn write_header(&mut self, rdb_version: &[u8; 4]) -> Result<(), RdbFileError> {
+        self.write_bytes(b"REDIS")?;
+        self.write_bytes(rdb_version)
+    }
+
+    // Writes the metadata Redis records about the server that created the file.
+    fn write_aux_fields(
+        &mut self,
+        databases: &[Database],
+        redis_version: &[u8],
+    ) -> Result<(), RdbFileError> {
+        let ctime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        // An estimate, since we don't track the memory we actually use.
+        let used_mem: usize = databases
+            .iter()
+            .flatten()
+            .map(|(key, value)| key.len() + value_len(value.value()))
+            .sum();
+        self.write_aux_field(b"redis-ver", redis_version)?;
+        self.write_aux_field(b"redis-bits", b"64")?;
+        self.write_aux_field(b"ctime", ctime.to_string().as_bytes())?;
+        self.write_aux_field(b"used-mem", used_mem.to_string().as_bytes())?;
+        if let Some(db) = self.stream_db {
+            self.write_aux_field(b"repl-stream-db", db.to_string().as_bytes())?;
+        }
+        self.write_aux_field(b"aof-base", b"0")
+    }
+
+    fn write_aux_field(&mut self, key: &[u8], value: &[u8]) -> Result<(), RdbFileError> {
+        self.write_bytes(&[0xfa])?;
+        self.write_string(key)?;
+        self.write_string(value)
+    }
+
+    fn write_database(&mut self, database_idx: usize, data: &Database) -> Result<(), RdbFileError> {
+        self.write_bytes(&[0xfe])?;
+        self.write_size(database_idx)?;
+        self.write_bytes(&[0xfb])?;
+        self.write_size(data.len())?;
+        self.write_size(data.values().filter(|v| v.expiration().is_some()).count())?;
+        for (key, value) in data {
+            if let Some(expiration) = value.expiration() {
+                // Expiration in milliseconds, 8 bytes, unsigned, little endian.
+                self.write_bytes(&[0xfc])?;
+                self.write_bytes(&unix_millis(expiration).to_le_bytes())?;
+            }
+            match value.value() {
+                Value::String(contents) => {
+                    self.write_bytes(&[0x00])?;
+                    self.write_string(key)?;
+                    self.write_string(contents)?;
+                }
+                Value::List(list) => {
+                    self.write_bytes(&[0x01])?;
+                    self.write_string(key)?;
+                    self.write_size(list.len())?;
+                    for element in list {
+                        self.write_string(element)?;
+                    }
+                }
+                Value::Set(set) => {
+                    self.write_bytes(&[0x02])?;
+                    self.write_string(key)?;
+                    self.write_size(set.len())?;
+                    for member in set.keys() {
+                        self.write_string(member)?;
+                    }
+                }
+                Value::SortedSet(set) => {
+                    self.write_bytes(&[0x05])?;
+                    self.write_string(key)?;
+                    self.write_size(set.len())?;
+                    for (member, score) in set.iter() {
+                        self.write_string(member)?;
+                        // Scores are binary doubles, 8 bytes, little endian.
+                        self.write_bytes(&score.to_le_bytes())?;
+                    }
+                }
+                Value::Stream(stream) => {
+                    self.write_bytes(&[0x15])?;
+                    self.write_string(key)?;
+                    self.write_stream(stream)?;
+                }
+                Value::Hash(hash) => match hash.values().filter_map(|f| f.expiration).min() {
+                    None => {
+                        self.write_bytes(&[0x04])?;
+                        self.write_string(key)?;
+                        self.write_size(hash.len())?;
+                        for (field, entry) in hash {
+                            self.write_string(field)?;
+                            self.write_string(&entry.value)?;
+                        }
+                    }
+                    Some(min_expiration) => {
+                        // With field expirations, the earliest is written first, then each
+                        // field's relative to it, plus one so that zero can mean none.
+                        let min_millis = unix_millis(min_expiration);
+                        self.write_bytes(&[0x18])?;
+                        self.write_string(key)?;
+                        self.write_bytes(&min_millis.to_le_bytes())?;
+                        self.write_size(hash.len())?;
+                        for (field, entry) in hash {
+                            let ttl = entry
+                                .expiration
+                                .map_or(0, |expiration| unix_millis(expiration) - min_millis + 1);
+                            self.write_size(ttl as usize)?;
+                            self.write_string(field)?;
+                            self.write_string(&entry.value)?;
+                        }
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Writes a stream's entries as listpacks, each keyed by the ID of its first entry,
+    // then the stream's metadata.
+    fn write_stream(&mut self, stream: &Stream) -> Result<(), RdbFileError> {
+        let entries: Vec<_> = stream.entries.iter().collect();
+        let nodes = entries.chunks(STREAM_NODE_MAX_ENTRIES);
+        self.write_size(nodes.len())?;
+        for node in nodes {
+            let master_id = *node[0].0;
+            self.write_string(&raw_stream_id(master_id))?;
+            self.write_string(&listpack::encode(&stream_node(master_id, node)))?;
+        }
+        self.write_size(stream.entries.len())?;
+        self.write_stream_id(stream.last_id)?;
+        self.write_stream_id(stream.first_id())?;
+        self.write_stream_id(stream.max_deleted_id)?;
+        self.write_size(stream.entries_added as usize)?;
+        self.write_size(stream.groups.len())?;
+        for (name, group) in &stream.groups {
+            self.write_string(name)?;
+            self.write_stream_id(group.last_id)?;
+            // An unknown number of entries read is written as -1.
+            self.write_size(group.entries_read.unwrap_or(u64::MAX) as usize)?;
+            // The pending entries, each with the time it was delivered and how often.
+            self.write_size(group.pending.len())?;
+            for (id, entry) in &group.pending {
+                self.write_bytes(&raw_stream_id(*id))?;
+                self.write_bytes(&entry.delivery_time.to_le_bytes())?;
+                self.write_size(entry.delivery_count as usize)?;
+            }
+            // The consumers, each with the IDs of its pending entries.
+            self.write_size(group.consumers.len())?;
+            for (consumer_name, consumer) in &group.consumers {
+                self.write_string(consumer_name)?;
+                self.write_bytes(&consumer.seen_time.to_le_bytes())?;
+                let active_time = consumer.active_time.map_or(-1, |time| time as i64);
+                self.write_bytes(&active_time.to_le_bytes())?;
+                let pending: Vec<_> = group.pending_for(consumer_name).collect();
+                self.write_size(pending.len())?;
+                for (id, _) in pending {
+                    self.write_bytes(&raw_stream_id(*id))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_stream_id(&mut self, id: StreamId) -> Result<(), RdbFileError> {
+        self.write_size(id.ms as usize)?;
+        self.write_size(id.seq as usize)
+    }
+
+    fn write_end_of_file(&mut self) -> Result<(), RdbFileError> {
+        self.write_bytes(&[0xff])?;
+        // The checksum covers everything before it, including the EOF marker.
+        self.writer.write_all(&self.checksum.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn write_size(&mut self, size: usize) -> Result<(), RdbFileError> {
+        if size < 1 << 6 {
+            // Size fits in the 6 bits after the 00 marker.
+            self.write_bytes(&[size as u8])?;
+        } else if size < 1 << 14 {
+            // Size is the next 14 bits after the 01 marker.
+            self.write_bytes(&[0x40 | (size >> 8) as u8, (size & 0xff) as u8])?;
+        } else if size <= u32::MAX as usize {
+            // Size is the next 4 bytes, big endian, after the 10 marker.
+            self.write_bytes(&[0x80])?;
+            self.write_bytes(&(size as u32).to_be_bytes())?;
+        } else {
+            // Size is the next 8 bytes, big endian.
+            self.write_bytes(&[0x81])?;
+            self.write_bytes(&(size as u64).to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_string(&mut self, value: &[u8]) -> Result<(), RdbFileError> {
+        if value.len() > MIN_COMPRESS_LEN {
+            let compressed = lzf::compress(value);
+            // Only worth it if it saves more than the extra length field costs.
+            if compressed.len() + 4 < value.len() {
+                self.write_bytes(&[0xc3])?;
+                self.write_size(compressed.len())?;
+                self.write_size(value.len())?;
+                return self.write_bytes(&compressed);
+            }
+        }
+        self.write_size(value.len())?;
+        self.write_bytes(value)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), RdbFileError> {
+        self.writer.write_all(bytes)?;
+        self.checksum = crc64(self.checksum, bytes);
+        Ok(())
+    }
+}
+
+// The number of bytes of data in a value.
+fn value_len(value: &Value) -> usize {
+    match value {
+        Value::String(contents) => contents.len(),
+        Value::List(list) => list.iter().map(Vec::len).sum(),
+        Value::Set(set) => set.keys().map(Vec::len).sum(),
+        Value::SortedSet(set) => set.iter().map(|(member, _)| member.len() + 8).sum(),
+        Value::Hash(hash) => hash
+            .iter()
+            .map(|(field, entry)| field.len() + entry.value.len())
+            .sum(),
+        Value::Stream(stream) => stream
+            .entries
+            .values()
+            .flatten()
+            .map(|(field, value)| field.len() + value.len())
+            .sum(),
+    }
+}
+
+// A stream ID as 16 bytes, the time then the sequence number, both big endian, so that
+// IDs sort as their bytes do.
+fn raw_stream_id(id: StreamId) -> [u8; 16] {
+    let mut raw = [0; 16];
+    raw[..8].copy_from_slice(&id.ms.to_be_bytes());
+    raw[8..].copy_from_slice(&id.seq.to_be_bytes());
+    raw
+}
+
+// The elements of a listpack holding stream entries.  A master entry comes first, with
+// the number of entries, the number deleted, and the first entry's fields.  Then each
+// entry has its flags, its ID relative to the master entry's, its fields and values, or
+// just the values if the fields match the master entry's, and its number of elements.
+fn stream_node(master_id: StreamId, entries: &[(&StreamId, &Fields)]) -> Vec<ListpackEntry> {
+    let master_fields: Vec<&[u8]> = entries[0].1.iter().map(|(f, _)| f.as_slice()).collect();
+    let mut node = vec![
+        ListpackEntry::Integer(entries.len() as i64),
+        ListpackEntry::Integer(0),
+        ListpackEntry::Integer(master_fields.len() as i64),
+    ];
+    node.extend(
+        master_fields
+            .iter()
+            .map(|field| ListpackEntry::String(field.to_vec())),
+    );
+    node.push(ListpackEntry::Integer(0));
+    for (id, fields) in entries {
+        let same_fields = fields.len() == master_fields.len()
+            && fields
+                .iter()
+                .zip(&master_fields)
+                .all(|((field, _), master_field)| field == master_field);
+        let flags = if same_fields {
+            STREAM_ITEM_SAME_FIELDS
+        } else {
+            0
+        };
+        node.push(ListpackEntry::Integer(flags));
+        node.push(ListpackEntry::Integer((id.ms - master_id.ms) as i64));
+        node.push(ListpackEntry::Integer(
+            id.seq.wrapping_sub(master_id.seq) as i64
+        ));
+        let mut elements = 3 + fields.len();
+        if !same_fields {
+            node.push(ListpackEntry::Integer(fields.len() as i64));
+            elements += fields.len() + 1;
+        }
+        for (field, value) in fields.iter() {
+            if !same_fields {
+                node.push(ListpackEntry::String(field.clone()));
+            }
+            node.push(ListpackEntry::String(value.clone()));
+        }
+        node.push(ListpackEntry::Integer(elements as i64));
+    }
+    node
+}
+
+// Milliseconds since the Unix epoch, as expirations are stored.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::hashes::{Hash, HashField};
+    use crate::rdb_parser::RdbReader;
+    use crate::redis_handler::ValueType;
+    use crate::streams::{Consumer, ConsumerGroup, PendingEntry};
+
+    #[test]
+    fn write_size() {
+        let mut buffer = Vec::new();
+        let mut writer = RdbWriter::new(&mut buffer);
+        writer.write_size(10).unwrap();
+        writer.write_size(700).unwrap();
+        writer.write_size(17000).unwrap();
+
+        assert_eq!(buffer, vec![0x0a, 0x42, 0xbc, 0x80, 0x00, 0x00, 0x42, 0x68]);
+    }
+
+    #[test]
+    fn write_aux_field() {
+        let mut buffer = Vec::new();
+        let mut writer = RdbWriter::new(&mut buffer);
+        writer.write_aux_field(b"redis-ver", b"6.0.16").unwrap();
+
+        let expected = [
+            0xFA, 0x09, 0x72, 0x65, 0x64, 0x69, 0x73, 0x2D, 0x76, 0x65, 0x72, 0x06, 0x36, 0x2E,
+            0x30, 0x2E, 0x31, 0x36,
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn write_compressed_string() {
+        let mut buffer = Vec::new();
+        let mut writer = RdbWriter::new(&mut buffer);
+        writer.write_string(&[b'x'; 100]).unwrap();
+
+        // Compressed length, then the uncompressed length.
+        assert_eq!(buffer[0], 0xc3);
+        assert_eq!(buffer[1] as usize, buffer.len() - 4);
+        assert_eq!(buffer[2..4], [0x40, 100]);
+        assert_eq!(lzf::decompress(&buffer[4..], 100), Some(vec![b'x'; 100]));
+    }
+
+    #[test]
+    fn write_incompressible_string() {
+        let mut buffer = Vec::new();
+        let mut writer = RdbWriter::new(&mut buffer);
+        writer.write_string(b"0123456789abcdefghijklmnop").unwrap();
+
+        assert_eq!(buffer[0], 26);
+        assert_eq!(buffer[1..], *b"0123456789abcdefghijklmnop");
+    }
+
+    #[test]
+    fn write_empty_database() {
+        let mut buffer = Vec::new();
+        let result = RdbWriter::new(&mut buffer).write_contents(&[Database::new()]);
+        assert!(
+            result.is_ok(),
+            "Expected successful write, got {:?}",
+            result.unwrap_err()
+        );
+
+        assert_eq!(buffer[..9], *b"REDIS0011");
+        // Only aux fields between the header and the end of file.
+        assert_eq!(buffer[9], 0xfa);
+        assert_eq!(buffer[buffer.len() - 9], 0xff);
+        assert_eq!(
+            RdbReader::new(&buffer[..]).read_contents().unwrap(),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn writes_version_with_hash_field_expirations_only_when_needed() {
+        let write = |expiration| {
+            let hash = Hash::from([(
+                b"field".to_vec(),
+                HashField {
+                    value: b"value".to_vec(),
+                    expiration,
+                },
+            )]);
+            let mut buffer = Vec::new();
+            RdbWriter::new(&mut buffer)
+                .write_contents(&[Database::from([(
+                    b"hash".to_vec(),
+                    ValueType::from(Value::Hash(hash)),
+                )])])
+                .unwrap();
+            buffer
+        };
+        let contains = |buffer: &[u8], part: &[u8]| buffer.windows(part.len()).any(|w| w == part);
+
+        let buffer = write(None);
+        assert_eq!(buffer[..9], *b"REDIS0011");
+        assert!(contains(&buffer, b"7.2.0"));
+        let buffer = write(Some(SystemTime::now() + Duration::from_secs(100)));
+        assert_eq!(buffer[..9], *b"REDIS0012");
+        assert!(contains(&buffer, b"7.4.0"));
+    }
+
+    #[test]
+    fn write_checksum() {
+        let mut buffer = Vec::new();
+        RdbWriter::new(&mut buffer)
+            .write_contents(&[Database::from([(
+                b"foo".to_vec(),
+                ValueType::new(b"bar".to_vec()),
+            )])])
+            .unwrap();
+
+        let (contents, checksum) = buffer.split_at(buffer.len() - 8);
+        assert_eq!(
+            u64::from_le_bytes(checksum.try_into().unwrap()),
+            crc64(0, contents)
+        );
+    }
+
+    #[test]
+    fn round_trips_database() {
+        let data = [
+            (b"foobar".to_vec(), ValueType::new(b"bazqux".to_vec())),
+            (
+                b"foo".to_vec(),
+                ValueType::new(b"bar".to_vec()).with_expiration_millis(1713824559637),
+            ),
+            (b"long".to_vec(), ValueType::new(vec![b'x'; 20000])),
+            (
+                b"list".to_vec(),
+                ValueType::from(Value::List([b"a".to_vec(), vec![b'y'; 100]].into())),
+            ),
+            (
+                b"set".to_vec(),
+                ValueType::from(Value::Set([b"a".to_vec(), b"b".to_vec()].into())),
+            ),
+            (
+                b"zset".to_vec(),
+                ValueType::from(Value::SortedSet(
+                    [(b"a".to_vec(), 1.5), (b"b".to_vec(), f64::NEG_INFINITY)]
+                        .into_iter()
+                        .collect(),
+                )),
+            ),
+            (
+                b"hash".to_vec(),
+                ValueType::from(Value::Hash(Hash::from([(
+                    b"field".to_vec(),
+                    HashField::new(b"value".to_vec()),
+                )]))),
+            ),
+            (
+                b"expiring hash".to_vec(),
+                ValueType::from(Value::Hash(Hash::from([
+                    (b"a".to_vec(), HashField::new(b"1".to_vec())),
+                    (
+                        b"b".to_vec(),
+                        HashField {
+                            value: b"2".to_vec(),
+                            expiration: Some(UNIX_EPOCH + Duration::from_millis(1713824559637)),
+                        },
+                    ),
+                    (
+                        b"c".to_vec(),
+                        HashField {
+                            value: b"3".to_vec(),
+                            expiration: Some(UNIX_EPOCH + Duration::from_millis(1713824600000)),
+                        },
+                    ),
+                ]))),
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect::<Database>();
+        let databases = vec![Database::new(), data];
+
+        let mut buffer = Vec::new();
+        let result = RdbWriter::new(&mut buffer).write_contents(&databases);
+        assert!(
+            result.is_ok(),
+            "Expected successful write, got {:?}",
+            result.unwrap_err()
+        );
+        // Hash field expirations need the version which added them.
+        assert_eq!(buffer[..9], *b"REDIS0012");
+
+        let actual = RdbReader::new(&buffer[..]).read_contents();
+        assert!(
+            actual.is_ok(),
+            "Expected successful read, got {:?}",
+            actual.unwrap_err()
+        );
+        assert_eq!(actual.unwrap(), databases);
+    }
+
+    #[test]
+    fn round_trips_stream() {
+        let mut stream = Stream::default();
+        for i in 0..250u64 {
+            // Some entries share the first entry's fields, and some have their own.
+            let fields = if i % 3 == 0 {
+                vec![(b"other".to_vec(), vec![b'x'; i as usize])]
+            } else {
+                vec![
+                    (b"name".to_vec(), format!("item {}", i).into_bytes()),
+                    (b"count".to_vec(), i.to_string().into_bytes()),
+                ]
+            };
+            stream
+                .entries
+                .insert(StreamId::new(1713824559637 + i / 2, i % 2), fields);
+        }
+        stream.entries.remove(&StreamId::new(1713824559637, 0));
+        stream.last_id = StreamId::new(1713824559800, 7);
+        stream.max_deleted_id = StreamId::new(1713824559637, 0);
+        stream.entries_added = 260;
+        let pending = |consumer: &[u8], delivery_count| PendingEntry {
+            consumer: consumer.to_vec(),
+            delivery_time: 1713824560000,
+            delivery_count,
+        };
+        let group = ConsumerGroup {
+            last_id: StreamId::new(1713824559640, 1),
+            entries_read: Some(18),
+            pending: [
+                (StreamId::new(1713824559637, 1), pending(b"alice", 3)),
+                (StreamId::new(1713824559640, 1), pending(b"bob", 1)),
+            ]
+            .into(),
+            consumers: [
+                (
+                    b"alice".to_vec(),
+                    Consumer {
+                        seen_time: 1713824561000,
+                        active_time: Some(1713824560000),
+                    },
+                ),
+                (
+                    b"bob".to_vec(),
+                    Consumer {
+                        seen_time: 1713824562000,
+                        active_time: None,
+                    },
+                ),
+            ]
+            .into(),
+        };
+        stream.groups.insert(b"readers".to_vec(), group);
+        stream
+            .groups
+            .insert(b"unread".to_vec(), ConsumerGroup::default());
+        let databases = vec![Database::from([(
+            b"stream".to_vec(),
+            ValueType::from(Value::Stream(stream)),
+        )])];
+
+        let mut buffer = Vec::new();
+        RdbWriter::new(&mut buffer)
+            .write_contents(&databases)
+            .unwrap();
+        assert_eq!(
+            RdbReader::new(&buffer[..]).read_contents().unwrap(),
+            databases
+        );
+    }
+
+    #[test]
+    fn write_stream_db() {
+        let mut buffer = Vec::new();
+        RdbWriter::new(&mut buffer)
+            .with_stream_db(3)
+            .write_contents(&[])
+            .unwrap();
+
+        let mut reader = RdbReader::new(&buffer[..]);
+        reader.read_contents().unwrap();
+        assert_eq!(reader.aux_field(b"repl-stream-db"), Some(&b"3"[..]));
+    }
+
+    #[test]
+    fn save_to_file_replaces_contents() {
+        let dir = std::env::temp_dir().join(format!("rdb_writer_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.rdb");
+        std::fs::write(&path, b"not an rdb file").unwrap();
+        let data = vec![Database::from([(
+            b"foo".to_vec(),
+            ValueType::new(b"bar".to_vec()),
+        )])];
+
+        let result = save_to_file(&path, &data);
+        assert!(
+            result.is_ok(),
+            "Expected successful save, got {:?}",
+            result.unwrap_err()
+        );
+
+        let input = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(RdbReader::new(&input[..]).read_contents().unwrap(), data);
+    }
+}
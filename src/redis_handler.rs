@@ -5,37 +5,180 @@
 // idea, but follows the actual Redis model, which uses a single thread
 // to avoid locking overheads.
 
-use std::cell::RefCell;
-use std::collections::HashMap;
+use bytes::{Buf, Bytes, BytesMut};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::time::Instant;
 
-use crate::errors::RedisError;
+use crate::blocking::{BlockedAction, BlockedClient, BlockedClients};
+use crate::dict::{self, Dict};
+use crate::errors::{RdbFileError, RedisError};
+use crate::hashes::{self, Hash};
+use crate::keys;
+use crate::lists::{self, List};
 use crate::rdb_parser::RdbReader;
-use crate::resp_command::{parse_commands, RedisRequest};
-use crate::resp_parser::RespValue;
+use crate::rdb_writer::{
+    save_to_file, save_to_file_if_current, RdbWriter, STREAM_NODE_MAX_ENTRIES,
+};
+use crate::replication::{new_replication_id, MasterLink, PsyncReply, ReplicationBacklog};
+use crate::resp_command::{
+    parse_command, parse_commands, ListEnd, PendingRange, RedisRequest, ReplConf, SetOptions,
+    StreamTrim, TrimThreshold,
+};
+use crate::resp_parser::{RespParser, RespValue};
+use crate::sets::{self, Set};
+use crate::sorted_sets::{self, ScoredMembers, SortedSet, ZAddReply};
+use crate::streams::{self, ConsumerGroup, Fields, GroupChanges, Stream, StreamId};
+use crate::strings;
+
+// How long a replica waits before reconnecting to its master after the link drops.
+const MASTER_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// Placeholder for an unset replication id.
+const NO_REPLID: &str = "0000000000000000000000000000000000000000";
+
+// Size of the replication backlog if not set in the config.
+const DEFAULT_REPL_BACKLOG_SIZE: usize = 1024 * 1024;
+
+// Number of databases if not set in the config.
+const DEFAULT_DATABASES: usize = 16;
+
+// How much room to make for each read from a client.
+const INPUT_BUFFER_SIZE: usize = 4096;
+
+// The most input a client may send ahead of what has been handled, as Redis's default
+// client-query-buffer-limit.  Clients which send more are disconnected.
+const MAX_QUERY_BUFFER_LEN: usize = 1024 * 1024 * 1024;
+
+// Values which take more than this many allocations to free are freed on another thread
+// by UNLINK.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+// How often the active expiration cycle runs, and how long each run may take.
+const ACTIVE_EXPIRE_PERIOD: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_TIME_LIMIT: Duration = Duration::from_millis(25);
 
 // The data store for Redis.
 #[derive(Debug)]
 pub(crate) struct RedisHandler {
-    data: RefCell<HashMap<Vec<u8>, ValueType>>,
-    replication_info: RedisReplicationInfo,
+    // The independent keyspaces clients can SELECT.
+    databases: RefCell<Vec<Database>>,
+    replication_info: RefCell<RedisReplicationInfo>,
     config: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+    replicas: RefCell<Vec<ReplicaConnection>>,
+    next_replica_id: Cell<u64>,
+    // Created once the server first has a replica or becomes one.
+    backlog: RefCell<Option<ReplicationBacklog>>,
+    // The database the replication stream last selected, if known.
+    stream_db: Cell<Option<usize>>,
+    // Notified whenever a replica acknowledges an offset.
+    replica_acks: Notify,
+    // Notified when REPLICAOF changes which master, if any, we replicate from.
+    master_changed: Notify,
+    // Shared with background saves, which run on another thread.
+    save_state: Arc<SaveState>,
+    // Clients waiting in blocking commands such as BLPOP.
+    blocked: RefCell<BlockedClients>,
+    // How many keys have been removed because they expired, for INFO, not counting those
+    // the databases have counted since it was last brought up to date.
+    expired_keys: Cell<u64>,
+}
+
+// The status of saving the dataset to disk.
+#[derive(Debug)]
+struct SaveState {
+    // Unix time in seconds of the last successful save.
+    last_save: AtomicU64,
+    bgsave_in_progress: AtomicBool,
+    // Advanced by FLUSHALL, so that a background save of the data from before can tell
+    // it mustn't replace the file.  Held while a background save replaces the file.
+    generation: Mutex<u64>,
+}
+
+// A replica connected to this server, which is sent the replication stream.
+#[derive(Debug)]
+struct ReplicaConnection {
+    id: u64,
+    ip: String,
+    listening_port: u16,
+    // The replication offset the replica last reported processing.
+    ack_offset: u64,
+    sender: mpsc::UnboundedSender<Bytes>,
+}
+
+// State tracked for each client connection.
+#[derive(Debug, Default)]
+struct ConnectionState {
+    peer_ip: String,
+    // The port a replica announced with REPLCONF listening-port.
+    replica_listening_port: Option<u16>,
+    // The database selected with SELECT.
+    db: usize,
+    // Set once PSYNC has turned the connection into a replica link.
+    replica: Option<(u64, mpsc::UnboundedReceiver<Bytes>)>,
+    // Set when a blocking command has to wait for a key.
+    blocked: Option<BlockedWait>,
+}
+
+// A client waiting to be served by a write to one of its keys.
+#[derive(Debug)]
+struct BlockedWait {
+    id: u64,
+    receiver: oneshot::Receiver<Vec<u8>>,
+    // None to wait forever.
+    deadline: Option<Instant>,
+}
+
+/// A keyspace holding the values of each key, which it dereferences to.  Keys expire
+/// wherever they are looked up, so it counts those it finds expired for the handler to
+/// collect.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Database {
+    keys: Dict<ValueType>,
+    // The keys which have an expiration, as in Redis, so that the active expiration
+    // cycle can sample them without wading through keys which never expire.
+    expires: Dict<()>,
+    expired: u64,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) struct ValueType {
-    value: Vec<u8>,
+    value: Value,
     expiration: Option<SystemTime>,
 }
 
+/// The value of a key, tagged with its type.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    String(Vec<u8>),
+    List(List),
+    Hash(Hash),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
 #[derive(Debug)]
 pub(crate) struct RedisReplicationInfo {
     pub(crate) role: RedisRole,
     pub(crate) connected_slaves: u16,
     pub(crate) master_replid: String,
-    pub(crate) master_repl_offset: u32,
+    pub(crate) master_repl_offset: u64,
+    // The previous replication id and the first offset it is not valid for, which let
+    // replicas of our previous master continue after a failover.
+    pub(crate) master_replid2: String,
+    pub(crate) second_repl_offset: i64,
+    // Only meaningful when role is Slave.
+    pub(crate) master_host: String,
+    pub(crate) master_port: u16,
+    pub(crate) master_link_up: bool,
 }
 
 #[derive(Debug)]
@@ -47,22 +190,40 @@ pub(crate) enum RedisRole {
 impl RedisHandler {
     pub(crate) fn new() -> Self {
         RedisHandler {
-            data: RefCell::new(HashMap::new()),
-            replication_info: RedisReplicationInfo::default(),
+            databases: RefCell::new(vec![Database::new(); DEFAULT_DATABASES]),
+            replication_info: RefCell::new(RedisReplicationInfo::default()),
             config: RefCell::new(HashMap::new()),
+            replicas: RefCell::new(Vec::new()),
+            next_replica_id: Cell::new(0),
+            backlog: RefCell::new(None),
+            stream_db: Cell::new(None),
+            replica_acks: Notify::new(),
+            master_changed: Notify::new(),
+            save_state: Arc::new(SaveState::new()),
+            blocked: RefCell::new(BlockedClients::default()),
+            expired_keys: Cell::new(0),
         }
     }
 
     pub(crate) fn new_with_contents(
         config: HashMap<Vec<u8>, Vec<u8>>,
         replication_info: RedisReplicationInfo,
-        data: HashMap<Vec<u8>, ValueType>,
-    ) -> Self {
-        RedisHandler {
-            data: RefCell::new(data),
-            replication_info: replication_info,
+        databases: Vec<Database>,
+    ) -> Result<Self, RedisError> {
+        Ok(RedisHandler {
+            databases: RefCell::new(with_configured_databases(&config, databases)?),
+            replication_info: RefCell::new(replication_info),
             config: RefCell::new(config),
-        }
+            replicas: RefCell::new(Vec::new()),
+            next_replica_id: Cell::new(0),
+            backlog: RefCell::new(None),
+            stream_db: Cell::new(None),
+            replica_acks: Notify::new(),
+            master_changed: Notify::new(),
+            save_state: Arc::new(SaveState::new()),
+            blocked: RefCell::new(BlockedClients::default()),
+            expired_keys: Cell::new(0),
+        })
     }
 
     pub(crate) fn new_from_file(
@@ -71,10 +232,20 @@ impl RedisHandler {
         config: HashMap<Vec<u8>, Vec<u8>>,
     ) -> Result<Self, RedisError> {
         let input = std::fs::read(path)?;
+        let databases = RdbReader::new(&input[..]).read_contents()?;
         Ok(RedisHandler {
-            data: RefCell::new(RdbReader::new(&input[..]).read_contents()?),
-            replication_info: replication_info,
+            databases: RefCell::new(with_configured_databases(&config, databases)?),
+            replication_info: RefCell::new(replication_info),
             config: RefCell::new(config),
+            replicas: RefCell::new(Vec::new()),
+            next_replica_id: Cell::new(0),
+            backlog: RefCell::new(None),
+            stream_db: Cell::new(None),
+            replica_acks: Notify::new(),
+            master_changed: Notify::new(),
+            save_state: Arc::new(SaveState::new()),
+            blocked: RefCell::new(BlockedClients::default()),
+            expired_keys: Cell::new(0),
         })
     }
 
@@ -86,200 +257,3280 @@ impl RedisHandler {
         &self,
         stream: &mut TcpStream,
     ) -> Result<(), RedisError> {
-        // Use a vec to avoid having a large stack state in the state machine.
-        let mut input_buf = vec![0u8; 512];
+        let mut connection = ConnectionState {
+            peer_ip: stream
+                .peer_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_default(),
+            ..ConnectionState::default()
+        };
+        let mut parser = RespParser::new();
+        // Holds input until it forms complete commands, which may span many reads.
+        let mut input_buf = BytesMut::with_capacity(INPUT_BUFFER_SIZE);
         loop {
-            let bytes_read = stream.read(&mut input_buf).await?;
-            if bytes_read == 0 {
+            if input_buf.capacity() - input_buf.len() < INPUT_BUFFER_SIZE {
+                input_buf.reserve(INPUT_BUFFER_SIZE);
+            }
+            if stream.read_buf(&mut input_buf).await? == 0 {
                 break;
             }
-            let requests = match parse_commands(&input_buf[0..bytes_read]) {
-                Ok(requests) => requests,
-                Err(error) => {
-                    // There's not much we can do if writing the error fails.
-                    let _ = RespValue::SimpleError(format!("{:?}", error).as_bytes())
+            loop {
+                let (value, len) = match parser.parse_frame(&input_buf) {
+                    Ok(Some(parsed)) => parsed,
+                    Ok(None) => break,
+                    Err(error) => {
+                        // The rest of the input can't be framed, so give up on the connection.
+                        // There's not much we can do if writing the error fails.
+                        let _ = RespValue::SimpleError(
+                            RedisError::from(error).reply_message().as_bytes(),
+                        )
+                        .write_async(stream)
+                        .await;
+                        return Ok(());
+                    }
+                };
+                // The raw command is kept so that writes can be propagated to replicas.
+                let command = &input_buf[..len];
+
+                let result = match parse_command(value) {
+                    Ok(request) if request.is_write() => {
+                        self.handle_write(request, command, stream, &mut connection)
+                            .await
+                    }
+                    Ok(request) => self.handle_request(request, stream, &mut connection).await,
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = result {
+                    let _ = RespValue::SimpleError(error.reply_message().as_bytes())
                         .write_async(stream)
                         .await;
-                    continue;
                 }
-            };
+                input_buf.advance(len);
 
-            for request in requests {
-                match self.handle_request(request, stream).await {
-                    Ok(()) => (),
-                    Err(error) => {
-                        let _ = RespValue::SimpleError(format!("{:?}", error).as_bytes())
-                            .write_async(stream)
-                            .await;
+                if let Some((id, receiver)) = connection.replica.take() {
+                    return self.serve_replica(stream, id, receiver, input_buf).await;
+                }
+                if let Some(wait) = connection.blocked.take() {
+                    if !self.wait_until_served(stream, &mut input_buf, wait).await? {
+                        return Ok(());
                     }
                 }
             }
+            if input_buf.len() > MAX_QUERY_BUFFER_LEN {
+                let _ = RespValue::SimpleError(b"ERR Protocol error: query buffer limit reached")
+                    .write_async(stream)
+                    .await;
+                return Ok(());
+            }
         }
         Ok(())
     }
 
-    // Handles a single request, writing the result to the provided stream.
-    async unsafe fn handle_request<'a>(
+    // Handles a write given as the parsed request and its raw command.  The write is
+    // propagated before the reply is sent, so that replicas get writes in the order they
+    // were made here, whichever client is slower to take its reply, and get them even if
+    // the client has gone.
+    async unsafe fn handle_write<W>(
+        &self,
+        request: RedisRequest<'_>,
+        command: &[u8],
+        stream: &mut W,
+        connection: &mut ConnectionState,
+    ) -> Result<(), RedisError>
+    where
+        W: tokio::io::AsyncWriteExt + Unpin,
+    {
+        let mut reply = Vec::new();
+        self.handle_request(request, &mut reply, connection).await?;
+        self.propagate_write(connection.db, command);
+        self.serve_blocked_clients();
+        stream.write_all(&reply).await?;
+        Ok(())
+    }
+
+    // Waits until a blocked client is served or times out, and sends it the reply.  Input
+    // that arrives meanwhile is kept for once the client is unblocked.  Returns false if
+    // the client disconnects while blocked, or is disconnected for sending too much.
+    async fn wait_until_served(
         &self,
-        request: RedisRequest<'a>,
         stream: &mut TcpStream,
-    ) -> Result<(), RedisError> {
-        match request {
-            RedisRequest::Ping => RespValue::SimpleString(b"PONG").write_async(stream).await?,
-            RedisRequest::Echo(contents) => {
-                RespValue::BulkString(contents).write_async(stream).await?
+        input_buf: &mut BytesMut,
+        wait: BlockedWait,
+    ) -> Result<bool, RedisError> {
+        let BlockedWait {
+            id,
+            mut receiver,
+            deadline,
+        } = wait;
+        let timeout = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
             }
-            RedisRequest::Set {
-                key,
-                value,
-                expiration,
-            } => {
-                self.data.borrow_mut().insert(
-                    key.to_vec(),
-                    ValueType {
-                        value: value.to_vec(),
-                        expiration,
-                    },
-                );
-                RespValue::SimpleString(b"OK").write_async(stream).await?;
+        };
+        tokio::pin!(timeout);
+        let reply = loop {
+            if input_buf.capacity() - input_buf.len() < INPUT_BUFFER_SIZE {
+                input_buf.reserve(INPUT_BUFFER_SIZE);
             }
-            RedisRequest::Get(key) => {
-                // We have to make a copy of the value, because while we are paused on the await, another
-                // future may overwrite the value for this key and invalidate the reference.
-                let value_copy = self.data.borrow().get(key).map(|v| v.to_owned());
-                match value_copy {
-                    Some(value) if value.is_expired() => {
-                        self.data.borrow_mut().remove(key);
-                        RespValue::NullBulkString.write_async(stream).await?
+            tokio::select! {
+                // A reply sent just as the timeout expires still counts.
+                biased;
+                reply = &mut receiver => break reply.ok(),
+                _ = &mut timeout => break None,
+                read = stream.read_buf(input_buf) => {
+                    if read? == 0 || input_buf.len() > MAX_QUERY_BUFFER_LEN {
+                        self.blocked.borrow_mut().unblock(id);
+                        return Ok(false);
                     }
-                    Some(ValueType { value, .. }) => {
-                        RespValue::BulkString(&value).write_async(stream).await?
+                }
+            }
+        };
+        match reply {
+            Some(reply) => stream.write_all(&reply).await?,
+            None => {
+                self.blocked.borrow_mut().unblock(id);
+                RespValue::NullArray.write_async(stream).await?
+            }
+        }
+        Ok(true)
+    }
+
+    // Blocks the client until one of the keys is ready for the action.
+    fn block_client(
+        &self,
+        connection: &mut ConnectionState,
+        keys: &[&[u8]],
+        action: BlockedAction,
+        timeout: Option<Duration>,
+    ) {
+        let (sender, receiver) = oneshot::channel();
+        let id = self.blocked.borrow_mut().block(BlockedClient {
+            db: connection.db,
+            keys: keys.iter().map(|key| key.to_vec()).collect(),
+            action,
+            sender,
+        });
+        connection.blocked = Some(BlockedWait {
+            id,
+            receiver,
+            // A deadline too far away to represent is as good as none.
+            deadline: timeout.and_then(|timeout| Instant::now().checked_add(timeout)),
+        });
+    }
+
+    // Serves the clients blocked on keys which are now ready for them, longest waiting
+    // first.
+    fn serve_blocked_clients(&self) {
+        // Serving one client can make a key ready for another, e.g. with BLMOVE, so repeat
+        // until nobody more can be served.
+        let mut served = true;
+        while served && !self.blocked.borrow().is_empty() {
+            served = false;
+            let waited_keys = self.blocked.borrow().waited_keys();
+            for (db, key) in waited_keys {
+                loop {
+                    let ready = |action: &BlockedAction| self.key_ready(db, &key, action);
+                    let Some((id, action)) =
+                        self.blocked.borrow_mut().first_waiting(db, &key, ready)
+                    else {
+                        break;
+                    };
+                    let reply = match self.apply_blocked_action(db, &[&key], &action) {
+                        Ok(Some(reply)) => reply,
+                        Ok(None) => break,
+                        Err(error) => {
+                            encode(&RespValue::SimpleError(error.reply_message().as_bytes()))
+                        }
+                    };
+                    if let Some(client) = self.blocked.borrow_mut().unblock(id) {
+                        let _ = client.sender.send(reply);
                     }
-                    None => RespValue::NullBulkString.write_async(stream).await?,
+                    served = true;
                 }
             }
-            RedisRequest::ConfigGet(params) => 'config_get: {
-                if params.is_empty() {
-                    RespValue::NullArray.write_async(stream).await?;
-                    break 'config_get;
+        }
+    }
+
+    // Whether the key holds a value the action can be applied to.  Clients stay blocked on
+    // keys of other types.
+    fn key_ready(&self, db: usize, key: &[u8], action: &BlockedAction) -> bool {
+        let db = &mut self.databases.borrow_mut()[db];
+        match action {
+            BlockedAction::Pop(_) | BlockedAction::Move { .. } => {
+                matches!(lists::len(db, key), Ok(len) if len > 0)
+            }
+            BlockedAction::ZPop { .. } => {
+                matches!(sorted_sets::len(db, key), Ok(len) if len > 0)
+            }
+            BlockedAction::XRead { after, .. } => after
+                .iter()
+                .filter(|(stream_key, _)| stream_key == key)
+                .any(|(_, id)| {
+                    matches!(
+                        streams::read_after(db, key, *id, Some(1)),
+                        Ok(entries) if !entries.is_empty()
+                    )
+                }),
+            // A group that no longer exists is ready too, so the client is told.
+            BlockedAction::XReadGroup { group, .. } => {
+                !matches!(streams::has_undelivered(db, key, group), Ok(false))
+            }
+        }
+    }
+
+    // Applies a blocking command's action to the first of the keys that is ready,
+    // returning the reply, or None if none of them do.  The equivalent non-blocking command
+    // is propagated, so that replicas never block.
+    fn apply_blocked_action(
+        &self,
+        db: usize,
+        keys: &[&[u8]],
+        action: &BlockedAction,
+    ) -> Result<Option<Vec<u8>>, RedisError> {
+        match action {
+            BlockedAction::Pop(end) => {
+                for key in keys {
+                    let popped = lists::pop(&mut self.databases.borrow_mut()[db], key, 1, *end)?;
+                    if let Some(popped) = popped {
+                        let command = match end {
+                            ListEnd::Left => &b"LPOP"[..],
+                            ListEnd::Right => &b"RPOP"[..],
+                        };
+                        self.propagate_command(db, &[command, key]);
+                        let element = RespValue::BulkString(&popped[0]);
+                        return Ok(Some(encode(&RespValue::Array(vec![
+                            RespValue::BulkString(key),
+                            element,
+                        ]))));
+                    }
                 }
-                // We need to make a copy of all the responses for the await point.
-                let mut values = Vec::with_capacity(2 * params.len());
-                {
-                    let config = self.config.borrow();
-                    for param in params {
-                        if let Some(value) = config.get(param) {
-                            values.push(param.to_owned());
-                            values.push(value.to_owned());
-                        }
+                Ok(None)
+            }
+            BlockedAction::Move {
+                destination,
+                from,
+                to,
+            } => {
+                let element = lists::move_element(
+                    &mut self.databases.borrow_mut()[db],
+                    keys[0],
+                    destination,
+                    *from,
+                    *to,
+                )?;
+                Ok(element.map(|element| {
+                    self.propagate_command(
+                        db,
+                        &[
+                            b"LMOVE",
+                            keys[0],
+                            destination,
+                            list_end_name(*from),
+                            list_end_name(*to),
+                        ],
+                    );
+                    encode(&RespValue::BulkString(&element))
+                }))
+            }
+            BlockedAction::ZPop { max } => {
+                for key in keys {
+                    let popped =
+                        sorted_sets::pop(&mut self.databases.borrow_mut()[db], key, 1, *max)?;
+                    if let Some((member, score)) = popped.first() {
+                        let command = if *max {
+                            &b"ZPOPMAX"[..]
+                        } else {
+                            &b"ZPOPMIN"[..]
+                        };
+                        self.propagate_command(db, &[command, key]);
+                        let score = score.to_string();
+                        return Ok(Some(encode(&RespValue::Array(vec![
+                            RespValue::BulkString(key),
+                            RespValue::BulkString(member),
+                            RespValue::BulkString(score.as_bytes()),
+                        ]))));
                     }
                 }
-                let response_array = values
-                    .iter()
-                    .map(|v| RespValue::BulkString(v))
-                    .collect::<Vec<_>>();
-                RespValue::Array(response_array).write_async(stream).await?
+                Ok(None)
             }
-            RedisRequest::Keys(params) => {
-                let keys = match params {
-                    b"*" => {
-                        // All keys.
-                        self.data
-                            .borrow()
-                            .keys()
-                            .map(|k| k.to_owned())
-                            .collect::<Vec<_>>()
-                    }
-                    _ => {
-                        return Err(RedisError::UnknownRequest(format!(
-                            "Only KEYS * supported, got KEYS {}",
-                            String::from_utf8_lossy(params)
-                        )));
+            // Reading doesn't change the streams, so there is nothing to propagate.
+            BlockedAction::XRead { after, count } => {
+                let mut read = Vec::new();
+                for (key, id) in after {
+                    let entries = streams::read_after(
+                        &mut self.databases.borrow_mut()[db],
+                        key,
+                        *id,
+                        *count,
+                    )?;
+                    if !entries.is_empty() {
+                        read.push((key.clone(), formatted_entries(entries)));
                     }
-                };
-                let response_array = keys
-                    .iter()
-                    .map(|v| RespValue::BulkString(v))
-                    .collect::<Vec<_>>();
-                RespValue::Array(response_array).write_async(stream).await?
+                }
+                if read.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(encode(&stream_reads_array(&read))))
             }
-            RedisRequest::Info(None) => self.replication_info.write_async(stream).await?,
-            RedisRequest::Info(Some(info_type)) => match info_type {
-                b"replication" => self.replication_info.write_async(stream).await?,
-                _ => RespValue::NullBulkString.write_async(stream).await?,
+            BlockedAction::XReadGroup {
+                group,
+                consumer,
+                count,
+                no_ack,
+            } => {
+                let reads: Vec<_> = keys.iter().map(|key| (*key, None)).collect();
+                let read = self.read_group(db, group, consumer, &reads, *count, *no_ack)?;
+                if read.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(encode(&stream_reads_array(&read))))
+            }
+        }
+    }
+
+    // Reads entries for a consumer of the group from each stream, after the ID given for
+    // it, as for XREADGROUP, and propagates the changes made to the groups.  Streams read
+    // with `>`, given as None, which have no new entries are left out.
+    fn read_group(
+        &self,
+        db: usize,
+        group: &[u8],
+        consumer: &[u8],
+        reads: &[(&[u8], Option<StreamId>)],
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Result<Vec<(Vec<u8>, FormattedEntries)>, RedisError> {
+        let mut read = Vec::new();
+        for (key, after) in reads {
+            let (entries, changes) = streams::read_group(
+                &mut self.databases.borrow_mut()[db],
+                key,
+                group,
+                consumer,
+                *after,
+                count,
+                no_ack,
+            )?;
+            self.propagate_group_changes(db, key, group, consumer, &changes);
+            if after.is_some() || !entries.is_empty() {
+                read.push((key.to_vec(), formatted_entries(entries)));
+            }
+        }
+        Ok(read)
+    }
+
+    // Propagates the changes that reading or claiming made to a group, as commands which
+    // make the same changes on replicas whatever the time there.
+    fn propagate_group_changes(
+        &self,
+        db: usize,
+        key: &[u8],
+        group: &[u8],
+        consumer: &[u8],
+        changes: &GroupChanges,
+    ) {
+        if changes.created_consumer {
+            self.propagate_command(db, &[b"XGROUP", b"CREATECONSUMER", key, group, consumer]);
+        }
+        for (id, entry) in &changes.claimed {
+            let id = id.to_string();
+            let time = entry.delivery_time.to_string();
+            let count = entry.delivery_count.to_string();
+            self.propagate_command(
+                db,
+                &[
+                    b"XCLAIM",
+                    key,
+                    group,
+                    &entry.consumer,
+                    b"0",
+                    id.as_bytes(),
+                    b"TIME",
+                    time.as_bytes(),
+                    b"RETRYCOUNT",
+                    count.as_bytes(),
+                    b"FORCE",
+                    b"JUSTID",
+                ],
+            );
+        }
+        if !changes.removed.is_empty() {
+            let ids: Vec<String> = changes.removed.iter().map(StreamId::to_string).collect();
+            let mut command: Vec<&[u8]> = vec![b"XACK", key, group];
+            command.extend(ids.iter().map(String::as_bytes));
+            self.propagate_command(db, &command);
+        }
+        if let Some((id, entries_read)) = changes.last_id {
+            let id = id.to_string();
+            let entries_read = entries_read.map_or(-1, |read| read as i64).to_string();
+            self.propagate_command(
+                db,
+                &[
+                    b"XGROUP",
+                    b"SETID",
+                    key,
+                    group,
+                    id.as_bytes(),
+                    b"ENTRIESREAD",
+                    entries_read.as_bytes(),
+                ],
+            );
+        }
+    }
+
+    // Sends the replication stream to a replica until either side closes the connection.
+    async fn serve_replica(
+        &self,
+        stream: &mut TcpStream,
+        id: u64,
+        mut receiver: mpsc::UnboundedReceiver<Bytes>,
+        mut input_buf: BytesMut,
+    ) -> Result<(), RedisError> {
+        let (mut reader, mut writer) = stream.split();
+        let mut parser = RespParser::new();
+        // The replica may have sent an acknowledgement along with PSYNC.
+        let result = match self.handle_replica_acks(id, &mut parser, &mut input_buf) {
+            Err(error) => Err(error),
+            Ok(()) => loop {
+                tokio::select! {
+                    command = receiver.recv() => match command {
+                        Some(command) => {
+                            if let Err(error) = writer.write_all(&command).await {
+                                break Err(error.into());
+                            }
+                        }
+                        None => break Ok(()),
+                    },
+                    bytes_read = reader.read_buf(&mut input_buf) => match bytes_read {
+                        Ok(0) => break Ok(()),
+                        Ok(_) => {
+                            if let Err(error) = self.handle_replica_acks(id, &mut parser, &mut input_buf) {
+                                break Err(error);
+                            }
+                        }
+                        Err(error) => break Err(error.into()),
+                    },
+                }
             },
+        };
+        self.unregister_replica(id);
+        result
+    }
+
+    // Records the acknowledgements in the commands a replica sent, leaving any incomplete
+    // command in the buffer.  Nothing the replica sends on this link needs a reply.
+    fn handle_replica_acks(
+        &self,
+        id: u64,
+        parser: &mut RespParser,
+        input_buf: &mut BytesMut,
+    ) -> Result<(), RedisError> {
+        loop {
+            let Some((value, len)) = parser.parse_frame(input_buf)? else {
+                return Ok(());
+            };
+            if let RedisRequest::ReplConf(ReplConf::Ack(offset)) = parse_command(value)? {
+                if let Some(replica) = self.replicas.borrow_mut().iter_mut().find(|r| r.id == id) {
+                    replica.ack_offset = offset;
+                }
+                self.replica_acks.notify_waiters();
+            }
+            input_buf.advance(len);
         }
-        Ok(())
     }
-}
 
-impl Default for RedisHandler {
-    fn default() -> Self {
-        RedisHandler::new()
+    // Waits until num_replicas replicas have acknowledged the current replication offset
+    // or the timeout expires, returning how many did.
+    async fn wait_for_replicas(&self, num_replicas: u64, timeout_millis: u64) -> u64 {
+        let target_offset = self.replication_info.borrow().master_repl_offset;
+        if self.count_replicas_acked(target_offset) < num_replicas {
+            self.propagate(b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n");
+        }
+        // A timeout of zero means wait forever.
+        let deadline =
+            (timeout_millis > 0).then(|| Instant::now() + Duration::from_millis(timeout_millis));
+        loop {
+            // Listen before counting, so that an acknowledgement in between isn't missed.
+            let acked = self.replica_acks.notified();
+            let count = self.count_replicas_acked(target_offset);
+            if count >= num_replicas {
+                return count;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, acked).await.is_err() {
+                        return self.count_replicas_acked(target_offset);
+                    }
+                }
+                None => acked.await,
+            }
+        }
     }
-}
 
-impl ValueType {
-    pub(crate) fn new(value: Vec<u8>) -> Self {
-        ValueType {
-            value,
-            expiration: None,
+    fn count_replicas_acked(&self, offset: u64) -> u64 {
+        self.replicas
+            .borrow()
+            .iter()
+            .filter(|replica| replica.ack_offset >= offset)
+            .count() as u64
+    }
+
+    fn register_replica(
+        &self,
+        connection: &ConnectionState,
+    ) -> (u64, mpsc::UnboundedReceiver<Bytes>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let id = self.next_replica_id.get();
+        self.next_replica_id.set(id + 1);
+        self.backlog.borrow_mut().get_or_insert_with(|| {
+            ReplicationBacklog::new(
+                self.repl_backlog_size(),
+                self.replication_info.borrow().master_repl_offset,
+            )
+        });
+        let mut replicas = self.replicas.borrow_mut();
+        replicas.push(ReplicaConnection {
+            id,
+            ip: connection.peer_ip.clone(),
+            listening_port: connection.replica_listening_port.unwrap_or(0),
+            ack_offset: 0,
+            sender,
+        });
+        self.replication_info.borrow_mut().connected_slaves = replicas.len() as u16;
+        (id, receiver)
+    }
+
+    fn unregister_replica(&self, id: u64) {
+        let mut replicas = self.replicas.borrow_mut();
+        replicas.retain(|replica| replica.id != id);
+        self.replication_info.borrow_mut().connected_slaves = replicas.len() as u16;
+    }
+
+    // Disconnects all replicas, which will have to resynchronise when they reconnect.
+    fn disconnect_replicas(&self) {
+        // Dropping the senders ends each replica's serve_replica loop.
+        self.replicas.borrow_mut().clear();
+        self.replication_info.borrow_mut().connected_slaves = 0;
+    }
+
+    // Propagates a successfully applied write command to the replicas.  Until there has
+    // been a replica there is no backlog, and the replication offset does not advance.
+    fn propagate(&self, command: &[u8]) {
+        if self.backlog.borrow().is_none() {
+            return;
         }
+        self.feed_replication_stream(command);
     }
 
-    pub(crate) fn new_from_seconds(value: Vec<u8>, seconds: u32) -> Self {
-        ValueType {
-            value,
-            expiration: Some(UNIX_EPOCH + Duration::from_secs(seconds as u64)),
+    // Propagates a write made in the given database, first switching the replication
+    // stream to that database if needed.  Writes made directly on a replica are not part
+    // of its master's stream, so are not propagated.
+    fn propagate_write(&self, db: usize, command: &[u8]) {
+        if self.backlog.borrow().is_none()
+            || matches!(self.replication_info.borrow().role, RedisRole::Slave)
+        {
+            return;
+        }
+        if self.stream_db.get() != Some(db) {
+            let db = db.to_string();
+            let select = format!("*2\r\n$6\r\nSELECT\r\n${}\r\n{}\r\n", db.len(), db);
+            self.feed_replication_stream(select.as_bytes());
         }
+        self.stream_db.set(Some(db));
+        self.feed_replication_stream(command);
     }
 
-    pub(crate) fn new_from_millis(value: Vec<u8>, millis: u64) -> Self {
-        ValueType {
-            value,
-            expiration: Some(UNIX_EPOCH + Duration::from_millis(millis)),
+    // Propagates a write made on behalf of another command, given as its arguments.
+    fn propagate_command(&self, db: usize, args: &[&[u8]]) {
+        let command = RespValue::Array(args.iter().map(|arg| RespValue::BulkString(arg)).collect());
+        self.propagate_write(db, &encode(&command));
+    }
+
+    // Appends to the replication stream, advancing the offset and passing it on to the
+    // backlog and replicas.
+    fn feed_replication_stream(&self, command: &[u8]) {
+        self.replication_info.borrow_mut().master_repl_offset += command.len() as u64;
+        if let Some(backlog) = self.backlog.borrow_mut().as_mut() {
+            backlog.feed(command);
+        }
+        let command = Bytes::copy_from_slice(command);
+        for replica in self.replicas.borrow().iter() {
+            // A failed send means the replica disconnected; it unregisters itself.
+            let _ = replica.sender.send(command.clone());
         }
     }
 
-    fn is_expired(&self) -> bool {
-        self.expiration
-            .map_or(false, |expiration| SystemTime::now() > expiration)
+    // Returns the part of the stream a replica missed if it can continue from the
+    // given replication id and offset.
+    fn backlog_since(&self, replid: &[u8], offset: i64) -> Option<Vec<u8>> {
+        let replication_info = self.replication_info.borrow();
+        let same_history = replid == replication_info.master_replid.as_bytes()
+            || (replid == replication_info.master_replid2.as_bytes()
+                && offset <= replication_info.second_repl_offset);
+        if !same_history || offset < 0 {
+            return None;
+        }
+        self.backlog.borrow().as_ref()?.read_from(offset as u64)
     }
-}
 
-unsafe impl Send for RedisHandler {}
-unsafe impl Sync for RedisHandler {}
+    fn repl_backlog_size(&self) -> usize {
+        self.config
+            .borrow()
+            .get(&b"repl-backlog-size"[..])
+            .and_then(|size| std::str::from_utf8(size).ok()?.parse().ok())
+            .unwrap_or(DEFAULT_REPL_BACKLOG_SIZE)
+    }
 
-impl RedisReplicationInfo {
-    async fn write_async<W>(&self, writer: &mut W) -> Result<(), RedisError>
-    where
-        W: tokio::io::AsyncWriteExt + Unpin,
-    {
-        let mut contents = String::default();
-        match self.role {
-            RedisRole::Master => {
-                contents.push_str("role:master\n");
-                contents.push_str("master_replid:");
-                contents.push_str(&self.master_replid);
-                contents.push_str(&format!("\nmaster_repl_offset:{}", self.master_repl_offset));
-                contents.push_str(&format!("\nconnected_slaves:{}", self.connected_slaves));
+    // Removes expired keys in the background, so that keys which are never looked up again
+    // don't use memory forever.  Replicas run it too, just as they remove expired keys
+    // when looking them up, since the master doesn't propagate expirations.
+    //
+    // Precondition: this can only be called from a single threaded context, since the data
+    // contents are not protected by a lock.
+    pub(crate) async unsafe fn run_active_expiration(&self) {
+        let mut interval = tokio::time::interval(ACTIVE_EXPIRE_PERIOD);
+        loop {
+            interval.tick().await;
+            self.remove_expired_keys();
+        }
+    }
+
+    // Runs the active expiration cycle once over each database.
+    fn remove_expired_keys(&self) {
+        let deadline = std::time::Instant::now() + ACTIVE_EXPIRE_TIME_LIMIT;
+        let removed: usize = self
+            .databases
+            .borrow_mut()
+            .iter_mut()
+            .map(|db| keys::remove_expired(db, deadline))
+            .sum();
+        self.expired_keys
+            .set(self.expired_keys.get() + removed as u64);
+    }
+
+    // Brings the count of expired keys up to date with those the databases counted, as
+    // must be done before any database is replaced, and returns it.
+    fn collect_expired_keys(&self) -> u64 {
+        let counted: u64 = self
+            .databases
+            .borrow_mut()
+            .iter_mut()
+            .map(Database::take_expired)
+            .sum();
+        self.expired_keys.set(self.expired_keys.get() + counted);
+        self.expired_keys.get()
+    }
+
+    // Replicates from the master in the replication info whenever we are a replica,
+    // reconnecting if the link drops, and switching masters when REPLICAOF is used.
+    //
+    // Precondition: this can only be called from a single threaded context, since the data
+    // contents are not protected by a lock.
+    pub(crate) async unsafe fn run_replication(&self) {
+        loop {
+            // Listen before reading the master, so that a change in between isn't missed.
+            let master_changed = self.master_changed.notified();
+            tokio::pin!(master_changed);
+            let master = {
+                let replication_info = self.replication_info.borrow();
+                match replication_info.role {
+                    RedisRole::Master => None,
+                    RedisRole::Slave => Some((
+                        replication_info.master_host.clone(),
+                        replication_info.master_port,
+                    )),
+                }
+            };
+            let Some((host, port)) = master else {
+                master_changed.await;
+                continue;
+            };
+            tokio::select! {
+                result = self.sync_with_master(&host, port) => {
+                    if let Err(error) = result {
+                        println!("replication from {}:{} failed: {}", host, port, error);
+                    }
+                    self.replication_info.borrow_mut().master_link_up = false;
+                    tokio::select! {
+                        _ = tokio::time::sleep(MASTER_RECONNECT_DELAY) => (),
+                        _ = &mut master_changed => (),
+                    }
+                }
+                // Dropping the sync closes the link to the old master.
+                _ = &mut master_changed => {
+                    self.replication_info.borrow_mut().master_link_up = false;
+                }
             }
-            RedisRole::Slave => contents.push_str("role:slave"),
-        };
-        RespValue::BulkString(contents.as_bytes())
-            .write_async(writer)
-            .await?;
+        }
+    }
+
+    fn check_db_index(&self, db: usize) -> Result<(), RedisError> {
+        if db >= self.databases.borrow().len() {
+            return Err(RedisError::UnexpectedArgumentType(
+                "DB index is out of range".to_string(),
+            ));
+        }
         Ok(())
     }
-}
 
-impl Default for RedisReplicationInfo {
-    fn default() -> Self {
-        RedisReplicationInfo {
-            role: RedisRole::Master,
-            connected_slaves: 0,
-            master_replid: String::default(),
-            master_repl_offset: 0,
+    // Where SAVE and BGSAVE write the dataset.
+    fn rdb_path(&self) -> std::path::PathBuf {
+        let config = self.config.borrow();
+        let mut path = std::path::PathBuf::new();
+        if let Some(dir) = config.get(b"dir".as_slice()) {
+            path.push(String::from_utf8_lossy(dir).as_ref());
+        }
+        match config.get(b"dbfilename".as_slice()) {
+            Some(dbfilename) => path.push(String::from_utf8_lossy(dbfilename).as_ref()),
+            None => path.push("dump.rdb"),
+        }
+        path
+    }
+
+    // Starts replicating from the given master, keeping our history so that we can
+    // partially resynchronise if it shares it.
+    fn set_master(&self, host: String, port: u16) {
+        let mut replication_info = self.replication_info.borrow_mut();
+        replication_info.role = RedisRole::Slave;
+        replication_info.master_host = host;
+        replication_info.master_port = port;
+        replication_info.master_link_up = false;
+        self.master_changed.notify_waiters();
+    }
+
+    // Stops replicating and becomes a master.  Our history continues under a new
+    // replication id, with the old one kept so that the other replicas of our previous
+    // master can continue from us.
+    fn unset_master(&self) {
+        {
+            let mut replication_info = self.replication_info.borrow_mut();
+            replication_info.role = RedisRole::Master;
+            replication_info.master_link_up = false;
+            replication_info.master_replid2 =
+                std::mem::replace(&mut replication_info.master_replid, new_replication_id());
+            replication_info.second_repl_offset = replication_info.master_repl_offset as i64 + 1;
         }
+        // Our replicas need to learn the new replication id, which they will when they
+        // reconnect and continue.
+        self.disconnect_replicas();
+        self.master_changed.notify_waiters();
+    }
+
+    // Connects to the master, loads its snapshot and then applies the commands it
+    // propagates until the connection is closed.
+    async unsafe fn sync_with_master(&self, host: &str, port: u16) -> Result<(), RedisError> {
+        let mut link = MasterLink::connect(host, port).await?;
+        let listening_port = self
+            .config
+            .borrow()
+            .get(&b"port"[..])
+            .cloned()
+            .unwrap_or_else(|| b"6379".to_vec());
+        link.handshake(&listening_port).await?;
+
+        // Try to continue from where we left off with the last master we followed.
+        let (replid, offset) = {
+            let replication_info = self.replication_info.borrow();
+            if replication_info.master_replid.is_empty() {
+                ("?".to_string(), -1)
+            } else {
+                (
+                    replication_info.master_replid.clone(),
+                    replication_info.master_repl_offset as i64 + 1,
+                )
+            }
+        };
+        match link.psync(replid.as_bytes(), offset).await? {
+            PsyncReply::FullResync {
+                replid,
+                offset,
+                rdb,
+            } => {
+                let mut reader = RdbReader::new(&rdb[..]);
+                let databases = reader.read_contents()?;
+                self.collect_expired_keys();
+                *self.databases.borrow_mut() =
+                    with_configured_databases(&self.config.borrow(), databases)?;
+                // The stream continues in the database the master had selected.
+                let stream_db = reader
+                    .aux_field(b"repl-stream-db")
+                    .and_then(|db| std::str::from_utf8(db).ok()?.parse().ok())
+                    .unwrap_or(0);
+                self.stream_db.set(Some(stream_db));
+                // Our own replicas hold a copy of the old dataset.
+                self.disconnect_replicas();
+                *self.backlog.borrow_mut() =
+                    Some(ReplicationBacklog::new(self.repl_backlog_size(), offset));
+                let mut replication_info = self.replication_info.borrow_mut();
+                replication_info.master_replid = replid;
+                replication_info.master_repl_offset = offset;
+                replication_info.master_replid2 = NO_REPLID.to_string();
+                replication_info.second_repl_offset = -1;
+            }
+            PsyncReply::Continue { replid } => {
+                println!("continuing replication from offset {}", offset);
+                let mut replication_info = self.replication_info.borrow_mut();
+                if let Some(replid) = replid.filter(|r| *r != replication_info.master_replid) {
+                    // The master's history now goes by a new id; keep the old one so our own
+                    // replicas can still continue.
+                    replication_info.master_replid2 =
+                        std::mem::replace(&mut replication_info.master_replid, replid);
+                    replication_info.second_repl_offset =
+                        replication_info.master_repl_offset as i64 + 1;
+                }
+                self.backlog.borrow_mut().get_or_insert_with(|| {
+                    ReplicationBacklog::new(
+                        self.repl_backlog_size(),
+                        replication_info.master_repl_offset,
+                    )
+                });
+            }
+        }
+        self.replication_info.borrow_mut().master_link_up = true;
+
+        // The master does not expect replies to the commands it propagates.
+        let mut sink = tokio::io::sink();
+        let mut connection = ConnectionState {
+            db: self.stream_db.get().unwrap_or(0),
+            ..ConnectionState::default()
+        };
+        while let Some(command) = link.next_command().await? {
+            match parse_commands(&command) {
+                Ok(requests) => {
+                    for request in requests {
+                        // The offset reported excludes the GETACK itself.
+                        if let RedisRequest::ReplConf(ReplConf::GetAck) = request {
+                            let offset = self.replication_info.borrow().master_repl_offset;
+                            link.send_ack(offset).await?;
+                            continue;
+                        }
+                        if let Err(error) = self
+                            .handle_request(request, &mut sink, &mut connection)
+                            .await
+                        {
+                            println!("error applying command from master: {}", error);
+                        }
+                    }
+                }
+                Err(error) => println!("error parsing command from master: {}", error),
+            }
+            // Replicas of this replica receive the master's stream as is.
+            self.stream_db.set(Some(connection.db));
+            self.feed_replication_stream(&command);
+        }
+        Ok(())
+    }
+
+    // Handles a single request, writing the result to the provided stream.
+    async unsafe fn handle_request<'a, W>(
+        &self,
+        request: RedisRequest<'a>,
+        stream: &mut W,
+        connection: &mut ConnectionState,
+    ) -> Result<(), RedisError>
+    where
+        W: tokio::io::AsyncWriteExt + Unpin,
+    {
+        match request {
+            RedisRequest::Ping => RespValue::SimpleString(b"PONG").write_async(stream).await?,
+            RedisRequest::Echo(contents) => {
+                RespValue::BulkString(contents).write_async(stream).await?
+            }
+            RedisRequest::Set {
+                key,
+                value,
+                expiration,
+                options,
+            } => {
+                let (written, old_value) = strings::set(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    value,
+                    expiration,
+                    options,
+                )?;
+                // Replicas get any expiration as an absolute time, as with EXPIRE, so that
+                // the key expires when it does here however late the command reaches them.
+                if written {
+                    let millis = expiration.map(unix_millis_string);
+                    let mut args: Vec<&[u8]> = vec![b"SET", key, value];
+                    if let Some(millis) = &millis {
+                        args.extend([b"PXAT", millis.as_bytes()]);
+                    }
+                    if options.nx {
+                        args.push(b"NX");
+                    }
+                    if options.xx {
+                        args.push(b"XX");
+                    }
+                    if options.keep_ttl {
+                        args.push(b"KEEPTTL");
+                    }
+                    self.propagate_command(connection.db, &args);
+                }
+                match (options.get, old_value) {
+                    (true, Some(value)) => {
+                        RespValue::BulkString(&value).write_async(stream).await?
+                    }
+                    (false, _) if written => {
+                        RespValue::SimpleString(b"OK").write_async(stream).await?
+                    }
+                    _ => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::Get(key) => {
+                // We have to make a copy of the value, because while we are paused on the await, another
+                // future may overwrite the value for this key and invalidate the reference.
+                let value_copy =
+                    strings::get(&mut self.databases.borrow_mut()[connection.db], key)?;
+                match value_copy {
+                    Some(value) => RespValue::BulkString(&value).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::SetNx { key, value } => {
+                let options = SetOptions {
+                    nx: true,
+                    ..SetOptions::default()
+                };
+                let (written, _) = strings::set(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    value,
+                    None,
+                    options,
+                )?;
+                RespValue::SimpleInteger(written as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Append { key, value } => {
+                let len =
+                    strings::append(&mut self.databases.borrow_mut()[connection.db], key, value)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::StrLen(key) => {
+                let len = strings::len(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::GetRange { key, start, end } => {
+                let range = strings::range(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    start,
+                    end,
+                )?;
+                RespValue::BulkString(&range).write_async(stream).await?
+            }
+            RedisRequest::SetRange { key, offset, value } => {
+                let len = strings::set_range(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    offset,
+                    value,
+                )?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::GetDel(key) => {
+                let value = strings::get_del(&mut self.databases.borrow_mut()[connection.db], key)?;
+                match value {
+                    Some(value) => RespValue::BulkString(&value).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::GetEx {
+                key,
+                expiration,
+                persist,
+            } => {
+                let value = strings::get_ex(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    expiration,
+                    persist,
+                )?;
+                // As with EXPIRE, replicas get the absolute time.
+                if value.is_some() {
+                    if let Some(expiration) = expiration {
+                        let millis = unix_millis_string(expiration);
+                        self.propagate_command(
+                            connection.db,
+                            &[b"PEXPIREAT", key, millis.as_bytes()],
+                        );
+                    } else if persist {
+                        self.propagate_command(connection.db, &[b"PERSIST", key]);
+                    }
+                }
+                match value {
+                    Some(value) => RespValue::BulkString(&value).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::MGet(keys) => {
+                let values =
+                    strings::get_multiple(&mut self.databases.borrow_mut()[connection.db], &keys);
+                RespValue::Array(
+                    values
+                        .iter()
+                        .map(|value| match value {
+                            Some(value) => RespValue::BulkString(value),
+                            None => RespValue::NullBulkString,
+                        })
+                        .collect(),
+                )
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::MSet { pairs, nx } => {
+                let written = strings::set_multiple(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    &pairs,
+                    nx,
+                );
+                if nx {
+                    RespValue::SimpleInteger(written as i64)
+                        .write_async(stream)
+                        .await?
+                } else {
+                    RespValue::SimpleString(b"OK").write_async(stream).await?
+                }
+            }
+            RedisRequest::ConfigGet(params) => 'config_get: {
+                if params.is_empty() {
+                    RespValue::NullArray.write_async(stream).await?;
+                    break 'config_get;
+                }
+                // We need to make a copy of all the responses for the await point.
+                let mut values = Vec::with_capacity(2 * params.len());
+                {
+                    let config = self.config.borrow();
+                    for param in params {
+                        if let Some(value) = config.get(param) {
+                            values.push(param.to_owned());
+                            values.push(value.to_owned());
+                        }
+                    }
+                }
+                let response_array = values
+                    .iter()
+                    .map(|v| RespValue::BulkString(v))
+                    .collect::<Vec<_>>();
+                RespValue::Array(response_array).write_async(stream).await?
+            }
+            RedisRequest::Keys(params) => {
+                let keys = keys::matching(&self.databases.borrow()[connection.db], params);
+                let response_array = keys
+                    .iter()
+                    .map(|v| RespValue::BulkString(v))
+                    .collect::<Vec<_>>();
+                RespValue::Array(response_array).write_async(stream).await?
+            }
+            RedisRequest::Scan {
+                cursor,
+                pattern,
+                count,
+                value_type,
+            } => {
+                let (cursor, keys) = keys::scan(
+                    &self.databases.borrow()[connection.db],
+                    cursor,
+                    pattern,
+                    value_type,
+                    count,
+                );
+                let cursor = cursor.to_string();
+                RespValue::Array(vec![
+                    RespValue::BulkString(cursor.as_bytes()),
+                    bulk_string_array(&keys),
+                ])
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::ReplConf(ReplConf::ListeningPort(port)) => {
+                connection.replica_listening_port = Some(port);
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::ReplConf(ReplConf::Capabilities) => {
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            // These are only meaningful on a replication link, and are never replied to.
+            RedisRequest::ReplConf(ReplConf::Ack(_) | ReplConf::GetAck) => (),
+            RedisRequest::Wait {
+                num_replicas,
+                timeout_millis,
+            } => {
+                if matches!(self.replication_info.borrow().role, RedisRole::Slave) {
+                    return Err(RedisError::ReplicationError(
+                        "WAIT cannot be used with replica instances".to_string(),
+                    ));
+                }
+                let count = self.wait_for_replicas(num_replicas, timeout_millis).await;
+                RespValue::SimpleInteger(count as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Psync { replid, offset } => 'psync: {
+                if let Some(missed) = self.backlog_since(replid, offset) {
+                    println!(
+                        "replica continuing replication from offset {}, sending {} bytes from backlog",
+                        offset,
+                        missed.len()
+                    );
+                    let reply =
+                        format!("CONTINUE {}", self.replication_info.borrow().master_replid);
+                    connection.replica = Some(self.register_replica(connection));
+                    RespValue::SimpleString(reply.as_bytes())
+                        .write_async(stream)
+                        .await?;
+                    stream.write_all(&missed).await?;
+                    break 'psync;
+                }
+                println!(
+                    "replica requested PSYNC {} {}, starting full resynchronization",
+                    String::from_utf8_lossy(replid),
+                    offset
+                );
+                let mut snapshot = Vec::new();
+                RdbWriter::new(&mut snapshot)
+                    .with_stream_db(self.stream_db.get().unwrap_or(0))
+                    .write_contents(&self.databases.borrow())?;
+                let reply = {
+                    let replication_info = self.replication_info.borrow();
+                    format!(
+                        "FULLRESYNC {} {}",
+                        replication_info.master_replid, replication_info.master_repl_offset
+                    )
+                };
+                // Register before yielding so that no write can slip in between the
+                // snapshot and the start of the stream.
+                connection.replica = Some(self.register_replica(connection));
+                RespValue::SimpleString(reply.as_bytes())
+                    .write_async(stream)
+                    .await?;
+                // The snapshot is sent like a bulk string, but without the trailing separator.
+                stream
+                    .write_all(format!("${}\r\n", snapshot.len()).as_bytes())
+                    .await?;
+                stream.write_all(&snapshot).await?;
+            }
+            RedisRequest::ReplicaOf(None) => {
+                if matches!(self.replication_info.borrow().role, RedisRole::Slave) {
+                    self.unset_master();
+                    println!("promoted to master");
+                }
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::ReplicaOf(Some((host, port))) => {
+                let host = String::from_utf8_lossy(host).into_owned();
+                let already_connected = {
+                    let replication_info = self.replication_info.borrow();
+                    matches!(replication_info.role, RedisRole::Slave)
+                        && replication_info.master_host == host
+                        && replication_info.master_port == port
+                };
+                if already_connected {
+                    RespValue::SimpleString(b"OK Already connected to specified master")
+                        .write_async(stream)
+                        .await?
+                } else {
+                    println!("replicating from {}:{}", host, port);
+                    self.set_master(host, port);
+                    RespValue::SimpleString(b"OK").write_async(stream).await?
+                }
+            }
+            RedisRequest::Save => {
+                if self.save_state.bgsave_in_progress.load(Ordering::SeqCst) {
+                    return Err(RedisError::SaveError(
+                        "Background save already in progress".to_string(),
+                    ));
+                }
+                save_to_file(&self.rdb_path(), &self.databases.borrow())?;
+                self.save_state.saved();
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::BgSave => {
+                if self
+                    .save_state
+                    .bgsave_in_progress
+                    .swap(true, Ordering::SeqCst)
+                {
+                    return Err(RedisError::SaveError(
+                        "Background save already in progress".to_string(),
+                    ));
+                }
+                // Snapshot the data so clients can keep modifying it during the save.
+                let data = self.databases.borrow().clone();
+                let path = self.rdb_path();
+                let save_state = self.save_state.clone();
+                let generation = *save_state.generation.lock().unwrap();
+                tokio::task::spawn_blocking(move || {
+                    let current = || {
+                        let guard = save_state.generation.lock().unwrap();
+                        (*guard == generation).then_some(guard)
+                    };
+                    match save_to_file_if_current(&path, &data, current) {
+                        Ok(true) => {
+                            save_state.saved();
+                            println!("background saving terminated with success");
+                        }
+                        Ok(false) => println!("background saving discarded after FLUSHALL"),
+                        Err(error) => println!("background saving failed: {}", error),
+                    }
+                    save_state.bgsave_in_progress.store(false, Ordering::SeqCst);
+                });
+                RespValue::SimpleString(b"Background saving started")
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::LastSave => {
+                RespValue::SimpleInteger(self.save_state.last_save.load(Ordering::SeqCst) as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Select(db) => {
+                self.check_db_index(db)?;
+                connection.db = db;
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::SwapDb(first, second) => {
+                self.check_db_index(first)?;
+                self.check_db_index(second)?;
+                self.databases.borrow_mut().swap(first, second);
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::Move { key, db } => {
+                self.check_db_index(db)?;
+                if db == connection.db {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "source and destination objects are the same".to_string(),
+                    ));
+                }
+                let moved = {
+                    let mut databases = self.databases.borrow_mut();
+                    match databases[connection.db].remove(key) {
+                        Some(value) if value.is_expired() => false,
+                        Some(value) => {
+                            if databases[db].get(key).is_some_and(|v| !v.is_expired()) {
+                                // The key exists in the destination, so leave it where it was.
+                                databases[connection.db].insert(key.to_vec(), value);
+                                false
+                            } else {
+                                databases[db].insert(key.to_vec(), value);
+                                true
+                            }
+                        }
+                        None => false,
+                    }
+                };
+                RespValue::SimpleInteger(moved as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Expire {
+                key,
+                expiration,
+                conditions,
+            } => {
+                let changed = keys::expire(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    expiration,
+                    &conditions,
+                );
+                // Replicas get the absolute time, so that the key expires when it does
+                // here however late the command reaches them.
+                if changed {
+                    let millis = unix_millis_string(expiration);
+                    self.propagate_command(connection.db, &[b"PEXPIREAT", key, millis.as_bytes()]);
+                }
+                RespValue::SimpleInteger(changed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Ttl { key, millis } => {
+                let ttl = keys::ttl(&mut self.databases.borrow_mut()[connection.db], key, millis);
+                RespValue::SimpleInteger(ttl).write_async(stream).await?
+            }
+            RedisRequest::ExpireTime { key, millis } => {
+                let time =
+                    keys::expire_time(&mut self.databases.borrow_mut()[connection.db], key, millis);
+                RespValue::SimpleInteger(time).write_async(stream).await?
+            }
+            RedisRequest::Persist(key) => {
+                let persisted = keys::persist(&mut self.databases.borrow_mut()[connection.db], key);
+                RespValue::SimpleInteger(persisted as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Del(keys) => {
+                let removed =
+                    keys::remove(&mut self.databases.borrow_mut()[connection.db], &keys).len();
+                RespValue::SimpleInteger(removed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Unlink(keys) => {
+                let removed = keys::remove(&mut self.databases.borrow_mut()[connection.db], &keys);
+                let count = removed.len();
+                // Freeing large values takes a while, so leave it to another thread.
+                if removed
+                    .iter()
+                    .any(|value| keys::free_effort(value.value()) > LAZYFREE_THRESHOLD)
+                {
+                    tokio::task::spawn_blocking(move || drop(removed));
+                }
+                RespValue::SimpleInteger(count as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Exists(keys) | RedisRequest::Touch(keys) => {
+                let count =
+                    keys::count_existing(&mut self.databases.borrow_mut()[connection.db], &keys);
+                RespValue::SimpleInteger(count as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Type(key) => {
+                let name = keys::type_name(&mut self.databases.borrow_mut()[connection.db], key);
+                RespValue::SimpleString(name.as_bytes())
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Rename { key, new_key, nx } => {
+                let renamed = keys::rename(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    new_key,
+                    nx,
+                )?;
+                if nx {
+                    RespValue::SimpleInteger(renamed as i64)
+                        .write_async(stream)
+                        .await?
+                } else {
+                    RespValue::SimpleString(b"OK").write_async(stream).await?
+                }
+            }
+            RedisRequest::Copy {
+                source,
+                destination,
+                db,
+                replace,
+            } => {
+                let db = db.unwrap_or(connection.db);
+                self.check_db_index(db)?;
+                if db == connection.db && source == destination {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "source and destination objects are the same".to_string(),
+                    ));
+                }
+                let copied = {
+                    let mut databases = self.databases.borrow_mut();
+                    if db == connection.db {
+                        keys::copy(&mut databases[db], source, None, destination, replace)
+                    } else {
+                        let [source_db, destination_db] = databases
+                            .get_disjoint_mut([connection.db, db])
+                            .expect("the databases are different");
+                        keys::copy(
+                            source_db,
+                            source,
+                            Some(destination_db),
+                            destination,
+                            replace,
+                        )
+                    }
+                };
+                RespValue::SimpleInteger(copied as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::RandomKey => {
+                let key = keys::random_key(&mut self.databases.borrow_mut()[connection.db]);
+                match key {
+                    Some(key) => RespValue::BulkString(&key).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::DbSize => {
+                let size = self.databases.borrow()[connection.db].len();
+                RespValue::SimpleInteger(size as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::FlushDb { asynchronous } => {
+                self.collect_expired_keys();
+                let old = std::mem::take(&mut self.databases.borrow_mut()[connection.db]);
+                if asynchronous {
+                    tokio::task::spawn_blocking(move || drop(old));
+                }
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::FlushAll { asynchronous } => {
+                self.collect_expired_keys();
+                let old = self
+                    .databases
+                    .borrow_mut()
+                    .iter_mut()
+                    .map(std::mem::take)
+                    .collect::<Vec<_>>();
+                if asynchronous {
+                    tokio::task::spawn_blocking(move || drop(old));
+                } else {
+                    drop(old);
+                }
+                if self.config.borrow().contains_key(&b"dbfilename"[..]) {
+                    // Any background save under way has the data from before the flush,
+                    // so stop it from replacing the file.
+                    let mut generation = self.save_state.generation.lock().unwrap();
+                    *generation += 1;
+                    save_to_file(&self.rdb_path(), &self.databases.borrow())?;
+                    self.save_state.saved();
+                }
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::Push { key, values, end } => {
+                let len = lists::push(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &values,
+                    end,
+                )?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Pop { key, count, end } => {
+                let popped = lists::pop(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    count.unwrap_or(1),
+                    end,
+                )?;
+                match (popped, count) {
+                    (Some(popped), Some(_)) => {
+                        bulk_string_array(&popped).write_async(stream).await?
+                    }
+                    (Some(popped), None) => {
+                        RespValue::BulkString(&popped[0])
+                            .write_async(stream)
+                            .await?
+                    }
+                    (None, Some(_)) => RespValue::NullArray.write_async(stream).await?,
+                    (None, None) => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::LLen(key) => {
+                let len = lists::len(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::LRange { key, start, stop } => {
+                let elements = lists::range(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    start,
+                    stop,
+                )?;
+                bulk_string_array(&elements).write_async(stream).await?
+            }
+            RedisRequest::LIndex { key, index } => {
+                let element =
+                    lists::index(&mut self.databases.borrow_mut()[connection.db], key, index)?;
+                match element {
+                    Some(element) => RespValue::BulkString(&element).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::LSet { key, index, value } => {
+                lists::set(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    index,
+                    value,
+                )?;
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::LRem { key, count, value } => {
+                let removed = lists::remove(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    count,
+                    value,
+                )?;
+                RespValue::SimpleInteger(removed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::LTrim { key, start, stop } => {
+                lists::trim(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    start,
+                    stop,
+                )?;
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::LInsert {
+                key,
+                before,
+                pivot,
+                value,
+            } => {
+                let len = lists::insert(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    before,
+                    pivot,
+                    value,
+                )?;
+                RespValue::SimpleInteger(len).write_async(stream).await?
+            }
+            RedisRequest::LMove {
+                source,
+                destination,
+                from,
+                to,
+            } => {
+                let element = lists::move_element(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    source,
+                    destination,
+                    from,
+                    to,
+                )?;
+                match element {
+                    Some(element) => RespValue::BulkString(&element).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::BlockingPop { keys, end, timeout } => {
+                match self.apply_blocked_action(connection.db, &keys, &BlockedAction::Pop(end))? {
+                    Some(reply) => stream.write_all(&reply).await?,
+                    None => self.block_client(connection, &keys, BlockedAction::Pop(end), timeout),
+                }
+            }
+            RedisRequest::BlockingMove {
+                source,
+                destination,
+                from,
+                to,
+                timeout,
+            } => {
+                let action = BlockedAction::Move {
+                    destination: destination.to_vec(),
+                    from,
+                    to,
+                };
+                match self.apply_blocked_action(connection.db, &[source], &action)? {
+                    Some(reply) => {
+                        // The pushed element may be what another client is waiting for.
+                        self.serve_blocked_clients();
+                        stream.write_all(&reply).await?;
+                    }
+                    None => self.block_client(connection, &[source], action, timeout),
+                }
+            }
+            RedisRequest::HSet { key, pairs } => {
+                let added =
+                    hashes::set(&mut self.databases.borrow_mut()[connection.db], key, &pairs)?;
+                RespValue::SimpleInteger(added as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HSetNx { key, field, value } => {
+                let set = hashes::set_if_absent(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    field,
+                    value,
+                )?;
+                RespValue::SimpleInteger(set as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HGet { key, field } => {
+                let value =
+                    hashes::get(&mut self.databases.borrow_mut()[connection.db], key, field)?;
+                match value {
+                    Some(value) => RespValue::BulkString(&value).write_async(stream).await?,
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::HMGet { key, fields } => {
+                let values = fields
+                    .iter()
+                    .map(|field| {
+                        hashes::get(&mut self.databases.borrow_mut()[connection.db], key, field)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                RespValue::Array(
+                    values
+                        .iter()
+                        .map(|value| match value {
+                            Some(value) => RespValue::BulkString(value),
+                            None => RespValue::NullBulkString,
+                        })
+                        .collect(),
+                )
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::HDel { key, fields } => {
+                let deleted = hashes::delete(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &fields,
+                )?;
+                RespValue::SimpleInteger(deleted as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HExists { key, field } => {
+                let exists =
+                    hashes::get(&mut self.databases.borrow_mut()[connection.db], key, field)?
+                        .is_some();
+                RespValue::SimpleInteger(exists as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HLen(key) => {
+                let len = hashes::len(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HGetAll(key) => {
+                let entries =
+                    hashes::entries(&mut self.databases.borrow_mut()[connection.db], key)?;
+                hash_entries_array(&entries, true)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HKeys(key) => {
+                let entries =
+                    hashes::entries(&mut self.databases.borrow_mut()[connection.db], key)?;
+                hash_entries_array(&entries, false)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::HVals(key) => {
+                let entries =
+                    hashes::entries(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::Array(
+                    entries
+                        .iter()
+                        .map(|(_, value)| RespValue::BulkString(value))
+                        .collect(),
+                )
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::HIncrBy {
+                key,
+                field,
+                increment,
+            } => {
+                let value = hashes::increment_by(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    field,
+                    increment,
+                )?;
+                RespValue::SimpleInteger(value).write_async(stream).await?
+            }
+            RedisRequest::HIncrByFloat {
+                key,
+                field,
+                increment,
+            } => {
+                let value = hashes::increment_by_float(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    field,
+                    increment,
+                )?;
+                RespValue::BulkString(&value).write_async(stream).await?
+            }
+            RedisRequest::HScan {
+                key,
+                cursor,
+                pattern,
+                count,
+                no_values,
+            } => {
+                let (cursor, entries) = hashes::scan(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    cursor,
+                    pattern,
+                    count,
+                )?;
+                let cursor = cursor.to_string();
+                RespValue::Array(vec![
+                    RespValue::BulkString(cursor.as_bytes()),
+                    hash_entries_array(&entries, !no_values),
+                ])
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::HRandField {
+                key,
+                count,
+                with_values,
+            } => {
+                let entries = hashes::random_entries(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    count.unwrap_or(1),
+                )?;
+                match (count, entries.first()) {
+                    (Some(_), _) => {
+                        hash_entries_array(&entries, with_values)
+                            .write_async(stream)
+                            .await?
+                    }
+                    (None, Some((field, _))) => {
+                        RespValue::BulkString(field).write_async(stream).await?
+                    }
+                    (None, None) => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::HExpire {
+                key,
+                expiration,
+                condition,
+                fields,
+            } => {
+                let replies = hashes::expire(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    expiration,
+                    condition,
+                    &fields,
+                )?;
+                // As with EXPIRE, replicas get the absolute time of the fields changed.
+                let changed: Vec<&[u8]> = fields
+                    .iter()
+                    .zip(&replies)
+                    .filter(|(_, reply)| **reply > 0)
+                    .map(|(field, _)| *field)
+                    .collect();
+                if !changed.is_empty() {
+                    let millis = unix_millis_string(expiration);
+                    let count = changed.len().to_string();
+                    let mut args: Vec<&[u8]> = vec![
+                        b"HPEXPIREAT",
+                        key,
+                        millis.as_bytes(),
+                        b"FIELDS",
+                        count.as_bytes(),
+                    ];
+                    args.extend(changed);
+                    self.propagate_command(connection.db, &args);
+                }
+                integer_array(&replies).write_async(stream).await?
+            }
+            RedisRequest::HTtl { key, fields } => {
+                let replies = hashes::ttl(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &fields,
+                )?;
+                integer_array(&replies).write_async(stream).await?
+            }
+            RedisRequest::HPersist { key, fields } => {
+                let replies = hashes::persist(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &fields,
+                )?;
+                integer_array(&replies).write_async(stream).await?
+            }
+            RedisRequest::SAdd { key, members } => {
+                let added = sets::add(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &members,
+                )?;
+                RespValue::SimpleInteger(added as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::SRem { key, members } => {
+                let removed = sets::remove(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &members,
+                )?;
+                RespValue::SimpleInteger(removed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::SMembers(key) => {
+                let members = sets::members(&mut self.databases.borrow_mut()[connection.db], key)?;
+                bulk_string_array(&members).write_async(stream).await?
+            }
+            RedisRequest::SIsMember { key, member } => {
+                let is_member =
+                    sets::is_member(&mut self.databases.borrow_mut()[connection.db], key, member)?;
+                RespValue::SimpleInteger(is_member as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::SMIsMember { key, members } => {
+                let replies = members
+                    .iter()
+                    .map(|member| {
+                        sets::is_member(
+                            &mut self.databases.borrow_mut()[connection.db],
+                            key,
+                            member,
+                        )
+                        .map(i64::from)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                integer_array(&replies).write_async(stream).await?
+            }
+            RedisRequest::SCard(key) => {
+                let len = sets::len(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::SPop { key, count } => {
+                let popped = sets::pop(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    count.unwrap_or(1),
+                )?
+                .unwrap_or_default();
+                // Replicas must remove the same members, rather than pick their own.
+                if !popped.is_empty() {
+                    let mut args: Vec<&[u8]> = vec![b"SREM", key];
+                    args.extend(popped.iter().map(Vec::as_slice));
+                    self.propagate_command(connection.db, &args);
+                }
+                match (count, popped.first()) {
+                    (Some(_), _) => bulk_string_array(&popped).write_async(stream).await?,
+                    (None, Some(member)) => {
+                        RespValue::BulkString(member).write_async(stream).await?
+                    }
+                    (None, None) => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::SRandMember { key, count } => {
+                let members = sets::random_members(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    count.unwrap_or(1),
+                )?;
+                match (count, members.first()) {
+                    (Some(_), _) => bulk_string_array(&members).write_async(stream).await?,
+                    (None, Some(member)) => {
+                        RespValue::BulkString(member).write_async(stream).await?
+                    }
+                    (None, None) => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::SCombine { op, keys } => {
+                let members: Vec<_> =
+                    sets::combine(&mut self.databases.borrow_mut()[connection.db], op, &keys)?
+                        .keys()
+                        .cloned()
+                        .collect();
+                bulk_string_array(&members).write_async(stream).await?
+            }
+            RedisRequest::SCombineStore {
+                op,
+                destination,
+                keys,
+            } => {
+                let len = sets::combine_and_store(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    op,
+                    destination,
+                    &keys,
+                )?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::SInterCard { keys, limit } => {
+                let len = sets::intersection_len(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    &keys,
+                    limit,
+                )?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::SMove {
+                source,
+                destination,
+                member,
+            } => {
+                let moved = sets::move_member(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    source,
+                    destination,
+                    member,
+                )?;
+                RespValue::SimpleInteger(moved as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZAdd {
+                key,
+                options,
+                pairs,
+            } => {
+                let reply = sorted_sets::add(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &pairs,
+                    options,
+                )?;
+                match reply {
+                    ZAddReply::Count(count) => {
+                        RespValue::SimpleInteger(count as i64)
+                            .write_async(stream)
+                            .await?
+                    }
+                    ZAddReply::Score(Some(score)) => {
+                        RespValue::BulkString(score.to_string().as_bytes())
+                            .write_async(stream)
+                            .await?
+                    }
+                    ZAddReply::Score(None) => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::ZRem { key, members } => {
+                let removed = sorted_sets::remove(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &members,
+                )?;
+                RespValue::SimpleInteger(removed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZCard(key) => {
+                let len = sorted_sets::len(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZScore { key, member } => {
+                let score = sorted_sets::score(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    member,
+                )?;
+                match score {
+                    Some(score) => {
+                        RespValue::BulkString(score.to_string().as_bytes())
+                            .write_async(stream)
+                            .await?
+                    }
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::ZMScore { key, members } => {
+                let scores = members
+                    .iter()
+                    .map(|member| {
+                        sorted_sets::score(
+                            &mut self.databases.borrow_mut()[connection.db],
+                            key,
+                            member,
+                        )
+                        .map(|score| score.map(|score| score.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                RespValue::Array(
+                    scores
+                        .iter()
+                        .map(|score| match score {
+                            Some(score) => RespValue::BulkString(score.as_bytes()),
+                            None => RespValue::NullBulkString,
+                        })
+                        .collect(),
+                )
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::ZRank {
+                key,
+                member,
+                rev,
+                with_score,
+            } => {
+                let rank = sorted_sets::rank(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    member,
+                    rev,
+                )?;
+                match (rank, with_score) {
+                    (Some((rank, _)), false) => {
+                        RespValue::SimpleInteger(rank as i64)
+                            .write_async(stream)
+                            .await?
+                    }
+                    (Some((rank, score)), true) => {
+                        let score = score.to_string();
+                        RespValue::Array(vec![
+                            RespValue::SimpleInteger(rank as i64),
+                            RespValue::BulkString(score.as_bytes()),
+                        ])
+                        .write_async(stream)
+                        .await?
+                    }
+                    (None, false) => RespValue::NullBulkString.write_async(stream).await?,
+                    (None, true) => RespValue::NullArray.write_async(stream).await?,
+                }
+            }
+            RedisRequest::ZCount { key, min, max } => {
+                let count = sorted_sets::count(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    min,
+                    max,
+                )?;
+                RespValue::SimpleInteger(count as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZLexCount { key, min, max } => {
+                let count = sorted_sets::lex_count(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &min,
+                    &max,
+                )?;
+                RespValue::SimpleInteger(count as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZRange {
+                key,
+                range,
+                rev,
+                limit,
+                with_scores,
+            } => {
+                let members = sorted_sets::range(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    &range,
+                    rev,
+                    limit,
+                )?;
+                bulk_string_array(&scored_members(members, with_scores))
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZPop { key, count, max } => {
+                let popped = sorted_sets::pop(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    count,
+                    max,
+                )?;
+                bulk_string_array(&scored_members(popped, true))
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::ZCombineStore {
+                op,
+                destination,
+                keys,
+                weights,
+                aggregate,
+            } => {
+                let len = sorted_sets::combine_and_store(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    op,
+                    destination,
+                    &keys,
+                    &weights,
+                    aggregate,
+                )?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::BlockingZPop { keys, max, timeout } => {
+                let action = BlockedAction::ZPop { max };
+                match self.apply_blocked_action(connection.db, &keys, &action)? {
+                    Some(reply) => stream.write_all(&reply).await?,
+                    None => self.block_client(connection, &keys, action, timeout),
+                }
+            }
+            RedisRequest::XAdd {
+                key,
+                no_mkstream,
+                trim,
+                id,
+                fields,
+            } => {
+                let added = streams::add(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    id,
+                    &fields,
+                    no_mkstream,
+                    trim.as_ref(),
+                )?;
+                match added {
+                    Some(id) => {
+                        // Replicas must use the same ID, rather than generate their own.
+                        let command = xadd_command(key, no_mkstream, trim.as_ref(), id, &fields);
+                        let args: Vec<&[u8]> = command.iter().map(Vec::as_slice).collect();
+                        self.propagate_command(connection.db, &args);
+                        self.serve_blocked_clients();
+                        RespValue::BulkString(id.to_string().as_bytes())
+                            .write_async(stream)
+                            .await?;
+                    }
+                    None => RespValue::NullBulkString.write_async(stream).await?,
+                }
+            }
+            RedisRequest::XLen(key) => {
+                let len = streams::len(&mut self.databases.borrow_mut()[connection.db], key)?;
+                RespValue::SimpleInteger(len as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XRange {
+                key,
+                start,
+                end,
+                count,
+                rev,
+            } => {
+                let entries = streams::range(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    start,
+                    end,
+                    count,
+                    rev,
+                )?;
+                stream_entries_array(&formatted_entries(entries))
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XDel { key, ids } => {
+                let deleted =
+                    streams::delete(&mut self.databases.borrow_mut()[connection.db], key, &ids)?;
+                RespValue::SimpleInteger(deleted as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XTrim { key, trim } => {
+                let trimmed =
+                    streams::trim(&mut self.databases.borrow_mut()[connection.db], key, &trim)?;
+                RespValue::SimpleInteger(trimmed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XRead {
+                keys,
+                ids,
+                count,
+                block,
+            } => {
+                // `$` reads whatever is added after the stream's current last entry.
+                let after = keys
+                    .iter()
+                    .zip(ids)
+                    .map(|(key, id)| {
+                        let id = match id {
+                            Some(id) => id,
+                            None => streams::last_id(
+                                &mut self.databases.borrow_mut()[connection.db],
+                                key,
+                            )?,
+                        };
+                        Ok((key.to_vec(), id))
+                    })
+                    .collect::<Result<Vec<_>, RedisError>>()?;
+                let action = BlockedAction::XRead { after, count };
+                match (
+                    self.apply_blocked_action(connection.db, &keys, &action)?,
+                    block,
+                ) {
+                    (Some(reply), _) => stream.write_all(&reply).await?,
+                    (None, Some(timeout)) => self.block_client(connection, &keys, action, timeout),
+                    (None, None) => RespValue::NullArray.write_async(stream).await?,
+                }
+            }
+            RedisRequest::XReadGroup {
+                group,
+                consumer,
+                keys,
+                ids,
+                count,
+                block,
+                no_ack,
+            } => {
+                // Check all the groups exist before reading from any.
+                for key in &keys {
+                    streams::get_group(
+                        &mut self.databases.borrow_mut()[connection.db],
+                        key,
+                        group,
+                    )?;
+                }
+                let reads: Vec<_> = keys.iter().copied().zip(ids).collect();
+                let read =
+                    self.read_group(connection.db, group, consumer, &reads, count, no_ack)?;
+                // Reading pending entries always has a reply, so only `>` ever blocks.
+                match (read.is_empty(), block) {
+                    (false, _) => stream_reads_array(&read).write_async(stream).await?,
+                    (true, Some(timeout)) => {
+                        let action = BlockedAction::XReadGroup {
+                            group: group.to_vec(),
+                            consumer: consumer.to_vec(),
+                            count,
+                            no_ack,
+                        };
+                        self.block_client(connection, &keys, action, timeout)
+                    }
+                    (true, None) => RespValue::NullArray.write_async(stream).await?,
+                }
+            }
+            RedisRequest::XGroupCreate {
+                key,
+                group,
+                id,
+                mkstream,
+                entries_read,
+            } => {
+                streams::create_group(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    id,
+                    mkstream,
+                    entries_read,
+                )?;
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::XGroupSetId {
+                key,
+                group,
+                id,
+                entries_read,
+            } => {
+                streams::set_group_id(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    id,
+                    entries_read,
+                )?;
+                RespValue::SimpleString(b"OK").write_async(stream).await?
+            }
+            RedisRequest::XGroupDestroy { key, group } => {
+                let destroyed = streams::destroy_group(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                )?;
+                RespValue::SimpleInteger(destroyed as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XGroupCreateConsumer {
+                key,
+                group,
+                consumer,
+            } => {
+                let created = streams::create_consumer(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    consumer,
+                )?;
+                RespValue::SimpleInteger(created as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XGroupDelConsumer {
+                key,
+                group,
+                consumer,
+            } => {
+                let pending = streams::delete_consumer(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    consumer,
+                )?;
+                RespValue::SimpleInteger(pending as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XAck { key, group, ids } => {
+                let acked = streams::ack(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    &ids,
+                )?;
+                RespValue::SimpleInteger(acked as i64)
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::XPending { key, group, range } => {
+                let reply = {
+                    let mut databases = self.databases.borrow_mut();
+                    let group = streams::get_group(&mut databases[connection.db], key, group)?;
+                    match range {
+                        Some(range) => encode_pending_entries(group, &range),
+                        None => encode_pending_summary(group),
+                    }
+                };
+                stream.write_all(&reply).await?
+            }
+            RedisRequest::XClaim {
+                key,
+                group,
+                consumer,
+                min_idle,
+                ids,
+                options,
+            } => {
+                let (claimed, changes) = streams::claim(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    consumer,
+                    min_idle,
+                    &ids,
+                    &options,
+                )?;
+                self.propagate_group_changes(connection.db, key, group, consumer, &changes);
+                let claimed = formatted_entries(claimed);
+                if options.just_id {
+                    let ids: Vec<_> = claimed.into_iter().map(|(id, _)| id).collect();
+                    bulk_string_array(&ids).write_async(stream).await?
+                } else {
+                    stream_entries_array(&claimed).write_async(stream).await?
+                }
+            }
+            RedisRequest::XAutoClaim {
+                key,
+                group,
+                consumer,
+                min_idle,
+                start,
+                count,
+                just_id,
+            } => {
+                let (next, claimed, changes) = streams::auto_claim(
+                    &mut self.databases.borrow_mut()[connection.db],
+                    key,
+                    group,
+                    consumer,
+                    min_idle,
+                    start,
+                    count,
+                    just_id,
+                )?;
+                self.propagate_group_changes(connection.db, key, group, consumer, &changes);
+                let next = next.to_string();
+                let claimed = formatted_entries(claimed);
+                let ids: Vec<_> = claimed.iter().map(|(id, _)| id.clone()).collect();
+                let deleted: Vec<_> = changes
+                    .removed
+                    .iter()
+                    .map(|id| id.to_string().into_bytes())
+                    .collect();
+                RespValue::Array(vec![
+                    RespValue::BulkString(next.as_bytes()),
+                    if just_id {
+                        bulk_string_array(&ids)
+                    } else {
+                        stream_entries_array(&claimed)
+                    },
+                    bulk_string_array(&deleted),
+                ])
+                .write_async(stream)
+                .await?
+            }
+            RedisRequest::XInfoStream { key, full } => {
+                let reply = encode_stream_info(
+                    streams::get(&mut self.databases.borrow_mut()[connection.db], key)?,
+                    full,
+                );
+                stream.write_all(&reply).await?
+            }
+            RedisRequest::XInfoGroups(key) => {
+                let reply = {
+                    let mut databases = self.databases.borrow_mut();
+                    let info = streams::get(&mut databases[connection.db], key)?;
+                    encode_array(
+                        info.groups
+                            .iter()
+                            .map(|(name, group)| {
+                                encode_map(vec![
+                                    ("name", encode(&RespValue::BulkString(name))),
+                                    ("consumers", encode_integer(group.consumers.len() as u64)),
+                                    ("pending", encode_integer(group.pending.len() as u64)),
+                                    ("last-delivered-id", encode_id(group.last_id)),
+                                    ("entries-read", encode_optional_integer(group.entries_read)),
+                                    ("lag", encode_optional_integer(info.lag(group))),
+                                ])
+                            })
+                            .collect(),
+                    )
+                };
+                stream.write_all(&reply).await?
+            }
+            RedisRequest::XInfoConsumers { key, group } => {
+                let reply = {
+                    let mut databases = self.databases.borrow_mut();
+                    let group = streams::get_group(&mut databases[connection.db], key, group)?;
+                    encode_array(
+                        group
+                            .consumers
+                            .iter()
+                            .map(|(name, consumer)| {
+                                let pending = group.pending_for(name).count();
+                                let inactive = consumer
+                                    .inactive_millis()
+                                    .map_or(-1, |millis| millis as i64);
+                                encode_map(vec![
+                                    ("name", encode(&RespValue::BulkString(name))),
+                                    ("pending", encode_integer(pending as u64)),
+                                    ("idle", encode_integer(consumer.idle_millis())),
+                                    ("inactive", encode(&RespValue::SimpleInteger(inactive))),
+                                ])
+                            })
+                            .collect(),
+                    )
+                };
+                stream.write_all(&reply).await?
+            }
+            RedisRequest::Info(None) => {
+                let contents = format!(
+                    "{}\n\n{}",
+                    self.replication_info_string(),
+                    self.stats_info_string()
+                );
+                RespValue::BulkString(contents.as_bytes())
+                    .write_async(stream)
+                    .await?
+            }
+            RedisRequest::Info(Some(info_type)) => match info_type {
+                b"replication" => {
+                    let contents = self.replication_info_string();
+                    RespValue::BulkString(contents.as_bytes())
+                        .write_async(stream)
+                        .await?
+                }
+                b"stats" => {
+                    RespValue::BulkString(self.stats_info_string().as_bytes())
+                        .write_async(stream)
+                        .await?
+                }
+                _ => RespValue::NullBulkString.write_async(stream).await?,
+            },
+        }
+        Ok(())
+    }
+
+    fn stats_info_string(&self) -> String {
+        format!("expired_keys:{}", self.collect_expired_keys())
+    }
+
+    fn replication_info_string(&self) -> String {
+        let mut contents = self.replication_info.borrow().to_info_string();
+        for (idx, replica) in self.replicas.borrow().iter().enumerate() {
+            contents.push_str(&format!(
+                "\nslave{}:ip={},port={},state=online,offset={}",
+                idx, replica.ip, replica.listening_port, replica.ack_offset
+            ));
+        }
+        match self.backlog.borrow().as_ref() {
+            Some(backlog) => {
+                contents.push_str("\nrepl_backlog_active:1");
+                contents.push_str(&format!("\nrepl_backlog_size:{}", backlog.size()));
+                contents.push_str(&format!(
+                    "\nrepl_backlog_first_byte_offset:{}",
+                    backlog.first_byte_offset()
+                ));
+                contents.push_str(&format!("\nrepl_backlog_histlen:{}", backlog.histlen()));
+            }
+            None => {
+                contents.push_str("\nrepl_backlog_active:0");
+                contents.push_str(&format!("\nrepl_backlog_size:{}", self.repl_backlog_size()));
+                contents.push_str("\nrepl_backlog_first_byte_offset:0");
+                contents.push_str("\nrepl_backlog_histlen:0");
+            }
+        }
+        contents
+    }
+}
+
+impl Default for RedisHandler {
+    fn default() -> Self {
+        RedisHandler::new()
+    }
+}
+
+impl ValueType {
+    /// A string value without an expiration.
+    pub(crate) fn new(value: Vec<u8>) -> Self {
+        ValueType::from(Value::String(value))
+    }
+
+    /// Sets the expiration to the given unix time in milliseconds.
+    pub(crate) fn with_expiration_millis(mut self, millis: u64) -> Self {
+        self.expiration = Some(UNIX_EPOCH + Duration::from_millis(millis));
+        self
+    }
+
+    pub(crate) fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub(crate) fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
+    pub(crate) fn expiration(&self) -> Option<SystemTime> {
+        self.expiration
+    }
+
+    /// Sets the expiration of a value which is not in a database; that of a key is set
+    /// with Database::set_expiration.
+    pub(crate) fn set_expiration(&mut self, expiration: Option<SystemTime>) {
+        self.expiration = expiration;
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expiration
+            .is_some_and(|expiration| SystemTime::now() > expiration)
+    }
+}
+
+impl Value {
+    /// The name of the value's type, as TYPE replies.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+        }
+    }
+}
+
+impl From<Value> for ValueType {
+    fn from(value: Value) -> Self {
+        ValueType {
+            value,
+            expiration: None,
+        }
+    }
+}
+
+/// Returns the value at key, first removing it if it has expired.
+pub(crate) fn live_value_mut<'d>(db: &'d mut Database, key: &[u8]) -> Option<&'d mut ValueType> {
+    if db.get(key).is_some_and(ValueType::is_expired) {
+        db.remove(key);
+        db.expired += 1;
+    }
+    db.get_mut(key)
+}
+
+impl Database {
+    pub(crate) fn new() -> Self {
+        Database::default()
+    }
+
+    /// Sets the key to the value, returning the value it replaces.
+    pub(crate) fn insert(&mut self, key: Vec<u8>, value: ValueType) -> Option<ValueType> {
+        if value.expiration.is_some() {
+            self.expires.insert(key.clone(), ());
+        } else {
+            self.expires.remove(&key);
+        }
+        self.keys.insert(key, value)
+    }
+
+    pub(crate) fn remove(&mut self, key: &[u8]) -> Option<ValueType> {
+        self.expires.remove(key);
+        self.keys.remove(key)
+    }
+
+    /// Sets or removes the expiration of the key, if it exists.
+    pub(crate) fn set_expiration(&mut self, key: &[u8], expiration: Option<SystemTime>) {
+        let Some(value) = self.keys.get_mut(key) else {
+            return;
+        };
+        value.expiration = expiration;
+        if expiration.is_some() {
+            self.expires.insert(key.to_vec(), ());
+        } else {
+            self.expires.remove(key);
+        }
+    }
+
+    /// Returns a key picked at random from those with an expiration, or None if no key
+    /// has one.
+    pub(crate) fn random_expiring_key(&self, rng: &mut impl rand::Rng) -> Option<&Vec<u8>> {
+        self.expires.random_key(rng)
+    }
+
+    // Returns how many keys were found expired since it was last called.
+    fn take_expired(&mut self) -> u64 {
+        std::mem::take(&mut self.expired)
+    }
+}
+
+impl Deref for Database {
+    type Target = Dict<ValueType>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.keys
+    }
+}
+
+impl DerefMut for Database {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.keys
+    }
+}
+
+impl PartialEq for Database {
+    fn eq(&self, other: &Self) -> bool {
+        self.keys == other.keys
+    }
+}
+
+impl<'a> IntoIterator for &'a Database {
+    type Item = (&'a Vec<u8>, &'a ValueType);
+    type IntoIter = dict::Iter<'a, ValueType>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.iter()
+    }
+}
+
+impl FromIterator<(Vec<u8>, ValueType)> for Database {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, ValueType)>>(entries: I) -> Self {
+        let mut db = Database::new();
+        for (key, value) in entries {
+            db.insert(key, value);
+        }
+        db
+    }
+}
+
+impl<const N: usize> From<[(Vec<u8>, ValueType); N]> for Database {
+    fn from(entries: [(Vec<u8>, ValueType); N]) -> Self {
+        entries.into_iter().collect()
+    }
+}
+
+unsafe impl Send for RedisHandler {}
+unsafe impl Sync for RedisHandler {}
+
+impl RedisReplicationInfo {
+    fn to_info_string(&self) -> String {
+        let mut contents = String::default();
+        match self.role {
+            RedisRole::Master => {
+                contents.push_str("role:master\n");
+                contents.push_str("master_replid:");
+                contents.push_str(&self.master_replid);
+                contents.push_str(&format!("\nmaster_repl_offset:{}", self.master_repl_offset));
+                contents.push_str(&format!("\nconnected_slaves:{}", self.connected_slaves));
+                contents.push_str(&format!("\nmaster_replid2:{}", self.master_replid2));
+                contents.push_str(&format!("\nsecond_repl_offset:{}", self.second_repl_offset));
+            }
+            RedisRole::Slave => {
+                contents.push_str("role:slave\n");
+                contents.push_str(&format!("master_host:{}\n", self.master_host));
+                contents.push_str(&format!("master_port:{}\n", self.master_port));
+                contents.push_str(&format!(
+                    "master_link_status:{}\n",
+                    if self.master_link_up { "up" } else { "down" }
+                ));
+                contents.push_str("master_replid:");
+                contents.push_str(&self.master_replid);
+                contents.push_str(&format!("\nmaster_repl_offset:{}", self.master_repl_offset));
+                contents.push_str(&format!("\nmaster_replid2:{}", self.master_replid2));
+                contents.push_str(&format!("\nsecond_repl_offset:{}", self.second_repl_offset));
+            }
+        };
+        contents
+    }
+}
+
+impl SaveState {
+    fn new() -> Self {
+        // Like Redis, treat startup as the last save, since it has the on disk data.
+        SaveState {
+            last_save: AtomicU64::new(unix_time_secs()),
+            bgsave_in_progress: AtomicBool::new(false),
+            generation: Mutex::new(0),
+        }
+    }
+
+    fn saved(&self) {
+        self.last_save.store(unix_time_secs(), Ordering::SeqCst);
+    }
+}
+
+// Serialises a value, e.g. to send it to a client later.
+fn encode(value: &RespValue) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    value
+        .write(&mut buffer)
+        .expect("Writing to a Vec can't fail");
+    buffer
+}
+
+fn list_end_name(end: ListEnd) -> &'static [u8] {
+    match end {
+        ListEnd::Left => b"LEFT",
+        ListEnd::Right => b"RIGHT",
+    }
+}
+
+// The fields of a hash, each followed by its value if with_values is set.
+fn hash_entries_array(entries: &[(Vec<u8>, Vec<u8>)], with_values: bool) -> RespValue<'_> {
+    RespValue::Array(
+        entries
+            .iter()
+            .flat_map(|(field, value)| {
+                std::iter::once(RespValue::BulkString(field))
+                    .chain(with_values.then_some(RespValue::BulkString(value)))
+            })
+            .collect(),
+    )
+}
+
+fn integer_array(values: &[i64]) -> RespValue<'static> {
+    RespValue::Array(
+        values
+            .iter()
+            .map(|value| RespValue::SimpleInteger(*value))
+            .collect(),
+    )
+}
+
+// An array reply holding the values as bulk strings.
+fn bulk_string_array(values: &[Vec<u8>]) -> RespValue<'_> {
+    RespValue::Array(values.iter().map(|v| RespValue::BulkString(v)).collect())
+}
+
+// The members of a sorted set, each followed by its score if with_scores is set.
+fn scored_members(members: ScoredMembers, with_scores: bool) -> Vec<Vec<u8>> {
+    members
+        .into_iter()
+        .flat_map(|(member, score)| {
+            std::iter::once(member).chain(with_scores.then(|| score.to_string().into_bytes()))
+        })
+        .collect()
+}
+
+// Stream entries with their IDs formatted, where the fields are None for entries since
+// deleted.
+type FormattedEntries = Vec<(Vec<u8>, Option<Fields>)>;
+
+// Formats the IDs of stream entries, for stream_entries_array.
+fn formatted_entries<F: Into<Option<Fields>>>(entries: Vec<(StreamId, F)>) -> FormattedEntries {
+    entries
+        .into_iter()
+        .map(|(id, fields)| (id.to_string().into_bytes(), fields.into()))
+        .collect()
+}
+
+// An array of stream entries, each an array of its ID and then its fields and values.
+fn stream_entries_array(entries: &[(Vec<u8>, Option<Fields>)]) -> RespValue<'_> {
+    RespValue::Array(
+        entries
+            .iter()
+            .map(|(id, fields)| {
+                RespValue::Array(vec![
+                    RespValue::BulkString(id),
+                    fields.as_ref().map_or(RespValue::NullArray, |fields| {
+                        hash_entries_array(fields, true)
+                    }),
+                ])
+            })
+            .collect(),
+    )
+}
+
+// The reply to XREAD and XREADGROUP, an array of each stream's key and its entries.
+fn stream_reads_array(reads: &[(Vec<u8>, FormattedEntries)]) -> RespValue<'_> {
+    RespValue::Array(
+        reads
+            .iter()
+            .map(|(key, entries)| {
+                RespValue::Array(vec![
+                    RespValue::BulkString(key),
+                    stream_entries_array(entries),
+                ])
+            })
+            .collect(),
+    )
+}
+
+// Serialises an array of values which are already serialised.  This suits replies made
+// of many values formatted on the way, e.g. for XINFO.
+fn encode_array(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut buffer = format!("*{}\r\n", items.len()).into_bytes();
+    for item in items {
+        buffer.extend_from_slice(&item);
+    }
+    buffer
+}
+
+// Serialises a map, as an array of each name followed by its already serialised value.
+fn encode_map(pairs: Vec<(&str, Vec<u8>)>) -> Vec<u8> {
+    encode_array(
+        pairs
+            .into_iter()
+            .flat_map(|(name, value)| [encode(&RespValue::BulkString(name.as_bytes())), value])
+            .collect(),
+    )
+}
+
+fn encode_integer(value: u64) -> Vec<u8> {
+    encode(&RespValue::SimpleInteger(value as i64))
+}
+
+fn encode_optional_integer(value: Option<u64>) -> Vec<u8> {
+    value.map_or_else(|| encode(&RespValue::NullBulkString), encode_integer)
+}
+
+fn encode_id(id: StreamId) -> Vec<u8> {
+    encode(&RespValue::BulkString(id.to_string().as_bytes()))
+}
+
+fn encode_entry(id: StreamId, fields: &Fields) -> Vec<u8> {
+    let id = id.to_string();
+    encode(&RespValue::Array(vec![
+        RespValue::BulkString(id.as_bytes()),
+        hash_entries_array(fields, true),
+    ]))
+}
+
+// The reply to XINFO STREAM, where FULL, given as the count of entries to show, includes
+// the entries and groups in detail.
+fn encode_stream_info(info: &Stream, full: Option<usize>) -> Vec<u8> {
+    let nodes = info.entries.len().div_ceil(STREAM_NODE_MAX_ENTRIES) as u64;
+    let mut pairs = vec![
+        ("length", encode_integer(info.entries.len() as u64)),
+        ("radix-tree-keys", encode_integer(nodes)),
+        ("radix-tree-nodes", encode_integer(nodes + 1)),
+        ("last-generated-id", encode_id(info.last_id)),
+        ("max-deleted-entry-id", encode_id(info.max_deleted_id)),
+        ("entries-added", encode_integer(info.entries_added)),
+        ("recorded-first-entry-id", encode_id(info.first_id())),
+    ];
+    let Some(count) = full else {
+        let entry = |entry: Option<(&StreamId, &Fields)>| {
+            entry.map_or_else(
+                || encode(&RespValue::NullBulkString),
+                |(id, fields)| encode_entry(*id, fields),
+            )
+        };
+        pairs.push(("groups", encode_integer(info.groups.len() as u64)));
+        pairs.push(("first-entry", entry(info.entries.first_key_value())));
+        pairs.push(("last-entry", entry(info.entries.last_key_value())));
+        return encode_map(pairs);
+    };
+    // A count of zero shows everything.
+    let count = if count == 0 { usize::MAX } else { count };
+    let entries: Vec<_> = info
+        .entries
+        .iter()
+        .take(count)
+        .map(|(id, fields)| (*id, fields.clone()))
+        .collect();
+    pairs.push((
+        "entries",
+        encode(&stream_entries_array(&formatted_entries(entries))),
+    ));
+    let groups = info
+        .groups
+        .iter()
+        .map(|(name, group)| encode_group_info(info, name, group, count))
+        .collect();
+    pairs.push(("groups", encode_array(groups)));
+    encode_map(pairs)
+}
+
+// The details of a group for XINFO STREAM FULL, showing up to count pending entries.
+fn encode_group_info(info: &Stream, name: &[u8], group: &ConsumerGroup, count: usize) -> Vec<u8> {
+    let pending = group
+        .pending
+        .iter()
+        .take(count)
+        .map(|(id, entry)| {
+            encode_array(vec![
+                encode_id(*id),
+                encode(&RespValue::BulkString(&entry.consumer)),
+                encode_integer(entry.delivery_time),
+                encode_integer(entry.delivery_count),
+            ])
+        })
+        .collect();
+    let consumers = group
+        .consumers
+        .iter()
+        .map(|(consumer_name, consumer)| {
+            let pending: Vec<_> = group
+                .pending_for(consumer_name)
+                .map(|(id, entry)| {
+                    encode_array(vec![
+                        encode_id(*id),
+                        encode_integer(entry.delivery_time),
+                        encode_integer(entry.delivery_count),
+                    ])
+                })
+                .collect();
+            let active_time = consumer.active_time.map_or(-1, |time| time as i64);
+            encode_map(vec![
+                ("name", encode(&RespValue::BulkString(consumer_name))),
+                ("seen-time", encode_integer(consumer.seen_time)),
+                (
+                    "active-time",
+                    encode(&RespValue::SimpleInteger(active_time)),
+                ),
+                ("pel-count", encode_integer(pending.len() as u64)),
+                (
+                    "pel",
+                    encode_array(pending.into_iter().take(count).collect()),
+                ),
+            ])
+        })
+        .collect();
+    encode_map(vec![
+        ("name", encode(&RespValue::BulkString(name))),
+        ("last-delivered-id", encode_id(group.last_id)),
+        ("entries-read", encode_optional_integer(group.entries_read)),
+        ("lag", encode_optional_integer(info.lag(group))),
+        ("pel-count", encode_integer(group.pending.len() as u64)),
+        ("pel", encode_array(pending)),
+        ("consumers", encode_array(consumers)),
+    ])
+}
+
+// The reply to XPENDING without a range: the number of pending entries, the lowest and
+// highest of their IDs, and how many each consumer has.
+fn encode_pending_summary(group: &ConsumerGroup) -> Vec<u8> {
+    let (Some((first, _)), Some((last, _))) = (
+        group.pending.first_key_value(),
+        group.pending.last_key_value(),
+    ) else {
+        let null = encode(&RespValue::NullBulkString);
+        return encode_array(vec![
+            encode_integer(0),
+            null.clone(),
+            null,
+            encode(&RespValue::NullArray),
+        ]);
+    };
+    let mut counts = BTreeMap::<&[u8], u64>::new();
+    for entry in group.pending.values() {
+        *counts.entry(&entry.consumer).or_default() += 1;
+    }
+    let counts = counts
+        .into_iter()
+        .map(|(consumer, count)| {
+            encode(&RespValue::Array(vec![
+                RespValue::BulkString(consumer),
+                RespValue::BulkString(count.to_string().as_bytes()),
+            ]))
+        })
+        .collect();
+    encode_array(vec![
+        encode_integer(group.pending.len() as u64),
+        encode_id(*first),
+        encode_id(*last),
+        encode_array(counts),
+    ])
+}
+
+// The reply to XPENDING with a range: each pending entry's ID, consumer, idle time and
+// delivery count.
+fn encode_pending_entries(group: &ConsumerGroup, range: &PendingRange) -> Vec<u8> {
+    encode_array(
+        group
+            .pending_between(range.start, range.end)
+            .filter(|(_, entry)| {
+                range
+                    .consumer
+                    .is_none_or(|consumer| entry.consumer == consumer)
+            })
+            .filter(|(_, entry)| entry.idle_millis() >= range.min_idle)
+            .take(range.count)
+            .map(|(id, entry)| {
+                encode_array(vec![
+                    encode_id(*id),
+                    encode(&RespValue::BulkString(&entry.consumer)),
+                    encode_integer(entry.idle_millis()),
+                    encode_integer(entry.delivery_count),
+                ])
+            })
+            .collect(),
+    )
+}
+
+// The arguments of an XADD with the entry's actual ID, to propagate in place of the
+// original.
+fn xadd_command(
+    key: &[u8],
+    no_mkstream: bool,
+    trim: Option<&StreamTrim>,
+    id: StreamId,
+    fields: &[(&[u8], &[u8])],
+) -> Vec<Vec<u8>> {
+    let mut command = vec![b"XADD".to_vec(), key.to_vec()];
+    if no_mkstream {
+        command.push(b"NOMKSTREAM".to_vec());
+    }
+    if let Some(trim) = trim {
+        let (option, threshold) = match trim.threshold {
+            TrimThreshold::MaxLen(max_len) => (&b"MAXLEN"[..], max_len.to_string()),
+            TrimThreshold::MinId(min_id) => (&b"MINID"[..], min_id.to_string()),
+        };
+        command.push(option.to_vec());
+        command.push(if trim.approximate { b"~" } else { b"=" }.to_vec());
+        command.push(threshold.into_bytes());
+        if let Some(limit) = trim.limit {
+            command.push(b"LIMIT".to_vec());
+            command.push(limit.to_string().into_bytes());
+        }
+    }
+    command.push(id.to_string().into_bytes());
+    for (field, value) in fields {
+        command.push(field.to_vec());
+        command.push(value.to_vec());
+    }
+    command
+}
+
+// Pads the loaded databases to the number in the config, checking they all fit.
+fn with_configured_databases(
+    config: &HashMap<Vec<u8>, Vec<u8>>,
+    mut databases: Vec<Database>,
+) -> Result<Vec<Database>, RdbFileError> {
+    let count = config
+        .get(b"databases".as_slice())
+        .and_then(|count| std::str::from_utf8(count).ok()?.parse().ok())
+        .unwrap_or(DEFAULT_DATABASES);
+    if databases.len() > count {
+        return Err(RdbFileError::InvalidFile(format!(
+            "Database {} is out of range, only {} databases configured",
+            databases.len() - 1,
+            count
+        )));
+    }
+    databases.resize_with(count, Database::new);
+    Ok(databases)
+}
+
+// The time in milliseconds since the epoch, as an argument to commands like PEXPIREAT.
+fn unix_millis_string(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+        .to_string()
+}
+
+fn unix_time_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Default for RedisReplicationInfo {
+    fn default() -> Self {
+        RedisReplicationInfo {
+            role: RedisRole::Master,
+            connected_slaves: 0,
+            master_replid: String::default(),
+            master_repl_offset: 0,
+            master_replid2: NO_REPLID.to_string(),
+            second_repl_offset: -1,
+            master_host: String::default(),
+            master_port: 0,
+            master_link_up: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Handles the command as sent by a client, returning the reply, which is an error
+    // reply if the command fails.
+    async fn run(handler: &RedisHandler, args: &[&[u8]]) -> Vec<u8> {
+        let input = encode(&RespValue::Array(
+            args.iter().map(|arg| RespValue::BulkString(arg)).collect(),
+        ));
+        let mut reply = Vec::new();
+        let result = match parse_commands(&input) {
+            Ok(mut requests) => unsafe {
+                handler
+                    .handle_request(
+                        requests.remove(0),
+                        &mut reply,
+                        &mut ConnectionState::default(),
+                    )
+                    .await
+            },
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            reply = encode(&RespValue::SimpleError(error.reply_message().as_bytes()));
+        }
+        reply
+    }
+
+    // A client which has gone, so that replies to it can't be written.
+    struct Disconnected;
+
+    impl tokio::io::AsyncWrite for Disconnected {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            _: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn propagates_write_when_reply_fails() {
+        let handler = RedisHandler::new();
+        let (_, mut replica) = handler.register_replica(&ConnectionState::default());
+        let command = encode(&RespValue::Array(vec![
+            RespValue::BulkString(b"SADD"),
+            RespValue::BulkString(b"s"),
+            RespValue::BulkString(b"a"),
+        ]));
+        let request = parse_commands(&command).unwrap().remove(0);
+        let mut connection = ConnectionState::default();
+        let result = unsafe {
+            handler
+                .handle_write(request, &command, &mut Disconnected, &mut connection)
+                .await
+        };
+        assert!(result.is_err());
+        assert_eq!(
+            replica.try_recv().unwrap(),
+            b"*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n"[..]
+        );
+        assert_eq!(replica.try_recv().unwrap(), command);
+    }
+
+    #[tokio::test]
+    async fn refuses_too_many_random_picks() {
+        let handler = RedisHandler::new();
+        run(&handler, &[b"SADD", b"set", b"a"]).await;
+        run(&handler, &[b"HSET", b"hash", b"f", b"v"]).await;
+        for count in [&b"-4611686018427387903"[..], b"-1000000000"] {
+            let reply = run(&handler, &[b"SRANDMEMBER", b"set", count]).await;
+            assert!(reply.starts_with(b"-ERR"));
+            let reply = run(&handler, &[b"HRANDFIELD", b"hash", count, b"WITHVALUES"]).await;
+            assert!(reply.starts_with(b"-ERR"));
+        }
+        let reply = run(&handler, &[b"SRANDMEMBER", b"set", b"-1000"]).await;
+        assert!(reply.starts_with(b"*1000\r\n"));
+        let reply = run(&handler, &[b"HRANDFIELD", b"hash", b"-1000"]).await;
+        assert!(reply.starts_with(b"*1000\r\n"));
+        assert_eq!(run(&handler, &[b"PING"]).await, b"+PONG\r\n");
+    }
+
+    #[tokio::test]
+    async fn info_counts_expired_keys() {
+        let handler = RedisHandler::new();
+        for key in [b"a", b"b", b"c"] {
+            run(&handler, &[b"SET", key, b"v", b"PXAT", b"1"]).await;
+        }
+        // Looking up a key finds it expired.
+        assert_eq!(run(&handler, &[b"GET", b"a"]).await, b"$-1\r\n");
+        assert_eq!(handler.stats_info_string(), "expired_keys:1");
+
+        // The active cycle removes the rest.
+        handler.remove_expired_keys();
+        assert_eq!(run(&handler, &[b"DBSIZE"]).await, b":0\r\n");
+        let reply = run(&handler, &[b"INFO", b"stats"]).await;
+        assert!(String::from_utf8_lossy(&reply).contains("expired_keys:3"));
+
+        // Each handler keeps its own count.
+        assert_eq!(RedisHandler::new().stats_info_string(), "expired_keys:0");
+    }
+
+    #[tokio::test]
+    async fn flushall_resets_file_during_background_save() {
+        let dir = std::env::temp_dir().join(format!("flushall-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = HashMap::from([
+            (b"dir".to_vec(), dir.to_string_lossy().as_bytes().to_vec()),
+            (b"dbfilename".to_vec(), b"dump.rdb".to_vec()),
+        ]);
+        // Enough keys that the background save is likely still writing when the flush
+        // comes, though the file must be reset either way.
+        let mut db = Database::new();
+        for i in 0..100_000 {
+            db.insert(i.to_string().into_bytes(), ValueType::new(vec![b'v'; 100]));
+        }
+        let handler =
+            RedisHandler::new_with_contents(config, RedisReplicationInfo::default(), vec![db])
+                .unwrap();
+
+        assert_eq!(
+            run(&handler, &[b"BGSAVE"]).await,
+            b"+Background saving started\r\n"
+        );
+        assert_eq!(run(&handler, &[b"FLUSHALL"]).await, b"+OK\r\n");
+        while handler.save_state.bgsave_in_progress.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let contents = std::fs::read(dir.join("dump.rdb")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let databases = RdbReader::new(&contents[..]).read_contents().unwrap();
+        assert!(databases.iter().all(|db| db.is_empty()));
     }
 }
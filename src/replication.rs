@@ -0,0 +1,334 @@
+// Support for the Redis replication protocol.
+//
+// A replica opens a connection to its master, performs the handshake
+// (PING, REPLCONF, PSYNC), receives an RDB snapshot of the master's
+// dataset and then receives the stream of write commands the master
+// applies.  If the link drops, the replica asks to continue from the
+// last offset it processed, which the master can serve from its
+// replication backlog.  This module only deals with the wire protocol
+// and the backlog; applying the snapshot and commands is left to the
+// RedisHandler.
+
+use bytes::{Buf, BytesMut};
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::errors::{RedisError, RespError};
+use crate::resp_parser::{parse_integer, RespParser, RespValue};
+
+/// A connection from a replica to its master.
+pub(crate) struct MasterLink {
+    stream: TcpStream,
+    buffer: BytesMut,
+    parser: RespParser<'static>,
+}
+
+/// The master's reply to PSYNC.
+#[derive(Debug)]
+pub(crate) enum PsyncReply {
+    /// The master sent a snapshot of its dataset, which the stream continues from.
+    FullResync {
+        replid: String,
+        offset: u64,
+        rdb: Vec<u8>,
+    },
+    /// The master continues the stream from the requested offset.  It includes its
+    /// replication id if that may have changed, e.g. after a failover.
+    Continue { replid: Option<String> },
+}
+
+/// A fixed size circular buffer holding the most recent part of the replication
+/// stream, so that replicas which briefly disconnect can resume without a full
+/// resynchronisation.
+#[derive(Debug)]
+pub(crate) struct ReplicationBacklog {
+    buffer: Vec<u8>,
+    // Index in the buffer where the next byte will be written.
+    next_idx: usize,
+    // Number of valid bytes held in the buffer.
+    histlen: usize,
+    // Replication offset of the last byte fed to the backlog.
+    end_offset: u64,
+}
+
+/// Generates a random 40 character replication id.
+pub(crate) fn new_replication_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+impl MasterLink {
+    pub(crate) async fn connect(host: &str, port: u16) -> Result<Self, RedisError> {
+        Ok(MasterLink {
+            stream: TcpStream::connect((host, port)).await?,
+            buffer: BytesMut::with_capacity(4096),
+            parser: RespParser::new(),
+        })
+    }
+
+    /// Performs the initial handshake, announcing the port this replica listens on.
+    pub(crate) async fn handshake(&mut self, listening_port: &[u8]) -> Result<(), RedisError> {
+        self.send_command(&[b"PING"]).await?;
+        self.expect_simple_string(b"PONG").await?;
+        self.send_command(&[b"REPLCONF", b"listening-port", listening_port])
+            .await?;
+        self.expect_simple_string(b"OK").await?;
+        self.send_command(&[b"REPLCONF", b"capa", b"psync2"])
+            .await?;
+        self.expect_simple_string(b"OK").await
+    }
+
+    /// Asks the master to continue the stream from the given replication id and offset,
+    /// which should be "?" and -1 to request a full resynchronisation.
+    pub(crate) async fn psync(
+        &mut self,
+        replid: &[u8],
+        offset: i64,
+    ) -> Result<PsyncReply, RedisError> {
+        self.send_command(&[b"PSYNC", replid, offset.to_string().as_bytes()])
+            .await?;
+        let reply = self.read_simple_string().await?;
+        let fields = reply.split(|b| *b == b' ').collect::<Vec<_>>();
+        match fields[..] {
+            [b"FULLRESYNC", replid, offset] => {
+                let replid = String::from_utf8_lossy(replid).into_owned();
+                let offset = parse_integer(offset)? as u64;
+                let rdb = self.read_rdb().await?;
+                Ok(PsyncReply::FullResync {
+                    replid,
+                    offset,
+                    rdb,
+                })
+            }
+            [b"CONTINUE"] => Ok(PsyncReply::Continue { replid: None }),
+            [b"CONTINUE", replid] => Ok(PsyncReply::Continue {
+                replid: Some(String::from_utf8_lossy(replid).into_owned()),
+            }),
+            _ => Err(RedisError::ReplicationError(format!(
+                "Unexpected reply to PSYNC: {}",
+                String::from_utf8_lossy(&reply)
+            ))),
+        }
+    }
+
+    /// Returns the raw bytes of the next command propagated by the master, or None
+    /// if the master closed the connection.
+    pub(crate) async fn next_command(&mut self) -> Result<Option<BytesMut>, RedisError> {
+        loop {
+            if let Some(len) = self.complete_value_length()? {
+                return Ok(Some(self.buffer.split_to(len)));
+            }
+            if !self.fill_buffer().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Reports the replication offset this replica has processed.
+    pub(crate) async fn send_ack(&mut self, offset: u64) -> Result<(), RedisError> {
+        self.send_command(&[b"REPLCONF", b"ACK", offset.to_string().as_bytes()])
+            .await
+    }
+
+    async fn send_command(&mut self, args: &[&[u8]]) -> Result<(), RedisError> {
+        let command = RespValue::Array(args.iter().map(|arg| RespValue::BulkString(arg)).collect());
+        // Serialise up front so the command goes out in a single write.
+        let mut buffer = Vec::new();
+        command.write(&mut buffer)?;
+        self.stream.write_all(&buffer).await?;
+        Ok(())
+    }
+
+    async fn expect_simple_string(&mut self, expected: &[u8]) -> Result<(), RedisError> {
+        let reply = self.read_simple_string().await?;
+        if reply != expected {
+            return Err(RedisError::ReplicationError(format!(
+                "Expected {} from master, got {}",
+                String::from_utf8_lossy(expected),
+                String::from_utf8_lossy(&reply)
+            )));
+        }
+        Ok(())
+    }
+
+    async fn read_simple_string(&mut self) -> Result<Vec<u8>, RedisError> {
+        let len = loop {
+            if let Some(len) = self.complete_value_length()? {
+                break len;
+            }
+            if !self.fill_buffer().await? {
+                return Err(RedisError::ReplicationError(
+                    "Master closed the connection during the handshake".to_string(),
+                ));
+            }
+        };
+        let reply = match self.parser.parse_value(&self.buffer[..len])? {
+            (RespValue::SimpleString(contents), _) => Ok(contents.to_vec()),
+            (RespValue::SimpleError(contents), _) => Err(RedisError::ReplicationError(format!(
+                "Master replied with error: {}",
+                String::from_utf8_lossy(contents)
+            ))),
+            (value, _) => Err(RedisError::ReplicationError(format!(
+                "Expected SimpleString from master, got {}",
+                value.type_string()
+            ))),
+        };
+        self.buffer.advance(len);
+        reply
+    }
+
+    // Reads the RDB payload following FULLRESYNC.  It is sent like a bulk string,
+    // but without the trailing separator.
+    async fn read_rdb(&mut self) -> Result<Vec<u8>, RedisError> {
+        loop {
+            // The master may send newlines to keep the link alive while it prepares
+            // the snapshot.
+            while self.buffer.first() == Some(&b'\n') {
+                self.buffer.advance(1);
+            }
+            if let Some(header_end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                if self.buffer[0] != b'$' {
+                    return Err(RedisError::ReplicationError(format!(
+                        "Expected RDB payload from master, got starting byte {:#04x}",
+                        self.buffer[0]
+                    )));
+                }
+                let size = parse_integer(&self.buffer[1..header_end])?;
+                if size < 0 {
+                    return Err(RespError::BadBulkStringSize(size).into());
+                }
+                let size = size as usize;
+                while self.buffer.len() < header_end + 2 + size {
+                    if !self.fill_buffer().await? {
+                        return Err(RespError::UnexpectedEnd.into());
+                    }
+                }
+                self.buffer.advance(header_end + 2);
+                return Ok(self.buffer.split_to(size).to_vec());
+            }
+            if !self.fill_buffer().await? {
+                return Err(RespError::UnexpectedEnd.into());
+            }
+        }
+    }
+
+    // Returns the length of the first value in the buffer if it has been received completely.
+    fn complete_value_length(&mut self) -> Result<Option<usize>, RedisError> {
+        Ok(self.parser.parse_frame(&self.buffer)?.map(|(_, len)| len))
+    }
+
+    // Reads more data from the master into the buffer, returning false if the
+    // connection was closed.
+    async fn fill_buffer(&mut self) -> Result<bool, RedisError> {
+        Ok(self.stream.read_buf(&mut self.buffer).await? != 0)
+    }
+}
+
+impl ReplicationBacklog {
+    /// Creates an empty backlog holding up to size bytes, for a stream currently at
+    /// the given replication offset.
+    pub(crate) fn new(size: usize, offset: u64) -> Self {
+        ReplicationBacklog {
+            buffer: vec![0u8; size.max(1)],
+            next_idx: 0,
+            histlen: 0,
+            end_offset: offset,
+        }
+    }
+
+    /// Appends part of the replication stream, discarding the oldest bytes if needed.
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        let size = self.buffer.len();
+        self.end_offset += data.len() as u64;
+        // Only the last size bytes can be retained.
+        let data = &data[data.len().saturating_sub(size)..];
+        let first_len = data.len().min(size - self.next_idx);
+        self.buffer[self.next_idx..self.next_idx + first_len].copy_from_slice(&data[..first_len]);
+        self.buffer[..data.len() - first_len].copy_from_slice(&data[first_len..]);
+        self.next_idx = (self.next_idx + data.len()) % size;
+        self.histlen = (self.histlen + data.len()).min(size);
+    }
+
+    /// Returns the stream from the given offset up to the end of the backlog, or None if
+    /// the backlog no longer (or does not yet) hold that offset.
+    pub(crate) fn read_from(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset < self.first_byte_offset() || offset > self.end_offset + 1 {
+            return None;
+        }
+        let size = self.buffer.len();
+        let count = (self.end_offset + 1 - offset) as usize;
+        let start_idx = (self.next_idx + size - count) % size;
+        let mut result = Vec::with_capacity(count);
+        let first_len = count.min(size - start_idx);
+        result.extend_from_slice(&self.buffer[start_idx..start_idx + first_len]);
+        result.extend_from_slice(&self.buffer[..count - first_len]);
+        Some(result)
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub(crate) fn histlen(&self) -> usize {
+        self.histlen
+    }
+
+    /// The replication offset of the oldest byte held.
+    pub(crate) fn first_byte_offset(&self) -> u64 {
+        self.end_offset + 1 - self.histlen as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backlog_reads_from_offset() {
+        let mut backlog = ReplicationBacklog::new(16, 100);
+        backlog.feed(b"hello");
+        backlog.feed(b"world");
+
+        assert_eq!(backlog.first_byte_offset(), 101);
+        assert_eq!(backlog.histlen(), 10);
+        assert_eq!(backlog.read_from(101), Some(b"helloworld".to_vec()));
+        assert_eq!(backlog.read_from(106), Some(b"world".to_vec()));
+        assert_eq!(backlog.read_from(111), Some(Vec::new()));
+    }
+
+    #[test]
+    fn backlog_rejects_offsets_outside_history() {
+        let mut backlog = ReplicationBacklog::new(16, 100);
+        backlog.feed(b"hello");
+
+        assert_eq!(backlog.read_from(100), None);
+        assert_eq!(backlog.read_from(107), None);
+    }
+
+    #[test]
+    fn backlog_wraps_around() {
+        let mut backlog = ReplicationBacklog::new(8, 0);
+        backlog.feed(b"abcdef");
+        backlog.feed(b"ghij");
+
+        assert_eq!(backlog.histlen(), 8);
+        assert_eq!(backlog.first_byte_offset(), 3);
+        assert_eq!(backlog.read_from(3), Some(b"cdefghij".to_vec()));
+        assert_eq!(backlog.read_from(8), Some(b"hij".to_vec()));
+        assert_eq!(backlog.read_from(2), None);
+    }
+
+    #[test]
+    fn backlog_keeps_tail_of_large_feed() {
+        let mut backlog = ReplicationBacklog::new(4, 0);
+        backlog.feed(b"ab");
+        backlog.feed(b"cdefgh");
+
+        assert_eq!(backlog.first_byte_offset(), 5);
+        assert_eq!(backlog.read_from(5), Some(b"efgh".to_vec()));
+    }
+}
@@ -1,7 +1,8 @@
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::errors::RedisError;
 use crate::resp_parser::{parse_integer, RespParser, RespValue};
+use crate::streams::StreamId;
 
 /// Redis commands parsed from RESP.
 #[derive(PartialEq, Clone, Debug)]
@@ -12,14 +13,699 @@ pub(crate) enum RedisRequest<'a> {
         key: &'a [u8],
         value: &'a [u8],
         expiration: Option<SystemTime>,
+        options: SetOptions,
     },
     ConfigGet(Vec<&'a [u8]>),
     Get(&'a [u8]),
+    // SET with NX, replying with whether the key was set.
+    SetNx {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    Append {
+        key: &'a [u8],
+        value: &'a [u8],
+    },
+    StrLen(&'a [u8]),
+    GetRange {
+        key: &'a [u8],
+        start: i64,
+        end: i64,
+    },
+    SetRange {
+        key: &'a [u8],
+        offset: usize,
+        value: &'a [u8],
+    },
+    GetDel(&'a [u8]),
+    // GETEX, which sets the expiration, or with persist removes it.
+    GetEx {
+        key: &'a [u8],
+        expiration: Option<SystemTime>,
+        persist: bool,
+    },
+    MGet(Vec<&'a [u8]>),
+    // MSET, or MSETNX which sets none of the keys if any exist.
+    MSet {
+        pairs: Vec<(&'a [u8], &'a [u8])>,
+        nx: bool,
+    },
     Keys(&'a [u8]),
-    Info(Option<&'a [u8]>)
+    Scan {
+        cursor: u64,
+        pattern: Option<&'a [u8]>,
+        count: usize,
+        // Only return keys holding values of this type.
+        value_type: Option<&'a [u8]>,
+    },
+    Info(Option<&'a [u8]>),
+    ReplConf(ReplConf),
+    Psync {
+        replid: &'a [u8],
+        offset: i64,
+    },
+    Wait {
+        num_replicas: u64,
+        timeout_millis: u64,
+    },
+    // The master's host and port, or None for REPLICAOF NO ONE.
+    ReplicaOf(Option<(&'a [u8], u16)>),
+    Save,
+    BgSave,
+    LastSave,
+    Select(usize),
+    SwapDb(usize, usize),
+    Move {
+        key: &'a [u8],
+        db: usize,
+    },
+    // The EXPIRE family, where the expiration may have been given relative to now.
+    Expire {
+        key: &'a [u8],
+        expiration: SystemTime,
+        conditions: Vec<ExpireCondition>,
+    },
+    // TTL, or PTTL in milliseconds.
+    Ttl {
+        key: &'a [u8],
+        millis: bool,
+    },
+    // EXPIRETIME, or PEXPIRETIME in milliseconds.
+    ExpireTime {
+        key: &'a [u8],
+        millis: bool,
+    },
+    Persist(&'a [u8]),
+    Del(Vec<&'a [u8]>),
+    Unlink(Vec<&'a [u8]>),
+    Exists(Vec<&'a [u8]>),
+    Touch(Vec<&'a [u8]>),
+    Type(&'a [u8]),
+    // RENAME, or RENAMENX which won't replace an existing key.
+    Rename {
+        key: &'a [u8],
+        new_key: &'a [u8],
+        nx: bool,
+    },
+    Copy {
+        source: &'a [u8],
+        destination: &'a [u8],
+        // The database to copy to, if not the selected one.
+        db: Option<usize>,
+        replace: bool,
+    },
+    RandomKey,
+    DbSize,
+    // FLUSHDB and FLUSHALL, which with ASYNC free the old data on another thread.
+    FlushDb {
+        asynchronous: bool,
+    },
+    FlushAll {
+        asynchronous: bool,
+    },
+    // LPUSH and RPUSH.
+    Push {
+        key: &'a [u8],
+        values: Vec<&'a [u8]>,
+        end: ListEnd,
+    },
+    // LPOP and RPOP, where a count asks for an array reply.
+    Pop {
+        key: &'a [u8],
+        count: Option<usize>,
+        end: ListEnd,
+    },
+    LLen(&'a [u8]),
+    LRange {
+        key: &'a [u8],
+        start: i64,
+        stop: i64,
+    },
+    LIndex {
+        key: &'a [u8],
+        index: i64,
+    },
+    LSet {
+        key: &'a [u8],
+        index: i64,
+        value: &'a [u8],
+    },
+    LRem {
+        key: &'a [u8],
+        count: i64,
+        value: &'a [u8],
+    },
+    LTrim {
+        key: &'a [u8],
+        start: i64,
+        stop: i64,
+    },
+    LInsert {
+        key: &'a [u8],
+        before: bool,
+        pivot: &'a [u8],
+        value: &'a [u8],
+    },
+    LMove {
+        source: &'a [u8],
+        destination: &'a [u8],
+        from: ListEnd,
+        to: ListEnd,
+    },
+    // BLPOP and BRPOP, where a timeout of None blocks indefinitely.
+    BlockingPop {
+        keys: Vec<&'a [u8]>,
+        end: ListEnd,
+        timeout: Option<Duration>,
+    },
+    BlockingMove {
+        source: &'a [u8],
+        destination: &'a [u8],
+        from: ListEnd,
+        to: ListEnd,
+        timeout: Option<Duration>,
+    },
+    HSet {
+        key: &'a [u8],
+        pairs: Vec<(&'a [u8], &'a [u8])>,
+    },
+    HSetNx {
+        key: &'a [u8],
+        field: &'a [u8],
+        value: &'a [u8],
+    },
+    HGet {
+        key: &'a [u8],
+        field: &'a [u8],
+    },
+    HMGet {
+        key: &'a [u8],
+        fields: Vec<&'a [u8]>,
+    },
+    HDel {
+        key: &'a [u8],
+        fields: Vec<&'a [u8]>,
+    },
+    HExists {
+        key: &'a [u8],
+        field: &'a [u8],
+    },
+    HLen(&'a [u8]),
+    HGetAll(&'a [u8]),
+    HKeys(&'a [u8]),
+    HVals(&'a [u8]),
+    HIncrBy {
+        key: &'a [u8],
+        field: &'a [u8],
+        increment: i64,
+    },
+    HIncrByFloat {
+        key: &'a [u8],
+        field: &'a [u8],
+        increment: f64,
+    },
+    HScan {
+        key: &'a [u8],
+        cursor: u64,
+        pattern: Option<&'a [u8]>,
+        count: usize,
+        no_values: bool,
+    },
+    // Without a count, a single field is returned rather than an array.
+    HRandField {
+        key: &'a [u8],
+        count: Option<i64>,
+        with_values: bool,
+    },
+    HExpire {
+        key: &'a [u8],
+        expiration: SystemTime,
+        condition: Option<ExpireCondition>,
+        fields: Vec<&'a [u8]>,
+    },
+    HTtl {
+        key: &'a [u8],
+        fields: Vec<&'a [u8]>,
+    },
+    HPersist {
+        key: &'a [u8],
+        fields: Vec<&'a [u8]>,
+    },
+    SAdd {
+        key: &'a [u8],
+        members: Vec<&'a [u8]>,
+    },
+    SRem {
+        key: &'a [u8],
+        members: Vec<&'a [u8]>,
+    },
+    SMembers(&'a [u8]),
+    SIsMember {
+        key: &'a [u8],
+        member: &'a [u8],
+    },
+    SMIsMember {
+        key: &'a [u8],
+        members: Vec<&'a [u8]>,
+    },
+    SCard(&'a [u8]),
+    // Without a count, a single member is returned rather than an array.
+    SPop {
+        key: &'a [u8],
+        count: Option<usize>,
+    },
+    SRandMember {
+        key: &'a [u8],
+        count: Option<i64>,
+    },
+    // SINTER, SUNION and SDIFF.
+    SCombine {
+        op: SetOp,
+        keys: Vec<&'a [u8]>,
+    },
+    // SINTERSTORE, SUNIONSTORE and SDIFFSTORE.
+    SCombineStore {
+        op: SetOp,
+        destination: &'a [u8],
+        keys: Vec<&'a [u8]>,
+    },
+    // A limit of zero means no limit.
+    SInterCard {
+        keys: Vec<&'a [u8]>,
+        limit: usize,
+    },
+    SMove {
+        source: &'a [u8],
+        destination: &'a [u8],
+        member: &'a [u8],
+    },
+    // ZINCRBY is parsed as ZADD with INCR.
+    ZAdd {
+        key: &'a [u8],
+        options: ZAddOptions,
+        pairs: Vec<(f64, &'a [u8])>,
+    },
+    ZRem {
+        key: &'a [u8],
+        members: Vec<&'a [u8]>,
+    },
+    ZCard(&'a [u8]),
+    ZScore {
+        key: &'a [u8],
+        member: &'a [u8],
+    },
+    ZMScore {
+        key: &'a [u8],
+        members: Vec<&'a [u8]>,
+    },
+    // ZRANK and ZREVRANK.
+    ZRank {
+        key: &'a [u8],
+        member: &'a [u8],
+        rev: bool,
+        with_score: bool,
+    },
+    ZCount {
+        key: &'a [u8],
+        min: ScoreBound,
+        max: ScoreBound,
+    },
+    ZLexCount {
+        key: &'a [u8],
+        min: LexBound<'a>,
+        max: LexBound<'a>,
+    },
+    // ZRANGE, along with ZREVRANGE, ZRANGEBYSCORE, ZREVRANGEBYSCORE, ZRANGEBYLEX and
+    // ZREVRANGEBYLEX which are older forms of it.  The limit is an offset and a count.
+    ZRange {
+        key: &'a [u8],
+        range: ZRangeBy<'a>,
+        rev: bool,
+        limit: Option<(i64, i64)>,
+        with_scores: bool,
+    },
+    // ZPOPMIN and ZPOPMAX.
+    ZPop {
+        key: &'a [u8],
+        count: usize,
+        max: bool,
+    },
+    // ZUNIONSTORE, ZINTERSTORE and ZDIFFSTORE.  Weights may be empty, meaning all one.
+    ZCombineStore {
+        op: SetOp,
+        destination: &'a [u8],
+        keys: Vec<&'a [u8]>,
+        weights: Vec<f64>,
+        aggregate: Aggregate,
+    },
+    // BZPOPMIN and BZPOPMAX.
+    BlockingZPop {
+        keys: Vec<&'a [u8]>,
+        max: bool,
+        timeout: Option<Duration>,
+    },
+    XAdd {
+        key: &'a [u8],
+        no_mkstream: bool,
+        trim: Option<StreamTrim>,
+        id: XAddId,
+        fields: Vec<(&'a [u8], &'a [u8])>,
+    },
+    XLen(&'a [u8]),
+    // XRANGE and XREVRANGE, between inclusive bounds.
+    XRange {
+        key: &'a [u8],
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    },
+    XDel {
+        key: &'a [u8],
+        ids: Vec<StreamId>,
+    },
+    XTrim {
+        key: &'a [u8],
+        trim: StreamTrim,
+    },
+    // Each key is read after the ID in the same position, where None, given as `$`, means
+    // after the stream's last ID.  Block is None not to block, or the timeout, which is
+    // None to wait forever.
+    XRead {
+        keys: Vec<&'a [u8]>,
+        ids: Vec<Option<StreamId>>,
+        count: Option<usize>,
+        block: Option<Option<Duration>>,
+    },
+    // Like XRead, but for a consumer of the group, where None, given as `>`, means the
+    // entries the group is yet to deliver.  Other IDs read the consumer's pending entries.
+    XReadGroup {
+        group: &'a [u8],
+        consumer: &'a [u8],
+        keys: Vec<&'a [u8]>,
+        ids: Vec<Option<StreamId>>,
+        count: Option<usize>,
+        block: Option<Option<Duration>>,
+        no_ack: bool,
+    },
+    // For the XGROUP subcommands, an ID of None, given as `$`, means the stream's last ID.
+    XGroupCreate {
+        key: &'a [u8],
+        group: &'a [u8],
+        id: Option<StreamId>,
+        mkstream: bool,
+        entries_read: Option<u64>,
+    },
+    XGroupSetId {
+        key: &'a [u8],
+        group: &'a [u8],
+        id: Option<StreamId>,
+        entries_read: Option<u64>,
+    },
+    XGroupDestroy {
+        key: &'a [u8],
+        group: &'a [u8],
+    },
+    XGroupCreateConsumer {
+        key: &'a [u8],
+        group: &'a [u8],
+        consumer: &'a [u8],
+    },
+    XGroupDelConsumer {
+        key: &'a [u8],
+        group: &'a [u8],
+        consumer: &'a [u8],
+    },
+    XAck {
+        key: &'a [u8],
+        group: &'a [u8],
+        ids: Vec<StreamId>,
+    },
+    // A summary of the group's pending entries, or given a range, the entries themselves.
+    XPending {
+        key: &'a [u8],
+        group: &'a [u8],
+        range: Option<PendingRange<'a>>,
+    },
+    XClaim {
+        key: &'a [u8],
+        group: &'a [u8],
+        consumer: &'a [u8],
+        min_idle: u64,
+        ids: Vec<StreamId>,
+        options: ClaimOptions,
+    },
+    XAutoClaim {
+        key: &'a [u8],
+        group: &'a [u8],
+        consumer: &'a [u8],
+        min_idle: u64,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    },
+    // With FULL, the entries and groups in detail, up to a count which is zero for all.
+    XInfoStream {
+        key: &'a [u8],
+        full: Option<usize>,
+    },
+    XInfoGroups(&'a [u8]),
+    XInfoConsumers {
+        key: &'a [u8],
+        group: &'a [u8],
+    },
+}
+
+/// The end of a list a command operates on.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum ListEnd {
+    Left,
+    Right,
+}
+
+/// How a set command combines several sets.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// The options of SET, besides the expiration.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub(crate) struct SetOptions {
+    // Only set the key if it doesn't exist.
+    pub(crate) nx: bool,
+    // Only set the key if it already exists.
+    pub(crate) xx: bool,
+    // Keep the key's current expiration rather than clearing it.
+    pub(crate) keep_ttl: bool,
+    // Reply with the old value, or null, rather than OK.
+    pub(crate) get: bool,
+}
+
+/// The options of ZADD.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub(crate) struct ZAddOptions {
+    // Only add new members.
+    pub(crate) nx: bool,
+    // Only update existing members.
+    pub(crate) xx: bool,
+    // Only update scores to greater ones.
+    pub(crate) gt: bool,
+    // Only update scores to lesser ones.
+    pub(crate) lt: bool,
+    // Count changed members as well as added ones.
+    pub(crate) ch: bool,
+    // Increment the score, like ZINCRBY, and reply with the new score.
+    pub(crate) incr: bool,
+}
+
+/// One end of a range of scores.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) struct ScoreBound {
+    pub(crate) value: f64,
+    pub(crate) exclusive: bool,
+}
+
+/// One end of a lexicographical range of sorted set members.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum LexBound<'a> {
+    // `-`, before every member.
+    Min,
+    // `+`, after every member.
+    Max,
+    Inclusive(&'a [u8]),
+    Exclusive(&'a [u8]),
 }
 
-pub(crate) fn parse_commands(input: &[u8]) -> Result<Vec<RedisRequest>, RedisError> {
+/// How ZRANGE selects members: by rank, which may be negative to count from the end, by
+/// score, or lexicographically.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum ZRangeBy<'a> {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound<'a>, LexBound<'a>),
+}
+
+/// How the sorted set store commands combine the scores of a member.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+/// The ID requested for a new stream entry.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum XAddId {
+    // `*`, generated from the current time.
+    Auto,
+    // `<ms>-*`, with the next sequence number for the time.
+    AutoSequence(u64),
+    Explicit(StreamId),
+}
+
+/// How to trim a stream, for XTRIM and XADD.  The approximate `~` form may trim fewer
+/// entries, and allows a limit on how many entries are removed.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) struct StreamTrim {
+    pub(crate) threshold: TrimThreshold,
+    pub(crate) approximate: bool,
+    pub(crate) limit: Option<usize>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum TrimThreshold {
+    // Remove the oldest entries beyond the length.
+    MaxLen(usize),
+    // Remove the entries with lower IDs.
+    MinId(StreamId),
+}
+
+/// Which of a group's pending entries XPENDING lists.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) struct PendingRange<'a> {
+    // In milliseconds.
+    pub(crate) min_idle: u64,
+    pub(crate) start: StreamId,
+    pub(crate) end: StreamId,
+    pub(crate) count: usize,
+    pub(crate) consumer: Option<&'a [u8]>,
+}
+
+/// How XCLAIM updates the pending entries it claims.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub(crate) struct ClaimOptions {
+    // The time since the last delivery to record, in milliseconds, rather than none.
+    pub(crate) idle: Option<u64>,
+    // The time of the last delivery to record, in milliseconds since the epoch.
+    pub(crate) time: Option<u64>,
+    // The delivery count to record, rather than one more.
+    pub(crate) retry_count: Option<u64>,
+    // Claim entries in the stream even if they aren't pending.
+    pub(crate) force: bool,
+    // Reply with just the IDs, leaving the delivery counts be.
+    pub(crate) just_id: bool,
+    // Move the group's last delivered ID on to this.
+    pub(crate) last_id: Option<StreamId>,
+}
+
+/// When the EXPIRE family of commands may change an expiration.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum ExpireCondition {
+    // Only if there is no expiration.
+    Nx,
+    // Only if there is an expiration.
+    Xx,
+    // Only if the new expiration is later, where no expiration counts as never.
+    Gt,
+    // Only if the new expiration is earlier.
+    Lt,
+}
+
+impl ExpireCondition {
+    /// Whether the condition allows replacing the current expiration, if any, with the
+    /// new one.
+    pub(crate) fn allows(self, current: Option<SystemTime>, new: SystemTime) -> bool {
+        match (self, current) {
+            (ExpireCondition::Nx, current) => current.is_none(),
+            (ExpireCondition::Xx, current) => current.is_some(),
+            (ExpireCondition::Gt, Some(current)) => new > current,
+            (ExpireCondition::Gt, None) => false,
+            (ExpireCondition::Lt, Some(current)) => new < current,
+            (ExpireCondition::Lt, None) => true,
+        }
+    }
+}
+
+/// The REPLCONF options exchanged during the replication handshake.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) enum ReplConf {
+    ListeningPort(u16),
+    Capabilities,
+    // Sent by a replica to report the replication offset it has processed.
+    Ack(u64),
+    // Sent by a master to ask its replicas to report their offsets.
+    GetAck,
+}
+
+impl RedisRequest<'_> {
+    /// Whether the request modifies the dataset, and so must be propagated to replicas.
+    /// Commands whose effect can't simply be replayed, such as SPOP which picks members
+    /// at random or XADD which may generate an ID, instead propagate an equivalent
+    /// command themselves.
+    pub(crate) fn is_write(&self) -> bool {
+        matches!(
+            self,
+            RedisRequest::SetNx { .. }
+                | RedisRequest::Append { .. }
+                | RedisRequest::SetRange { .. }
+                | RedisRequest::GetDel(..)
+                | RedisRequest::MSet { .. }
+                | RedisRequest::SwapDb(..)
+                | RedisRequest::Move { .. }
+                | RedisRequest::Persist(..)
+                | RedisRequest::Del(..)
+                | RedisRequest::Unlink(..)
+                | RedisRequest::Rename { .. }
+                | RedisRequest::Copy { .. }
+                | RedisRequest::FlushDb { .. }
+                | RedisRequest::FlushAll { .. }
+                | RedisRequest::Push { .. }
+                | RedisRequest::Pop { .. }
+                | RedisRequest::LSet { .. }
+                | RedisRequest::LRem { .. }
+                | RedisRequest::LTrim { .. }
+                | RedisRequest::LInsert { .. }
+                | RedisRequest::LMove { .. }
+                | RedisRequest::HSet { .. }
+                | RedisRequest::HSetNx { .. }
+                | RedisRequest::HDel { .. }
+                | RedisRequest::HIncrBy { .. }
+                | RedisRequest::HIncrByFloat { .. }
+                | RedisRequest::HPersist { .. }
+                | RedisRequest::SAdd { .. }
+                | RedisRequest::SRem { .. }
+                | RedisRequest::SCombineStore { .. }
+                | RedisRequest::SMove { .. }
+                | RedisRequest::ZAdd { .. }
+                | RedisRequest::ZRem { .. }
+                | RedisRequest::ZPop { .. }
+                | RedisRequest::ZCombineStore { .. }
+                | RedisRequest::XDel { .. }
+                | RedisRequest::XTrim { .. }
+                | RedisRequest::XGroupCreate { .. }
+                | RedisRequest::XGroupSetId { .. }
+                | RedisRequest::XGroupDestroy { .. }
+                | RedisRequest::XGroupCreateConsumer { .. }
+                | RedisRequest::XGroupDelConsumer { .. }
+                | RedisRequest::XAck { .. }
+        )
+    }
+}
+
+pub(crate) fn parse_commands(input: &[u8]) -> Result<Vec<RedisRequest<'_>>, RedisError> {
     if input.is_empty() {
         return Ok(Vec::new());
     }
@@ -31,7 +717,7 @@ pub(crate) fn parse_commands(input: &[u8]) -> Result<Vec<RedisRequest>, RedisErr
     Ok(requests)
 }
 
-fn parse_command(value: RespValue) -> Result<RedisRequest, RedisError> {
+pub(crate) fn parse_command(value: RespValue<'_>) -> Result<RedisRequest<'_>, RedisError> {
     match value {
         RespValue::Array(values) => {
             if values.is_empty() {
@@ -43,9 +729,166 @@ fn parse_command(value: RespValue) -> Result<RedisRequest, RedisError> {
                     b"ECHO" => parse_echo(&values[1..]),
                     b"SET" => parse_set(&values[1..]),
                     b"GET" => parse_get(&values[1..]),
+                    b"SETNX" => parse_setnx(&values[1..]),
+                    b"SETEX" => parse_setex("SETEX", b"EX", &values[1..]),
+                    b"PSETEX" => parse_setex("PSETEX", b"PX", &values[1..]),
+                    b"GETSET" => parse_getset(&values[1..]),
+                    b"APPEND" => parse_append(&values[1..]),
+                    b"STRLEN" => parse_key_only("STRLEN", RedisRequest::StrLen, &values[1..]),
+                    b"GETRANGE" => parse_getrange(&values[1..]),
+                    b"SETRANGE" => parse_setrange(&values[1..]),
+                    b"GETDEL" => parse_key_only("GETDEL", RedisRequest::GetDel, &values[1..]),
+                    b"GETEX" => parse_getex(&values[1..]),
+                    b"MGET" => parse_keys_only("MGET", RedisRequest::MGet, &values[1..]),
+                    b"MSET" => parse_mset("MSET", false, &values[1..]),
+                    b"MSETNX" => parse_mset("MSETNX", true, &values[1..]),
                     b"CONFIG" => parse_config(&values[1..]),
                     b"KEYS" => parse_keys(&values[1..]),
+                    b"SCAN" => parse_scan(&values[1..]),
                     b"INFO" => parse_info(&values[1..]),
+                    b"REPLCONF" => parse_replconf(&values[1..]),
+                    b"PSYNC" => parse_psync(&values[1..]),
+                    b"WAIT" => parse_wait(&values[1..]),
+                    b"REPLICAOF" | b"SLAVEOF" => parse_replicaof(&values[1..]),
+                    b"SAVE" => parse_no_args("SAVE", RedisRequest::Save, &values[1..]),
+                    b"BGSAVE" => parse_no_args("BGSAVE", RedisRequest::BgSave, &values[1..]),
+                    b"LASTSAVE" => parse_no_args("LASTSAVE", RedisRequest::LastSave, &values[1..]),
+                    b"SELECT" => parse_select(&values[1..]),
+                    b"SWAPDB" => parse_swapdb(&values[1..]),
+                    b"MOVE" => parse_move(&values[1..]),
+                    b"EXPIRE" => parse_expire("EXPIRE", false, false, &values[1..]),
+                    b"PEXPIRE" => parse_expire("PEXPIRE", true, false, &values[1..]),
+                    b"EXPIREAT" => parse_expire("EXPIREAT", false, true, &values[1..]),
+                    b"PEXPIREAT" => parse_expire("PEXPIREAT", true, true, &values[1..]),
+                    b"TTL" => parse_ttl("TTL", false, &values[1..]),
+                    b"PTTL" => parse_ttl("PTTL", true, &values[1..]),
+                    b"EXPIRETIME" => parse_expiretime("EXPIRETIME", false, &values[1..]),
+                    b"PEXPIRETIME" => parse_expiretime("PEXPIRETIME", true, &values[1..]),
+                    b"PERSIST" => parse_key_only("PERSIST", RedisRequest::Persist, &values[1..]),
+                    b"DEL" => parse_keys_only("DEL", RedisRequest::Del, &values[1..]),
+                    b"UNLINK" => parse_keys_only("UNLINK", RedisRequest::Unlink, &values[1..]),
+                    b"EXISTS" => parse_keys_only("EXISTS", RedisRequest::Exists, &values[1..]),
+                    b"TOUCH" => parse_keys_only("TOUCH", RedisRequest::Touch, &values[1..]),
+                    b"TYPE" => parse_key_only("TYPE", RedisRequest::Type, &values[1..]),
+                    b"RENAME" => parse_rename("RENAME", false, &values[1..]),
+                    b"RENAMENX" => parse_rename("RENAMENX", true, &values[1..]),
+                    b"COPY" => parse_copy(&values[1..]),
+                    b"RANDOMKEY" => {
+                        parse_no_args("RANDOMKEY", RedisRequest::RandomKey, &values[1..])
+                    }
+                    b"DBSIZE" => parse_no_args("DBSIZE", RedisRequest::DbSize, &values[1..]),
+                    b"FLUSHDB" => parse_flush("FLUSHDB", &values[1..]),
+                    b"FLUSHALL" => parse_flush("FLUSHALL", &values[1..]),
+                    b"LPUSH" => parse_push("LPUSH", ListEnd::Left, &values[1..]),
+                    b"RPUSH" => parse_push("RPUSH", ListEnd::Right, &values[1..]),
+                    b"LPOP" => parse_pop("LPOP", ListEnd::Left, &values[1..]),
+                    b"RPOP" => parse_pop("RPOP", ListEnd::Right, &values[1..]),
+                    b"LLEN" => parse_llen(&values[1..]),
+                    b"LRANGE" => parse_lrange(&values[1..]),
+                    b"LINDEX" => parse_lindex(&values[1..]),
+                    b"LSET" => parse_lset(&values[1..]),
+                    b"LREM" => parse_lrem(&values[1..]),
+                    b"LTRIM" => parse_ltrim(&values[1..]),
+                    b"LINSERT" => parse_linsert(&values[1..]),
+                    b"LMOVE" => parse_lmove(&values[1..]),
+                    b"BLPOP" => parse_blocking_pop("BLPOP", ListEnd::Left, &values[1..]),
+                    b"BRPOP" => parse_blocking_pop("BRPOP", ListEnd::Right, &values[1..]),
+                    b"BLMOVE" => parse_blmove(&values[1..]),
+                    b"HSET" => parse_hset(&values[1..]),
+                    b"HSETNX" => parse_hsetnx(&values[1..]),
+                    b"HGET" => parse_hget(&values[1..]),
+                    b"HMGET" => parse_hmget(&values[1..]),
+                    b"HDEL" => parse_hdel(&values[1..]),
+                    b"HEXISTS" => parse_hexists(&values[1..]),
+                    b"HLEN" => parse_key_only("HLEN", RedisRequest::HLen, &values[1..]),
+                    b"HGETALL" => parse_key_only("HGETALL", RedisRequest::HGetAll, &values[1..]),
+                    b"HKEYS" => parse_key_only("HKEYS", RedisRequest::HKeys, &values[1..]),
+                    b"HVALS" => parse_key_only("HVALS", RedisRequest::HVals, &values[1..]),
+                    b"HINCRBY" => parse_hincrby(&values[1..]),
+                    b"HINCRBYFLOAT" => parse_hincrbyfloat(&values[1..]),
+                    b"HSCAN" => parse_hscan(&values[1..]),
+                    b"HRANDFIELD" => parse_hrandfield(&values[1..]),
+                    b"HEXPIRE" => parse_hexpire("HEXPIRE", false, false, &values[1..]),
+                    b"HPEXPIRE" => parse_hexpire("HPEXPIRE", true, false, &values[1..]),
+                    b"HEXPIREAT" => parse_hexpire("HEXPIREAT", false, true, &values[1..]),
+                    b"HPEXPIREAT" => parse_hexpire("HPEXPIREAT", true, true, &values[1..]),
+                    b"HTTL" => parse_httl(&values[1..]),
+                    b"HPERSIST" => parse_hpersist(&values[1..]),
+                    b"SADD" => parse_sadd(&values[1..]),
+                    b"SREM" => parse_srem(&values[1..]),
+                    b"SMEMBERS" => parse_key_only("SMEMBERS", RedisRequest::SMembers, &values[1..]),
+                    b"SISMEMBER" => parse_sismember(&values[1..]),
+                    b"SMISMEMBER" => parse_smismember(&values[1..]),
+                    b"SCARD" => parse_key_only("SCARD", RedisRequest::SCard, &values[1..]),
+                    b"SPOP" => parse_spop(&values[1..]),
+                    b"SRANDMEMBER" => parse_srandmember(&values[1..]),
+                    b"SINTER" => parse_scombine("SINTER", SetOp::Inter, &values[1..]),
+                    b"SUNION" => parse_scombine("SUNION", SetOp::Union, &values[1..]),
+                    b"SDIFF" => parse_scombine("SDIFF", SetOp::Diff, &values[1..]),
+                    b"SINTERSTORE" => {
+                        parse_scombinestore("SINTERSTORE", SetOp::Inter, &values[1..])
+                    }
+                    b"SUNIONSTORE" => {
+                        parse_scombinestore("SUNIONSTORE", SetOp::Union, &values[1..])
+                    }
+                    b"SDIFFSTORE" => parse_scombinestore("SDIFFSTORE", SetOp::Diff, &values[1..]),
+                    b"SINTERCARD" => parse_sintercard(&values[1..]),
+                    b"SMOVE" => parse_smove(&values[1..]),
+                    b"ZADD" => parse_zadd(&values[1..]),
+                    b"ZINCRBY" => parse_zincrby(&values[1..]),
+                    b"ZREM" => parse_zrem(&values[1..]),
+                    b"ZCARD" => parse_key_only("ZCARD", RedisRequest::ZCard, &values[1..]),
+                    b"ZSCORE" => parse_zscore(&values[1..]),
+                    b"ZMSCORE" => parse_zmscore(&values[1..]),
+                    b"ZRANK" => parse_zrank("ZRANK", false, &values[1..]),
+                    b"ZREVRANK" => parse_zrank("ZREVRANK", true, &values[1..]),
+                    b"ZCOUNT" => parse_zcount(&values[1..]),
+                    b"ZLEXCOUNT" => parse_zlexcount(&values[1..]),
+                    b"ZRANGE" => parse_zrange("ZRANGE", None, &values[1..]),
+                    b"ZREVRANGE" => {
+                        parse_zrange("ZREVRANGE", Some((RangeKind::Rank, true)), &values[1..])
+                    }
+                    b"ZRANGEBYSCORE" => parse_zrange(
+                        "ZRANGEBYSCORE",
+                        Some((RangeKind::Score, false)),
+                        &values[1..],
+                    ),
+                    b"ZREVRANGEBYSCORE" => parse_zrange(
+                        "ZREVRANGEBYSCORE",
+                        Some((RangeKind::Score, true)),
+                        &values[1..],
+                    ),
+                    b"ZRANGEBYLEX" => {
+                        parse_zrange("ZRANGEBYLEX", Some((RangeKind::Lex, false)), &values[1..])
+                    }
+                    b"ZREVRANGEBYLEX" => {
+                        parse_zrange("ZREVRANGEBYLEX", Some((RangeKind::Lex, true)), &values[1..])
+                    }
+                    b"ZPOPMIN" => parse_zpop("ZPOPMIN", false, &values[1..]),
+                    b"ZPOPMAX" => parse_zpop("ZPOPMAX", true, &values[1..]),
+                    b"ZUNIONSTORE" => {
+                        parse_zcombinestore("ZUNIONSTORE", SetOp::Union, &values[1..])
+                    }
+                    b"ZINTERSTORE" => {
+                        parse_zcombinestore("ZINTERSTORE", SetOp::Inter, &values[1..])
+                    }
+                    b"ZDIFFSTORE" => parse_zcombinestore("ZDIFFSTORE", SetOp::Diff, &values[1..]),
+                    b"BZPOPMIN" => parse_blocking_zpop("BZPOPMIN", false, &values[1..]),
+                    b"BZPOPMAX" => parse_blocking_zpop("BZPOPMAX", true, &values[1..]),
+                    b"XADD" => parse_xadd(&values[1..]),
+                    b"XLEN" => parse_key_only("XLEN", RedisRequest::XLen, &values[1..]),
+                    b"XRANGE" => parse_xrange("XRANGE", false, &values[1..]),
+                    b"XREVRANGE" => parse_xrange("XREVRANGE", true, &values[1..]),
+                    b"XDEL" => parse_xdel(&values[1..]),
+                    b"XTRIM" => parse_xtrim(&values[1..]),
+                    b"XREAD" => parse_xread("XREAD", &values[1..]),
+                    b"XREADGROUP" => parse_xread("XREADGROUP", &values[1..]),
+                    b"XGROUP" => parse_xgroup(&values[1..]),
+                    b"XACK" => parse_xack(&values[1..]),
+                    b"XPENDING" => parse_xpending(&values[1..]),
+                    b"XCLAIM" => parse_xclaim(&values[1..]),
+                    b"XAUTOCLAIM" => parse_xautoclaim(&values[1..]),
+                    b"XINFO" => parse_xinfo(&values[1..]),
                     _ => Err(RedisError::UnknownRequest(format!(
                         "Unexpected command name {}",
                         String::from_utf8_lossy(contents)
@@ -93,45 +936,45 @@ fn parse_echo<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisErr
 }
 
 fn parse_set<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
-    if values.len() != 2 && values.len() != 4 {
+    let args = bulk_strings("SET", values)?;
+    let [key, value, options @ ..] = &args[..] else {
         return Err(RedisError::UnexpectedNumberOfArgs(format!(
-            "For ECHO expected 2 args found {}",
-            values.len()
+            "For SET expected at least 2 args found {}",
+            args.len()
         )));
     };
-    if values.len() == 2 {
-        return match (&values[0], &values[1]) {
-            (RespValue::BulkString(key), RespValue::BulkString(value)) => Ok(RedisRequest::Set {
-                key,
-                value,
-                expiration: None,
-            }),
-            _ => Err(RedisError::UnexpectedArgumentType(format!(
-                "For PUT expected arguments of type BulkString, BulkString got {},{}",
-                values[0].type_string(),
-                values[1].type_string()
-            ))),
-        };
-    }
-    // Version with expiration.
-    match (&values[0], &values[1], &values[2], &values[3]) {
-        (RespValue::BulkString(key),
-         RespValue::BulkString(value),
-         RespValue::BulkString(expiration_type),
-         RespValue::BulkString(expiration_value)) =>
-            Ok(RedisRequest::Set {
-                key,
-                value,
-                expiration: Some(parse_expiration(expiration_type, expiration_value)?)
-            }),
-        _ => Err(RedisError::UnexpectedArgumentType(format!(
-            "For PUT with expriation expected arguments of type 4x BulkString, BulkString got {},{}, {}, {}",
-            values[0].type_string(),
-            values[1].type_string(),
-            values[2].type_string(),
-            values[3].type_string()
-        ))),
+    let mut options = options;
+    let mut expiration = None;
+    let mut set_options = SetOptions::default();
+    while let Some((option, remaining)) = options.split_first() {
+        options = remaining;
+        match &uppercase(option)[..] {
+            b"NX" if !set_options.xx => set_options.nx = true,
+            b"XX" if !set_options.nx => set_options.xx = true,
+            b"GET" => set_options.get = true,
+            b"KEEPTTL" if expiration.is_none() => set_options.keep_ttl = true,
+            b"EX" | b"PX" | b"EXAT" | b"PXAT" if expiration.is_none() && !set_options.keep_ttl => {
+                let Some((amount, remaining)) = options.split_first() else {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "syntax error".to_string(),
+                    ));
+                };
+                expiration = Some(parse_expiration("set", option, amount)?);
+                options = remaining;
+            }
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        }
     }
+    Ok(RedisRequest::Set {
+        key,
+        value,
+        expiration,
+        options: set_options,
+    })
 }
 
 fn parse_get<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
@@ -151,6 +994,123 @@ fn parse_get<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisErro
     }
 }
 
+fn parse_setnx<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("SETNX", values, 2)?;
+    Ok(RedisRequest::SetNx {
+        key: args[0],
+        value: args[1],
+    })
+}
+
+// Parses SETEX, or PSETEX with the time to live in milliseconds, as the SET it stands for.
+fn parse_setex<'a>(
+    command: &str,
+    expiration_type: &[u8],
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args(command, values, 3)?;
+    Ok(RedisRequest::Set {
+        key: args[0],
+        value: args[2],
+        expiration: Some(parse_expiration(
+            &command.to_ascii_lowercase(),
+            expiration_type,
+            args[1],
+        )?),
+        options: SetOptions::default(),
+    })
+}
+
+// Parses GETSET as the SET with GET it stands for.
+fn parse_getset<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("GETSET", values, 2)?;
+    Ok(RedisRequest::Set {
+        key: args[0],
+        value: args[1],
+        expiration: None,
+        options: SetOptions {
+            get: true,
+            ..SetOptions::default()
+        },
+    })
+}
+
+fn parse_append<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("APPEND", values, 2)?;
+    Ok(RedisRequest::Append {
+        key: args[0],
+        value: args[1],
+    })
+}
+
+fn parse_getrange<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("GETRANGE", values, 3)?;
+    Ok(RedisRequest::GetRange {
+        key: args[0],
+        start: parse_integer(args[1])?,
+        end: parse_integer(args[2])?,
+    })
+}
+
+fn parse_setrange<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("SETRANGE", values, 3)?;
+    let offset = usize::try_from(parse_integer(args[1])?)
+        .map_err(|_| RedisError::UnexpectedArgumentType("offset is out of range".to_string()))?;
+    Ok(RedisRequest::SetRange {
+        key: args[0],
+        offset,
+        value: args[2],
+    })
+}
+
+fn parse_getex<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("GETEX", values)?;
+    let syntax_error = || RedisError::UnexpectedArgumentType("syntax error".to_string());
+    let (key, expiration, persist) = match &args[..] {
+        [key] => (*key, None, false),
+        [key, option] if uppercase(option) == b"PERSIST" => (*key, None, true),
+        [key, option, amount]
+            if matches!(&uppercase(option)[..], b"EX" | b"PX" | b"EXAT" | b"PXAT") =>
+        {
+            (
+                *key,
+                Some(parse_expiration("getex", option, amount)?),
+                false,
+            )
+        }
+        [] => {
+            return Err(RedisError::UnexpectedNumberOfArgs(
+                "For GETEX expected at least 1 arg found 0".to_string(),
+            ))
+        }
+        _ => return Err(syntax_error()),
+    };
+    Ok(RedisRequest::GetEx {
+        key,
+        expiration,
+        persist,
+    })
+}
+
+fn parse_mset<'a>(
+    command: &str,
+    nx: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    if args.is_empty() || args.len() % 2 == 1 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected key value pairs, found {} args",
+            command,
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::MSet {
+        pairs: args.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+        nx,
+    })
+}
+
 fn parse_config<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
     if values.len() < 2 {
         return Err(RedisError::UnexpectedNumberOfArgs(
@@ -224,240 +1184,3499 @@ fn parse_info<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisErr
     }
 }
 
-fn uppercase(value: &[u8]) -> Vec<u8> {
-    value.iter().map(|u| u.to_ascii_uppercase()).collect()
-}
-
-fn parse_expiration(
-    expiration_type: &[u8],
-    expiration_value: &[u8],
-) -> Result<SystemTime, RedisError> {
-    match &uppercase(expiration_type)[..] {
-        b"PX" => {
-            Ok(SystemTime::now() + Duration::from_millis(parse_integer(expiration_value)? as u64))
+fn parse_replconf<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("REPLCONF", values)?;
+    if args.is_empty() || args.len() % 2 != 0 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For REPLCONF expected <option> <value> pairs, found {} args",
+            args.len()
+        )));
+    }
+    match &uppercase(args[0])[..] {
+        b"LISTENING-PORT" if args.len() == 2 => {
+            let port = u16::try_from(parse_integer(args[1])?).map_err(|_| {
+                RedisError::UnexpectedArgumentType(format!(
+                    "For REPLCONF listening-port, invalid port {}",
+                    String::from_utf8_lossy(args[1])
+                ))
+            })?;
+            Ok(RedisRequest::ReplConf(ReplConf::ListeningPort(port)))
+        }
+        // Replicas may announce several capabilities, e.g. capa eof capa psync2.
+        b"CAPA" if args.chunks(2).all(|pair| uppercase(pair[0]) == b"CAPA") => {
+            Ok(RedisRequest::ReplConf(ReplConf::Capabilities))
         }
+        // Newer replicas append further fields such as FACK, which we don't use.
+        b"ACK" => {
+            let offset = u64::try_from(parse_integer(args[1])?).map_err(|_| {
+                RedisError::UnexpectedArgumentType(format!(
+                    "For REPLCONF ACK, invalid offset {}",
+                    String::from_utf8_lossy(args[1])
+                ))
+            })?;
+            Ok(RedisRequest::ReplConf(ReplConf::Ack(offset)))
+        }
+        b"GETACK" if args.len() == 2 => Ok(RedisRequest::ReplConf(ReplConf::GetAck)),
         _ => Err(RedisError::UnknownRequest(format!(
-            "For SET, unexpected expiry spec {}",
-            String::from_utf8_lossy(expiration_type)
+            "Unrecognized REPLCONF option {}",
+            String::from_utf8_lossy(args[0])
         ))),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::errors::RespError;
-    use crate::resp_parser::RespValue;
+fn parse_psync<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("PSYNC", values)?;
+    if args.len() != 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For PSYNC expected 2 args found {}",
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::Psync {
+        replid: args[0],
+        offset: parse_integer(args[1])?,
+    })
+}
 
-    #[test]
-    fn parse_ping() {
-        let ping_value = RespValue::Array(vec![RespValue::BulkString(b"PING")]);
-        let parsed = parse_command(ping_value);
-        assert!(
-            parsed.is_ok(),
-            "Expected ok result, got: {}",
-            parsed.err().unwrap()
+fn parse_wait<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("WAIT", values)?;
+    if args.len() != 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For WAIT expected 2 args found {}",
+            args.len()
+        )));
+    }
+    let num_replicas = parse_integer(args[0])?;
+    let timeout_millis = parse_integer(args[1])?;
+    if timeout_millis < 0 {
+        return Err(RedisError::UnexpectedArgumentType(
+            "For WAIT, timeout is negative".to_string(),
+        ));
+    }
+    Ok(RedisRequest::Wait {
+        // Asking for a negative number of replicas is trivially satisfied.
+        num_replicas: num_replicas.max(0) as u64,
+        timeout_millis: timeout_millis as u64,
+    })
+}
+
+// Parses a command which takes no arguments.
+fn parse_no_args<'a>(
+    command: &str,
+    request: RedisRequest<'a>,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    if !values.is_empty() {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected 0 args found {}",
+            command,
+            values.len()
+        )));
+    }
+    Ok(request)
+}
+
+fn parse_select<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("SELECT", values)?;
+    if args.len() != 1 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For SELECT expected 1 args found {}",
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::Select(parse_db_index(args[0])?))
+}
+
+fn parse_swapdb<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("SWAPDB", values)?;
+    if args.len() != 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For SWAPDB expected 2 args found {}",
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::SwapDb(
+        parse_db_index(args[0])?,
+        parse_db_index(args[1])?,
+    ))
+}
+
+fn parse_move<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("MOVE", values)?;
+    if args.len() != 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For MOVE expected 2 args found {}",
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::Move {
+        key: args[0],
+        db: parse_db_index(args[1])?,
+    })
+}
+
+// Parses a database number.  Whether the database exists is left to the handler.
+fn parse_db_index(arg: &[u8]) -> Result<usize, RedisError> {
+    usize::try_from(parse_integer(arg)?)
+        .map_err(|_| RedisError::UnexpectedArgumentType("DB index is out of range".to_string()))
+}
+
+fn parse_push<'a>(
+    command: &str,
+    end: ListEnd,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    if args.len() < 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 2 args found {}",
+            command,
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::Push {
+        key: args[0],
+        values: args[1..].to_vec(),
+        end,
+    })
+}
+
+fn parse_pop<'a>(
+    command: &str,
+    end: ListEnd,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let count = match args[..] {
+        [_] => None,
+        [_, count] => Some(parse_count(count)?),
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For {} expected 1 or 2 args found {}",
+                command,
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::Pop {
+        key: args[0],
+        count,
+        end,
+    })
+}
+
+// Parses the EXPIRE family, whose time is in seconds or milliseconds, and either from now
+// or since the epoch.  Negative times are allowed, and delete the key.
+fn parse_expire<'a>(
+    command: &str,
+    millis: bool,
+    absolute: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let [key, time, options @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 2 args found {}",
+            command,
+            args.len()
+        )));
+    };
+    let time = parse_integer(time)?;
+    let duration = if millis {
+        Duration::from_millis(time.unsigned_abs())
+    } else {
+        Duration::from_secs(time.unsigned_abs())
+    };
+    let base = if absolute {
+        UNIX_EPOCH
+    } else {
+        SystemTime::now()
+    };
+    let expiration = if time >= 0 {
+        base.checked_add(duration).ok_or_else(|| {
+            RedisError::UnexpectedArgumentType(format!(
+                "invalid expire time in '{}' command",
+                command.to_lowercase()
+            ))
+        })?
+    } else {
+        base.checked_sub(duration)
+            .map_or(UNIX_EPOCH, |time| time.max(UNIX_EPOCH))
+    };
+    let conditions = options
+        .iter()
+        .map(|option| match &uppercase(option)[..] {
+            b"NX" => Ok(ExpireCondition::Nx),
+            b"XX" => Ok(ExpireCondition::Xx),
+            b"GT" => Ok(ExpireCondition::Gt),
+            b"LT" => Ok(ExpireCondition::Lt),
+            _ => Err(RedisError::UnexpectedArgumentType(format!(
+                "Unsupported option {}",
+                String::from_utf8_lossy(option)
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let has = |condition| conditions.contains(&condition);
+    if has(ExpireCondition::Nx) && conditions.iter().any(|c| *c != ExpireCondition::Nx) {
+        return Err(RedisError::UnexpectedArgumentType(
+            "NX and XX, GT or LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    if has(ExpireCondition::Gt) && has(ExpireCondition::Lt) {
+        return Err(RedisError::UnexpectedArgumentType(
+            "GT and LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    Ok(RedisRequest::Expire {
+        key,
+        expiration,
+        conditions,
+    })
+}
+
+fn parse_rename<'a>(
+    command: &str,
+    nx: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args(command, values, 2)?;
+    Ok(RedisRequest::Rename {
+        key: args[0],
+        new_key: args[1],
+        nx,
+    })
+}
+
+fn parse_copy<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("COPY", values)?;
+    let [source, destination, options @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For COPY expected at least 2 args found {}",
+            args.len()
+        )));
+    };
+    let mut db = None;
+    let mut replace = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match &uppercase(option)[..] {
+            b"DB" => db = Some(parse_db_index(option_value("COPY", "DB", options.next())?)?),
+            b"REPLACE" => replace = true,
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(RedisRequest::Copy {
+        source,
+        destination,
+        db,
+        replace,
+    })
+}
+
+fn parse_flush<'a>(
+    command: &str,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let asynchronous = match args.as_slice() {
+        [] => false,
+        [mode] if uppercase(mode) == b"SYNC" => false,
+        [mode] if uppercase(mode) == b"ASYNC" => true,
+        _ => {
+            return Err(RedisError::UnexpectedArgumentType(
+                "syntax error".to_string(),
+            ))
+        }
+    };
+    Ok(if command == "FLUSHALL" {
+        RedisRequest::FlushAll { asynchronous }
+    } else {
+        RedisRequest::FlushDb { asynchronous }
+    })
+}
+
+fn parse_ttl<'a>(
+    command: &str,
+    millis: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args(command, values, 1)?;
+    Ok(RedisRequest::Ttl {
+        key: args[0],
+        millis,
+    })
+}
+
+fn parse_expiretime<'a>(
+    command: &str,
+    millis: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args(command, values, 1)?;
+    Ok(RedisRequest::ExpireTime {
+        key: args[0],
+        millis,
+    })
+}
+
+fn parse_llen<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LLEN", values, 1)?;
+    Ok(RedisRequest::LLen(args[0]))
+}
+
+fn parse_lrange<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LRANGE", values, 3)?;
+    Ok(RedisRequest::LRange {
+        key: args[0],
+        start: parse_integer(args[1])?,
+        stop: parse_integer(args[2])?,
+    })
+}
+
+fn parse_lindex<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LINDEX", values, 2)?;
+    Ok(RedisRequest::LIndex {
+        key: args[0],
+        index: parse_integer(args[1])?,
+    })
+}
+
+fn parse_lset<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LSET", values, 3)?;
+    Ok(RedisRequest::LSet {
+        key: args[0],
+        index: parse_integer(args[1])?,
+        value: args[2],
+    })
+}
+
+fn parse_lrem<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LREM", values, 3)?;
+    Ok(RedisRequest::LRem {
+        key: args[0],
+        count: parse_integer(args[1])?,
+        value: args[2],
+    })
+}
+
+fn parse_ltrim<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LTRIM", values, 3)?;
+    Ok(RedisRequest::LTrim {
+        key: args[0],
+        start: parse_integer(args[1])?,
+        stop: parse_integer(args[2])?,
+    })
+}
+
+fn parse_linsert<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LINSERT", values, 4)?;
+    let before = match &uppercase(args[1])[..] {
+        b"BEFORE" => true,
+        b"AFTER" => false,
+        _ => {
+            return Err(RedisError::UnexpectedArgumentType(format!(
+                "For LINSERT expected BEFORE or AFTER got {}",
+                String::from_utf8_lossy(args[1])
+            )))
+        }
+    };
+    Ok(RedisRequest::LInsert {
+        key: args[0],
+        before,
+        pivot: args[2],
+        value: args[3],
+    })
+}
+
+fn parse_lmove<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("LMOVE", values, 4)?;
+    Ok(RedisRequest::LMove {
+        source: args[0],
+        destination: args[1],
+        from: parse_list_end("LMOVE", args[2])?,
+        to: parse_list_end("LMOVE", args[3])?,
+    })
+}
+
+fn parse_blocking_pop<'a>(
+    command: &str,
+    end: ListEnd,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let Some((timeout, keys)) = args.split_last().filter(|(_, keys)| !keys.is_empty()) else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 2 args found {}",
+            command,
+            args.len()
+        )));
+    };
+    Ok(RedisRequest::BlockingPop {
+        keys: keys.to_vec(),
+        end,
+        timeout: parse_timeout(timeout)?,
+    })
+}
+
+fn parse_blmove<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("BLMOVE", values, 5)?;
+    Ok(RedisRequest::BlockingMove {
+        source: args[0],
+        destination: args[1],
+        from: parse_list_end("BLMOVE", args[2])?,
+        to: parse_list_end("BLMOVE", args[3])?,
+        timeout: parse_timeout(args[4])?,
+    })
+}
+
+// Parses a timeout in seconds for a blocking command, where zero means to wait forever.
+fn parse_timeout(arg: &[u8]) -> Result<Option<Duration>, RedisError> {
+    let seconds: f64 = std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse().ok())
+        .filter(|seconds: &f64| seconds.is_finite())
+        .ok_or_else(|| {
+            RedisError::UnexpectedArgumentType("timeout is not a float or out of range".to_string())
+        })?;
+    if seconds < 0.0 {
+        return Err(RedisError::UnexpectedArgumentType(
+            "timeout is negative".to_string(),
+        ));
+    }
+    // A timeout too long to represent is as good as forever.
+    Ok(Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|timeout| !timeout.is_zero()))
+}
+
+fn parse_hset<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("HSET", values)?;
+    if args.len() < 3 || args.len() % 2 == 0 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For HSET expected a key and field value pairs, found {} args",
+            args.len()
+        )));
+    }
+    Ok(RedisRequest::HSet {
+        key: args[0],
+        pairs: args[1..].chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+    })
+}
+
+fn parse_hsetnx<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("HSETNX", values, 3)?;
+    Ok(RedisRequest::HSetNx {
+        key: args[0],
+        field: args[1],
+        value: args[2],
+    })
+}
+
+fn parse_hget<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("HGET", values, 2)?;
+    Ok(RedisRequest::HGet {
+        key: args[0],
+        field: args[1],
+    })
+}
+
+fn parse_hmget<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, fields) = key_and_fields("HMGET", values)?;
+    Ok(RedisRequest::HMGet { key, fields })
+}
+
+fn parse_hdel<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, fields) = key_and_fields("HDEL", values)?;
+    Ok(RedisRequest::HDel { key, fields })
+}
+
+fn parse_hexists<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("HEXISTS", values, 2)?;
+    Ok(RedisRequest::HExists {
+        key: args[0],
+        field: args[1],
+    })
+}
+
+fn parse_hincrby<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("HINCRBY", values, 3)?;
+    Ok(RedisRequest::HIncrBy {
+        key: args[0],
+        field: args[1],
+        increment: parse_integer(args[2])?,
+    })
+}
+
+fn parse_hincrbyfloat<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("HINCRBYFLOAT", values, 3)?;
+    Ok(RedisRequest::HIncrByFloat {
+        key: args[0],
+        field: args[1],
+        increment: parse_float(args[2])?,
+    })
+}
+
+fn parse_scan<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("SCAN", values)?;
+    let Some((cursor, options)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For SCAN expected at least 1 arg found 0".to_string(),
+        ));
+    };
+    let cursor = parse_cursor(cursor)?;
+    let mut pattern = None;
+    let mut count = 10;
+    let mut value_type = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match &uppercase(option)[..] {
+            b"MATCH" => pattern = Some(option_value("SCAN", "MATCH", options.next())?),
+            b"COUNT" => {
+                count = parse_count(option_value("SCAN", "COUNT", options.next())?)?;
+                if count == 0 {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "syntax error".to_string(),
+                    ));
+                }
+            }
+            b"TYPE" => value_type = Some(option_value("SCAN", "TYPE", options.next())?),
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(RedisRequest::Scan {
+        cursor,
+        pattern,
+        count,
+        value_type,
+    })
+}
+
+fn parse_hscan<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("HSCAN", values)?;
+    if args.len() < 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For HSCAN expected at least 2 args found {}",
+            args.len()
+        )));
+    }
+    let cursor = parse_cursor(args[1])?;
+    let mut pattern = None;
+    let mut count = 10;
+    let mut no_values = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match &uppercase(option)[..] {
+            b"MATCH" => pattern = Some(option_value("HSCAN", "MATCH", options.next())?),
+            b"COUNT" => {
+                count = parse_count(option_value("HSCAN", "COUNT", options.next())?)?;
+                if count == 0 {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "value is out of range, must be positive".to_string(),
+                    ));
+                }
+            }
+            b"NOVALUES" => no_values = true,
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(format!(
+                    "For HSCAN unexpected option {}",
+                    String::from_utf8_lossy(option)
+                )))
+            }
+        }
+    }
+    Ok(RedisRequest::HScan {
+        key: args[0],
+        cursor,
+        pattern,
+        count,
+        no_values,
+    })
+}
+
+fn parse_hrandfield<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("HRANDFIELD", values)?;
+    let (count, with_values) = match args[..] {
+        [_] => (None, false),
+        [_, count] => (Some(parse_random_count(count)?), false),
+        [_, count, with_values] if uppercase(with_values) == b"WITHVALUES" => {
+            (Some(parse_random_count(count)?), true)
+        }
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For HRANDFIELD expected a key, optionally followed by a count and WITHVALUES, found {} args",
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::HRandField {
+        key: args[0],
+        count,
+        with_values,
+    })
+}
+
+// Parses HEXPIRE, or with millis HPEXPIRE, and with absolute HEXPIREAT and HPEXPIREAT
+// where the time is since the epoch.
+fn parse_hexpire<'a>(
+    command: &str,
+    millis: bool,
+    absolute: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    if args.len() < 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 2 args found {}",
+            command,
+            args.len()
+        )));
+    }
+    let time = u64::try_from(parse_integer(args[1])?).map_err(|_| {
+        RedisError::UnexpectedArgumentType("invalid expire time, must be >= 0".to_string())
+    })?;
+    let duration = if millis {
+        Duration::from_millis(time)
+    } else {
+        Duration::from_secs(time)
+    };
+    let base = if absolute {
+        UNIX_EPOCH
+    } else {
+        SystemTime::now()
+    };
+    let expiration = base.checked_add(duration).ok_or_else(|| {
+        RedisError::UnexpectedArgumentType(format!(
+            "invalid expire time in '{}' command",
+            command.to_lowercase()
+        ))
+    })?;
+    let (condition, fields) = match args.get(2).map(|arg| uppercase(arg)).as_deref() {
+        Some(b"NX") => (Some(ExpireCondition::Nx), &args[3..]),
+        Some(b"XX") => (Some(ExpireCondition::Xx), &args[3..]),
+        Some(b"GT") => (Some(ExpireCondition::Gt), &args[3..]),
+        Some(b"LT") => (Some(ExpireCondition::Lt), &args[3..]),
+        _ => (None, &args[2..]),
+    };
+    Ok(RedisRequest::HExpire {
+        key: args[0],
+        expiration,
+        condition,
+        fields: parse_fields_argument(command, fields)?,
+    })
+}
+
+fn parse_httl<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, args) = key_and_fields("HTTL", values)?;
+    Ok(RedisRequest::HTtl {
+        key,
+        fields: parse_fields_argument("HTTL", &args)?,
+    })
+}
+
+fn parse_hpersist<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, args) = key_and_fields("HPERSIST", values)?;
+    Ok(RedisRequest::HPersist {
+        key,
+        fields: parse_fields_argument("HPERSIST", &args)?,
+    })
+}
+
+fn parse_sadd<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, members) = key_and_fields("SADD", values)?;
+    Ok(RedisRequest::SAdd { key, members })
+}
+
+fn parse_srem<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, members) = key_and_fields("SREM", values)?;
+    Ok(RedisRequest::SRem { key, members })
+}
+
+fn parse_sismember<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("SISMEMBER", values, 2)?;
+    Ok(RedisRequest::SIsMember {
+        key: args[0],
+        member: args[1],
+    })
+}
+
+fn parse_smismember<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, members) = key_and_fields("SMISMEMBER", values)?;
+    Ok(RedisRequest::SMIsMember { key, members })
+}
+
+fn parse_spop<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("SPOP", values)?;
+    let count = match args[..] {
+        [_] => None,
+        [_, count] => Some(parse_count(count)?),
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For SPOP expected 1 or 2 args found {}",
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::SPop {
+        key: args[0],
+        count,
+    })
+}
+
+fn parse_srandmember<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("SRANDMEMBER", values)?;
+    let count = match args[..] {
+        [_] => None,
+        [_, count] => Some(parse_random_count(count)?),
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For SRANDMEMBER expected 1 or 2 args found {}",
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::SRandMember {
+        key: args[0],
+        count,
+    })
+}
+
+fn parse_scombine<'a>(
+    command: &str,
+    op: SetOp,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let keys = bulk_strings(command, values)?;
+    if keys.is_empty() {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 1 arg found 0",
+            command
+        )));
+    }
+    Ok(RedisRequest::SCombine { op, keys })
+}
+
+fn parse_scombinestore<'a>(
+    command: &str,
+    op: SetOp,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let (destination, keys) = key_and_fields(command, values)?;
+    Ok(RedisRequest::SCombineStore {
+        op,
+        destination,
+        keys,
+    })
+}
+
+fn parse_sintercard<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("SINTERCARD", values)?;
+    let Some((num_keys, rest)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For SINTERCARD expected at least 2 args found 0".to_string(),
+        ));
+    };
+    let num_keys = parse_count(num_keys)?;
+    if num_keys == 0 {
+        return Err(RedisError::UnexpectedArgumentType(
+            "numkeys should be greater than 0".to_string(),
+        ));
+    }
+    if num_keys > rest.len() {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "Number of keys can't be greater than number of args".to_string(),
+        ));
+    }
+    let (keys, options) = rest.split_at(num_keys);
+    let limit = match options {
+        [] => 0,
+        [option, limit] if uppercase(option) == b"LIMIT" => parse_count(limit).map_err(|_| {
+            RedisError::UnexpectedArgumentType("LIMIT can't be negative".to_string())
+        })?,
+        _ => {
+            return Err(RedisError::UnexpectedArgumentType(
+                "For SINTERCARD expected only LIMIT after the keys".to_string(),
+            ))
+        }
+    };
+    Ok(RedisRequest::SInterCard {
+        keys: keys.to_vec(),
+        limit,
+    })
+}
+
+fn parse_smove<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("SMOVE", values, 3)?;
+    Ok(RedisRequest::SMove {
+        source: args[0],
+        destination: args[1],
+        member: args[2],
+    })
+}
+
+fn parse_zadd<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("ZADD", values)?;
+    let Some((key, rest)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For ZADD expected at least 3 args found 0".to_string(),
+        ));
+    };
+    let mut options = ZAddOptions::default();
+    let mut rest = rest;
+    while let Some((option, remaining)) = rest.split_first() {
+        match &uppercase(option)[..] {
+            b"NX" => options.nx = true,
+            b"XX" => options.xx = true,
+            b"GT" => options.gt = true,
+            b"LT" => options.lt = true,
+            b"CH" => options.ch = true,
+            b"INCR" => options.incr = true,
+            _ => break,
+        }
+        rest = remaining;
+    }
+    if options.nx && options.xx {
+        return Err(RedisError::UnexpectedArgumentType(
+            "XX and NX options at the same time are not compatible".to_string(),
+        ));
+    }
+    if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+        return Err(RedisError::UnexpectedArgumentType(
+            "GT, LT, and/or NX options at the same time are not compatible".to_string(),
+        ));
+    }
+    if rest.is_empty() || rest.len() % 2 != 0 {
+        return Err(RedisError::UnexpectedArgumentType(
+            "syntax error".to_string(),
+        ));
+    }
+    if options.incr && rest.len() != 2 {
+        return Err(RedisError::UnexpectedArgumentType(
+            "INCR option supports a single increment-element pair".to_string(),
+        ));
+    }
+    let pairs = rest
+        .chunks(2)
+        .map(|pair| Ok((parse_score(pair[0])?, pair[1])))
+        .collect::<Result<_, RedisError>>()?;
+    Ok(RedisRequest::ZAdd {
+        key,
+        options,
+        pairs,
+    })
+}
+
+fn parse_zincrby<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("ZINCRBY", values, 3)?;
+    Ok(RedisRequest::ZAdd {
+        key: args[0],
+        options: ZAddOptions {
+            incr: true,
+            ..ZAddOptions::default()
+        },
+        pairs: vec![(parse_score(args[1])?, args[2])],
+    })
+}
+
+fn parse_zrem<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, members) = key_and_fields("ZREM", values)?;
+    Ok(RedisRequest::ZRem { key, members })
+}
+
+fn parse_zscore<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("ZSCORE", values, 2)?;
+    Ok(RedisRequest::ZScore {
+        key: args[0],
+        member: args[1],
+    })
+}
+
+fn parse_zmscore<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, members) = key_and_fields("ZMSCORE", values)?;
+    Ok(RedisRequest::ZMScore { key, members })
+}
+
+fn parse_zrank<'a>(
+    command: &str,
+    rev: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let with_score = match args[..] {
+        [_, _] => false,
+        [_, _, option] if uppercase(option) == b"WITHSCORE" => true,
+        [_, _, _] => {
+            return Err(RedisError::UnexpectedArgumentType(
+                "syntax error".to_string(),
+            ))
+        }
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For {} expected 2 or 3 args found {}",
+                command,
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::ZRank {
+        key: args[0],
+        member: args[1],
+        rev,
+        with_score,
+    })
+}
+
+fn parse_zcount<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("ZCOUNT", values, 3)?;
+    Ok(RedisRequest::ZCount {
+        key: args[0],
+        min: parse_score_bound(args[1])?,
+        max: parse_score_bound(args[2])?,
+    })
+}
+
+fn parse_zlexcount<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args("ZLEXCOUNT", values, 3)?;
+    Ok(RedisRequest::ZLexCount {
+        key: args[0],
+        min: parse_lex_bound(args[1])?,
+        max: parse_lex_bound(args[2])?,
+    })
+}
+
+// What a ZRANGE selects by, before its bounds are parsed.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+// Parses ZRANGE, or with fixed set one of its older forms, which select by a fixed kind
+// of range in a fixed direction and don't accept BYSCORE, BYLEX or REV.
+fn parse_zrange<'a>(
+    command: &str,
+    fixed: Option<(RangeKind, bool)>,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let [key, start, stop, options @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 3 args found {}",
+            command,
+            args.len()
+        )));
+    };
+    let (mut kind, mut rev) = fixed.unwrap_or((RangeKind::Rank, false));
+    let mut limit = None;
+    let mut with_scores = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match (&uppercase(option)[..], fixed) {
+            (b"WITHSCORES", _) => with_scores = true,
+            (b"LIMIT", _) => {
+                let offset = parse_integer(option_value(command, "LIMIT", options.next())?)?;
+                let count = parse_integer(option_value(command, "LIMIT", options.next())?)?;
+                limit = Some((offset, count));
+            }
+            (b"BYSCORE", None) => kind = RangeKind::Score,
+            (b"BYLEX", None) => kind = RangeKind::Lex,
+            (b"REV", None) => rev = true,
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        }
+    }
+    if limit.is_some() && kind == RangeKind::Rank {
+        return Err(RedisError::UnexpectedArgumentType(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .to_string(),
+        ));
+    }
+    if with_scores && kind == RangeKind::Lex {
+        return Err(RedisError::UnexpectedArgumentType(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".to_string(),
+        ));
+    }
+    // Reversed score and lexicographical ranges give the maximum first.
+    let (min, max) = if rev { (stop, start) } else { (start, stop) };
+    let range = match kind {
+        RangeKind::Rank => ZRangeBy::Rank(parse_integer(start)?, parse_integer(stop)?),
+        RangeKind::Score => ZRangeBy::Score(parse_score_bound(min)?, parse_score_bound(max)?),
+        RangeKind::Lex => ZRangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?),
+    };
+    Ok(RedisRequest::ZRange {
+        key,
+        range,
+        rev,
+        limit,
+        with_scores,
+    })
+}
+
+fn parse_zpop<'a>(
+    command: &str,
+    max: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let count = match args[..] {
+        [_] => 1,
+        [_, count] => parse_count(count)?,
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For {} expected 1 or 2 args found {}",
+                command,
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::ZPop {
+        key: args[0],
+        count,
+        max,
+    })
+}
+
+fn parse_zcombinestore<'a>(
+    command: &str,
+    op: SetOp,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let [destination, num_keys, rest @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 3 args found {}",
+            command,
+            args.len()
+        )));
+    };
+    let num_keys = parse_count(num_keys)?;
+    if num_keys == 0 {
+        return Err(RedisError::UnexpectedArgumentType(format!(
+            "at least 1 input key is needed for '{}' command",
+            command.to_lowercase()
+        )));
+    }
+    if num_keys > rest.len() {
+        return Err(RedisError::UnexpectedArgumentType(
+            "syntax error".to_string(),
+        ));
+    }
+    let (keys, mut options) = rest.split_at(num_keys);
+    let mut weights = Vec::new();
+    let mut aggregate = Aggregate::Sum;
+    while let Some((option, remaining)) = options.split_first() {
+        match &uppercase(option)[..] {
+            b"WEIGHTS" if op != SetOp::Diff && remaining.len() >= num_keys => {
+                weights = remaining[..num_keys]
+                    .iter()
+                    .map(|weight| {
+                        parse_score(weight).map_err(|_| {
+                            RedisError::UnexpectedArgumentType(
+                                "weight value is not a float".to_string(),
+                            )
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                options = &remaining[num_keys..];
+            }
+            b"AGGREGATE" if op != SetOp::Diff => {
+                let value = option_value(command, "AGGREGATE", remaining.first())?;
+                aggregate = match &uppercase(value)[..] {
+                    b"SUM" => Aggregate::Sum,
+                    b"MIN" => Aggregate::Min,
+                    b"MAX" => Aggregate::Max,
+                    _ => {
+                        return Err(RedisError::UnexpectedArgumentType(
+                            "syntax error".to_string(),
+                        ))
+                    }
+                };
+                options = &remaining[1..];
+            }
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(RedisRequest::ZCombineStore {
+        op,
+        destination,
+        keys: keys.to_vec(),
+        weights,
+        aggregate,
+    })
+}
+
+fn parse_blocking_zpop<'a>(
+    command: &str,
+    max: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let Some((timeout, keys)) = args.split_last().filter(|(_, keys)| !keys.is_empty()) else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 2 args found {}",
+            command,
+            args.len()
+        )));
+    };
+    Ok(RedisRequest::BlockingZPop {
+        keys: keys.to_vec(),
+        max,
+        timeout: parse_timeout(timeout)?,
+    })
+}
+
+fn parse_xadd<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XADD", values)?;
+    let Some((key, rest)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For XADD expected at least 4 args found 0".to_string(),
+        ));
+    };
+    let (no_mkstream, trim, rest) = parse_stream_options("XADD", rest)?;
+    let Some((id, pairs)) = rest.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For XADD expected an ID".to_string(),
+        ));
+    };
+    if pairs.is_empty() || pairs.len() % 2 != 0 {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "wrong number of arguments for 'xadd' command".to_string(),
+        ));
+    }
+    let id = match id.split(|c| *c == b'-').collect::<Vec<_>>()[..] {
+        [b"*"] => XAddId::Auto,
+        [ms, b"*"] => XAddId::AutoSequence(parse_id_part(ms)?),
+        _ => XAddId::Explicit(parse_stream_id(id, 0)?),
+    };
+    Ok(RedisRequest::XAdd {
+        key,
+        no_mkstream,
+        trim,
+        id,
+        fields: pairs.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+    })
+}
+
+fn parse_xrange<'a>(
+    command: &str,
+    rev: bool,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let count = match args[..] {
+        [_, _, _] => None,
+        [_, _, _, option, count] if uppercase(option) == b"COUNT" => Some(parse_count(count)?),
+        [_, _, _, _, _] => {
+            return Err(RedisError::UnexpectedArgumentType(
+                "syntax error".to_string(),
+            ))
+        }
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For {} expected 3 or 5 args found {}",
+                command,
+                args.len()
+            )))
+        }
+    };
+    // XREVRANGE gives the end first.
+    let (start, end) = if rev {
+        (args[2], args[1])
+    } else {
+        (args[1], args[2])
+    };
+    Ok(RedisRequest::XRange {
+        key: args[0],
+        start: parse_range_start(start)?,
+        end: parse_range_end(end)?,
+        count,
+        rev,
+    })
+}
+
+fn parse_xdel<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let (key, ids) = key_and_fields("XDEL", values)?;
+    Ok(RedisRequest::XDel {
+        key,
+        ids: ids
+            .iter()
+            .map(|id| parse_stream_id(id, 0))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn parse_xtrim<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XTRIM", values)?;
+    let Some((key, rest)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For XTRIM expected at least 3 args found 0".to_string(),
+        ));
+    };
+    match parse_stream_options("XTRIM", rest)? {
+        (false, Some(trim), []) => Ok(RedisRequest::XTrim { key, trim }),
+        _ => Err(RedisError::UnexpectedArgumentType(
+            "syntax error".to_string(),
+        )),
+    }
+}
+
+// Parses XREAD, or XREADGROUP which also takes the group and consumer.
+fn parse_xread<'a>(
+    command: &str,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    let with_group = command == "XREADGROUP";
+    let mut group = None;
+    let mut count = None;
+    let mut block = None;
+    let mut no_ack = false;
+    let mut rest = &args[..];
+    let streams = loop {
+        let Some((option, remaining)) = rest.split_first() else {
+            return Err(RedisError::UnexpectedArgumentType(
+                "syntax error".to_string(),
+            ));
+        };
+        rest = match &uppercase(option)[..] {
+            b"COUNT" => {
+                count = Some(parse_count(option_value(
+                    command,
+                    "COUNT",
+                    remaining.first(),
+                )?)?);
+                &remaining[1..]
+            }
+            b"BLOCK" => {
+                let millis = parse_integer(option_value(command, "BLOCK", remaining.first())?)?;
+                let millis = u64::try_from(millis).map_err(|_| {
+                    RedisError::UnexpectedArgumentType("timeout is negative".to_string())
+                })?;
+                // A timeout of zero waits forever.
+                block = Some(Some(Duration::from_millis(millis)).filter(|t| !t.is_zero()));
+                &remaining[1..]
+            }
+            b"GROUP" if with_group => {
+                let [name, consumer, ..] = remaining else {
+                    return Err(RedisError::UnexpectedNumberOfArgs(
+                        "For XREADGROUP, GROUP needs a group and a consumer".to_string(),
+                    ));
+                };
+                group = Some((*name, *consumer));
+                &remaining[2..]
+            }
+            b"NOACK" if with_group => {
+                no_ack = true;
+                remaining
+            }
+            b"STREAMS" => break remaining,
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        };
+    };
+    if streams.is_empty() || streams.len() % 2 != 0 {
+        return Err(RedisError::UnexpectedArgumentType(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            command.to_lowercase(),
+            if with_group { ">" } else { "$" }
+        )));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    let ids = ids
+        .iter()
+        .map(|id| match *id {
+            b"$" if !with_group => Ok(None),
+            b">" if with_group => Ok(None),
+            b"$" => Err(RedisError::UnexpectedArgumentType(
+                "The $ ID is meaningless in the context of XREADGROUP".to_string(),
+            )),
+            b">" => Err(RedisError::UnexpectedArgumentType(
+                "The > ID can be specified only when calling XREADGROUP using the GROUP \
+                 <group> <consumer> option."
+                    .to_string(),
+            )),
+            id => parse_stream_id(id, 0).map(Some),
+        })
+        .collect::<Result<_, _>>()?;
+    if !with_group {
+        return Ok(RedisRequest::XRead {
+            keys: keys.to_vec(),
+            ids,
+            count,
+            block,
+        });
+    }
+    let Some((group, consumer)) = group else {
+        return Err(RedisError::UnexpectedArgumentType(
+            "Missing GROUP option for XREADGROUP".to_string(),
+        ));
+    };
+    Ok(RedisRequest::XReadGroup {
+        group,
+        consumer,
+        keys: keys.to_vec(),
+        ids,
+        count,
+        block,
+        no_ack,
+    })
+}
+
+fn parse_xgroup<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XGROUP", values)?;
+    let Some((subcommand, args)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For XGROUP expected at least XGROUP <SUBCOMMAND>".to_string(),
+        ));
+    };
+    let subcommand = uppercase(subcommand);
+    match (&subcommand[..], args) {
+        (b"CREATE", [key, group, id, options @ ..]) => {
+            let (mkstream, entries_read) = parse_xgroup_options(true, options)?;
+            Ok(RedisRequest::XGroupCreate {
+                key,
+                group,
+                id: parse_group_id(id)?,
+                mkstream,
+                entries_read,
+            })
+        }
+        (b"SETID", [key, group, id, options @ ..]) => {
+            let (_, entries_read) = parse_xgroup_options(false, options)?;
+            Ok(RedisRequest::XGroupSetId {
+                key,
+                group,
+                id: parse_group_id(id)?,
+                entries_read,
+            })
+        }
+        (b"DESTROY", [key, group]) => Ok(RedisRequest::XGroupDestroy { key, group }),
+        (b"CREATECONSUMER", [key, group, consumer]) => Ok(RedisRequest::XGroupCreateConsumer {
+            key,
+            group,
+            consumer,
+        }),
+        (b"DELCONSUMER", [key, group, consumer]) => Ok(RedisRequest::XGroupDelConsumer {
+            key,
+            group,
+            consumer,
+        }),
+        (b"CREATE" | b"SETID" | b"DESTROY" | b"CREATECONSUMER" | b"DELCONSUMER", _) => {
+            Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For XGROUP {} found {} args",
+                String::from_utf8_lossy(&subcommand),
+                args.len()
+            )))
+        }
+        _ => Err(RedisError::UnknownRequest(format!(
+            "Unknown SUBCOMMAND after XGROUP: {}",
+            String::from_utf8_lossy(&subcommand)
+        ))),
+    }
+}
+
+// Parses the MKSTREAM, for CREATE only, and ENTRIESREAD options of XGROUP.
+fn parse_xgroup_options(
+    create: bool,
+    mut args: &[&[u8]],
+) -> Result<(bool, Option<u64>), RedisError> {
+    let mut mkstream = false;
+    let mut entries_read = None;
+    while let Some((option, rest)) = args.split_first() {
+        args = match &uppercase(option)[..] {
+            b"MKSTREAM" if create => {
+                mkstream = true;
+                rest
+            }
+            b"ENTRIESREAD" => {
+                let value = parse_integer(option_value("XGROUP", "ENTRIESREAD", rest.first())?)?;
+                // -1 means the number read is unknown.
+                entries_read = match value {
+                    -1 => None,
+                    value => Some(u64::try_from(value).map_err(|_| {
+                        RedisError::UnexpectedArgumentType(
+                            "value for ENTRIESREAD must be positive or -1".to_string(),
+                        )
+                    })?),
+                };
+                &rest[1..]
+            }
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        };
+    }
+    Ok((mkstream, entries_read))
+}
+
+// Parses the ID for XGROUP CREATE and SETID, where `$` means the stream's last ID.
+fn parse_group_id(arg: &[u8]) -> Result<Option<StreamId>, RedisError> {
+    match arg {
+        b"$" => Ok(None),
+        id => parse_stream_id(id, 0).map(Some),
+    }
+}
+
+fn parse_xack<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XACK", values)?;
+    let [key, group, ids @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For XACK expected at least 3 args found {}",
+            args.len()
+        )));
+    };
+    if ids.is_empty() {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For XACK expected at least 3 args found 2".to_string(),
+        ));
+    }
+    Ok(RedisRequest::XAck {
+        key,
+        group,
+        ids: ids
+            .iter()
+            .map(|id| parse_stream_id(id, 0))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn parse_xpending<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XPENDING", values)?;
+    let (key, group, range) = match &args[..] {
+        [key, group] => (*key, *group, None),
+        [key, group, rest @ ..] => {
+            let (min_idle, rest) = match rest {
+                [option, min_idle, rest @ ..] if uppercase(option) == b"IDLE" => {
+                    (parse_millis(min_idle)?, rest)
+                }
+                _ => (0, rest),
+            };
+            let (start, end, count, consumer) = match rest {
+                [start, end, count] => (start, end, count, None),
+                [start, end, count, consumer] => (start, end, count, Some(*consumer)),
+                _ => {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "syntax error".to_string(),
+                    ))
+                }
+            };
+            let range = PendingRange {
+                min_idle,
+                start: parse_range_start(start)?,
+                end: parse_range_end(end)?,
+                count: parse_count(count)?,
+                consumer,
+            };
+            (*key, *group, Some(range))
+        }
+        _ => {
+            return Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For XPENDING expected at least 2 args found {}",
+                args.len()
+            )))
+        }
+    };
+    Ok(RedisRequest::XPending { key, group, range })
+}
+
+fn parse_xclaim<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XCLAIM", values)?;
+    let [key, group, consumer, min_idle, first_id, rest @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For XCLAIM expected at least 5 args found {}",
+            args.len()
+        )));
+    };
+    // The IDs run until the first argument that isn't one.
+    let mut ids = vec![parse_stream_id(first_id, 0)?];
+    let mut rest = rest;
+    while let Some(id) = rest.first().and_then(|arg| parse_stream_id(arg, 0).ok()) {
+        ids.push(id);
+        rest = &rest[1..];
+    }
+    let mut options = ClaimOptions::default();
+    while let Some((option, remaining)) = rest.split_first() {
+        let name = String::from_utf8_lossy(option).to_uppercase();
+        let value = || option_value("XCLAIM", &name, remaining.first());
+        rest = match &name[..] {
+            "IDLE" => {
+                options.idle = Some(parse_millis(value()?)?);
+                &remaining[1..]
+            }
+            "TIME" => {
+                options.time = Some(parse_millis(value()?)?);
+                &remaining[1..]
+            }
+            "RETRYCOUNT" => {
+                options.retry_count = Some(parse_count(value()?)? as u64);
+                &remaining[1..]
+            }
+            "LASTID" => {
+                options.last_id = Some(parse_stream_id(value()?, 0)?);
+                &remaining[1..]
+            }
+            "FORCE" => {
+                options.force = true;
+                remaining
+            }
+            "JUSTID" => {
+                options.just_id = true;
+                remaining
+            }
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(format!(
+                    "Unrecognized XCLAIM option '{}'",
+                    String::from_utf8_lossy(option)
+                )))
+            }
+        };
+    }
+    Ok(RedisRequest::XClaim {
+        key,
+        group,
+        consumer,
+        min_idle: parse_millis(min_idle)?,
+        ids,
+        options,
+    })
+}
+
+fn parse_xautoclaim<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XAUTOCLAIM", values)?;
+    let [key, group, consumer, min_idle, start, rest @ ..] = &args[..] else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For XAUTOCLAIM expected at least 5 args found {}",
+            args.len()
+        )));
+    };
+    let mut count = 100;
+    let mut just_id = false;
+    let mut rest = rest;
+    while let Some((option, remaining)) = rest.split_first() {
+        rest = match &uppercase(option)[..] {
+            b"COUNT" => {
+                count = parse_count(option_value("XAUTOCLAIM", "COUNT", remaining.first())?)?;
+                if count == 0 {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "COUNT must be > 0".to_string(),
+                    ));
+                }
+                &remaining[1..]
+            }
+            b"JUSTID" => {
+                just_id = true;
+                remaining
+            }
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error".to_string(),
+                ))
+            }
+        };
+    }
+    Ok(RedisRequest::XAutoClaim {
+        key,
+        group,
+        consumer,
+        min_idle: parse_millis(min_idle)?,
+        start: parse_range_start(start)?,
+        count,
+        just_id,
+    })
+}
+
+fn parse_xinfo<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("XINFO", values)?;
+    let Some((subcommand, args)) = args.split_first() else {
+        return Err(RedisError::UnexpectedNumberOfArgs(
+            "For XINFO expected at least XINFO <SUBCOMMAND>".to_string(),
+        ));
+    };
+    let subcommand = uppercase(subcommand);
+    match (&subcommand[..], args) {
+        (b"STREAM", [key]) => Ok(RedisRequest::XInfoStream { key, full: None }),
+        (b"STREAM", [key, full, options @ ..]) if uppercase(full) == b"FULL" => {
+            let count = match options {
+                [] => 10,
+                [option, count] if uppercase(option) == b"COUNT" => parse_count(count)?,
+                _ => {
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "syntax error".to_string(),
+                    ))
+                }
+            };
+            Ok(RedisRequest::XInfoStream {
+                key,
+                full: Some(count),
+            })
+        }
+        (b"GROUPS", [key]) => Ok(RedisRequest::XInfoGroups(key)),
+        (b"CONSUMERS", [key, group]) => Ok(RedisRequest::XInfoConsumers { key, group }),
+        (b"STREAM" | b"GROUPS" | b"CONSUMERS", _) => {
+            Err(RedisError::UnexpectedNumberOfArgs(format!(
+                "For XINFO {} found {} args",
+                String::from_utf8_lossy(&subcommand),
+                args.len()
+            )))
+        }
+        _ => Err(RedisError::UnknownRequest(format!(
+            "Unknown SUBCOMMAND after XINFO: {}",
+            String::from_utf8_lossy(&subcommand)
+        ))),
+    }
+}
+
+// Parses a time in milliseconds, where negative times count as zero.
+fn parse_millis(arg: &[u8]) -> Result<u64, RedisError> {
+    Ok(u64::try_from(parse_integer(arg)?).unwrap_or(0))
+}
+
+// Whether NOMKSTREAM was given, and the trimming, then the arguments after them.
+type StreamOptions<'a, 'b> = (bool, Option<StreamTrim>, &'b [&'a [u8]]);
+
+// Parses the NOMKSTREAM and trimming options which start XADD's arguments after the key,
+// or make up XTRIM's.
+fn parse_stream_options<'a, 'b>(
+    command: &str,
+    mut args: &'b [&'a [u8]],
+) -> Result<StreamOptions<'a, 'b>, RedisError> {
+    let mut no_mkstream = false;
+    let mut trim = None;
+    let mut limit = None;
+    while let Some((option, mut rest)) = args.split_first() {
+        match &uppercase(option)[..] {
+            b"NOMKSTREAM" if command == "XADD" => no_mkstream = true,
+            option @ (b"MAXLEN" | b"MINID") => {
+                let approximate = match rest.first().copied() {
+                    Some(b"~") => true,
+                    Some(b"=") => false,
+                    _ => {
+                        // Without = or ~, the threshold comes straight after.
+                        rest = args;
+                        false
+                    }
+                };
+                let name = String::from_utf8_lossy(option).to_string();
+                let threshold = option_value(command, &name, rest.get(1))?;
+                let threshold = if option == b"MAXLEN" {
+                    TrimThreshold::MaxLen(parse_count(threshold)?)
+                } else {
+                    TrimThreshold::MinId(parse_stream_id(threshold, 0)?)
+                };
+                trim = Some(StreamTrim {
+                    threshold,
+                    approximate,
+                    limit: None,
+                });
+                rest = &rest[2..];
+            }
+            b"LIMIT" => {
+                limit = Some(parse_count(option_value(command, "LIMIT", rest.first())?)?);
+                rest = &rest[1..];
+            }
+            _ => break,
+        }
+        args = rest;
+    }
+    if let Some(limit) = limit {
+        match &mut trim {
+            Some(trim) if trim.approximate => trim.limit = Some(limit),
+            _ => {
+                return Err(RedisError::UnexpectedArgumentType(
+                    "syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+                ))
+            }
+        }
+    }
+    Ok((no_mkstream, trim, args))
+}
+
+// Parses a stream ID, `<ms>-<seq>` or just `<ms>` with the given sequence number.
+fn parse_stream_id(arg: &[u8], default_seq: u64) -> Result<StreamId, RedisError> {
+    match arg.iter().position(|c| *c == b'-') {
+        Some(dash) => Ok(StreamId::new(
+            parse_id_part(&arg[..dash])?,
+            parse_id_part(&arg[dash + 1..])?,
+        )),
+        None => Ok(StreamId::new(parse_id_part(arg)?, default_seq)),
+    }
+}
+
+fn parse_id_part(arg: &[u8]) -> Result<u64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .filter(|arg| arg.bytes().all(|c| c.is_ascii_digit()))
+        .and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| {
+            RedisError::UnexpectedArgumentType(
+                "Invalid stream ID specified as stream command argument".to_string(),
+            )
+        })
+}
+
+// Parses the start of a stream range: `-` for the first entry, an ID, where the
+// sequence number defaults to zero, or an ID prefixed with `(` to exclude it.
+fn parse_range_start(arg: &[u8]) -> Result<StreamId, RedisError> {
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => parse_stream_id(id, 0)?.next().ok_or_else(|| {
+            RedisError::UnexpectedArgumentType("invalid start ID for the interval".to_string())
+        }),
+        id => parse_stream_id(id, 0),
+    }
+}
+
+// Parses the end of a stream range: `+` for the last entry, an ID, where the sequence
+// number defaults to the greatest, or an ID prefixed with `(` to exclude it.
+fn parse_range_end(arg: &[u8]) -> Result<StreamId, RedisError> {
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => parse_stream_id(id, u64::MAX)?.prev().ok_or_else(|| {
+            RedisError::UnexpectedArgumentType("invalid end ID for the interval".to_string())
+        }),
+        id => parse_stream_id(id, u64::MAX),
+    }
+}
+
+// Parses a sorted set score, which unlike other floats may be infinite.
+fn parse_score(arg: &[u8]) -> Result<f64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| RedisError::UnexpectedArgumentType("value is not a valid float".to_string()))
+}
+
+// Parses one end of a score range, which is exclusive when prefixed with `(`.
+fn parse_score_bound(arg: &[u8]) -> Result<ScoreBound, RedisError> {
+    let (value, exclusive) = match arg.strip_prefix(b"(") {
+        Some(value) => (value, true),
+        None => (arg, false),
+    };
+    let value = parse_score(value)
+        .map_err(|_| RedisError::UnexpectedArgumentType("min or max is not a float".to_string()))?;
+    Ok(ScoreBound { value, exclusive })
+}
+
+// Parses one end of a lexicographical range: `-`, `+`, or a member prefixed with `[`
+// to include it or `(` to exclude it.
+fn parse_lex_bound(arg: &[u8]) -> Result<LexBound<'_>, RedisError> {
+    match arg {
+        b"-" => Ok(LexBound::Min),
+        b"+" => Ok(LexBound::Max),
+        [b'[', member @ ..] => Ok(LexBound::Inclusive(member)),
+        [b'(', member @ ..] => Ok(LexBound::Exclusive(member)),
+        _ => Err(RedisError::UnexpectedArgumentType(
+            "min or max not valid string range item".to_string(),
+        )),
+    }
+}
+
+// Parses the `FIELDS numfields field [field ...]` arguments of the hash field
+// expiration commands.
+fn parse_fields_argument<'a>(
+    command: &str,
+    args: &[&'a [u8]],
+) -> Result<Vec<&'a [u8]>, RedisError> {
+    let [keyword, num_fields, fields @ ..] = args else {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected FIELDS numfields field [field ...]",
+            command
+        )));
+    };
+    if uppercase(keyword) != b"FIELDS" {
+        return Err(RedisError::UnexpectedArgumentType(format!(
+            "For {} expected FIELDS got {}",
+            command,
+            String::from_utf8_lossy(keyword)
+        )));
+    }
+    let num_fields = parse_integer(num_fields)?;
+    if num_fields < 1 || num_fields as usize != fields.len() {
+        return Err(RedisError::UnexpectedArgumentType(
+            "The `numfields` parameter must match the number of arguments".to_string(),
+        ));
+    }
+    Ok(fields.to_vec())
+}
+
+// Parses a command whose only argument is a key.
+fn parse_key_only<'a>(
+    command: &str,
+    request: fn(&'a [u8]) -> RedisRequest<'a>,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let args = exact_args(command, values, 1)?;
+    Ok(request(args[0]))
+}
+
+fn parse_keys_only<'a>(
+    command: &str,
+    request: fn(Vec<&'a [u8]>) -> RedisRequest<'a>,
+    values: &[RespValue<'a>],
+) -> Result<RedisRequest<'a>, RedisError> {
+    let keys = bulk_strings(command, values)?;
+    if keys.is_empty() {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 1 arg found 0",
+            command
+        )));
+    }
+    Ok(request(keys))
+}
+
+// Extracts a key followed by at least one field.
+fn key_and_fields<'a>(
+    command: &str,
+    values: &[RespValue<'a>],
+) -> Result<(&'a [u8], Vec<&'a [u8]>), RedisError> {
+    let args = bulk_strings(command, values)?;
+    if args.len() < 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected at least 2 args found {}",
+            command,
+            args.len()
+        )));
+    }
+    Ok((args[0], args[1..].to_vec()))
+}
+
+// Extracts the value following an option, such as the pattern after MATCH.
+fn option_value<'a>(
+    command: &str,
+    option: &str,
+    value: Option<&&'a [u8]>,
+) -> Result<&'a [u8], RedisError> {
+    value.copied().ok_or_else(|| {
+        RedisError::UnexpectedNumberOfArgs(format!("For {}, {} needs a value", command, option))
+    })
+}
+
+fn parse_float(arg: &[u8]) -> Result<f64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| RedisError::UnexpectedArgumentType("value is not a valid float".to_string()))
+}
+
+fn parse_list_end(command: &str, arg: &[u8]) -> Result<ListEnd, RedisError> {
+    match &uppercase(arg)[..] {
+        b"LEFT" => Ok(ListEnd::Left),
+        b"RIGHT" => Ok(ListEnd::Right),
+        _ => Err(RedisError::UnexpectedArgumentType(format!(
+            "For {} expected LEFT or RIGHT got {}",
+            command,
+            String::from_utf8_lossy(arg)
+        ))),
+    }
+}
+
+// Parses a count, which must not be negative.
+fn parse_cursor(arg: &[u8]) -> Result<u64, RedisError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|cursor| cursor.parse().ok())
+        .ok_or_else(|| RedisError::UnexpectedArgumentType("invalid cursor".to_string()))
+}
+
+fn parse_count(arg: &[u8]) -> Result<usize, RedisError> {
+    usize::try_from(parse_integer(arg)?).map_err(|_| {
+        RedisError::UnexpectedArgumentType("value is out of range, must be positive".to_string())
+    })
+}
+
+// The most picks SRANDMEMBER and HRANDFIELD make for a negative count.  Each pick is a
+// copy in the reply, which is built in memory, so an unbounded count could exhaust it.
+const MAX_RANDOM_PICKS: i64 = 1 << 20;
+
+// Parses the count of SRANDMEMBER or HRANDFIELD, where a negative count asks for that
+// many picks which may repeat.  Counts asking for more picks than can be replied with
+// are refused.
+fn parse_random_count(arg: &[u8]) -> Result<i64, RedisError> {
+    let count = parse_integer(arg)?;
+    if count < -MAX_RANDOM_PICKS {
+        return Err(RedisError::UnexpectedArgumentType(format!(
+            "value is out of range, at most {} picks may repeat",
+            MAX_RANDOM_PICKS
+        )));
+    }
+    Ok(count)
+}
+
+fn parse_replicaof<'a>(values: &[RespValue<'a>]) -> Result<RedisRequest<'a>, RedisError> {
+    let args = bulk_strings("REPLICAOF", values)?;
+    if args.len() != 2 {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For REPLICAOF expected 2 args found {}",
+            args.len()
+        )));
+    }
+    if uppercase(args[0]) == b"NO" && uppercase(args[1]) == b"ONE" {
+        return Ok(RedisRequest::ReplicaOf(None));
+    }
+    let port = u16::try_from(parse_integer(args[1])?).map_err(|_| {
+        RedisError::UnexpectedArgumentType(format!(
+            "For REPLICAOF, invalid master port {}",
+            String::from_utf8_lossy(args[1])
+        ))
+    })?;
+    Ok(RedisRequest::ReplicaOf(Some((args[0], port))))
+}
+
+// Extracts the contents of arguments which must all be BulkStrings.
+fn bulk_strings<'a>(command: &str, values: &[RespValue<'a>]) -> Result<Vec<&'a [u8]>, RedisError> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(idx, value)| match value {
+            RespValue::BulkString(contents) => Ok(*contents),
+            _ => Err(RedisError::UnexpectedArgumentType(format!(
+                "For {} expected arguments of type BulkString, got {} at position {}",
+                command,
+                value.type_string(),
+                idx
+            ))),
+        })
+        .collect()
+}
+
+// Extracts the contents of exactly count arguments which must all be BulkStrings.
+fn exact_args<'a>(
+    command: &str,
+    values: &[RespValue<'a>],
+    count: usize,
+) -> Result<Vec<&'a [u8]>, RedisError> {
+    let args = bulk_strings(command, values)?;
+    if args.len() != count {
+        return Err(RedisError::UnexpectedNumberOfArgs(format!(
+            "For {} expected {} args found {}",
+            command,
+            count,
+            args.len()
+        )));
+    }
+    Ok(args)
+}
+
+fn uppercase(value: &[u8]) -> Vec<u8> {
+    value.iter().map(|u| u.to_ascii_uppercase()).collect()
+}
+
+// Parses an expiration for the command, given in seconds or milliseconds from now with
+// EX and PX, or since the epoch with EXAT and PXAT.
+fn parse_expiration(
+    command: &str,
+    expiration_type: &[u8],
+    expiration_value: &[u8],
+) -> Result<SystemTime, RedisError> {
+    let invalid = || {
+        RedisError::UnexpectedArgumentType(format!("invalid expire time in '{command}' command"))
+    };
+    let value = parse_integer(expiration_value)?;
+    if value <= 0 {
+        return Err(invalid());
+    }
+    let value = value as u64;
+    match &uppercase(expiration_type)[..] {
+        b"EX" => SystemTime::now().checked_add(Duration::from_secs(value)),
+        b"PX" => SystemTime::now().checked_add(Duration::from_millis(value)),
+        b"EXAT" => UNIX_EPOCH.checked_add(Duration::from_secs(value)),
+        _ => UNIX_EPOCH.checked_add(Duration::from_millis(value)),
+    }
+    .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::RespError;
+    use crate::resp_parser::RespValue;
+
+    #[test]
+    fn parse_ping() {
+        let ping_value = RespValue::Array(vec![RespValue::BulkString(b"PING")]);
+        let parsed = parse_command(ping_value);
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert!(matches!(parsed.unwrap(), RedisRequest::Ping));
+    }
+
+    #[test]
+    fn fail_to_parse_ping() {
+        let ping_value = RespValue::Array(vec![
+            RespValue::BulkString(b"PING"),
+            RespValue::SimpleString(b"string"),
+        ]);
+        let parsed = parse_command(ping_value);
+        assert!(
+            parsed.is_err(),
+            "Expected error, got: {:?}",
+            parsed.unwrap()
+        );
+        assert!(matches!(
+            parsed.unwrap_err(),
+            RedisError::UnexpectedNumberOfArgs(_)
+        ));
+    }
+
+    #[test]
+    fn parse_echo() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"ECHO"),
+            RespValue::BulkString(b"contents"),
+        ]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert!(matches!(parsed.unwrap(), RedisRequest::Echo(b"contents")));
+    }
+
+    #[test]
+    fn fail_parse_echo_missing_contents() {
+        let echo_value = RespValue::Array(vec![RespValue::BulkString(b"ECHO")]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_err(),
+            "Expected error, got: {:?}",
+            parsed.unwrap()
+        );
+        assert!(matches!(
+            parsed.unwrap_err(),
+            RedisError::UnexpectedNumberOfArgs(_)
+        ));
+    }
+
+    #[test]
+    fn fail_parse_echo_wrong_contents_type() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"ECHO"),
+            RespValue::SimpleError(b"ERROR"),
+        ]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_err(),
+            "Expected error, got: {:?}",
+            parsed.unwrap()
+        );
+        assert!(matches!(
+            parsed.unwrap_err(),
+            RedisError::UnexpectedArgumentType(_)
+        ));
+    }
+
+    #[test]
+    fn parse_set() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"SET"),
+            RespValue::BulkString(b"key"),
+            RespValue::BulkString(b"contents"),
+        ]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert!(matches!(
+            parsed.unwrap(),
+            RedisRequest::Set {
+                key: b"key",
+                value: b"contents",
+                expiration: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_set_with_expiration() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"SET"),
+            RespValue::BulkString(b"key"),
+            RespValue::BulkString(b"contents"),
+            RespValue::BulkString(b"px"),
+            RespValue::BulkString(b"1000"),
+        ]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert!(matches!(
+            parsed.unwrap(),
+            RedisRequest::Set {
+                key: b"key",
+                value: b"contents",
+                expiration: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_set_with_bad_expiration_type() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"SET"),
+            RespValue::BulkString(b"key"),
+            RespValue::BulkString(b"contents"),
+            RespValue::BulkString(b"unknown"),
+            RespValue::BulkString(b"1000"),
+        ]);
+
+        assert!(matches!(
+            parse_command(echo_value),
+            Err(RedisError::UnexpectedArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn parse_set_with_bad_expiration_value() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"SET"),
+            RespValue::BulkString(b"key"),
+            RespValue::BulkString(b"contents"),
+            RespValue::BulkString(b"px"),
+            RespValue::BulkString(b"not a number"),
+        ]);
+
+        assert!(matches!(
+            parse_command(echo_value),
+            Err(RedisError::RespParseError(RespError::IntParseFailure(_)))
+        ));
+    }
+
+    #[test]
+    fn parse_set_with_options() {
+        assert_eq!(
+            parse_command(command(&[
+                b"SET",
+                b"k",
+                b"v",
+                b"EXAT",
+                b"2000000000",
+                b"nx",
+                b"GET"
+            ]))
+            .unwrap(),
+            RedisRequest::Set {
+                key: b"k",
+                value: b"v",
+                expiration: Some(UNIX_EPOCH + Duration::from_secs(2_000_000_000)),
+                options: SetOptions {
+                    nx: true,
+                    get: true,
+                    ..SetOptions::default()
+                }
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"SET", b"k", b"v", b"KEEPTTL", b"XX"])).unwrap(),
+            RedisRequest::Set {
+                key: b"k",
+                value: b"v",
+                expiration: None,
+                options: SetOptions {
+                    xx: true,
+                    keep_ttl: true,
+                    ..SetOptions::default()
+                }
+            }
+        );
+        for conflicting in [
+            &[b"NX" as &[u8], b"XX"][..],
+            &[b"EX", b"1", b"PX", b"1"],
+            &[b"KEEPTTL", b"EX", b"1"],
+            &[b"PXAT", b"1", b"KEEPTTL"],
+            &[b"EX"],
+            &[b"EX", b"0"],
+            &[b"EX", b"9223372036854775807"],
+        ] {
+            let mut args = vec![b"SET" as &[u8], b"k", b"v"];
+            args.extend_from_slice(conflicting);
+            assert!(
+                matches!(
+                    parse_command(command(&args)),
+                    Err(RedisError::UnexpectedArgumentType(_))
+                ),
+                "{:?}",
+                conflicting
+            );
+        }
+    }
+
+    #[test]
+    fn parse_expire() {
+        assert_eq!(
+            parse_command(command(&[b"PEXPIREAT", b"k", b"1500", b"xx", b"GT"])).unwrap(),
+            RedisRequest::Expire {
+                key: b"k",
+                expiration: UNIX_EPOCH + Duration::from_millis(1500),
+                conditions: vec![ExpireCondition::Xx, ExpireCondition::Gt]
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"EXPIREAT", b"k", b"-10"])).unwrap(),
+            RedisRequest::Expire {
+                key: b"k",
+                expiration: UNIX_EPOCH,
+                conditions: vec![]
+            }
+        );
+        for options in [
+            &[b"NX" as &[u8], b"XX"][..],
+            &[b"GT", b"NX"],
+            &[b"GT", b"LT"],
+            &[b"SOON"],
+        ] {
+            let mut args = vec![b"EXPIRE" as &[u8], b"k", b"10"];
+            args.extend_from_slice(options);
+            assert!(parse_command(command(&args)).is_err(), "{:?}", options);
+        }
+        assert!(parse_command(command(&[b"EXPIRE", b"k", b"9223372036854775807"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"PTTL", b"k"])).unwrap(),
+            RedisRequest::Ttl {
+                key: b"k",
+                millis: true
+            }
+        );
+    }
+
+    #[test]
+    fn parse_string_commands() {
+        assert_eq!(
+            parse_command(command(&[b"GETSET", b"k", b"v"])).unwrap(),
+            RedisRequest::Set {
+                key: b"k",
+                value: b"v",
+                expiration: None,
+                options: SetOptions {
+                    get: true,
+                    ..SetOptions::default()
+                }
+            }
+        );
+        assert!(matches!(
+            parse_command(command(&[b"PSETEX", b"k", b"100", b"v"])).unwrap(),
+            RedisRequest::Set {
+                expiration: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(
+            parse_command(command(&[b"SETEX", b"k", b"0", b"v"]))
+                .unwrap_err()
+                .to_string(),
+            "Unexpected argument type: invalid expire time in 'setex' command"
+        );
+        assert_eq!(
+            parse_command(command(&[b"SETRANGE", b"k", b"3", b"v"])).unwrap(),
+            RedisRequest::SetRange {
+                key: b"k",
+                offset: 3,
+                value: b"v"
+            }
+        );
+        assert!(parse_command(command(&[b"SETRANGE", b"k", b"-1", b"v"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"GETEX", b"k", b"persist"])).unwrap(),
+            RedisRequest::GetEx {
+                key: b"k",
+                expiration: None,
+                persist: true
+            }
+        );
+        assert!(matches!(
+            parse_command(command(&[b"GETEX", b"k", b"EXAT", b"100"])).unwrap(),
+            RedisRequest::GetEx {
+                expiration: Some(_),
+                persist: false,
+                ..
+            }
+        ));
+        assert!(parse_command(command(&[b"GETEX", b"k", b"EX", b"1", b"PERSIST"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"MSETNX", b"a", b"1", b"b", b"2"])).unwrap(),
+            RedisRequest::MSet {
+                pairs: vec![(b"a", b"1"), (b"b", b"2")],
+                nx: true
+            }
+        );
+        assert!(matches!(
+            parse_command(command(&[b"MSET", b"a", b"1", b"b"])),
+            Err(RedisError::UnexpectedNumberOfArgs(_))
+        ));
+    }
+
+    #[test]
+    fn parse_keyspace_commands() {
+        assert_eq!(
+            parse_command(command(&[b"DEL", b"a", b"b"])).unwrap(),
+            RedisRequest::Del(vec![b"a", b"b"])
+        );
+        assert!(parse_command(command(&[b"UNLINK"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"RENAMENX", b"a", b"b"])).unwrap(),
+            RedisRequest::Rename {
+                key: b"a",
+                new_key: b"b",
+                nx: true
+            }
+        );
+        assert!(parse_command(command(&[b"RENAME", b"a"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"COPY", b"a", b"b", b"replace", b"DB", b"3"])).unwrap(),
+            RedisRequest::Copy {
+                source: b"a",
+                destination: b"b",
+                db: Some(3),
+                replace: true
+            }
+        );
+        assert!(parse_command(command(&[b"COPY", b"a", b"b", b"DB"])).is_err());
+        assert!(parse_command(command(&[b"COPY", b"a", b"b", b"DB", b"-1"])).is_err());
+        assert!(parse_command(command(&[b"DBSIZE", b"extra"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"FLUSHALL", b"async"])).unwrap(),
+            RedisRequest::FlushAll { asynchronous: true }
+        );
+        assert_eq!(
+            parse_command(command(&[b"FLUSHDB"])).unwrap(),
+            RedisRequest::FlushDb {
+                asynchronous: false
+            }
+        );
+        assert!(parse_command(command(&[b"FLUSHDB", b"SYNC", b"ASYNC"])).is_err());
+    }
+
+    #[test]
+    fn parse_get() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"GET"),
+            RespValue::BulkString(b"key"),
+        ]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert!(matches!(parsed.unwrap(), RedisRequest::Get(b"key")));
+    }
+
+    #[test]
+    fn parse_config_get_single() {
+        let config_get = RespValue::Array(vec![
+            RespValue::BulkString(b"CONFIG"),
+            RespValue::BulkString(b"GET"),
+            RespValue::BulkString(b"dir"),
+        ]);
+        assert!(matches!(parse_command(config_get),
+            Ok(RedisRequest::ConfigGet(params)) if matches!(params[..], [b"dir"])));
+    }
+
+    #[test]
+    fn parse_config_get_multiple() {
+        let values = RespValue::Array(vec![
+            RespValue::BulkString(b"CONFIG"),
+            RespValue::BulkString(b"GET"),
+            RespValue::BulkString(b"dir"),
+            RespValue::BulkString(b"max_concurrency"),
+        ]);
+        assert!(matches!(parse_command(values),
+            Ok(RedisRequest::ConfigGet(params)) if matches!(params[..], [b"dir", b"max_concurrency"])));
+    }
+
+    #[test]
+    fn parse_keys() {
+        let echo_value = RespValue::Array(vec![
+            RespValue::BulkString(b"KEYS"),
+            RespValue::BulkString(b"*"),
+        ]);
+        let parsed = parse_command(echo_value);
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert!(matches!(parsed.unwrap(), RedisRequest::Keys(b"*")));
+    }
+
+    #[test]
+    fn parse_replconf_listening_port() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"REPLCONF"),
+            RespValue::BulkString(b"listening-port"),
+            RespValue::BulkString(b"6380"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::ReplConf(ReplConf::ListeningPort(6380)))
+        ));
+    }
+
+    #[test]
+    fn parse_replconf_capabilities() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"REPLCONF"),
+            RespValue::BulkString(b"capa"),
+            RespValue::BulkString(b"eof"),
+            RespValue::BulkString(b"capa"),
+            RespValue::BulkString(b"psync2"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::ReplConf(ReplConf::Capabilities))
+        ));
+    }
+
+    #[test]
+    fn parse_replconf_unknown_option() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"REPLCONF"),
+            RespValue::BulkString(b"unknown"),
+            RespValue::BulkString(b"1"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Err(RedisError::UnknownRequest(_))
+        ));
+    }
+
+    #[test]
+    fn parse_replconf_ack() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"REPLCONF"),
+            RespValue::BulkString(b"ACK"),
+            RespValue::BulkString(b"154"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::ReplConf(ReplConf::Ack(154)))
+        ));
+    }
+
+    #[test]
+    fn parse_replconf_getack() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"REPLCONF"),
+            RespValue::BulkString(b"GETACK"),
+            RespValue::BulkString(b"*"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::ReplConf(ReplConf::GetAck))
+        ));
+    }
+
+    #[test]
+    fn parse_wait() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"WAIT"),
+            RespValue::BulkString(b"2"),
+            RespValue::BulkString(b"500"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::Wait {
+                num_replicas: 2,
+                timeout_millis: 500
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_wait_negative_timeout() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"WAIT"),
+            RespValue::BulkString(b"2"),
+            RespValue::BulkString(b"-1"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Err(RedisError::UnexpectedArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn parse_replicaof() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"REPLICAOF"),
+            RespValue::BulkString(b"localhost"),
+            RespValue::BulkString(b"6379"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::ReplicaOf(Some((b"localhost", 6379))))
+        ));
+    }
+
+    #[test]
+    fn parse_slaveof_no_one() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"SLAVEOF"),
+            RespValue::BulkString(b"no"),
+            RespValue::BulkString(b"one"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::ReplicaOf(None))
+        ));
+    }
+
+    #[test]
+    fn parse_bgsave() {
+        let value = RespValue::Array(vec![RespValue::BulkString(b"bgsave")]);
+        assert!(matches!(parse_command(value), Ok(RedisRequest::BgSave)));
+    }
+
+    #[test]
+    fn parse_save_with_args() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"SAVE"),
+            RespValue::BulkString(b"now"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Err(RedisError::UnexpectedNumberOfArgs(_))
+        ));
+    }
+
+    #[test]
+    fn parse_select() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"SELECT"),
+            RespValue::BulkString(b"3"),
+        ]);
+        assert!(matches!(parse_command(value), Ok(RedisRequest::Select(3))));
+    }
+
+    #[test]
+    fn parse_select_negative_index() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"SELECT"),
+            RespValue::BulkString(b"-1"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Err(RedisError::UnexpectedArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn parse_swapdb() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"SWAPDB"),
+            RespValue::BulkString(b"0"),
+            RespValue::BulkString(b"1"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::SwapDb(0, 1))
+        ));
+    }
+
+    #[test]
+    fn parse_move() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"MOVE"),
+            RespValue::BulkString(b"foo"),
+            RespValue::BulkString(b"2"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::Move { key: b"foo", db: 2 })
+        ));
+    }
+
+    #[test]
+    fn parse_lpush() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"LPUSH"),
+            RespValue::BulkString(b"list"),
+            RespValue::BulkString(b"a"),
+            RespValue::BulkString(b"b"),
+        ]);
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::Push {
+                key: b"list",
+                values: vec![b"a", b"b"],
+                end: ListEnd::Left
+            }
+        );
+    }
+
+    #[test]
+    fn parse_rpop_with_count() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"RPOP"),
+            RespValue::BulkString(b"list"),
+            RespValue::BulkString(b"3"),
+        ]);
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::Pop {
+                key: b"list",
+                count: Some(3),
+                end: ListEnd::Right
+            }
+        );
+    }
+
+    #[test]
+    fn parse_lpop_negative_count() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"LPOP"),
+            RespValue::BulkString(b"list"),
+            RespValue::BulkString(b"-1"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Err(RedisError::UnexpectedArgumentType(_))
+        ));
+    }
+
+    #[test]
+    fn parse_lrange() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"LRANGE"),
+            RespValue::BulkString(b"list"),
+            RespValue::BulkString(b"0"),
+            RespValue::BulkString(b"-1"),
+        ]);
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::LRange {
+                key: b"list",
+                start: 0,
+                stop: -1
+            }
+        );
+    }
+
+    #[test]
+    fn parse_linsert() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"LINSERT"),
+            RespValue::BulkString(b"list"),
+            RespValue::BulkString(b"before"),
+            RespValue::BulkString(b"pivot"),
+            RespValue::BulkString(b"value"),
+        ]);
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::LInsert {
+                key: b"list",
+                before: true,
+                pivot: b"pivot",
+                value: b"value"
+            }
         );
-        assert!(matches!(parsed.unwrap(), RedisRequest::Ping));
     }
 
     #[test]
-    fn fail_to_parse_ping() {
-        let ping_value = RespValue::Array(vec![
-            RespValue::BulkString(b"PING"),
-            RespValue::SimpleString(b"string"),
+    fn parse_lmove() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"LMOVE"),
+            RespValue::BulkString(b"source"),
+            RespValue::BulkString(b"destination"),
+            RespValue::BulkString(b"RIGHT"),
+            RespValue::BulkString(b"left"),
         ]);
-        let parsed = parse_command(ping_value);
-        assert!(
-            parsed.is_err(),
-            "Expected error, got: {:?}",
-            parsed.unwrap()
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::LMove {
+                source: b"source",
+                destination: b"destination",
+                from: ListEnd::Right,
+                to: ListEnd::Left
+            }
         );
+    }
+
+    #[test]
+    fn parse_lmove_bad_direction() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"LMOVE"),
+            RespValue::BulkString(b"source"),
+            RespValue::BulkString(b"destination"),
+            RespValue::BulkString(b"UP"),
+            RespValue::BulkString(b"LEFT"),
+        ]);
         assert!(matches!(
-            parsed.unwrap_err(),
-            RedisError::UnexpectedNumberOfArgs(_)
+            parse_command(value),
+            Err(RedisError::UnexpectedArgumentType(_))
         ));
     }
 
     #[test]
-    fn parse_echo() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"ECHO"),
-            RespValue::BulkString(b"contents"),
+    fn parse_blpop() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"BLPOP"),
+            RespValue::BulkString(b"first"),
+            RespValue::BulkString(b"second"),
+            RespValue::BulkString(b"0.5"),
         ]);
-        let parsed = parse_command(echo_value);
-        assert!(
-            parsed.is_ok(),
-            "Expected ok result, got: {}",
-            parsed.err().unwrap()
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::BlockingPop {
+                keys: vec![b"first", b"second"],
+                end: ListEnd::Left,
+                timeout: Some(Duration::from_millis(500))
+            }
         );
-        assert!(matches!(parsed.unwrap(), RedisRequest::Echo(b"contents")));
     }
 
     #[test]
-    fn fail_parse_echo_missing_contents() {
-        let echo_value = RespValue::Array(vec![RespValue::BulkString(b"ECHO")]);
-        let parsed = parse_command(echo_value);
-        assert!(
-            parsed.is_err(),
-            "Expected error, got: {:?}",
-            parsed.unwrap()
-        );
-        assert!(matches!(
-            parsed.unwrap_err(),
-            RedisError::UnexpectedNumberOfArgs(_)
-        ));
+    fn parse_blocking_timeouts() {
+        assert_eq!(parse_timeout(b"0").unwrap(), None);
+        assert_eq!(parse_timeout(b"2").unwrap(), Some(Duration::from_secs(2)));
+        assert_eq!(parse_timeout(b"1e300").unwrap(), None);
+        assert!(parse_timeout(b"-1").is_err());
+        assert!(parse_timeout(b"soon").is_err());
+        assert!(parse_timeout(b"inf").is_err());
     }
 
     #[test]
-    fn fail_parse_echo_wrong_contents_type() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"ECHO"),
-            RespValue::SimpleError(b"ERROR"),
+    fn parse_blmove() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"BLMOVE"),
+            RespValue::BulkString(b"source"),
+            RespValue::BulkString(b"destination"),
+            RespValue::BulkString(b"LEFT"),
+            RespValue::BulkString(b"RIGHT"),
+            RespValue::BulkString(b"0"),
         ]);
-        let parsed = parse_command(echo_value);
-        assert!(
-            parsed.is_err(),
-            "Expected error, got: {:?}",
-            parsed.unwrap()
+        assert_eq!(
+            parse_command(value).unwrap(),
+            RedisRequest::BlockingMove {
+                source: b"source",
+                destination: b"destination",
+                from: ListEnd::Left,
+                to: ListEnd::Right,
+                timeout: None
+            }
+        );
+    }
+
+    fn command<'a>(args: &[&'a [u8]]) -> RespValue<'a> {
+        RespValue::Array(args.iter().map(|arg| RespValue::BulkString(arg)).collect())
+    }
+
+    #[test]
+    fn parse_hset() {
+        assert_eq!(
+            parse_command(command(&[b"HSET", b"hash", b"a", b"1", b"b", b"2"])).unwrap(),
+            RedisRequest::HSet {
+                key: b"hash",
+                pairs: vec![(b"a", b"1"), (b"b", b"2")]
+            }
         );
         assert!(matches!(
-            parsed.unwrap_err(),
-            RedisError::UnexpectedArgumentType(_)
+            parse_command(command(&[b"HSET", b"hash", b"a", b"1", b"b"])),
+            Err(RedisError::UnexpectedNumberOfArgs(_))
         ));
     }
 
     #[test]
-    fn parse_set() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"SET"),
-            RespValue::BulkString(b"key"),
-            RespValue::BulkString(b"contents"),
-        ]);
-        let parsed = parse_command(echo_value);
-        assert!(
-            parsed.is_ok(),
-            "Expected ok result, got: {}",
-            parsed.err().unwrap()
+    fn parse_scan() {
+        assert_eq!(
+            parse_command(command(&[b"SCAN", b"0"])).unwrap(),
+            RedisRequest::Scan {
+                cursor: 0,
+                pattern: None,
+                count: 10,
+                value_type: None
+            }
         );
-        assert!(matches!(
-            parsed.unwrap(),
-            RedisRequest::Set {
-                key: b"key",
-                value: b"contents",
-                expiration: None
+        assert_eq!(
+            parse_command(command(&[
+                b"SCAN",
+                b"18446744073709551615",
+                b"type",
+                b"hash",
+                b"MATCH",
+                b"user:*",
+                b"COUNT",
+                b"100"
+            ]))
+            .unwrap(),
+            RedisRequest::Scan {
+                cursor: u64::MAX,
+                pattern: Some(b"user:*"),
+                count: 100,
+                value_type: Some(b"hash")
             }
-        ));
+        );
+        assert!(parse_command(command(&[b"SCAN", b"-1"])).is_err());
+        assert!(parse_command(command(&[b"SCAN", b"0", b"COUNT", b"0"])).is_err());
+        assert!(parse_command(command(&[b"SCAN", b"0", b"MATCH"])).is_err());
+        assert!(parse_command(command(&[b"SCAN", b"0", b"NOVALUES"])).is_err());
     }
 
     #[test]
-    fn parse_set_with_expiration() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"SET"),
-            RespValue::BulkString(b"key"),
-            RespValue::BulkString(b"contents"),
-            RespValue::BulkString(b"px"),
-            RespValue::BulkString(b"1000"),
-        ]);
-        let parsed = parse_command(echo_value);
-        assert!(
-            parsed.is_ok(),
-            "Expected ok result, got: {}",
-            parsed.err().unwrap()
+    fn parse_hscan() {
+        assert_eq!(
+            parse_command(command(&[
+                b"HSCAN",
+                b"hash",
+                b"0",
+                b"match",
+                b"a*",
+                b"COUNT",
+                b"20",
+                b"NOVALUES"
+            ]))
+            .unwrap(),
+            RedisRequest::HScan {
+                key: b"hash",
+                cursor: 0,
+                pattern: Some(b"a*"),
+                count: 20,
+                no_values: true
+            }
         );
-        assert!(matches!(
-            parsed.unwrap(),
-            RedisRequest::Set {
-                key: b"key",
-                value: b"contents",
-                expiration: Some(_)
+        assert!(parse_command(command(&[b"HSCAN", b"hash", b"0", b"MATCH"])).is_err());
+        assert!(parse_command(command(&[b"HSCAN", b"hash", b"x"])).is_err());
+    }
+
+    #[test]
+    fn parse_hrandfield() {
+        assert_eq!(
+            parse_command(command(&[b"HRANDFIELD", b"hash", b"-3", b"WITHVALUES"])).unwrap(),
+            RedisRequest::HRandField {
+                key: b"hash",
+                count: Some(-3),
+                with_values: true
             }
-        ));
+        );
+        assert!(parse_command(command(&[b"HRANDFIELD", b"hash", b"1", b"VALUES"])).is_err());
+        assert!(
+            parse_command(command(&[b"HRANDFIELD", b"hash", b"-9223372036854775807"])).is_err()
+        );
+        assert!(
+            parse_command(command(&[b"HRANDFIELD", b"hash", b"-4611686018427387903"])).is_err()
+        );
     }
 
     #[test]
-    fn parse_set_with_bad_expiration_type() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"SET"),
-            RespValue::BulkString(b"key"),
-            RespValue::BulkString(b"contents"),
-            RespValue::BulkString(b"unknown"),
-            RespValue::BulkString(b"1000"),
-        ]);
+    fn parse_hexpire() {
+        let before = SystemTime::now();
+        let Ok(RedisRequest::HExpire {
+            key,
+            expiration,
+            condition,
+            fields,
+        }) = parse_command(command(&[
+            b"HEXPIRE", b"hash", b"100", b"GT", b"FIELDS", b"2", b"a", b"b",
+        ]))
+        else {
+            panic!("Expected HEXPIRE");
+        };
+        assert_eq!(key, b"hash");
+        assert!(expiration >= before + Duration::from_secs(100));
+        assert_eq!(condition, Some(ExpireCondition::Gt));
+        assert_eq!(fields, vec![b"a", b"b"]);
 
         assert!(matches!(
-            parse_command(echo_value),
-            Err(RedisError::UnknownRequest(_))
+            parse_command(command(&[b"HPEXPIREAT", b"hash", b"1500", b"FIELDS", b"1", b"a"])),
+            Ok(RedisRequest::HExpire { expiration, condition: None, .. })
+                if expiration == UNIX_EPOCH + Duration::from_millis(1500)
         ));
     }
 
     #[test]
-    fn parse_set_with_bad_expiration_value() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"SET"),
-            RespValue::BulkString(b"key"),
-            RespValue::BulkString(b"contents"),
-            RespValue::BulkString(b"px"),
-            RespValue::BulkString(b"not a number"),
-        ]);
-
+    fn parse_hexpire_mismatched_fields() {
         assert!(matches!(
-            parse_command(echo_value),
-            Err(RedisError::RespParseError(RespError::IntParseFailure(_)))
+            parse_command(command(&[b"HEXPIRE", b"hash", b"1", b"FIELDS", b"2", b"a"])),
+            Err(RedisError::UnexpectedArgumentType(_))
         ));
+        assert!(parse_command(command(&[
+            b"HEXPIRE", b"hash", b"-1", b"FIELDS", b"1", b"a"
+        ]))
+        .is_err());
     }
 
     #[test]
-    fn parse_get() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"GET"),
-            RespValue::BulkString(b"key"),
-        ]);
-        let parsed = parse_command(echo_value);
-        assert!(
-            parsed.is_ok(),
-            "Expected ok result, got: {}",
-            parsed.err().unwrap()
+    fn parse_httl() {
+        assert_eq!(
+            parse_command(command(&[b"HTTL", b"hash", b"FIELDS", b"1", b"a"])).unwrap(),
+            RedisRequest::HTtl {
+                key: b"hash",
+                fields: vec![b"a"]
+            }
         );
-        assert!(matches!(parsed.unwrap(), RedisRequest::Get(b"key")));
     }
 
     #[test]
-    fn parse_config_get_single() {
-        let config_get = RespValue::Array(vec![
-            RespValue::BulkString(b"CONFIG"),
-            RespValue::BulkString(b"GET"),
-            RespValue::BulkString(b"dir"),
-        ]);
-        assert!(matches!(parse_command(config_get),
-            Ok(RedisRequest::ConfigGet(params)) if matches!(params[..], [b"dir"])));
+    fn parse_sadd() {
+        assert_eq!(
+            parse_command(command(&[b"SADD", b"set", b"a", b"b"])).unwrap(),
+            RedisRequest::SAdd {
+                key: b"set",
+                members: vec![b"a", b"b"]
+            }
+        );
+        assert!(parse_command(command(&[b"SADD", b"set"])).is_err());
     }
 
     #[test]
-    fn parse_config_get_multiple() {
-        let values = RespValue::Array(vec![
-            RespValue::BulkString(b"CONFIG"),
-            RespValue::BulkString(b"GET"),
-            RespValue::BulkString(b"dir"),
-            RespValue::BulkString(b"max_concurrency"),
-        ]);
-        assert!(matches!(parse_command(values),
-            Ok(RedisRequest::ConfigGet(params)) if matches!(params[..], [b"dir", b"max_concurrency"])));
+    fn parse_set_combinations() {
+        assert_eq!(
+            parse_command(command(&[b"SDIFF", b"a", b"b"])).unwrap(),
+            RedisRequest::SCombine {
+                op: SetOp::Diff,
+                keys: vec![b"a", b"b"]
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"SUNIONSTORE", b"dest", b"a"])).unwrap(),
+            RedisRequest::SCombineStore {
+                op: SetOp::Union,
+                destination: b"dest",
+                keys: vec![b"a"]
+            }
+        );
     }
 
     #[test]
-    fn parse_keys() {
-        let echo_value = RespValue::Array(vec![
-            RespValue::BulkString(b"KEYS"),
-            RespValue::BulkString(b"*"),
-        ]);
-        let parsed = parse_command(echo_value);
+    fn parse_sintercard() {
+        assert_eq!(
+            parse_command(command(&[b"SINTERCARD", b"2", b"a", b"b", b"limit", b"5"])).unwrap(),
+            RedisRequest::SInterCard {
+                keys: vec![b"a", b"b"],
+                limit: 5
+            }
+        );
+        assert!(parse_command(command(&[b"SINTERCARD", b"3", b"a", b"b"])).is_err());
+        assert!(parse_command(command(&[b"SINTERCARD", b"0", b"a"])).is_err());
+        assert!(parse_command(command(&[b"SINTERCARD", b"1", b"a", b"LIMIT"])).is_err());
+    }
+
+    #[test]
+    fn parse_spop() {
+        assert_eq!(
+            parse_command(command(&[b"SPOP", b"set", b"2"])).unwrap(),
+            RedisRequest::SPop {
+                key: b"set",
+                count: Some(2)
+            }
+        );
+        assert!(parse_command(command(&[b"SPOP", b"set", b"-2"])).is_err());
+        assert_eq!(
+            parse_command(command(&[b"SRANDMEMBER", b"set", b"-1048576"])).unwrap(),
+            RedisRequest::SRandMember {
+                key: b"set",
+                count: Some(-1048576)
+            }
+        );
+        assert!(parse_command(command(&[b"SRANDMEMBER", b"set", b"-1048577"])).is_err());
         assert!(
-            parsed.is_ok(),
-            "Expected ok result, got: {}",
-            parsed.err().unwrap()
+            parse_command(command(&[b"SRANDMEMBER", b"set", b"-9223372036854775808"])).is_err()
         );
-        assert!(matches!(parsed.unwrap(), RedisRequest::Keys(b"*")));
+    }
+
+    #[test]
+    fn parse_zadd() {
+        assert_eq!(
+            parse_command(command(&[
+                b"ZADD", b"z", b"xx", b"CH", b"1", b"a", b"-inf", b"b"
+            ]))
+            .unwrap(),
+            RedisRequest::ZAdd {
+                key: b"z",
+                options: ZAddOptions {
+                    xx: true,
+                    ch: true,
+                    ..ZAddOptions::default()
+                },
+                pairs: vec![(1.0, b"a"), (f64::NEG_INFINITY, b"b")]
+            }
+        );
+        assert!(parse_command(command(&[b"ZADD", b"z", b"NX", b"XX", b"1", b"a"])).is_err());
+        assert!(parse_command(command(&[b"ZADD", b"z", b"GT", b"LT", b"1", b"a"])).is_err());
+        assert!(parse_command(command(&[b"ZADD", b"z", b"INCR", b"1", b"a", b"2", b"b"])).is_err());
+        assert!(parse_command(command(&[b"ZADD", b"z", b"nan", b"a"])).is_err());
+        assert!(parse_command(command(&[b"ZADD", b"z", b"1"])).is_err());
+    }
+
+    #[test]
+    fn parse_zrange() {
+        assert_eq!(
+            parse_command(command(&[
+                b"ZRANGE", b"z", b"(5", b"-inf", b"BYSCORE", b"REV", b"LIMIT", b"1", b"2"
+            ]))
+            .unwrap(),
+            RedisRequest::ZRange {
+                key: b"z",
+                range: ZRangeBy::Score(
+                    ScoreBound {
+                        value: f64::NEG_INFINITY,
+                        exclusive: false
+                    },
+                    ScoreBound {
+                        value: 5.0,
+                        exclusive: true
+                    }
+                ),
+                rev: true,
+                limit: Some((1, 2)),
+                with_scores: false
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"ZRANGEBYLEX", b"z", b"-", b"(c"])).unwrap(),
+            RedisRequest::ZRange {
+                key: b"z",
+                range: ZRangeBy::Lex(LexBound::Min, LexBound::Exclusive(b"c")),
+                rev: false,
+                limit: None,
+                with_scores: false
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"ZREVRANGE", b"z", b"0", b"-1", b"WITHSCORES"])).unwrap(),
+            RedisRequest::ZRange {
+                key: b"z",
+                range: ZRangeBy::Rank(0, -1),
+                rev: true,
+                limit: None,
+                with_scores: true
+            }
+        );
+        assert!(parse_command(command(&[
+            b"ZRANGE", b"z", b"0", b"1", b"LIMIT", b"0", b"1"
+        ]))
+        .is_err());
+        assert!(parse_command(command(&[b"ZRANGE", b"z", b"a", b"b", b"BYLEX"])).is_err());
+        assert!(parse_command(command(&[b"ZREVRANGE", b"z", b"0", b"1", b"REV"])).is_err());
+        assert!(parse_command(command(&[
+            b"ZRANGE",
+            b"z",
+            b"-",
+            b"+",
+            b"BYLEX",
+            b"WITHSCORES"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_zunionstore() {
+        assert_eq!(
+            parse_command(command(&[
+                b"ZUNIONSTORE",
+                b"d",
+                b"2",
+                b"a",
+                b"b",
+                b"WEIGHTS",
+                b"2",
+                b"3",
+                b"AGGREGATE",
+                b"max"
+            ]))
+            .unwrap(),
+            RedisRequest::ZCombineStore {
+                op: SetOp::Union,
+                destination: b"d",
+                keys: vec![b"a", b"b"],
+                weights: vec![2.0, 3.0],
+                aggregate: Aggregate::Max
+            }
+        );
+        assert!(parse_command(command(&[b"ZUNIONSTORE", b"d", b"2", b"a"])).is_err());
+        assert!(parse_command(command(&[
+            b"ZINTERSTORE",
+            b"d",
+            b"2",
+            b"a",
+            b"b",
+            b"WEIGHTS",
+            b"1"
+        ]))
+        .is_err());
+        assert!(parse_command(command(&[
+            b"ZDIFFSTORE",
+            b"d",
+            b"1",
+            b"a",
+            b"AGGREGATE",
+            b"MIN"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_xadd() {
+        assert_eq!(
+            parse_command(command(&[
+                b"XADD",
+                b"s",
+                b"NOMKSTREAM",
+                b"MAXLEN",
+                b"~",
+                b"10",
+                b"LIMIT",
+                b"5",
+                b"1-*",
+                b"f",
+                b"v"
+            ]))
+            .unwrap(),
+            RedisRequest::XAdd {
+                key: b"s",
+                no_mkstream: true,
+                trim: Some(StreamTrim {
+                    threshold: TrimThreshold::MaxLen(10),
+                    approximate: true,
+                    limit: Some(5)
+                }),
+                id: XAddId::AutoSequence(1),
+                fields: vec![(b"f", b"v")]
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"XADD", b"s", b"MINID", b"5", b"7", b"f", b"v"])).unwrap(),
+            RedisRequest::XAdd {
+                key: b"s",
+                no_mkstream: false,
+                trim: Some(StreamTrim {
+                    threshold: TrimThreshold::MinId(StreamId::new(5, 0)),
+                    approximate: false,
+                    limit: None
+                }),
+                id: XAddId::Explicit(StreamId::new(7, 0)),
+                fields: vec![(b"f", b"v")]
+            }
+        );
+        assert!(parse_command(command(&[b"XADD", b"s", b"*", b"f"])).is_err());
+        assert!(parse_command(command(&[b"XADD", b"s", b"1-x", b"f", b"v"])).is_err());
+        assert!(parse_command(command(&[
+            b"XADD", b"s", b"MAXLEN", b"1", b"LIMIT", b"1", b"*", b"f", b"v"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_xrange() {
+        assert_eq!(
+            parse_command(command(&[
+                b"XREVRANGE",
+                b"s",
+                b"+",
+                b"(5-1",
+                b"COUNT",
+                b"2"
+            ]))
+            .unwrap(),
+            RedisRequest::XRange {
+                key: b"s",
+                start: StreamId::new(5, 2),
+                end: StreamId::MAX,
+                count: Some(2),
+                rev: true
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[b"XRANGE", b"s", b"-", b"5"])).unwrap(),
+            RedisRequest::XRange {
+                key: b"s",
+                start: StreamId::MIN,
+                end: StreamId::new(5, u64::MAX),
+                count: None,
+                rev: false
+            }
+        );
+        assert!(parse_command(command(&[
+            b"XRANGE",
+            b"s",
+            b"(18446744073709551615-18446744073709551615",
+            b"+"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_xread() {
+        assert_eq!(
+            parse_command(command(&[
+                b"XREAD", b"COUNT", b"2", b"BLOCK", b"0", b"STREAMS", b"a", b"b", b"$", b"3-4"
+            ]))
+            .unwrap(),
+            RedisRequest::XRead {
+                keys: vec![b"a", b"b"],
+                ids: vec![None, Some(StreamId::new(3, 4))],
+                count: Some(2),
+                block: Some(None)
+            }
+        );
+        assert!(parse_command(command(&[b"XREAD", b"STREAMS", b"a", b"b", b"0"])).is_err());
+        assert!(parse_command(command(&[
+            b"XREAD", b"BLOCK", b"-1", b"STREAMS", b"a", b"0"
+        ]))
+        .is_err());
+        assert!(parse_command(command(&[b"XREAD", b"a", b"0"])).is_err());
+    }
+
+    #[test]
+    fn parse_xreadgroup() {
+        assert_eq!(
+            parse_command(command(&[
+                b"XREADGROUP",
+                b"GROUP",
+                b"g",
+                b"c",
+                b"NOACK",
+                b"BLOCK",
+                b"10",
+                b"STREAMS",
+                b"a",
+                b"b",
+                b">",
+                b"1"
+            ]))
+            .unwrap(),
+            RedisRequest::XReadGroup {
+                group: b"g",
+                consumer: b"c",
+                keys: vec![b"a", b"b"],
+                ids: vec![None, Some(StreamId::new(1, 0))],
+                count: None,
+                block: Some(Some(Duration::from_millis(10))),
+                no_ack: true
+            }
+        );
+        assert!(parse_command(command(&[b"XREADGROUP", b"STREAMS", b"a", b">"])).is_err());
+        assert!(parse_command(command(&[
+            b"XREADGROUP",
+            b"GROUP",
+            b"g",
+            b"c",
+            b"STREAMS",
+            b"a",
+            b"$"
+        ]))
+        .is_err());
+        assert!(parse_command(command(&[b"XREAD", b"STREAMS", b"a", b">"])).is_err());
+        assert!(parse_command(command(&[b"XREAD", b"NOACK", b"STREAMS", b"a", b"0"])).is_err());
+    }
+
+    #[test]
+    fn parse_xgroup() {
+        assert_eq!(
+            parse_command(command(&[
+                b"XGROUP",
+                b"create",
+                b"s",
+                b"g",
+                b"$",
+                b"MKSTREAM",
+                b"ENTRIESREAD",
+                b"3"
+            ]))
+            .unwrap(),
+            RedisRequest::XGroupCreate {
+                key: b"s",
+                group: b"g",
+                id: None,
+                mkstream: true,
+                entries_read: Some(3)
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[
+                b"XGROUP",
+                b"SETID",
+                b"s",
+                b"g",
+                b"5-1",
+                b"ENTRIESREAD",
+                b"-1"
+            ]))
+            .unwrap(),
+            RedisRequest::XGroupSetId {
+                key: b"s",
+                group: b"g",
+                id: Some(StreamId::new(5, 1)),
+                entries_read: None
+            }
+        );
+        assert!(parse_command(command(&[
+            b"XGROUP",
+            b"SETID",
+            b"s",
+            b"g",
+            b"0",
+            b"MKSTREAM"
+        ]))
+        .is_err());
+        assert!(parse_command(command(&[b"XGROUP", b"DESTROY", b"s"])).is_err());
+        assert!(parse_command(command(&[b"XGROUP", b"UNKNOWN", b"s"])).is_err());
+    }
+
+    #[test]
+    fn parse_xclaim() {
+        assert_eq!(
+            parse_command(command(&[
+                b"XCLAIM",
+                b"s",
+                b"g",
+                b"c",
+                b"-5",
+                b"1-1",
+                b"2",
+                b"TIME",
+                b"1000",
+                b"RETRYCOUNT",
+                b"4",
+                b"FORCE",
+                b"JUSTID",
+                b"LASTID",
+                b"3-0"
+            ]))
+            .unwrap(),
+            RedisRequest::XClaim {
+                key: b"s",
+                group: b"g",
+                consumer: b"c",
+                min_idle: 0,
+                ids: vec![StreamId::new(1, 1), StreamId::new(2, 0)],
+                options: ClaimOptions {
+                    idle: None,
+                    time: Some(1000),
+                    retry_count: Some(4),
+                    force: true,
+                    just_id: true,
+                    last_id: Some(StreamId::new(3, 0))
+                }
+            }
+        );
+        assert!(parse_command(command(&[b"XCLAIM", b"s", b"g", b"c", b"0", b"x"])).is_err());
+        assert!(parse_command(command(&[
+            b"XCLAIM", b"s", b"g", b"c", b"0", b"1", b"BOGUS"
+        ]))
+        .is_err());
+        assert_eq!(
+            parse_command(command(&[b"XAUTOCLAIM", b"s", b"g", b"c", b"10", b"(1-1"])).unwrap(),
+            RedisRequest::XAutoClaim {
+                key: b"s",
+                group: b"g",
+                consumer: b"c",
+                min_idle: 10,
+                start: StreamId::new(1, 2),
+                count: 100,
+                just_id: false
+            }
+        );
+        assert!(parse_command(command(&[
+            b"XAUTOCLAIM",
+            b"s",
+            b"g",
+            b"c",
+            b"10",
+            b"0",
+            b"COUNT",
+            b"0"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_xpending() {
+        assert_eq!(
+            parse_command(command(&[b"XPENDING", b"s", b"g"])).unwrap(),
+            RedisRequest::XPending {
+                key: b"s",
+                group: b"g",
+                range: None
+            }
+        );
+        assert_eq!(
+            parse_command(command(&[
+                b"XPENDING",
+                b"s",
+                b"g",
+                b"IDLE",
+                b"50",
+                b"-",
+                b"+",
+                b"10",
+                b"c"
+            ]))
+            .unwrap(),
+            RedisRequest::XPending {
+                key: b"s",
+                group: b"g",
+                range: Some(PendingRange {
+                    min_idle: 50,
+                    start: StreamId::MIN,
+                    end: StreamId::MAX,
+                    count: 10,
+                    consumer: Some(b"c")
+                })
+            }
+        );
+        assert!(parse_command(command(&[b"XPENDING", b"s", b"g", b"-", b"+"])).is_err());
+    }
+
+    #[test]
+    fn expire_conditions() {
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(1);
+        assert!(ExpireCondition::Nx.allows(None, now));
+        assert!(!ExpireCondition::Xx.allows(None, now));
+        assert!(ExpireCondition::Gt.allows(Some(now), later));
+        assert!(!ExpireCondition::Gt.allows(None, later));
+        assert!(ExpireCondition::Lt.allows(None, later));
+        assert!(!ExpireCondition::Lt.allows(Some(now), later));
+    }
+
+    #[test]
+    fn parse_psync() {
+        let value = RespValue::Array(vec![
+            RespValue::BulkString(b"PSYNC"),
+            RespValue::BulkString(b"?"),
+            RespValue::BulkString(b"-1"),
+        ]);
+        assert!(matches!(
+            parse_command(value),
+            Ok(RedisRequest::Psync {
+                replid: b"?",
+                offset: -1
+            })
+        ));
     }
 
     #[test]
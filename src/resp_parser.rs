@@ -5,6 +5,20 @@ use crate::errors::RespError;
 
 const SEPARATOR: &[u8] = b"\r\n";
 
+// The longest bulk string accepted, as Redis's default proto-max-bulk-len.
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+// The most elements an array may have, as in Redis.
+const MAX_ARRAY_LEN: i64 = i32::MAX as i64;
+
+// The most elements reserved for an array before they have arrived, so that a large
+// length in the header alone can't claim much memory.
+const MAX_ARRAY_PREALLOCATION: usize = 1024;
+
+// The longest line, such as the header of a bulk string, that may go without a
+// separator, as Redis limits inline requests.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
 #[derive(PartialEq, Clone, Debug)]
 pub(crate) enum RespValue<'a> {
     SimpleString(&'a [u8]),
@@ -17,7 +31,6 @@ pub(crate) enum RespValue<'a> {
 }
 
 impl<'a> RespValue<'a> {
-    #[allow(dead_code)]
     pub(crate) fn write<W: std::io::Write>(&self, writer: &mut W) -> Result<(), RespError> {
         match self {
             RespValue::SimpleString(contents) => {
@@ -116,6 +129,17 @@ impl<'a> RespValue<'a> {
 
 pub(crate) struct RespParser<'a> {
     finder: memchr::memmem::Finder<'a>,
+    // How far parse_frame got through an array which hadn't all arrived, so that the
+    // next call carries on from there rather than starting over.
+    partial: Option<PartialArray>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PartialArray {
+    // The number of elements not yet parsed.
+    remaining: i64,
+    // Where the next element starts in the input.
+    offset: usize,
 }
 
 struct RespPartialParse<'a> {
@@ -137,6 +161,7 @@ impl<'a> RespParser<'a> {
     pub(crate) fn new() -> Self {
         RespParser {
             finder: memchr::memmem::Finder::new(SEPARATOR),
+            partial: None,
         }
     }
 
@@ -154,6 +179,72 @@ impl<'a> RespParser<'a> {
         Ok(resp_values)
     }
 
+    /// Parses the first value in the input, returning it along with the number of bytes
+    /// of the input that it occupied.
+    pub(crate) fn parse_value<'b>(
+        &self,
+        input: &'b [u8],
+    ) -> Result<(RespValue<'b>, usize), RespError> {
+        let RespParseStep { value, remainder } = self.next_value(input)?;
+        Ok((value, input.len() - remainder.len()))
+    }
+
+    /// Parses the first value in a buffer which may only hold part of it so far, returning
+    /// None if more input is needed to complete the value.  Until a value is returned,
+    /// each call must be given the same input with more added to the end; the elements
+    /// of an array are then only parsed once as they arrive, however many calls it takes.
+    pub(crate) fn parse_frame<'b>(
+        &mut self,
+        input: &'b [u8],
+    ) -> Result<Option<(RespValue<'b>, usize)>, RespError> {
+        match self.find_frame_end(input) {
+            Ok(()) => (),
+            Err(RespError::UnexpectedEnd) => return Ok(None),
+            Err(error) => {
+                self.partial = None;
+                return Err(error);
+            }
+        }
+        self.partial = None;
+        match self.parse_value(input) {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(RespError::UnexpectedEnd) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Checks that all the elements of an array at the start of the input have arrived,
+    // carrying on from the element the last call stopped at, and failing with
+    // UnexpectedEnd if some haven't.  Other values are cheap enough to parse again.
+    fn find_frame_end(&mut self, input: &[u8]) -> Result<(), RespError> {
+        if self.partial.is_none() {
+            if input.first() != Some(&b'*') {
+                return Ok(());
+            }
+            let RespPartialParse { word, remainder } = self.next_word(input)?;
+            self.partial = Some(PartialArray {
+                remaining: parse_array_len(&word[1..])?,
+                offset: input.len() - remainder.len(),
+            });
+        }
+        let Some(mut partial) = self.partial else {
+            return Ok(());
+        };
+        while partial.remaining > 0 {
+            match self.next_value(&input[partial.offset..]) {
+                Ok(RespParseStep { remainder, .. }) => {
+                    partial.remaining -= 1;
+                    partial.offset = input.len() - remainder.len();
+                }
+                Err(error) => {
+                    self.partial = Some(partial);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
     // Extracts the next RespValue from the input, returning the value and
     // a slice pointing at the remainder of the input after that word.
     fn next_value<'b>(&self, input: &'b [u8]) -> RespResult<'b> {
@@ -190,20 +281,21 @@ impl<'a> RespParser<'a> {
                 word: &input[0..separator_pos],
                 remainder: &input[separator_pos + 2..],
             }),
+            None if input.len() > MAX_LINE_LEN => Err(RespError::LineTooLong),
             None => Err(RespError::UnexpectedEnd),
         }
     }
 
     fn parse_bulk_string<'b>(&self, input: &'b [u8], remainder: &'b [u8]) -> RespResult<'b> {
         let size = parse_integer(input)?;
-        if size < -1 {
+        if !(-1..=MAX_BULK_LEN).contains(&size) {
             Err(RespError::BadBulkStringSize(size))
         } else if size == -1 {
             Ok(RespParseStep {
                 value: RespValue::NullBulkString,
                 remainder,
             })
-        } else if (size as usize) > (remainder.len() - 2) {
+        } else if (size as usize) + 2 > remainder.len() {
             Err(RespError::UnexpectedEnd)
         } else if &remainder[(size as usize)..(size as usize + 2)] != SEPARATOR {
             Err(RespError::BadBulkStringSize(size))
@@ -216,7 +308,7 @@ impl<'a> RespParser<'a> {
     }
 
     fn parse_array<'b>(&self, input: &'b [u8], remainder: &'b [u8]) -> RespResult<'b> {
-        let size = parse_integer(input)?;
+        let size = parse_array_len(input)?;
         match size.cmp(&-1) {
             std::cmp::Ordering::Less => Err(RespError::BadArraySize(size)),
             std::cmp::Ordering::Equal => Ok(RespParseStep {
@@ -224,7 +316,7 @@ impl<'a> RespParser<'a> {
                 remainder,
             }),
             std::cmp::Ordering::Greater => {
-                let mut vals = Vec::with_capacity(size as usize);
+                let mut vals = Vec::with_capacity((size as usize).min(MAX_ARRAY_PREALLOCATION));
                 let mut curr_remainder = remainder;
                 for _ in 0..size {
                     let RespParseStep { value, remainder } = self.next_value(curr_remainder)?;
@@ -244,6 +336,15 @@ pub(crate) fn parse_integer(input: &[u8]) -> Result<i64, RespError> {
     Ok(std::str::from_utf8(input)?.parse::<i64>()?)
 }
 
+// Parses the length of an array, which is -1 for a null array.
+fn parse_array_len(input: &[u8]) -> Result<i64, RespError> {
+    let size = parse_integer(input)?;
+    if !(-1..=MAX_ARRAY_LEN).contains(&size) {
+        return Err(RespError::BadArraySize(size));
+    }
+    Ok(size)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -428,6 +529,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_value_reports_consumed_length() {
+        let parser = RespParser::new();
+        let parsed = parser.parse_value(b"*1\r\n$4\r\nPING\r\n+OK\r\n");
+        assert!(
+            parsed.is_ok(),
+            "Expected ok result, got: {}",
+            parsed.err().unwrap()
+        );
+        assert_eq!(
+            parsed.unwrap(),
+            (RespValue::Array(vec![RespValue::BulkString(b"PING")]), 14)
+        );
+    }
+
+    #[test]
+    fn parse_frame_waits_for_complete_value() {
+        let mut parser = RespParser::new();
+        let input = b"*2\r\n$3\r\nGET\r\n$6\r\nfoobar\r\n";
+        for end in 0..input.len() {
+            let parsed = parser.parse_frame(&input[..end]);
+            assert!(
+                matches!(parsed, Ok(None)),
+                "Expected incomplete frame for {} bytes, got: {:?}",
+                end,
+                parsed
+            );
+        }
+        assert_eq!(
+            parser.parse_frame(input).unwrap(),
+            Some((
+                RespValue::Array(vec![
+                    RespValue::BulkString(b"GET"),
+                    RespValue::BulkString(b"foobar")
+                ]),
+                input.len()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_frame_reports_malformed_value() {
+        let mut parser = RespParser::new();
+        assert!(matches!(
+            parser.parse_frame(b"?foo\r\n"),
+            Err(RespError::UnknownStartingByte(b'?'))
+        ));
+    }
+
     #[test]
     fn writes_simple_string() {
         let value = RespValue::SimpleString(b"OK");
@@ -733,6 +883,14 @@ mod tests {
         assert!(matches!(parsed.unwrap_err(), RespError::UnexpectedEnd));
     }
 
+    #[test]
+    fn bulk_string_missing_separator() {
+        let parser = RespParser::new();
+        let parsed = parser.next_value(b"$3\r\nA");
+        assert!(parsed.is_err(), "Expected error");
+        assert!(matches!(parsed.unwrap_err(), RespError::UnexpectedEnd));
+    }
+
     #[test]
     fn unterminated_array() {
         let parser = RespParser::new();
@@ -761,4 +919,69 @@ mod tests {
         assert!(parsed.is_err(), "Expected error");
         assert!(matches!(parsed.unwrap_err(), RespError::UnexpectedEnd));
     }
+
+    #[test]
+    fn oversized_lengths_are_errors() {
+        let mut parser = RespParser::new();
+        assert!(matches!(
+            parser.parse_frame(b"*9223372036854775807\r\n"),
+            Err(RespError::BadArraySize(_))
+        ));
+        assert!(matches!(
+            parser.parse_frame(b"*2147483648\r\n"),
+            Err(RespError::BadArraySize(_))
+        ));
+        assert!(matches!(
+            parser.parse_frame(b"$536870913\r\n"),
+            Err(RespError::BadBulkStringSize(_))
+        ));
+        // A large length only claims memory as the elements arrive.
+        assert!(matches!(parser.parse_frame(b"*100000000\r\n"), Ok(None)));
+        assert!(matches!(
+            parser.next_value(b"*100000000\r\n"),
+            Err(RespError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn long_lines_are_errors() {
+        let mut parser = RespParser::new();
+        let mut line = vec![b'+'; MAX_LINE_LEN];
+        assert!(matches!(parser.parse_frame(&line), Ok(None)));
+        line.push(b'+');
+        assert!(matches!(
+            parser.parse_frame(&line),
+            Err(RespError::LineTooLong)
+        ));
+    }
+
+    #[test]
+    fn parses_frames_arriving_in_parts() {
+        let args: Vec<String> = (0..1000).map(|idx| format!("arg{}", idx)).collect();
+        let value = RespValue::Array(
+            args.iter()
+                .map(|arg| RespValue::BulkString(arg.as_bytes()))
+                .collect(),
+        );
+        let mut input = Vec::new();
+        value.write(&mut input).unwrap();
+        let frame_len = input.len();
+        input.extend_from_slice(b"+OK\r\n");
+
+        let mut parser = RespParser::new();
+        for len in (0..frame_len).step_by(7) {
+            assert!(parser.parse_frame(&input[..len]).unwrap().is_none());
+        }
+        // Elements already seen aren't parsed again.
+        let partial = parser.partial.unwrap();
+        assert!(partial.remaining < 10, "{:?}", partial);
+        assert_eq!(
+            parser.parse_frame(&input).unwrap(),
+            Some((value, frame_len))
+        );
+        assert_eq!(
+            parser.parse_frame(&input[frame_len..]).unwrap(),
+            Some((RespValue::SimpleString(b"OK"), 5))
+        );
+    }
 }
@@ -0,0 +1,400 @@
+//! Operations on set values for the set commands.
+//!
+//! Sets are unordered collections of distinct members.  As with lists, a set key never
+//! holds an empty set, and the commands combining several sets treat missing keys as
+//! empty sets.
+
+use std::collections::HashSet;
+
+use rand::seq::IteratorRandom;
+
+use crate::dict::Dict;
+use crate::errors::RedisError;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::SetOp;
+
+// Sets are dicts without values, as in Redis, so that random members are quick to find.
+pub(crate) type Set = Dict<()>;
+
+/// Adds the members to the set at key, creating it if needed, and returns how many
+/// were not already members.
+pub(crate) fn add(db: &mut Database, key: &[u8], members: &[&[u8]]) -> Result<usize, RedisError> {
+    if set_mut(db, key)?.is_none() {
+        db.insert(key.to_vec(), ValueType::from(Value::Set(Set::new())));
+    }
+    let set = set_mut(db, key)?.expect("set was just inserted");
+    Ok(members
+        .iter()
+        .filter(|member| set.insert(member.to_vec(), ()).is_none())
+        .count())
+}
+
+/// Removes the members from the set at key, returning how many were members.
+pub(crate) fn remove(
+    db: &mut Database,
+    key: &[u8],
+    members: &[&[u8]],
+) -> Result<usize, RedisError> {
+    let Some(set) = set_mut(db, key)? else {
+        return Ok(0);
+    };
+    let removed = members
+        .iter()
+        .filter(|member| set.remove(member).is_some())
+        .count();
+    remove_if_empty(db, key);
+    Ok(removed)
+}
+
+pub(crate) fn members(db: &mut Database, key: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
+    Ok(set_mut(db, key)?.map_or_else(Vec::new, |set| set.keys().cloned().collect()))
+}
+
+pub(crate) fn is_member(db: &mut Database, key: &[u8], member: &[u8]) -> Result<bool, RedisError> {
+    Ok(set_mut(db, key)?.is_some_and(|set| set.contains_key(member)))
+}
+
+pub(crate) fn len(db: &mut Database, key: &[u8]) -> Result<usize, RedisError> {
+    Ok(set_mut(db, key)?.map_or(0, |set| set.len()))
+}
+
+/// Removes and returns up to count random members, or None if there is no set.
+pub(crate) fn pop(
+    db: &mut Database,
+    key: &[u8],
+    count: usize,
+) -> Result<Option<Vec<Vec<u8>>>, RedisError> {
+    let Some(set) = set_mut(db, key)? else {
+        return Ok(None);
+    };
+    let mut rng = rand::thread_rng();
+    let popped: Vec<_> = (0..count.min(set.len()))
+        .map(|_| {
+            let member = set.random_key(&mut rng).expect("set is not empty").clone();
+            set.remove(&member);
+            member
+        })
+        .collect();
+    remove_if_empty(db, key);
+    Ok(Some(popped))
+}
+
+/// Returns random members.  A positive count returns that many distinct members, or
+/// the whole set if it is smaller, and a negative count returns that many members which
+/// may repeat.
+pub(crate) fn random_members(
+    db: &mut Database,
+    key: &[u8],
+    count: i64,
+) -> Result<Vec<Vec<u8>>, RedisError> {
+    let Some(set) = set_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    let mut rng = rand::thread_rng();
+    if count < 0 {
+        return Ok((0..count.unsigned_abs())
+            .map(|_| set.random_key(&mut rng).expect("set is not empty").clone())
+            .collect());
+    }
+    let count = (count as usize).min(set.len());
+    // As in Redis, when most of the set is wanted it is cheaper to sample it in one
+    // pass, and otherwise to pick random members until there are enough distinct ones.
+    if count * 3 > set.len() {
+        return Ok(set.keys().cloned().choose_multiple(&mut rng, count));
+    }
+    let mut members = HashSet::with_capacity(count);
+    while members.len() < count {
+        members.insert(set.random_key(&mut rng).expect("set is not empty"));
+    }
+    Ok(members.into_iter().cloned().collect())
+}
+
+/// Combines the sets at the keys: the intersection of them all, their union, or the
+/// members of the first which are in none of the others.
+pub(crate) fn combine(db: &mut Database, op: SetOp, keys: &[&[u8]]) -> Result<Set, RedisError> {
+    let sets = sets(db, keys)?;
+    let result = match op {
+        SetOp::Inter => {
+            // Any missing key makes the intersection empty.
+            let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+                return Ok(Set::new());
+            };
+            // Only the members of the smallest set need checking.
+            sets.sort_by_key(|set| set.len());
+            let (smallest, others) = sets.split_first().expect("at least one key");
+            smallest
+                .keys()
+                .filter(|member| others.iter().all(|set| set.contains_key(member)))
+                .cloned()
+                .collect()
+        }
+        SetOp::Union => sets
+            .into_iter()
+            .flatten()
+            .flat_map(Set::keys)
+            .cloned()
+            .collect(),
+        SetOp::Diff => {
+            let (first, others) = sets.split_first().expect("at least one key");
+            first.map_or_else(Set::new, |first| {
+                first
+                    .keys()
+                    .filter(|member| others.iter().flatten().all(|set| !set.contains_key(member)))
+                    .cloned()
+                    .collect()
+            })
+        }
+    };
+    Ok(result)
+}
+
+/// Stores the result of combining the sets at destination, replacing any value it
+/// had, and returns its size.
+pub(crate) fn combine_and_store(
+    db: &mut Database,
+    op: SetOp,
+    destination: &[u8],
+    keys: &[&[u8]],
+) -> Result<usize, RedisError> {
+    let result = combine(db, op, keys)?;
+    let len = result.len();
+    if result.is_empty() {
+        db.remove(destination);
+    } else {
+        db.insert(destination.to_vec(), ValueType::from(Value::Set(result)));
+    }
+    Ok(len)
+}
+
+/// Returns the size of the intersection of the sets, counting no further than limit
+/// unless it is zero.
+pub(crate) fn intersection_len(
+    db: &mut Database,
+    keys: &[&[u8]],
+    limit: usize,
+) -> Result<usize, RedisError> {
+    let Some(mut sets) = sets(db, keys)?.into_iter().collect::<Option<Vec<_>>>() else {
+        return Ok(0);
+    };
+    sets.sort_by_key(|set| set.len());
+    let (smallest, others) = sets.split_first().expect("at least one key");
+    let common = smallest
+        .keys()
+        .filter(|member| others.iter().all(|set| set.contains_key(member)));
+    Ok(match limit {
+        0 => common.count(),
+        limit => common.take(limit).count(),
+    })
+}
+
+/// Moves the member from the set at source to the set at destination, returning
+/// whether it was a member of the source.
+pub(crate) fn move_member(
+    db: &mut Database,
+    source: &[u8],
+    destination: &[u8],
+    member: &[u8],
+) -> Result<bool, RedisError> {
+    // Check the destination first, so that nothing changes on error.
+    set_mut(db, destination)?;
+    if !is_member(db, source, member)? {
+        return Ok(false);
+    }
+    if source != destination {
+        remove(db, source, &[member])?;
+        add(db, destination, &[member])?;
+    }
+    Ok(true)
+}
+
+// Returns the set at key, or None if the key doesn't exist.
+fn set_mut<'d>(db: &'d mut Database, key: &[u8]) -> Result<Option<&'d mut Set>, RedisError> {
+    match live_value_mut(db, key).map(ValueType::value_mut) {
+        Some(Value::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+// Returns the set at each of the keys, or None for those that don't exist.  Fails if
+// any of the keys holds another type.
+fn sets<'d>(db: &'d mut Database, keys: &[&[u8]]) -> Result<Vec<Option<&'d Set>>, RedisError> {
+    for key in keys {
+        set_mut(db, key)?;
+    }
+    let db: &Database = db;
+    Ok(keys
+        .iter()
+        .map(|key| match db.get(key).map(ValueType::value) {
+            Some(Value::Set(set)) => Some(set),
+            _ => None,
+        })
+        .collect())
+}
+
+fn remove_if_empty(db: &mut Database, key: &[u8]) {
+    if let Some(Value::Set(set)) = db.get(key).map(ValueType::value) {
+        if set.is_empty() {
+            db.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_db(sets: &[(&[u8], &[&[u8]])]) -> Database {
+        let mut db = Database::new();
+        for (key, members) in sets {
+            add(&mut db, key, members).unwrap();
+        }
+        db
+    }
+
+    fn set_of(members: &[&[u8]]) -> Set {
+        members.iter().map(|member| member.to_vec()).collect()
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut db = set_db(&[(b"set", &[b"a", b"b"])]);
+        assert_eq!(add(&mut db, b"set", &[b"b", b"c", b"c"]).unwrap(), 1);
+        assert_eq!(len(&mut db, b"set").unwrap(), 3);
+        assert!(is_member(&mut db, b"set", b"c").unwrap());
+        assert_eq!(remove(&mut db, b"set", &[b"a", b"x"]).unwrap(), 1);
+        assert_eq!(remove(&mut db, b"set", &[b"b", b"c"]).unwrap(), 2);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn add_to_string_is_wrong_type() {
+        let mut db = Database::from([(b"key".to_vec(), ValueType::new(b"value".to_vec()))]);
+        assert!(matches!(
+            add(&mut db, b"key", &[b"a"]),
+            Err(RedisError::WrongType)
+        ));
+    }
+
+    #[test]
+    fn pop_removes_members() {
+        let mut db = set_db(&[(b"set", &[b"a", b"b", b"c"])]);
+        let popped = pop(&mut db, b"set", 2).unwrap().unwrap();
+        assert_eq!(popped.len(), 2);
+        assert_eq!(len(&mut db, b"set").unwrap(), 1);
+        assert_eq!(pop(&mut db, b"set", 5).unwrap().unwrap().len(), 1);
+        assert_eq!(pop(&mut db, b"set", 1).unwrap(), None);
+
+        // Huge counts are limited to the size of the set.
+        let mut db = set_db(&[(b"set", &[b"a", b"b", b"c"])]);
+        assert_eq!(pop(&mut db, b"set", usize::MAX).unwrap().unwrap().len(), 3);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn random_members_counts() {
+        let mut db = set_db(&[(b"set", &[b"a", b"b"])]);
+        assert_eq!(random_members(&mut db, b"set", 5).unwrap().len(), 2);
+        assert_eq!(random_members(&mut db, b"set", -5).unwrap().len(), 5);
+        assert!(random_members(&mut db, b"missing", 1).unwrap().is_empty());
+        assert_eq!(
+            random_members(&mut db, b"set", 1_000_000_000_000)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // A few distinct members of a large set are picked one at a time.
+        let members: Vec<Vec<u8>> = (0..1000).map(|idx| idx.to_string().into_bytes()).collect();
+        let members: Vec<&[u8]> = members.iter().map(Vec::as_slice).collect();
+        let mut db = set_db(&[(b"set", &members)]);
+        let picked: HashSet<_> = random_members(&mut db, b"set", 10)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(picked.len(), 10);
+        assert_eq!(random_members(&mut db, b"set", 900).unwrap().len(), 900);
+    }
+
+    #[test]
+    fn combines_sets() {
+        let mut db = set_db(&[
+            (b"a", &[b"1", b"2", b"3"]),
+            (b"b", &[b"2", b"3", b"4"]),
+            (b"c", &[b"3"]),
+        ]);
+        assert_eq!(
+            combine(&mut db, SetOp::Inter, &[b"a", b"b", b"c"]).unwrap(),
+            set_of(&[b"3"])
+        );
+        assert_eq!(
+            combine(&mut db, SetOp::Inter, &[b"a", b"missing"]).unwrap(),
+            Set::new()
+        );
+        assert_eq!(
+            combine(&mut db, SetOp::Union, &[b"a", b"missing", b"b"]).unwrap(),
+            set_of(&[b"1", b"2", b"3", b"4"])
+        );
+        assert_eq!(
+            combine(&mut db, SetOp::Diff, &[b"a", b"b"]).unwrap(),
+            set_of(&[b"1"])
+        );
+        assert_eq!(
+            combine(&mut db, SetOp::Diff, &[b"missing", b"a"]).unwrap(),
+            Set::new()
+        );
+    }
+
+    #[test]
+    fn combine_checks_every_type() {
+        let mut db = set_db(&[(b"a", &[b"1"])]);
+        db.insert(b"s".to_vec(), ValueType::new(b"value".to_vec()));
+        assert!(matches!(
+            combine(&mut db, SetOp::Union, &[b"a", b"s"]),
+            Err(RedisError::WrongType)
+        ));
+    }
+
+    #[test]
+    fn combine_and_store_replaces_destination() {
+        let mut db = set_db(&[(b"a", &[b"1", b"2"]), (b"b", &[b"2"])]);
+        db.insert(b"dest".to_vec(), ValueType::new(b"value".to_vec()));
+        assert_eq!(
+            combine_and_store(&mut db, SetOp::Diff, b"dest", &[b"a", b"b"]).unwrap(),
+            1
+        );
+        assert_eq!(members(&mut db, b"dest").unwrap(), vec![b"1".to_vec()]);
+        assert_eq!(
+            combine_and_store(&mut db, SetOp::Inter, b"dest", &[b"a", b"missing"]).unwrap(),
+            0
+        );
+        assert!(!db.contains_key(&b"dest"[..]));
+    }
+
+    #[test]
+    fn intersection_len_limit() {
+        let mut db = set_db(&[(b"a", &[b"1", b"2", b"3"]), (b"b", &[b"1", b"2", b"3"])]);
+        assert_eq!(intersection_len(&mut db, &[b"a", b"b"], 0).unwrap(), 3);
+        assert_eq!(intersection_len(&mut db, &[b"a", b"b"], 2).unwrap(), 2);
+        assert_eq!(
+            intersection_len(&mut db, &[b"a", b"missing"], 0).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn move_member_between_sets() {
+        let mut db = set_db(&[(b"a", &[b"1"]), (b"b", &[b"2"])]);
+        assert!(move_member(&mut db, b"a", b"b", b"1").unwrap());
+        assert!(!move_member(&mut db, b"a", b"b", b"1").unwrap());
+        assert!(!db.contains_key(&b"a"[..]));
+        assert_eq!(len(&mut db, b"b").unwrap(), 2);
+
+        db.insert(b"s".to_vec(), ValueType::new(b"value".to_vec()));
+        assert!(matches!(
+            move_member(&mut db, b"b", b"s", b"1"),
+            Err(RedisError::WrongType)
+        ));
+        assert_eq!(len(&mut db, b"b").unwrap(), 2);
+    }
+}
@@ -0,0 +1,399 @@
+//! A skiplist of (score, member) pairs, ordered by score then member, which backs
+//! sorted sets.
+//!
+//! This follows the Redis zskiplist: each level of a node records how many nodes its
+//! forward link spans, so that ranks can be found in O(log n) along with scores.  The
+//! nodes live in an arena and refer to each other by index, with the header at index 0.
+
+use rand::Rng;
+
+// Enough levels for 4^32 elements.
+const MAX_LEVEL: usize = 32;
+
+// The chance of a node reaching each level above the first.
+const LEVEL_PROBABILITY: f64 = 0.25;
+
+const HEAD: usize = 0;
+
+/// Identifies a node of a skiplist.  Only valid until the list is next modified.
+pub(crate) type NodeId = usize;
+
+#[derive(Clone, Debug)]
+pub(crate) struct SkipList {
+    nodes: Vec<Node>,
+    // Indices of removed nodes, to be reused.
+    free: Vec<NodeId>,
+    tail: Option<NodeId>,
+    len: usize,
+    // The number of levels in use.
+    level: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    score: f64,
+    member: Vec<u8>,
+    backward: Option<NodeId>,
+    levels: Vec<Level>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    forward: Option<NodeId>,
+    // The number of nodes the forward link moves along the bottom level.
+    span: usize,
+}
+
+impl SkipList {
+    pub(crate) fn new() -> Self {
+        let head = Node {
+            score: 0.0,
+            member: Vec::new(),
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+
+    /// Inserts the pair, which must not already be in the list.
+    pub(crate) fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let mut update = [HEAD; MAX_LEVEL];
+        // The rank of update[i], counting the header as zero.
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(x, i) {
+                if !self.node_before(next, score, &member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let id = self.allocate(Node {
+            score,
+            member,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0
+                };
+                level
+            ],
+        });
+        for i in 0..level {
+            let prev = update[i];
+            let prev_level = self.nodes[prev].levels[i];
+            self.nodes[id].levels[i] = Level {
+                forward: prev_level.forward,
+                span: prev_level.span - (rank[0] - rank[i]),
+            };
+            self.nodes[prev].levels[i] = Level {
+                forward: Some(id),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        // Links above the new node's height now pass over one more node.
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+        match self.forward(id, 0) {
+            Some(next) => self.nodes[next].backward = Some(id),
+            None => self.tail = Some(id),
+        }
+        self.len += 1;
+    }
+
+    /// Removes the pair, returning whether it was in the list.
+    pub(crate) fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !self.node_before(next, score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        let Some(id) = self.forward(x, 0) else {
+            return false;
+        };
+        if self.nodes[id].score != score || self.nodes[id].member != member {
+            return false;
+        }
+
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            let prev = *prev;
+            if self.forward(prev, i) == Some(id) {
+                let removed = self.nodes[id].levels[i];
+                self.nodes[prev].levels[i].span += removed.span;
+                self.nodes[prev].levels[i].span -= 1;
+                self.nodes[prev].levels[i].forward = removed.forward;
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        match self.forward(id, 0) {
+            Some(next) => self.nodes[next].backward = self.nodes[id].backward,
+            None => self.tail = self.nodes[id].backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+        self.nodes[id].member = Vec::new();
+        self.free.push(id);
+        true
+    }
+
+    /// The zero based rank of the pair, if it is in the list.
+    pub(crate) fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                // Move to the last node at or before the pair.
+                if self.node_before(next, score, member)
+                    || (self.nodes[next].score == score && self.nodes[next].member == member)
+                {
+                    rank += self.nodes[x].levels[i].span;
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+            if x != HEAD && self.nodes[x].score == score && self.nodes[x].member == member {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    /// The node with the given zero based rank.
+    pub(crate) fn by_rank(&self, rank: usize) -> Option<NodeId> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// The first node, with its rank, for which before is false.  Before must be true for
+    /// some prefix of the list and false for the rest.
+    pub(crate) fn first_where_not(
+        &self,
+        before: impl Fn(f64, &[u8]) -> bool,
+    ) -> Option<(usize, NodeId)> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !before(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        self.forward(x, 0).map(|id| (traversed, id))
+    }
+
+    /// The last node, with its rank, for which within is true.  Within must be true for
+    /// some prefix of the list and false for the rest.
+    pub(crate) fn last_where(
+        &self,
+        within: impl Fn(f64, &[u8]) -> bool,
+    ) -> Option<(usize, NodeId)> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !within(self.nodes[next].score, &self.nodes[next].member) {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        (x != HEAD).then(|| (traversed - 1, x))
+    }
+
+    pub(crate) fn first(&self) -> Option<NodeId> {
+        self.forward(HEAD, 0)
+    }
+
+    pub(crate) fn last(&self) -> Option<NodeId> {
+        self.tail
+    }
+
+    pub(crate) fn next(&self, id: NodeId) -> Option<NodeId> {
+        self.forward(id, 0)
+    }
+
+    pub(crate) fn prev(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id].backward
+    }
+
+    pub(crate) fn score(&self, id: NodeId) -> f64 {
+        self.nodes[id].score
+    }
+
+    pub(crate) fn member(&self, id: NodeId) -> &[u8] {
+        &self.nodes[id].member
+    }
+
+    fn forward(&self, id: NodeId, level: usize) -> Option<NodeId> {
+        self.nodes[id].levels[level].forward
+    }
+
+    // Whether the node orders before the pair.
+    fn node_before(&self, id: NodeId, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[id];
+        node.score < score || (node.score == score && node.member[..] < *member)
+    }
+
+    fn allocate(&mut self, node: Node) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+fn random_level() -> usize {
+    let mut rng = rand::thread_rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.gen_bool(LEVEL_PROBABILITY) {
+        level += 1;
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(list: &SkipList) -> Vec<Vec<u8>> {
+        let mut members = Vec::new();
+        let mut node = list.first();
+        while let Some(id) = node {
+            members.push(list.member(id).to_vec());
+            node = list.next(id);
+        }
+        members
+    }
+
+    fn numbered_list(len: usize) -> SkipList {
+        let mut list = SkipList::new();
+        // Insert out of order to exercise the search.
+        for i in (0..len).map(|i| (i * 7919) % len) {
+            list.insert(i as f64, format!("{:05}", i).into_bytes());
+        }
+        list
+    }
+
+    #[test]
+    fn orders_by_score_then_member() {
+        let mut list = SkipList::new();
+        list.insert(2.0, b"a".to_vec());
+        list.insert(1.0, b"c".to_vec());
+        list.insert(1.0, b"b".to_vec());
+        assert_eq!(
+            members(&list),
+            vec![b"b".to_vec(), b"c".to_vec(), b"a".to_vec()]
+        );
+        assert_eq!(list.score(list.last().unwrap()), 2.0);
+        assert_eq!(list.member(list.prev(list.last().unwrap()).unwrap()), b"c");
+    }
+
+    #[test]
+    fn ranks_match_positions() {
+        let list = numbered_list(1000);
+        assert_eq!(list.len, 1000);
+        for i in [0, 1, 499, 998, 999] {
+            let member = format!("{:05}", i).into_bytes();
+            assert_eq!(list.rank(i as f64, &member), Some(i));
+            assert_eq!(list.member(list.by_rank(i).unwrap()), &member[..]);
+        }
+        assert_eq!(list.rank(5.0, b"missing"), None);
+        assert_eq!(list.by_rank(1000), None);
+    }
+
+    #[test]
+    fn remove_keeps_ranks() {
+        let mut list = numbered_list(500);
+        for i in (0..500).step_by(2) {
+            assert!(list.remove(i as f64, format!("{:05}", i).as_bytes()));
+        }
+        assert!(!list.remove(0.0, b"00000"));
+        assert_eq!(list.len, 250);
+        for rank in [0, 100, 249] {
+            let id = list.by_rank(rank).unwrap();
+            assert_eq!(list.score(id), (rank * 2 + 1) as f64);
+            assert_eq!(list.rank(list.score(id), list.member(id)), Some(rank));
+        }
+        assert_eq!(list.score(list.last().unwrap()), 499.0);
+
+        // Freed nodes are reused.
+        list.insert(0.5, b"new".to_vec());
+        assert_eq!(list.rank(0.5, b"new"), Some(0));
+        assert_eq!(list.nodes.len(), 501);
+    }
+
+    #[test]
+    fn finds_range_ends() {
+        let list = numbered_list(100);
+        let (rank, id) = list.first_where_not(|score, _| score < 10.5).unwrap();
+        assert_eq!((rank, list.score(id)), (11, 11.0));
+        let (rank, id) = list.last_where(|score, _| score <= 20.0).unwrap();
+        assert_eq!((rank, list.score(id)), (20, 20.0));
+
+        assert_eq!(list.first_where_not(|score, _| score < 1000.0), None);
+        assert_eq!(list.last_where(|score, _| score < 0.0), None);
+    }
+}
@@ -0,0 +1,793 @@
+//! Operations on sorted set values for the sorted set commands.
+//!
+//! A sorted set maps each member to a score, keeping the members ordered by score and
+//! then lexicographically.  As in Redis, a hash table gives each member's score and a
+//! skiplist keeps the order, so scores, ranks and ranges can all be found in O(log n).
+//! A sorted set key never holds an empty sorted set.
+
+use std::collections::HashMap;
+
+use crate::errors::RedisError;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::{Aggregate, LexBound, ScoreBound, SetOp, ZAddOptions, ZRangeBy};
+use crate::skiplist::{NodeId, SkipList};
+
+#[derive(Clone, Debug)]
+pub(crate) struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    list: SkipList,
+}
+
+/// Members paired with their scores, in order.
+pub(crate) type ScoredMembers = Vec<(Vec<u8>, f64)>;
+
+/// The reply to ZADD: the number of members added, or changed if asked for, or with
+/// INCR the new score unless the conditions prevented the update.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ZAddReply {
+    Count(usize),
+    Score(Option<f64>),
+}
+
+impl SortedSet {
+    pub(crate) fn new() -> Self {
+        SortedSet {
+            scores: HashMap::new(),
+            list: SkipList::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub(crate) fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the member's score, returning whether it is a new member.
+    pub(crate) fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        match self.scores.insert(member.to_vec(), score) {
+            Some(old) if old == score => false,
+            Some(old) => {
+                self.list.remove(old, member);
+                self.list.insert(score, member.to_vec());
+                false
+            }
+            None => {
+                self.list.insert(score, member.to_vec());
+                true
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The members in order, with their scores.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&[u8], f64)> + '_ {
+        std::iter::successors(self.list.first(), |id| self.list.next(*id))
+            .map(|id| (self.list.member(id), self.list.score(id)))
+    }
+
+    // The zero based rank of the member, counting from the highest score if rev is set.
+    fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let rank = self.list.rank(self.score(member)?, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    // Collects up to count members, after skipping offset, starting from a node and
+    // moving in the given direction while in range.
+    fn collect_from(
+        &self,
+        start: Option<NodeId>,
+        rev: bool,
+        in_range: impl Fn(f64, &[u8]) -> bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> ScoredMembers {
+        let step = |id: &NodeId| {
+            if rev {
+                self.list.prev(*id)
+            } else {
+                self.list.next(*id)
+            }
+        };
+        std::iter::successors(start, step)
+            .map(|id| (self.list.member(id), self.list.score(id)))
+            .take_while(|(member, score)| in_range(*score, member))
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(member, score)| (member.to_vec(), score))
+            .collect()
+    }
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        // The skiplist holds the same pairs as the scores.
+        self.scores == other.scores
+    }
+}
+
+impl FromIterator<(Vec<u8>, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, f64)>>(iter: I) -> Self {
+        let mut set = SortedSet::new();
+        for (member, score) in iter {
+            set.insert(&member, score);
+        }
+        set
+    }
+}
+
+/// Adds the members with their scores, or updates the scores of existing members,
+/// subject to the options.
+pub(crate) fn add(
+    db: &mut Database,
+    key: &[u8],
+    pairs: &[(f64, &[u8])],
+    options: ZAddOptions,
+) -> Result<ZAddReply, RedisError> {
+    if options.xx && sorted_set_mut(db, key)?.is_none() {
+        // Nothing could be updated, and XX must not create the key.
+        return Ok(if options.incr {
+            ZAddReply::Score(None)
+        } else {
+            ZAddReply::Count(0)
+        });
+    }
+    let set = sorted_set_or_insert(db, key)?;
+    let mut added = 0;
+    let mut changed = 0;
+    let mut last_score = None;
+    for (score, member) in pairs {
+        last_score = None;
+        let new_score = match set.score(member) {
+            Some(current) => {
+                if options.nx {
+                    continue;
+                }
+                let new_score = if options.incr {
+                    current + score
+                } else {
+                    *score
+                };
+                if new_score.is_nan() {
+                    remove_if_empty(db, key);
+                    return Err(RedisError::UnexpectedArgumentType(
+                        "resulting score is not a number (NaN)".to_string(),
+                    ));
+                }
+                if (options.gt && new_score <= current) || (options.lt && new_score >= current) {
+                    continue;
+                }
+                if new_score != current {
+                    changed += 1;
+                }
+                new_score
+            }
+            None => {
+                if options.xx {
+                    continue;
+                }
+                added += 1;
+                *score
+            }
+        };
+        set.insert(member, new_score);
+        last_score = Some(new_score);
+    }
+    remove_if_empty(db, key);
+    Ok(if options.incr {
+        ZAddReply::Score(last_score)
+    } else if options.ch {
+        ZAddReply::Count(added + changed)
+    } else {
+        ZAddReply::Count(added)
+    })
+}
+
+/// Removes the members, returning how many were members.
+pub(crate) fn remove(
+    db: &mut Database,
+    key: &[u8],
+    members: &[&[u8]],
+) -> Result<usize, RedisError> {
+    let Some(set) = sorted_set_mut(db, key)? else {
+        return Ok(0);
+    };
+    let removed = members.iter().filter(|member| set.remove(member)).count();
+    remove_if_empty(db, key);
+    Ok(removed)
+}
+
+pub(crate) fn len(db: &mut Database, key: &[u8]) -> Result<usize, RedisError> {
+    Ok(sorted_set_mut(db, key)?.map_or(0, |set| set.len()))
+}
+
+pub(crate) fn score(
+    db: &mut Database,
+    key: &[u8],
+    member: &[u8],
+) -> Result<Option<f64>, RedisError> {
+    Ok(sorted_set_mut(db, key)?.and_then(|set| set.score(member)))
+}
+
+/// Returns the member's rank, counting from the highest score if rev is set, and its
+/// score.
+pub(crate) fn rank(
+    db: &mut Database,
+    key: &[u8],
+    member: &[u8],
+    rev: bool,
+) -> Result<Option<(usize, f64)>, RedisError> {
+    Ok(sorted_set_mut(db, key)?.and_then(|set| Some((set.rank(member, rev)?, set.score(member)?))))
+}
+
+/// Counts the members with scores in the range.
+pub(crate) fn count(
+    db: &mut Database,
+    key: &[u8],
+    min: ScoreBound,
+    max: ScoreBound,
+) -> Result<usize, RedisError> {
+    let Some(set) = sorted_set_mut(db, key)? else {
+        return Ok(0);
+    };
+    Ok(count_between(
+        &set.list,
+        |score, _| !min.allows_above(score),
+        |score, _| max.allows_below(score),
+    ))
+}
+
+/// Counts the members in the lexicographical range, assuming all scores are equal.
+pub(crate) fn lex_count(
+    db: &mut Database,
+    key: &[u8],
+    min: &LexBound,
+    max: &LexBound,
+) -> Result<usize, RedisError> {
+    let Some(set) = sorted_set_mut(db, key)? else {
+        return Ok(0);
+    };
+    Ok(count_between(
+        &set.list,
+        |_, member| !min.allows_above(member),
+        |_, member| max.allows_below(member),
+    ))
+}
+
+/// Returns the members in the range with their scores, in order, or in reverse order
+/// if rev is set.  The limit, which only applies to score and lexicographical ranges,
+/// skips an offset into the range and then returns at most count members, or all of
+/// them if count is negative.
+pub(crate) fn range(
+    db: &mut Database,
+    key: &[u8],
+    by: &ZRangeBy,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+) -> Result<ScoredMembers, RedisError> {
+    let Some(set) = sorted_set_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    let (offset, count) = match limit {
+        Some((offset, _)) if offset < 0 => return Ok(Vec::new()),
+        Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+        None => (0, None),
+    };
+    let list = &set.list;
+    Ok(match by {
+        ZRangeBy::Rank(start, stop) => {
+            let Some((start, stop)) = rank_range(set.len(), *start, *stop) else {
+                return Ok(Vec::new());
+            };
+            let first = if rev { set.len() - 1 - start } else { start };
+            set.collect_from(
+                list.by_rank(first),
+                rev,
+                |_, _| true,
+                0,
+                Some(stop - start + 1),
+            )
+        }
+        ZRangeBy::Score(min, max) => {
+            let start = if rev {
+                list.last_where(|score, _| max.allows_below(score))
+            } else {
+                list.first_where_not(|score, _| !min.allows_above(score))
+            };
+            set.collect_from(
+                start.map(|(_, id)| id),
+                rev,
+                |score, _| min.allows_above(score) && max.allows_below(score),
+                offset,
+                count,
+            )
+        }
+        ZRangeBy::Lex(min, max) => {
+            let start = if rev {
+                list.last_where(|_, member| max.allows_below(member))
+            } else {
+                list.first_where_not(|_, member| !min.allows_above(member))
+            };
+            set.collect_from(
+                start.map(|(_, id)| id),
+                rev,
+                |_, member| min.allows_above(member) && max.allows_below(member),
+                offset,
+                count,
+            )
+        }
+    })
+}
+
+/// Removes and returns up to count of the members with the lowest scores, or the
+/// highest if max is set.
+pub(crate) fn pop(
+    db: &mut Database,
+    key: &[u8],
+    count: usize,
+    max: bool,
+) -> Result<ScoredMembers, RedisError> {
+    let Some(set) = sorted_set_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    let start = if max {
+        set.list.last()
+    } else {
+        set.list.first()
+    };
+    let popped = set.collect_from(start, max, |_, _| true, 0, Some(count));
+    for (member, _) in &popped {
+        set.remove(member);
+    }
+    remove_if_empty(db, key);
+    Ok(popped)
+}
+
+/// Stores the union or intersection of the sorted sets at destination, replacing any
+/// value it had, and returns its size.  Each input's scores are multiplied by its
+/// weight, defaulting to one, and a member's scores are combined by the aggregate.
+/// Plain sets may be used as inputs, with every member scoring one.
+pub(crate) fn combine_and_store(
+    db: &mut Database,
+    op: SetOp,
+    destination: &[u8],
+    keys: &[&[u8]],
+    weights: &[f64],
+    aggregate: Aggregate,
+) -> Result<usize, RedisError> {
+    let mut inputs = Vec::with_capacity(keys.len());
+    for (idx, key) in keys.iter().enumerate() {
+        let weight = weights.get(idx).copied().unwrap_or(1.0);
+        let members: ScoredMembers = match live_value_mut(db, key).map(|value| value.value()) {
+            Some(Value::SortedSet(set)) => set
+                .iter()
+                .map(|(member, score)| (member.to_vec(), score))
+                .collect(),
+            Some(Value::Set(set)) => set.keys().map(|member| (member.clone(), 1.0)).collect(),
+            Some(_) => return Err(RedisError::WrongType),
+            None => Vec::new(),
+        };
+        inputs.push(
+            members
+                .into_iter()
+                .map(|(member, score)| (member, weighted(score, weight)))
+                .collect::<HashMap<_, _>>(),
+        );
+    }
+
+    let mut result: HashMap<Vec<u8>, f64> = HashMap::new();
+    match op {
+        SetOp::Union => {
+            for input in inputs {
+                for (member, score) in input {
+                    result
+                        .entry(member)
+                        .and_modify(|current| *current = aggregate.combine(*current, score))
+                        .or_insert(score);
+                }
+            }
+        }
+        SetOp::Inter => {
+            if let Some((first, others)) = inputs.split_first() {
+                for (member, score) in first {
+                    let combined = others.iter().try_fold(*score, |combined, other| {
+                        Some(aggregate.combine(combined, *other.get(member)?))
+                    });
+                    if let Some(combined) = combined {
+                        result.insert(member.clone(), combined);
+                    }
+                }
+            }
+        }
+        SetOp::Diff => {
+            if let Some((first, others)) = inputs.split_first() {
+                result = first
+                    .iter()
+                    .filter(|(member, _)| others.iter().all(|other| !other.contains_key(*member)))
+                    .map(|(member, score)| (member.clone(), *score))
+                    .collect();
+            }
+        }
+    }
+
+    let len = result.len();
+    if result.is_empty() {
+        db.remove(destination);
+    } else {
+        db.insert(
+            destination.to_vec(),
+            ValueType::from(Value::SortedSet(result.into_iter().collect())),
+        );
+    }
+    Ok(len)
+}
+
+impl Aggregate {
+    fn combine(self, a: f64, b: f64) -> f64 {
+        match self {
+            // Adding opposite infinities gives zero rather than NaN.
+            Aggregate::Sum => Some(a + b).filter(|sum| !sum.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+impl ScoreBound {
+    // Whether the score is within the range this bound starts.
+    fn allows_above(&self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.value
+        } else {
+            score >= self.value
+        }
+    }
+
+    // Whether the score is within the range this bound ends.
+    fn allows_below(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.value
+        } else {
+            score <= self.value
+        }
+    }
+}
+
+impl LexBound<'_> {
+    // Whether the member is within the range this bound starts.
+    fn allows_above(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(value) => member >= *value,
+            LexBound::Exclusive(value) => member > *value,
+        }
+    }
+
+    // Whether the member is within the range this bound ends.
+    fn allows_below(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(value) => member <= *value,
+            LexBound::Exclusive(value) => member < *value,
+        }
+    }
+}
+
+// Multiplies a score by a weight, where zero times infinity is zero.
+fn weighted(score: f64, weight: f64) -> f64 {
+    Some(score * weight)
+        .filter(|score| !score.is_nan())
+        .unwrap_or(0.0)
+}
+
+// Counts the nodes which are neither before the range nor after it, using their ranks.
+fn count_between(
+    list: &SkipList,
+    before: impl Fn(f64, &[u8]) -> bool,
+    not_after: impl Fn(f64, &[u8]) -> bool,
+) -> usize {
+    match (list.first_where_not(before), list.last_where(not_after)) {
+        (Some((first, _)), Some((last, _))) if first <= last => last - first + 1,
+        _ => 0,
+    }
+}
+
+// Converts possibly negative start and stop ranks into an inclusive range of ranks in
+// a sorted set of length len, or None if the range is empty.
+fn rank_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    (start <= stop).then_some((start as usize, stop as usize))
+}
+
+// Returns the sorted set at key, or None if the key doesn't exist.
+fn sorted_set_mut<'d>(
+    db: &'d mut Database,
+    key: &[u8],
+) -> Result<Option<&'d mut SortedSet>, RedisError> {
+    match live_value_mut(db, key).map(ValueType::value_mut) {
+        Some(Value::SortedSet(set)) => Ok(Some(set)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn sorted_set_or_insert<'d>(
+    db: &'d mut Database,
+    key: &[u8],
+) -> Result<&'d mut SortedSet, RedisError> {
+    if sorted_set_mut(db, key)?.is_none() {
+        db.insert(
+            key.to_vec(),
+            ValueType::from(Value::SortedSet(SortedSet::new())),
+        );
+    }
+    Ok(sorted_set_mut(db, key)?.expect("sorted set was just inserted"))
+}
+
+fn remove_if_empty(db: &mut Database, key: &[u8]) {
+    if let Some(Value::SortedSet(set)) = db.get(key).map(ValueType::value) {
+        if set.is_empty() {
+            db.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zset_db(key: &[u8], pairs: &[(f64, &[u8])]) -> Database {
+        let mut db = Database::new();
+        add(&mut db, key, pairs, ZAddOptions::default()).unwrap();
+        db
+    }
+
+    fn members(scored: ScoredMembers) -> Vec<Vec<u8>> {
+        scored.into_iter().map(|(member, _)| member).collect()
+    }
+
+    fn inclusive(value: f64) -> ScoreBound {
+        ScoreBound {
+            value,
+            exclusive: false,
+        }
+    }
+
+    #[test]
+    fn add_with_options() {
+        let mut db = zset_db(b"z", &[(1.0, b"a"), (2.0, b"b")]);
+        assert_eq!(
+            add(
+                &mut db,
+                b"z",
+                &[(5.0, b"a"), (1.0, b"b"), (3.0, b"c")],
+                ZAddOptions {
+                    gt: true,
+                    ch: true,
+                    ..ZAddOptions::default()
+                }
+            )
+            .unwrap(),
+            ZAddReply::Count(2)
+        );
+        assert_eq!(score(&mut db, b"z", b"a").unwrap(), Some(5.0));
+        assert_eq!(score(&mut db, b"z", b"b").unwrap(), Some(2.0));
+        assert_eq!(
+            add(
+                &mut db,
+                b"z",
+                &[(1.5, b"c"), (9.0, b"d")],
+                ZAddOptions {
+                    xx: true,
+                    ..ZAddOptions::default()
+                }
+            )
+            .unwrap(),
+            ZAddReply::Count(0)
+        );
+        assert_eq!(score(&mut db, b"z", b"c").unwrap(), Some(1.5));
+        assert_eq!(score(&mut db, b"z", b"d").unwrap(), None);
+        let incr = ZAddOptions {
+            incr: true,
+            ..ZAddOptions::default()
+        };
+        assert_eq!(
+            add(&mut db, b"z", &[(2.5, b"c")], incr).unwrap(),
+            ZAddReply::Score(Some(4.0))
+        );
+        assert_eq!(
+            add(
+                &mut db,
+                b"z",
+                &[(1.0, b"c")],
+                ZAddOptions { nx: true, ..incr }
+            )
+            .unwrap(),
+            ZAddReply::Score(None)
+        );
+        assert_eq!(
+            add(
+                &mut db,
+                b"missing",
+                &[(1.0, b"a")],
+                ZAddOptions {
+                    xx: true,
+                    ..ZAddOptions::default()
+                }
+            )
+            .unwrap(),
+            ZAddReply::Count(0)
+        );
+        assert!(!db.contains_key(&b"missing"[..]));
+    }
+
+    #[test]
+    fn incrementing_infinity_by_its_opposite_is_an_error() {
+        let mut db = zset_db(b"z", &[(f64::INFINITY, b"a")]);
+        let incr = ZAddOptions {
+            incr: true,
+            ..ZAddOptions::default()
+        };
+        assert!(add(&mut db, b"z", &[(f64::NEG_INFINITY, b"a")], incr).is_err());
+        assert_eq!(score(&mut db, b"z", b"a").unwrap(), Some(f64::INFINITY));
+    }
+
+    #[test]
+    fn ranks_and_counts() {
+        let mut db = zset_db(b"z", &[(1.0, b"a"), (2.0, b"b"), (2.0, b"c"), (3.0, b"d")]);
+        assert_eq!(rank(&mut db, b"z", b"c", false).unwrap(), Some((2, 2.0)));
+        assert_eq!(rank(&mut db, b"z", b"c", true).unwrap(), Some((1, 2.0)));
+        assert_eq!(rank(&mut db, b"z", b"x", false).unwrap(), None);
+        let exclusive = ScoreBound {
+            value: 1.0,
+            exclusive: true,
+        };
+        assert_eq!(count(&mut db, b"z", exclusive, inclusive(2.0)).unwrap(), 2);
+        assert_eq!(
+            count(
+                &mut db,
+                b"z",
+                inclusive(f64::NEG_INFINITY),
+                inclusive(f64::INFINITY)
+            )
+            .unwrap(),
+            4
+        );
+        assert_eq!(
+            count(&mut db, b"z", inclusive(3.0), inclusive(1.0)).unwrap(),
+            0
+        );
+        assert_eq!(
+            lex_count(&mut db, b"z", &LexBound::Inclusive(b"b"), &LexBound::Max).unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let mut db = zset_db(
+            b"z",
+            &[
+                (1.0, b"a"),
+                (2.0, b"b"),
+                (3.0, b"c"),
+                (4.0, b"d"),
+                (5.0, b"e"),
+            ],
+        );
+        let mut range = |by, rev, limit| members(range(&mut db, b"z", &by, rev, limit).unwrap());
+        assert_eq!(
+            range(ZRangeBy::Rank(1, -2), false, None),
+            [b"b", b"c", b"d"]
+        );
+        assert_eq!(range(ZRangeBy::Rank(0, 1), true, None), [b"e", b"d"]);
+        assert!(range(ZRangeBy::Rank(4, 2), false, None).is_empty());
+        assert_eq!(
+            range(
+                ZRangeBy::Score(inclusive(2.0), inclusive(f64::INFINITY)),
+                false,
+                Some((1, 2))
+            ),
+            [b"c", b"d"]
+        );
+        assert_eq!(
+            range(
+                ZRangeBy::Score(inclusive(2.0), inclusive(4.0)),
+                true,
+                Some((0, -1))
+            ),
+            [b"d", b"c", b"b"]
+        );
+        assert_eq!(
+            range(
+                ZRangeBy::Lex(LexBound::Exclusive(b"b"), LexBound::Inclusive(b"d")),
+                true,
+                None
+            ),
+            [b"d", b"c"]
+        );
+        assert!(range(ZRangeBy::Lex(LexBound::Max, LexBound::Min), false, None).is_empty());
+    }
+
+    #[test]
+    fn pop_from_either_end() {
+        let mut db = zset_db(b"z", &[(1.0, b"a"), (2.0, b"b"), (3.0, b"c")]);
+        assert_eq!(
+            pop(&mut db, b"z", 2, true).unwrap(),
+            vec![(b"c".to_vec(), 3.0), (b"b".to_vec(), 2.0)]
+        );
+        assert_eq!(
+            pop(&mut db, b"z", 5, false).unwrap(),
+            vec![(b"a".to_vec(), 1.0)]
+        );
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn combines_with_weights() {
+        let mut db = zset_db(b"a", &[(1.0, b"x"), (2.0, b"y")]);
+        add(
+            &mut db,
+            b"b",
+            &[(10.0, b"y"), (20.0, b"z")],
+            ZAddOptions::default(),
+        )
+        .unwrap();
+        db.insert(
+            b"set".to_vec(),
+            ValueType::from(Value::Set([b"y".to_vec()].into())),
+        );
+
+        let len = combine_and_store(
+            &mut db,
+            SetOp::Union,
+            b"dest",
+            &[b"a", b"b"],
+            &[2.0, 1.0],
+            Aggregate::Sum,
+        )
+        .unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(score(&mut db, b"dest", b"y").unwrap(), Some(14.0));
+
+        let len = combine_and_store(
+            &mut db,
+            SetOp::Inter,
+            b"dest",
+            &[b"a", b"b", b"set"],
+            &[],
+            Aggregate::Min,
+        )
+        .unwrap();
+        assert_eq!(len, 1);
+        assert_eq!(score(&mut db, b"dest", b"y").unwrap(), Some(1.0));
+
+        let len = combine_and_store(
+            &mut db,
+            SetOp::Inter,
+            b"dest",
+            &[b"a", b"missing"],
+            &[],
+            Aggregate::Sum,
+        )
+        .unwrap();
+        assert_eq!(len, 0);
+        assert!(!db.contains_key(&b"dest"[..]));
+    }
+}
@@ -0,0 +1,992 @@
+//! Operations on stream values for the stream commands.
+//!
+//! A stream is an append only log of entries, each a list of field-value pairs under
+//! an ID made of a millisecond time and a sequence number.  IDs only ever increase, so
+//! the last ID handed out is kept even once its entry is deleted.  Unlike the other
+//! types, a stream key stays when all its entries are removed.
+//!
+//! Consumer groups share out a stream's entries among their consumers.  Each group
+//! remembers the last entry it delivered, and keeps the entries delivered but not yet
+//! acknowledged as pending, along with who they were delivered to, when, and how often.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::RedisError;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::{ClaimOptions, StreamTrim, TrimThreshold, XAddId};
+
+// XAUTOCLAIM looks at no more than this many pending entries for each one it may claim.
+const AUTOCLAIM_ATTEMPTS_FACTOR: usize = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct StreamId {
+    pub(crate) ms: u64,
+    pub(crate) seq: u64,
+}
+
+/// The fields of a stream entry, with their values, in the order they were given.
+pub(crate) type Fields = Vec<(Vec<u8>, Vec<u8>)>;
+
+pub(crate) type StreamEntry = (StreamId, Fields);
+
+/// An entry delivered to a consumer, whose fields are None if it has since been deleted.
+pub(crate) type GroupEntry = (StreamId, Option<Fields>);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Stream {
+    pub(crate) entries: BTreeMap<StreamId, Fields>,
+    // The ID of the most recently added entry, even if it has since been deleted.
+    pub(crate) last_id: StreamId,
+    // The greatest ID of any entry deleted or trimmed so far.
+    pub(crate) max_deleted_id: StreamId,
+    // The number of entries ever added.
+    pub(crate) entries_added: u64,
+    pub(crate) groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ConsumerGroup {
+    // The ID of the last entry delivered to the group.
+    pub(crate) last_id: StreamId,
+    // How many of the stream's entries the group has read, if that can be told.
+    pub(crate) entries_read: Option<u64>,
+    // The entries delivered but not yet acknowledged.
+    pub(crate) pending: BTreeMap<StreamId, PendingEntry>,
+    pub(crate) consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PendingEntry {
+    // The consumer the entry was last delivered to.
+    pub(crate) consumer: Vec<u8>,
+    // When the entry was last delivered, in milliseconds since the epoch.
+    pub(crate) delivery_time: u64,
+    pub(crate) delivery_count: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Consumer {
+    // When the consumer last tried to read or claim entries, in milliseconds since the
+    // epoch.
+    pub(crate) seen_time: u64,
+    // When the consumer last read or claimed any entries, if it ever has.
+    pub(crate) active_time: Option<u64>,
+}
+
+/// The changes reading or claiming made to a group, so that replicas can be told to make
+/// exactly the same ones.
+#[derive(Debug, Default)]
+pub(crate) struct GroupChanges {
+    pub(crate) created_consumer: bool,
+    // The entries delivered to the consumer, as they now are.
+    pub(crate) claimed: Vec<(StreamId, PendingEntry)>,
+    // The pending entries dropped because they were deleted from the stream.
+    pub(crate) removed: Vec<StreamId>,
+    // The group's new last delivered ID and read counter, if they changed.
+    pub(crate) last_id: Option<(StreamId, Option<u64>)>,
+}
+
+impl StreamId {
+    pub(crate) const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub(crate) const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub(crate) fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// The ID straight after this one, if any.
+    pub(crate) fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The ID straight before this one, if any.
+    pub(crate) fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl Stream {
+    /// The ID of the first entry, or zero if there are none.
+    pub(crate) fn first_id(&self) -> StreamId {
+        self.entries.keys().next().copied().unwrap_or_default()
+    }
+
+    /// How many entries the group has yet to read, if that can be told.
+    pub(crate) fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(entries_read) if !self.has_deletions_from(group.last_id) => Some(entries_read),
+            _ => self.entries_added_until(group.last_id),
+        }?;
+        Some(self.entries_added.saturating_sub(entries_read))
+    }
+
+    // Whether an entry from start on has been deleted, so that the entries after start
+    // can't be counted from the number added.
+    fn has_deletions_from(&self, start: StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && self.max_deleted_id >= start
+    }
+
+    // How many entries were added up to and including id, if that can be told.
+    fn entries_added_until(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 || (self.entries.is_empty() && id <= self.last_id) {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            Ordering::Equal => return Some(self.entries_added),
+            Ordering::Greater => return None,
+            Ordering::Less => {}
+        }
+        // Unless entries after the first were deleted, all those added since it remain.
+        let first_id = self.first_id();
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            let before_first = self.entries_added - self.entries.len() as u64;
+            match id.cmp(&first_id) {
+                Ordering::Less => return Some(before_first),
+                Ordering::Equal => return Some(before_first + 1),
+                Ordering::Greater => {}
+            }
+        }
+        None
+    }
+}
+
+impl ConsumerGroup {
+    /// The entries pending for the consumer.
+    pub(crate) fn pending_for<'g>(
+        &'g self,
+        consumer: &'g [u8],
+    ) -> impl Iterator<Item = (&'g StreamId, &'g PendingEntry)> {
+        self.pending
+            .iter()
+            .filter(move |(_, entry)| entry.consumer == consumer)
+    }
+
+    /// The pending entries with IDs from start to end inclusive.
+    pub(crate) fn pending_between(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl Iterator<Item = (&StreamId, &PendingEntry)> {
+        (start <= end)
+            .then(|| self.pending.range(start..=end))
+            .into_iter()
+            .flatten()
+    }
+
+    // Returns the consumer, creating it if need be, and whether it was created.
+    fn consumer_mut(&mut self, name: &[u8], now: u64) -> (&mut Consumer, bool) {
+        let created = !self.consumers.contains_key(name);
+        let consumer = self.consumers.entry(name.to_vec()).or_insert(Consumer {
+            seen_time: now,
+            active_time: None,
+        });
+        (consumer, created)
+    }
+}
+
+impl PendingEntry {
+    /// How long since the entry was last delivered, in milliseconds.
+    pub(crate) fn idle_millis(&self) -> u64 {
+        now_millis().saturating_sub(self.delivery_time)
+    }
+}
+
+impl Consumer {
+    /// How long since the consumer last tried to read or claim entries, in milliseconds.
+    pub(crate) fn idle_millis(&self) -> u64 {
+        now_millis().saturating_sub(self.seen_time)
+    }
+
+    /// How long since the consumer last read or claimed entries, in milliseconds.
+    pub(crate) fn inactive_millis(&self) -> Option<u64> {
+        self.active_time
+            .map(|active_time| now_millis().saturating_sub(active_time))
+    }
+}
+
+/// Appends an entry with the fields to the stream at key, creating the stream unless
+/// no_mkstream is set, then trims the stream if asked to.  Returns the new entry's ID,
+/// or None if there was no stream to add to.
+pub(crate) fn add(
+    db: &mut Database,
+    key: &[u8],
+    id: XAddId,
+    fields: &[(&[u8], &[u8])],
+    no_mkstream: bool,
+    trim: Option<&StreamTrim>,
+) -> Result<Option<StreamId>, RedisError> {
+    let last_id = match stream_mut(db, key)? {
+        Some(stream) => stream.last_id,
+        None if no_mkstream => return Ok(None),
+        None => StreamId::MIN,
+    };
+    let id = next_id(last_id, id)?;
+    if stream_mut(db, key)?.is_none() {
+        db.insert(
+            key.to_vec(),
+            ValueType::from(Value::Stream(Stream::default())),
+        );
+    }
+    let stream = stream_mut(db, key)?.expect("stream was just inserted");
+    let fields = fields
+        .iter()
+        .map(|(field, value)| (field.to_vec(), value.to_vec()))
+        .collect();
+    stream.entries.insert(id, fields);
+    stream.last_id = id;
+    stream.entries_added += 1;
+    if let Some(trim) = trim {
+        trim_entries(stream, trim);
+    }
+    Ok(Some(id))
+}
+
+pub(crate) fn len(db: &mut Database, key: &[u8]) -> Result<usize, RedisError> {
+    Ok(stream_mut(db, key)?.map_or(0, |stream| stream.entries.len()))
+}
+
+/// Returns up to count of the entries with IDs from start to end inclusive, in order,
+/// or in reverse order if rev is set.
+pub(crate) fn range(
+    db: &mut Database,
+    key: &[u8],
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+    rev: bool,
+) -> Result<Vec<StreamEntry>, RedisError> {
+    let Some(stream) = stream_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    if start > end {
+        return Ok(Vec::new());
+    }
+    let entries = stream.entries.range(start..=end);
+    let count = count.unwrap_or(usize::MAX);
+    let cloned = |(id, fields): (&StreamId, &Fields)| (*id, fields.clone());
+    Ok(if rev {
+        entries.rev().take(count).map(cloned).collect()
+    } else {
+        entries.take(count).map(cloned).collect()
+    })
+}
+
+/// Returns up to count of the entries with IDs greater than after, in order.
+pub(crate) fn read_after(
+    db: &mut Database,
+    key: &[u8],
+    after: StreamId,
+    count: Option<usize>,
+) -> Result<Vec<StreamEntry>, RedisError> {
+    match after.next() {
+        Some(start) => range(db, key, start, StreamId::MAX, count, false),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The ID of the last entry added to the stream at key, or zero if there is no stream.
+pub(crate) fn last_id(db: &mut Database, key: &[u8]) -> Result<StreamId, RedisError> {
+    Ok(stream_mut(db, key)?.map_or(StreamId::MIN, |stream| stream.last_id))
+}
+
+/// Deletes the entries with the IDs, returning how many there were.
+pub(crate) fn delete(db: &mut Database, key: &[u8], ids: &[StreamId]) -> Result<usize, RedisError> {
+    let Some(stream) = stream_mut(db, key)? else {
+        return Ok(0);
+    };
+    let mut deleted = 0;
+    for id in ids {
+        if stream.entries.remove(id).is_some() {
+            stream.max_deleted_id = stream.max_deleted_id.max(*id);
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Trims the stream at key, returning how many entries were removed.
+pub(crate) fn trim(db: &mut Database, key: &[u8], trim: &StreamTrim) -> Result<usize, RedisError> {
+    Ok(stream_mut(db, key)?.map_or(0, |stream| trim_entries(stream, trim)))
+}
+
+/// Returns the stream at key, which must exist.
+pub(crate) fn get<'d>(db: &'d mut Database, key: &[u8]) -> Result<&'d Stream, RedisError> {
+    stream_mut(db, key)?
+        .map(|stream| &*stream)
+        .ok_or(RedisError::NoSuchKey)
+}
+
+/// Returns the group of the stream at key, which must both exist.
+pub(crate) fn get_group<'d>(
+    db: &'d mut Database,
+    key: &[u8],
+    group: &[u8],
+) -> Result<&'d ConsumerGroup, RedisError> {
+    stream_mut(db, key)?
+        .and_then(|stream| stream.groups.get(group))
+        .ok_or_else(|| no_such_group(key, group))
+}
+
+/// Whether the group has entries it is yet to deliver.
+pub(crate) fn has_undelivered(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+) -> Result<bool, RedisError> {
+    let stream = stream_mut(db, key)?.ok_or_else(|| no_such_group(key, group))?;
+    let group = stream
+        .groups
+        .get(group)
+        .ok_or_else(|| no_such_group(key, group))?;
+    Ok(stream
+        .entries
+        .last_key_value()
+        .is_some_and(|(id, _)| *id > group.last_id))
+}
+
+/// Creates a consumer group which will deliver the entries after id, or after the last
+/// entry if id is None.  With mkstream, an empty stream is created if there is none.
+pub(crate) fn create_group(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+    id: Option<StreamId>,
+    mkstream: bool,
+    entries_read: Option<u64>,
+) -> Result<(), RedisError> {
+    if mkstream && stream_mut(db, key)?.is_none() {
+        db.insert(
+            key.to_vec(),
+            ValueType::from(Value::Stream(Stream::default())),
+        );
+    }
+    let stream = existing_stream(db, key)?;
+    if stream.groups.contains_key(group) {
+        return Err(RedisError::BusyGroup);
+    }
+    let last_id = id.unwrap_or(stream.last_id);
+    stream.groups.insert(
+        group.to_vec(),
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            ..ConsumerGroup::default()
+        },
+    );
+    Ok(())
+}
+
+/// Sets the ID of the last entry delivered to the group, or the last entry if id is None.
+pub(crate) fn set_group_id(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+    id: Option<StreamId>,
+    entries_read: Option<u64>,
+) -> Result<(), RedisError> {
+    let stream = existing_stream(db, key)?;
+    let last_id = id.unwrap_or(stream.last_id);
+    let group = group_mut(stream, key, group)?;
+    group.last_id = last_id;
+    group.entries_read = entries_read;
+    Ok(())
+}
+
+/// Removes the group, returning whether there was one.
+pub(crate) fn destroy_group(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+) -> Result<bool, RedisError> {
+    Ok(existing_stream(db, key)?.groups.remove(group).is_some())
+}
+
+/// Adds the consumer to the group, returning whether it is new.
+pub(crate) fn create_consumer(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+    consumer: &[u8],
+) -> Result<bool, RedisError> {
+    let group = group_mut(existing_stream(db, key)?, key, group)?;
+    Ok(group.consumer_mut(consumer, now_millis()).1)
+}
+
+/// Removes the consumer from the group, along with its pending entries, returning how
+/// many of those there were.
+pub(crate) fn delete_consumer(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+    consumer: &[u8],
+) -> Result<usize, RedisError> {
+    let group = group_mut(existing_stream(db, key)?, key, group)?;
+    if group.consumers.remove(consumer).is_none() {
+        return Ok(0);
+    }
+    let pending = group.pending.len();
+    group.pending.retain(|_, entry| entry.consumer != consumer);
+    Ok(pending - group.pending.len())
+}
+
+/// Reads entries for a consumer of the group, creating the consumer if need be.  With
+/// after None, delivers up to count of the entries the group is yet to deliver, which are
+/// then pending for the consumer unless no_ack is set.  Otherwise delivers again up to
+/// count of the consumer's pending entries with IDs greater than after, with None for any
+/// since deleted from the stream.
+pub(crate) fn read_group(
+    db: &mut Database,
+    key: &[u8],
+    group_name: &[u8],
+    consumer: &[u8],
+    after: Option<StreamId>,
+    count: Option<usize>,
+    no_ack: bool,
+) -> Result<(Vec<GroupEntry>, GroupChanges), RedisError> {
+    let now = now_millis();
+    let count = count.unwrap_or(usize::MAX);
+    let stream = stream_mut(db, key)?.ok_or_else(|| no_such_group(key, group_name))?;
+    let group = stream
+        .groups
+        .get(group_name)
+        .ok_or_else(|| no_such_group(key, group_name))?;
+    let mut entries_read = group.entries_read;
+    let entries: Vec<_> = match after {
+        None => {
+            let start = group.last_id.next().unwrap_or(StreamId::MAX);
+            let entries: Vec<_> = stream
+                .entries
+                .range(start..)
+                .take(count)
+                .map(|(id, fields)| (*id, Some(fields.clone())))
+                .collect();
+            // Keep count of how far through the stream the group is, which just goes up by
+            // one unless entries it skipped over were deleted.
+            let mut previous = group.last_id;
+            for (id, _) in &entries {
+                entries_read = match entries_read {
+                    Some(read) if !stream.has_deletions_from(previous) => Some(read + 1),
+                    _ => stream.entries_added_until(*id),
+                };
+                previous = *id;
+            }
+            entries
+        }
+        Some(after) => match after.next() {
+            Some(start) => group
+                .pending
+                .range(start..)
+                .filter(|(_, entry)| entry.consumer == consumer)
+                .take(count)
+                .map(|(id, _)| (*id, stream.entries.get(id).cloned()))
+                .collect(),
+            None => Vec::new(),
+        },
+    };
+
+    let group = group_mut(stream, key, group_name)?;
+    let mut changes = GroupChanges::default();
+    let (reader, created) = group.consumer_mut(consumer, now);
+    reader.seen_time = now;
+    if after.is_none() && !entries.is_empty() {
+        reader.active_time = Some(now);
+    }
+    changes.created_consumer = created;
+    for (id, fields) in &entries {
+        if after.is_none() {
+            if !no_ack {
+                let entry = PendingEntry {
+                    consumer: consumer.to_vec(),
+                    delivery_time: now,
+                    delivery_count: 1,
+                };
+                group.pending.insert(*id, entry.clone());
+                changes.claimed.push((*id, entry));
+            }
+        } else if fields.is_some() {
+            let entry = group.pending.get_mut(id).expect("entry was just found");
+            entry.delivery_time = now;
+            entry.delivery_count += 1;
+            changes.claimed.push((*id, entry.clone()));
+        }
+    }
+    if let (None, Some((last_id, _))) = (after, entries.last()) {
+        group.last_id = *last_id;
+        group.entries_read = entries_read;
+        changes.last_id = Some((*last_id, entries_read));
+    }
+    Ok((entries, changes))
+}
+
+/// Acknowledges the pending entries with the IDs, returning how many there were.
+pub(crate) fn ack(
+    db: &mut Database,
+    key: &[u8],
+    group: &[u8],
+    ids: &[StreamId],
+) -> Result<usize, RedisError> {
+    let Some(group) = stream_mut(db, key)?.and_then(|stream| stream.groups.get_mut(group)) else {
+        return Ok(0);
+    };
+    Ok(ids
+        .iter()
+        .filter(|id| group.pending.remove(id).is_some())
+        .count())
+}
+
+/// Transfers to the consumer those of the pending entries with the IDs which have been
+/// idle for at least min_idle milliseconds, returning them.  Any since deleted from the
+/// stream are no longer pending instead.
+pub(crate) fn claim(
+    db: &mut Database,
+    key: &[u8],
+    group_name: &[u8],
+    consumer: &[u8],
+    min_idle: u64,
+    ids: &[StreamId],
+    options: &ClaimOptions,
+) -> Result<(Vec<StreamEntry>, GroupChanges), RedisError> {
+    let now = now_millis();
+    let delivery_time = options
+        .time
+        .unwrap_or_else(|| now.saturating_sub(options.idle.unwrap_or(0)));
+    let stream = stream_mut(db, key)?.ok_or_else(|| no_such_group(key, group_name))?;
+    let Stream {
+        entries, groups, ..
+    } = stream;
+    let group = groups
+        .get_mut(group_name)
+        .ok_or_else(|| no_such_group(key, group_name))?;
+    let mut changes = GroupChanges::default();
+    if let Some(last_id) = options.last_id.filter(|last_id| *last_id > group.last_id) {
+        group.last_id = last_id;
+        changes.last_id = Some((last_id, group.entries_read));
+    }
+    let mut claimed = Vec::new();
+    for id in ids {
+        // FORCE makes entries pending which weren't, as long as they are in the stream.
+        if options.force && min_idle == 0 && entries.contains_key(id) {
+            group.pending.entry(*id).or_insert(PendingEntry {
+                consumer: consumer.to_vec(),
+                delivery_time: now,
+                delivery_count: 1,
+            });
+        }
+        let Some(entry) = group.pending.get_mut(id) else {
+            continue;
+        };
+        if now.saturating_sub(entry.delivery_time) < min_idle {
+            continue;
+        }
+        let Some(fields) = entries.get(id) else {
+            group.pending.remove(id);
+            changes.removed.push(*id);
+            continue;
+        };
+        entry.consumer = consumer.to_vec();
+        entry.delivery_time = delivery_time;
+        match options.retry_count {
+            Some(retry_count) => entry.delivery_count = retry_count,
+            None if !options.just_id => entry.delivery_count += 1,
+            None => {}
+        }
+        changes.claimed.push((*id, entry.clone()));
+        claimed.push((*id, fields.clone()));
+    }
+    claimed_by(group, consumer, now, !claimed.is_empty(), &mut changes);
+    Ok((claimed, changes))
+}
+
+/// Claims for the consumer up to count of the pending entries from start on which have
+/// been idle for at least min_idle milliseconds.  Returns the ID to carry on from, or zero
+/// once all the pending entries have been looked at, and the entries claimed.  Like
+/// claim, any since deleted from the stream are no longer pending instead, which also
+/// count towards count.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn auto_claim(
+    db: &mut Database,
+    key: &[u8],
+    group_name: &[u8],
+    consumer: &[u8],
+    min_idle: u64,
+    start: StreamId,
+    count: usize,
+    just_id: bool,
+) -> Result<(StreamId, Vec<StreamEntry>, GroupChanges), RedisError> {
+    let now = now_millis();
+    let stream = stream_mut(db, key)?.ok_or_else(|| no_such_group(key, group_name))?;
+    let Stream {
+        entries, groups, ..
+    } = stream;
+    let group = groups
+        .get_mut(group_name)
+        .ok_or_else(|| no_such_group(key, group_name))?;
+    let mut changes = GroupChanges::default();
+    let mut claimed = Vec::new();
+    let mut attempts = count.saturating_mul(AUTOCLAIM_ATTEMPTS_FACTOR);
+    let mut remaining = count;
+    let mut next = StreamId::MIN;
+    let ids: Vec<StreamId> = group.pending.range(start..).map(|(id, _)| *id).collect();
+    for id in ids {
+        if attempts == 0 || remaining == 0 {
+            next = id;
+            break;
+        }
+        attempts -= 1;
+        let entry = group.pending.get_mut(&id).expect("ID was just found");
+        if now.saturating_sub(entry.delivery_time) < min_idle {
+            continue;
+        }
+        remaining -= 1;
+        let Some(fields) = entries.get(&id) else {
+            group.pending.remove(&id);
+            changes.removed.push(id);
+            continue;
+        };
+        entry.consumer = consumer.to_vec();
+        entry.delivery_time = now;
+        if !just_id {
+            entry.delivery_count += 1;
+        }
+        changes.claimed.push((id, entry.clone()));
+        claimed.push((id, fields.clone()));
+    }
+    claimed_by(group, consumer, now, !claimed.is_empty(), &mut changes);
+    Ok((next, claimed, changes))
+}
+
+// Records that the consumer tried to claim entries, and whether it claimed any, creating
+// the consumer if it did.
+fn claimed_by(
+    group: &mut ConsumerGroup,
+    consumer: &[u8],
+    now: u64,
+    claimed_any: bool,
+    changes: &mut GroupChanges,
+) {
+    if claimed_any {
+        let (claimer, created) = group.consumer_mut(consumer, now);
+        claimer.seen_time = now;
+        claimer.active_time = Some(now);
+        changes.created_consumer = created;
+    } else if let Some(claimer) = group.consumers.get_mut(consumer) {
+        claimer.seen_time = now;
+    }
+}
+
+// Removes the oldest entries until the stream is within the threshold, or the limit on
+// how many to remove is reached.  Trimming is always exact, which the approximate `~`
+// form also allows, so that replicas trim the same entries.
+fn trim_entries(stream: &mut Stream, trim: &StreamTrim) -> usize {
+    let limit = trim.limit.unwrap_or(usize::MAX);
+    let mut removed = 0;
+    while removed < limit {
+        let Some(first) = stream.entries.first_key_value().map(|(id, _)| *id) else {
+            break;
+        };
+        let within = match trim.threshold {
+            TrimThreshold::MaxLen(max_len) => stream.entries.len() <= max_len,
+            TrimThreshold::MinId(min_id) => first >= min_id,
+        };
+        if within {
+            break;
+        }
+        stream.entries.remove(&first);
+        stream.max_deleted_id = stream.max_deleted_id.max(first);
+        removed += 1;
+    }
+    removed
+}
+
+// Works out the ID for a new entry, which must be greater than the last.
+fn next_id(last_id: StreamId, id: XAddId) -> Result<StreamId, RedisError> {
+    let too_small = || {
+        RedisError::UnexpectedArgumentType(
+            "The ID specified in XADD is equal or smaller than the target stream top item"
+                .to_string(),
+        )
+    };
+    match id {
+        XAddId::Explicit(StreamId::MIN) => Err(RedisError::UnexpectedArgumentType(
+            "The ID specified in XADD must be greater than 0-0".to_string(),
+        )),
+        XAddId::Explicit(id) if id <= last_id => Err(too_small()),
+        XAddId::Explicit(id) => Ok(id),
+        XAddId::AutoSequence(ms) if ms < last_id.ms => Err(too_small()),
+        XAddId::AutoSequence(ms) if ms == last_id.ms => {
+            let seq = last_id.seq.checked_add(1).ok_or_else(too_small)?;
+            Ok(StreamId::new(ms, seq))
+        }
+        XAddId::AutoSequence(ms) => Ok(StreamId::new(ms, 0)),
+        XAddId::Auto => {
+            let now = now_millis();
+            if now > last_id.ms {
+                Ok(StreamId::new(now, 0))
+            } else {
+                // The clock may have gone backwards, so carry on from the last ID.
+                last_id.next().ok_or_else(|| {
+                    RedisError::UnexpectedArgumentType(
+                        "The stream has exhausted the last possible ID, unable to add more items"
+                            .to_string(),
+                    )
+                })
+            }
+        }
+    }
+}
+
+// Returns the stream at key, which the XGROUP subcommands need to exist.
+fn existing_stream<'d>(db: &'d mut Database, key: &[u8]) -> Result<&'d mut Stream, RedisError> {
+    stream_mut(db, key)?.ok_or_else(|| {
+        RedisError::UnexpectedArgumentType(
+            "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want \
+             to use the MKSTREAM option to create an empty stream automatically."
+                .to_string(),
+        )
+    })
+}
+
+fn group_mut<'s>(
+    stream: &'s mut Stream,
+    key: &[u8],
+    group: &[u8],
+) -> Result<&'s mut ConsumerGroup, RedisError> {
+    stream
+        .groups
+        .get_mut(group)
+        .ok_or_else(|| no_such_group(key, group))
+}
+
+fn no_such_group(key: &[u8], group: &[u8]) -> RedisError {
+    RedisError::NoGroup {
+        key: String::from_utf8_lossy(key).to_string(),
+        group: String::from_utf8_lossy(group).to_string(),
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// Returns the stream at key, or None if the key doesn't exist.
+fn stream_mut<'d>(db: &'d mut Database, key: &[u8]) -> Result<Option<&'d mut Stream>, RedisError> {
+    match live_value_mut(db, key).map(ValueType::value_mut) {
+        Some(Value::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId::new(ms, seq)
+    }
+
+    fn stream_db(ids: &[StreamId]) -> Database {
+        let mut db = Database::new();
+        for entry_id in ids {
+            add(
+                &mut db,
+                b"s",
+                XAddId::Explicit(*entry_id),
+                &[(b"f", b"v")],
+                false,
+                None,
+            )
+            .unwrap();
+        }
+        db
+    }
+
+    fn ids(entries: Vec<StreamEntry>) -> Vec<StreamId> {
+        entries.into_iter().map(|(id, _)| id).collect()
+    }
+
+    fn ids_of(entries: &[GroupEntry]) -> Vec<StreamId> {
+        entries.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn ids_must_increase() {
+        let mut db = stream_db(&[id(5, 1)]);
+        let mut add_id = |requested| add(&mut db, b"s", requested, &[], false, None);
+        assert!(add_id(XAddId::Explicit(id(5, 1))).is_err());
+        assert!(add_id(XAddId::Explicit(id(4, 9))).is_err());
+        assert!(add_id(XAddId::AutoSequence(4)).is_err());
+        assert_eq!(add_id(XAddId::AutoSequence(5)).unwrap(), Some(id(5, 2)));
+        assert_eq!(add_id(XAddId::AutoSequence(7)).unwrap(), Some(id(7, 0)));
+        let auto = add_id(XAddId::Auto).unwrap().unwrap();
+        assert!(auto > id(7, 0));
+
+        let mut empty = Database::new();
+        assert!(add(
+            &mut empty,
+            b"s",
+            XAddId::Explicit(id(0, 0)),
+            &[],
+            false,
+            None
+        )
+        .is_err());
+        assert!(empty.is_empty());
+        assert_eq!(
+            add(&mut empty, b"s", XAddId::AutoSequence(0), &[], false, None).unwrap(),
+            Some(id(0, 1))
+        );
+        assert_eq!(
+            add(&mut empty, b"new", XAddId::Auto, &[], true, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn ranges_in_either_direction() {
+        let mut db = stream_db(&[id(1, 0), id(1, 1), id(2, 0), id(3, 0)]);
+        assert_eq!(
+            ids(range(&mut db, b"s", id(1, 1), id(2, u64::MAX), None, false).unwrap()),
+            [id(1, 1), id(2, 0)]
+        );
+        assert_eq!(
+            ids(range(&mut db, b"s", StreamId::MIN, StreamId::MAX, Some(2), true).unwrap()),
+            [id(3, 0), id(2, 0)]
+        );
+        assert!(range(&mut db, b"s", id(3, 0), id(1, 0), None, false)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            ids(read_after(&mut db, b"s", id(1, 1), Some(5)).unwrap()),
+            [id(2, 0), id(3, 0)]
+        );
+    }
+
+    #[test]
+    fn delete_keeps_last_id() {
+        let mut db = stream_db(&[id(1, 0), id(2, 0)]);
+        assert_eq!(delete(&mut db, b"s", &[id(2, 0), id(9, 0)]).unwrap(), 1);
+        assert_eq!(delete(&mut db, b"s", &[id(1, 0)]).unwrap(), 1);
+        assert_eq!(len(&mut db, b"s").unwrap(), 0);
+        assert_eq!(last_id(&mut db, b"s").unwrap(), id(2, 0));
+        assert!(add(&mut db, b"s", XAddId::Explicit(id(2, 0)), &[], false, None).is_err());
+    }
+
+    #[test]
+    fn groups_deliver_each_entry_once() {
+        let mut db = stream_db(&[id(1, 0), id(2, 0), id(3, 0)]);
+        create_group(&mut db, b"s", b"g", Some(StreamId::MIN), false, None).unwrap();
+        let (read, changes) = read_group(&mut db, b"s", b"g", b"a", None, Some(2), false).unwrap();
+        assert_eq!(ids_of(&read), [id(1, 0), id(2, 0)]);
+        assert!(changes.created_consumer);
+        // The number read is worked out from the number added.
+        assert_eq!(changes.last_id, Some((id(2, 0), Some(2))));
+        let (read, _) = read_group(&mut db, b"s", b"g", b"b", None, None, false).unwrap();
+        assert_eq!(ids_of(&read), [id(3, 0)]);
+        let (read, _) = read_group(&mut db, b"s", b"g", b"b", None, None, false).unwrap();
+        assert!(read.is_empty());
+
+        assert_eq!(ack(&mut db, b"s", b"g", &[id(1, 0), id(3, 0)]).unwrap(), 2);
+        delete(&mut db, b"s", &[id(2, 0)]).unwrap();
+        // Pending entries since deleted are delivered again without their fields.
+        let (read, _) =
+            read_group(&mut db, b"s", b"g", b"a", Some(StreamId::MIN), None, false).unwrap();
+        assert_eq!(read, [(id(2, 0), None)]);
+        let group = get_group(&mut db, b"s", b"g").unwrap();
+        assert_eq!(group.pending.len(), 1);
+        assert_eq!(group.pending_for(b"b").count(), 0);
+        assert!(read_group(&mut db, b"s", b"missing", b"a", None, None, false).is_err());
+    }
+
+    #[test]
+    fn claims_idle_entries() {
+        let mut db = stream_db(&[id(1, 0), id(2, 0), id(3, 0)]);
+        create_group(&mut db, b"s", b"g", Some(StreamId::MIN), false, None).unwrap();
+        read_group(&mut db, b"s", b"g", b"a", None, None, false).unwrap();
+        let to_claim = [id(1, 0), id(2, 0)];
+
+        let options = ClaimOptions::default();
+        let (claimed, _) = claim(&mut db, b"s", b"g", b"b", 60_000, &to_claim, &options).unwrap();
+        assert!(claimed.is_empty());
+        let (claimed, changes) = claim(&mut db, b"s", b"g", b"b", 0, &to_claim, &options).unwrap();
+        assert_eq!(ids(claimed), to_claim);
+        assert!(changes.created_consumer);
+        assert_eq!(changes.claimed[0].1.delivery_count, 2);
+
+        delete(&mut db, b"s", &[id(2, 0)]).unwrap();
+        let (next, claimed, changes) =
+            auto_claim(&mut db, b"s", b"g", b"c", 0, StreamId::MIN, 1, false).unwrap();
+        assert_eq!((next, ids(claimed)), (id(2, 0), vec![id(1, 0)]));
+        let (next, claimed, changes_after) =
+            auto_claim(&mut db, b"s", b"g", b"c", 0, next, 5, true).unwrap();
+        assert_eq!((next, ids(claimed)), (StreamId::MIN, vec![id(3, 0)]));
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes_after.removed, [id(2, 0)]);
+        assert_eq!(changes_after.claimed[0].1.delivery_count, 1);
+    }
+
+    #[test]
+    fn lag_counts_unread_entries() {
+        let mut db = stream_db(&[id(1, 0), id(2, 0), id(3, 0)]);
+        create_group(&mut db, b"s", b"g", Some(StreamId::MIN), false, None).unwrap();
+        let lag = |db: &mut Database| {
+            let stream = get(db, b"s").unwrap();
+            stream.lag(&stream.groups[&b"g"[..]])
+        };
+        assert_eq!(lag(&mut db), Some(3));
+        read_group(&mut db, b"s", b"g", b"a", None, Some(1), true).unwrap();
+        assert_eq!(lag(&mut db), Some(2));
+        // A deletion ahead of the group means its progress can't be told.
+        delete(&mut db, b"s", &[id(2, 0)]).unwrap();
+        assert_eq!(lag(&mut db), None);
+        read_group(&mut db, b"s", b"g", b"a", None, None, true).unwrap();
+        assert_eq!(lag(&mut db), Some(0));
+    }
+
+    #[test]
+    fn trims_oldest_entries() {
+        let mut db = stream_db(&[id(1, 0), id(2, 0), id(3, 0), id(4, 0), id(5, 0)]);
+        let max_len = StreamTrim {
+            threshold: TrimThreshold::MaxLen(2),
+            approximate: true,
+            limit: Some(2),
+        };
+        assert_eq!(trim(&mut db, b"s", &max_len).unwrap(), 2);
+        assert_eq!(trim(&mut db, b"s", &max_len).unwrap(), 1);
+        let min_id = StreamTrim {
+            threshold: TrimThreshold::MinId(id(5, 0)),
+            approximate: false,
+            limit: None,
+        };
+        assert_eq!(trim(&mut db, b"s", &min_id).unwrap(), 1);
+        assert_eq!(
+            ids(range(&mut db, b"s", StreamId::MIN, StreamId::MAX, None, false).unwrap()),
+            [id(5, 0)]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Operations on string values for the string commands.
+//!
+//! Strings are binary safe.  Unlike the values of the other types, which commands only
+//! change in place, a string set with SET or MSET replaces a value of any type and its
+//! expiration.
+
+use std::time::SystemTime;
+
+use crate::errors::RedisError;
+use crate::keys;
+use crate::redis_handler::{live_value_mut, Database, Value, ValueType};
+use crate::resp_command::SetOptions;
+
+// The longest string APPEND and SETRANGE may make, as in Redis by default.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+pub(crate) fn get(db: &mut Database, key: &[u8]) -> Result<Option<Vec<u8>>, RedisError> {
+    Ok(string_mut(db, key)?.cloned())
+}
+
+/// Sets the key to the value as SET does, returning whether it was set and, with the
+/// GET option, the string it held.
+pub(crate) fn set(
+    db: &mut Database,
+    key: &[u8],
+    value: &[u8],
+    expiration: Option<SystemTime>,
+    options: SetOptions,
+) -> Result<(bool, Option<Vec<u8>>), RedisError> {
+    // Only GET cares what the old value was.
+    let old_value = if options.get { get(db, key)? } else { None };
+    let old_expiration = live_value_mut(db, key).and_then(|value| value.expiration());
+    let exists = db.contains_key(key);
+    if options.nx && exists || options.xx && !exists {
+        return Ok((false, old_value));
+    }
+    let mut value = ValueType::new(value.to_vec());
+    value.set_expiration(if options.keep_ttl {
+        old_expiration
+    } else {
+        expiration
+    });
+    db.insert(key.to_vec(), value);
+    Ok((true, old_value))
+}
+
+/// Returns the string at each key, or None for keys which don't exist or don't hold
+/// strings.
+pub(crate) fn get_multiple(db: &mut Database, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+    keys.iter().map(|key| get(db, key).ok().flatten()).collect()
+}
+
+/// Sets each key to its value, returning whether they were set.  With nx set, none of
+/// them are if any of the keys exist.
+pub(crate) fn set_multiple(db: &mut Database, pairs: &[(&[u8], &[u8])], nx: bool) -> bool {
+    if nx
+        && pairs
+            .iter()
+            .any(|(key, _)| live_value_mut(db, key).is_some())
+    {
+        return false;
+    }
+    for (key, value) in pairs {
+        db.insert(key.to_vec(), ValueType::new(value.to_vec()));
+    }
+    true
+}
+
+/// Appends to the string at key, creating it if needed, and returns its new length.
+pub(crate) fn append(db: &mut Database, key: &[u8], value: &[u8]) -> Result<usize, RedisError> {
+    match string_mut(db, key)? {
+        Some(string) => {
+            check_len(string.len() + value.len())?;
+            string.extend_from_slice(value);
+            Ok(string.len())
+        }
+        None => {
+            db.insert(key.to_vec(), ValueType::new(value.to_vec()));
+            Ok(value.len())
+        }
+    }
+}
+
+pub(crate) fn len(db: &mut Database, key: &[u8]) -> Result<usize, RedisError> {
+    Ok(string_mut(db, key)?.map_or(0, |string| string.len()))
+}
+
+/// Returns the bytes of the string from start to end inclusive, where negative indices
+/// count back from the end, as GETRANGE does.
+pub(crate) fn range(
+    db: &mut Database,
+    key: &[u8],
+    start: i64,
+    end: i64,
+) -> Result<Vec<u8>, RedisError> {
+    let Some(string) = string_mut(db, key)? else {
+        return Ok(Vec::new());
+    };
+    let len = string.len() as i64;
+    if len == 0 || start < 0 && end < 0 && start > end {
+        return Ok(Vec::new());
+    }
+    // As in Redis, an end before the start of the string still includes the first byte.
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.clamp(0, len - 1);
+    Ok(if start <= end {
+        string[start as usize..=end as usize].to_vec()
+    } else {
+        Vec::new()
+    })
+}
+
+/// Overwrites the string at key from offset with the value, first padding it with zero
+/// bytes up to offset if it is shorter, and returns its new length.  The key is created
+/// if needed, unless the value is empty.
+pub(crate) fn set_range(
+    db: &mut Database,
+    key: &[u8],
+    offset: usize,
+    value: &[u8],
+) -> Result<usize, RedisError> {
+    let string = string_mut(db, key)?;
+    if value.is_empty() {
+        return Ok(string.map_or(0, |string| string.len()));
+    }
+    let end = offset.saturating_add(value.len());
+    check_len(end)?;
+    match string {
+        Some(string) => {
+            if string.len() < end {
+                string.resize(end, 0);
+            }
+            string[offset..end].copy_from_slice(value);
+            Ok(string.len())
+        }
+        None => {
+            let mut string = vec![0; offset];
+            string.extend_from_slice(value);
+            db.insert(key.to_vec(), ValueType::new(string));
+            Ok(end)
+        }
+    }
+}
+
+/// Removes the key if it holds a string, returning the string.
+pub(crate) fn get_del(db: &mut Database, key: &[u8]) -> Result<Option<Vec<u8>>, RedisError> {
+    let value = get(db, key)?;
+    if value.is_some() {
+        db.remove(key);
+    }
+    Ok(value)
+}
+
+/// Returns the string at key, first setting its expiration, or removing it if persist is
+/// set, as GETEX does.
+pub(crate) fn get_ex(
+    db: &mut Database,
+    key: &[u8],
+    expiration: Option<SystemTime>,
+    persist: bool,
+) -> Result<Option<Vec<u8>>, RedisError> {
+    let value = get(db, key)?;
+    if value.is_some() {
+        match expiration {
+            Some(expiration) => {
+                keys::expire(db, key, expiration, &[]);
+            }
+            None if persist => {
+                keys::persist(db, key);
+            }
+            None => (),
+        }
+    }
+    Ok(value)
+}
+
+fn string_mut<'d>(db: &'d mut Database, key: &[u8]) -> Result<Option<&'d mut Vec<u8>>, RedisError> {
+    match live_value_mut(db, key).map(ValueType::value_mut) {
+        Some(Value::String(string)) => Ok(Some(string)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn check_len(len: usize) -> Result<(), RedisError> {
+    if len > MAX_STRING_LEN {
+        return Err(RedisError::UnexpectedArgumentType(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lists::{self, List};
+    use crate::resp_command::ListEnd;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn string_db(key: &[u8], value: &[u8]) -> Database {
+        let mut db = Database::new();
+        db.insert(key.to_vec(), ValueType::new(value.to_vec()));
+        db
+    }
+
+    #[test]
+    fn set_honours_options() {
+        let mut db = Database::new();
+        let nx = SetOptions {
+            nx: true,
+            get: true,
+            ..SetOptions::default()
+        };
+        assert_eq!(set(&mut db, b"k", b"1", None, nx).unwrap(), (true, None));
+        assert_eq!(
+            set(&mut db, b"k", b"2", None, nx).unwrap(),
+            (false, Some(b"1".to_vec()))
+        );
+        let later = SystemTime::now() + Duration::from_secs(100);
+        set(&mut db, b"k", b"3", Some(later), SetOptions::default()).unwrap();
+        let keep_ttl = SetOptions {
+            keep_ttl: true,
+            xx: true,
+            ..SetOptions::default()
+        };
+        assert_eq!(
+            set(&mut db, b"k", b"4", None, keep_ttl).unwrap(),
+            (true, None)
+        );
+        assert_eq!(keys::ttl(&mut db, b"k", false), 100);
+        assert_eq!(
+            set(&mut db, b"missing", b"4", None, keep_ttl).unwrap(),
+            (false, None)
+        );
+
+        // Only GET needs the old value to be a string.
+        lists::push(&mut db, b"list", &[b"a"], ListEnd::Left).unwrap();
+        assert!(matches!(
+            set(&mut db, b"list", b"v", None, nx),
+            Err(RedisError::WrongType)
+        ));
+        set(&mut db, b"list", b"v", None, SetOptions::default()).unwrap();
+        assert_eq!(get(&mut db, b"list").unwrap(), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn sets_multiple_keys() {
+        let mut db = string_db(b"a", b"1");
+        assert!(!set_multiple(&mut db, &[(b"b", b"2"), (b"a", b"3")], true));
+        assert_eq!(get(&mut db, b"b").unwrap(), None);
+        assert!(set_multiple(&mut db, &[(b"b", b"2"), (b"a", b"3")], false));
+        db.insert(b"list".to_vec(), ValueType::from(Value::List(List::new())));
+        assert_eq!(
+            get_multiple(&mut db, &[b"a", b"missing", b"list", b"b"]),
+            vec![Some(b"3".to_vec()), None, None, Some(b"2".to_vec())]
+        );
+    }
+
+    #[test]
+    fn appends() {
+        let mut db = Database::new();
+        assert_eq!(append(&mut db, b"log", b"abc").unwrap(), 3);
+        assert_eq!(append(&mut db, b"log", b"de").unwrap(), 5);
+        assert_eq!(get(&mut db, b"log").unwrap(), Some(b"abcde".to_vec()));
+        assert_eq!(len(&mut db, b"log").unwrap(), 5);
+        assert_eq!(len(&mut db, b"missing").unwrap(), 0);
+    }
+
+    #[test]
+    fn gets_ranges() {
+        let mut db = string_db(b"k", b"This is a string");
+        assert_eq!(range(&mut db, b"k", 0, 3).unwrap(), b"This");
+        assert_eq!(range(&mut db, b"k", -3, -1).unwrap(), b"ing");
+        assert_eq!(range(&mut db, b"k", 0, -1).unwrap(), b"This is a string");
+        assert_eq!(range(&mut db, b"k", 10, 100).unwrap(), b"string");
+        assert_eq!(range(&mut db, b"k", 0, -100).unwrap(), b"T");
+        assert!(range(&mut db, b"k", -1, -3).unwrap().is_empty());
+        assert!(range(&mut db, b"k", 5, 3).unwrap().is_empty());
+        assert!(range(&mut db, b"missing", 0, -1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sets_ranges() {
+        let mut db = string_db(b"k", b"Hello World");
+        assert_eq!(set_range(&mut db, b"k", 6, b"Redis").unwrap(), 11);
+        assert_eq!(get(&mut db, b"k").unwrap(), Some(b"Hello Redis".to_vec()));
+        assert_eq!(set_range(&mut db, b"k", 13, b"!").unwrap(), 14);
+        assert_eq!(
+            get(&mut db, b"k").unwrap(),
+            Some(b"Hello Redis\0\0!".to_vec())
+        );
+        assert_eq!(set_range(&mut db, b"new", 2, b"ab").unwrap(), 4);
+        assert_eq!(get(&mut db, b"new").unwrap(), Some(b"\0\0ab".to_vec()));
+        assert_eq!(set_range(&mut db, b"empty", 5, b"").unwrap(), 0);
+        assert_eq!(get(&mut db, b"empty").unwrap(), None);
+        assert!(set_range(&mut db, b"k", MAX_STRING_LEN, b"x").is_err());
+    }
+
+    #[test]
+    fn gets_and_changes_keys() {
+        let mut db = string_db(b"k", b"v");
+        let later = SystemTime::now() + Duration::from_secs(100);
+        assert_eq!(
+            get_ex(&mut db, b"k", Some(later), false).unwrap(),
+            Some(b"v".to_vec())
+        );
+        assert_eq!(keys::ttl(&mut db, b"k", false), 100);
+        get_ex(&mut db, b"k", None, true).unwrap();
+        assert_eq!(keys::ttl(&mut db, b"k", false), -1);
+        get_ex(&mut db, b"k", Some(UNIX_EPOCH), false).unwrap();
+        assert_eq!(get(&mut db, b"k").unwrap(), None);
+
+        let mut db = string_db(b"k", b"v");
+        assert_eq!(get_del(&mut db, b"k").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(get_del(&mut db, b"k").unwrap(), None);
+        assert!(db.is_empty());
+    }
+}